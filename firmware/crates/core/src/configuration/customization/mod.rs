@@ -2,7 +2,18 @@ use core::{ops::RangeInclusive, time::Duration};
 
 use a13c_embedded::{peripherals::time::real_time::time::Time, utils::collections::list::List};
 
-use crate::features::trigger::EnableOnConditions;
+use crate::features::{
+	connectivity::WifiSupervisorSettings,
+	mqtt::MqttSettings,
+	ota::OtaSettings,
+	provisioning::ProvisioningSettings,
+	recording::EventRecordingSettings,
+	rtsp::RtspSettings,
+	storage::RetentionPolicy,
+	timelapse::TimelapseSettings,
+	trigger::{EnableOnConditions, PirSettings, TriggerSources},
+	webhooks::WebhookSettings,
+};
 
 pub trait Customization
 {
@@ -10,4 +21,24 @@ pub trait Customization
 
 	fn enable_image_trigger_on(&self) -> EnableOnConditions<Self::EnableOnConditionsList>;
 	fn trigger_duration(&self) -> Duration;
+	/// After the `trigger_duration`, motion is ignored for this long.
+	fn trigger_cooldown(&self) -> Duration;
+	fn pir_sensor(&self) -> PirSettings;
+	fn trigger_sources(&self) -> TriggerSources;
+	fn retention_policy(&self) -> RetentionPolicy;
+	/// If it's `None`, each image captured during the `trigger_duration` is stored on its own instead of recording the
+	/// events.
+	fn event_recording(&self) -> Option<EventRecordingSettings>;
+	/// `None` if the timelapse is disabled.
+	fn timelapse(&self) -> Option<TimelapseSettings<Self::EnableOnConditionsList>>;
+	/// `None` if the camera doesn't connect to an MQTT broker, even if the board provides a client.
+	fn mqtt(&self) -> Option<MqttSettings>;
+	/// `None` (or no URLs) if the motion events aren't sent to any webhook.
+	fn webhooks(&self) -> Option<WebhookSettings>;
+	/// Used when there are no WiFi networks in the settings.
+	fn provisioning(&self) -> ProvisioningSettings;
+	fn wifi_supervisor(&self) -> WifiSupervisorSettings;
+	fn ota(&self) -> OtaSettings;
+	/// `None` if the frames aren't streamed over RTSP.
+	fn rtsp(&self) -> Option<RtspSettings>;
 }
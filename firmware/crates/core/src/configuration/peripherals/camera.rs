@@ -1,14 +1,52 @@
-use core::{fmt::Debug, time::Duration};
+use core::{
+	fmt::{Debug, Display},
+	ops::RangeInclusive,
+	time::Duration,
+};
 
 use a13c_embedded::utils::math::micromath::micromath::vector::U16x2;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
 
 pub trait Camera
 {
 	type Image<'a>: Image
 	where Self: 'a;
 	type Error: Debug;
+	type Sensor<'a>: SensorControl<Error = Self::Error>
+	where Self: 'a;
+	/// The settings replaced by [`Camera::apply_capture_settings`], kept in the units of the camera so that they are
+	/// restored exactly.
+	type PreviousCaptureSettings: Default;
 
 	fn get_image<'a>(&'a self) -> Result<Self::Image<'a>, Self::Error>;
+
+	/// Applies the settings to the next images and returns the previous ones, so that they can be restored with
+	/// [`Camera::restore_capture_settings`]. Cameras that can't change their settings ignore them.
+	fn apply_capture_settings(&self, settings: CaptureSettings) -> Result<Self::PreviousCaptureSettings, Self::Error>
+	{
+		let _ = settings;
+		Ok(Self::PreviousCaptureSettings::default())
+	}
+
+	fn restore_capture_settings(&self, previous_settings: Self::PreviousCaptureSettings) -> Result<(), Self::Error>
+	{
+		let _ = previous_settings;
+		Ok(())
+	}
+
+	/// Returns the image sensor, whose settings can be changed while the camera is running.
+	fn get_sensor<'a>(&'a self) -> Self::Sensor<'a>;
+}
+
+/// Tuning of the image sensor, independent from the hardware.
+pub trait SensorControl
+{
+	type Error: Debug;
+
+	fn get_settings(&self) -> Result<SensorSettings, Self::Error>;
+	/// Applies all the `settings`, which must have been validated with [`SensorSettings::validate`].
+	fn set_settings(&self, settings: &SensorSettings) -> Result<(), Self::Error>;
 }
 
 pub trait Image
@@ -16,4 +54,196 @@ pub trait Image
 	fn get_pixels(&self) -> &[u8];
 	fn get_size(&self) -> U16x2;
 	fn get_timestamp(&self) -> Duration;
+
+	/// Returns a grayscale copy of the image, preferably downscaled while it's decoded (the motion detector only needs
+	/// a few thousand pixels). Returns `None` if the camera can't convert its images.
+	fn to_grayscale(&self) -> Option<GrayscaleImage>
+	{
+		None
+	}
+}
+
+/// An image with 1 byte per pixel (the brightness), row by row.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GrayscaleImage
+{
+	pub size: U16x2,
+	pub pixels: Vec<u8>,
+}
+
+/// Settings that can be changed for a single capture. A `None` field keeps the current value.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CaptureSettings
+{
+	/// The camera uses the closest resolution it supports.
+	pub resolution: Option<U16x2>,
+	/// JPEG quality, from 0 (smallest file) to 100 (best image).
+	pub quality: Option<u8>,
+}
+
+impl CaptureSettings
+{
+	pub fn is_empty(&self) -> bool
+	{
+		self.resolution.is_none() && self.quality.is_none()
+	}
+}
+
+/// How the camera encodes the pixels of the images.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PixelFormat
+{
+	Rgb565,
+	Yuv422,
+	Yuv420,
+	Grayscale,
+	Jpeg,
+	Rgb888,
+	Raw,
+	Rgb444,
+	Rgb555,
+}
+
+/// The settings of an image sensor. The ranges are the ones of the OV2640 (check [`SensorSettings::validate`]).
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct SensorSettings
+{
+	/// From -2 to 2.
+	pub brightness: i8,
+	/// From -2 to 2.
+	pub contrast: i8,
+	/// From -2 to 2.
+	pub saturation: i8,
+	/// From 0 to 6 (no effect, negative, grayscale, red tint, green tint, blue tint, sepia).
+	pub special_effect: u8,
+	/// Automatic white balance.
+	pub white_balance: bool,
+	pub white_balance_gain: bool,
+	/// From 0 to 4 (auto, sunny, cloudy, office, home), used only if `white_balance_gain` is enabled.
+	pub white_balance_mode: u8,
+	/// Automatic exposure.
+	pub exposure_control: bool,
+	/// Automatic exposure computed by the DSP.
+	pub aec2: bool,
+	/// From -2 to 2, used only if `exposure_control` is enabled.
+	pub ae_level: i8,
+	/// From 0 to 1200, used only if `exposure_control` is disabled.
+	pub aec_value: u16,
+	/// Automatic gain.
+	pub gain_control: bool,
+	/// From 0 to 30, used only if `gain_control` is disabled.
+	pub agc_gain: u8,
+	/// From 0 (2x) to 6 (128x), used only if `gain_control` is enabled.
+	pub gain_ceiling: u8,
+	pub black_pixel_correction: bool,
+	pub white_pixel_correction: bool,
+	pub raw_gamma: bool,
+	pub lens_correction: bool,
+	pub horizontal_mirror: bool,
+	pub vertical_flip: bool,
+	/// Downsizes the image in the sensor when a small resolution is used.
+	pub downsize: bool,
+	/// Replaces the image with a test pattern.
+	pub color_bar: bool,
+}
+
+impl SensorSettings
+{
+	/// Returns an error with the first setting that is out of its range.
+	pub fn validate(&self) -> Result<(), InvalidSensorSetting>
+	{
+		let ranges = [
+			("brightness", self.brightness as i32, -2..=2),
+			("contrast", self.contrast as i32, -2..=2),
+			("saturation", self.saturation as i32, -2..=2),
+			("special_effect", self.special_effect as i32, 0..=6),
+			("white_balance_mode", self.white_balance_mode as i32, 0..=4),
+			("ae_level", self.ae_level as i32, -2..=2),
+			("aec_value", self.aec_value as i32, 0..=1200),
+			("agc_gain", self.agc_gain as i32, 0..=30),
+			("gain_ceiling", self.gain_ceiling as i32, 0..=6),
+		];
+
+		match ranges.into_iter().find(|(_, value, range)| !range.contains(value))
+		{
+			Some((name, _, range)) => Err(InvalidSensorSetting { name, range }),
+			None => Ok(()),
+		}
+	}
+
+	/// Returns these settings with the changes in the JSON object `changes` applied (the settings missing from it keep
+	/// their current value). If a setting is unknown or out of its range, the error describes it.
+	pub fn with_json_changes(&self, changes: &[u8]) -> Result<Self, String>
+	{
+		let changes = match serde_json::from_slice::<Value>(changes)
+		{
+			Ok(Value::Object(changes)) => changes,
+			Ok(_) => return Err("The settings must be a JSON object".to_string()),
+			Err(error) => return Err(error.to_string()),
+		};
+
+		let Ok(Value::Object(mut settings)) = serde_json::to_value(self)
+		else
+		{
+			unreachable!("`SensorSettings` is serialized as a JSON object");
+		};
+		settings.extend(changes);
+		let settings = serde_json::from_value::<Self>(Value::Object(settings)).map_err(|error| error.to_string())?;
+		settings.validate().map_err(|error| error.to_string())?;
+
+		Ok(settings)
+	}
+}
+
+impl Default for SensorSettings
+{
+	fn default() -> Self
+	{
+		Self {
+			brightness: 0,
+			contrast: 0,
+			saturation: 0,
+			special_effect: 0,
+			white_balance: true,
+			white_balance_gain: true,
+			white_balance_mode: 0,
+			exposure_control: true,
+			aec2: false,
+			ae_level: 0,
+			aec_value: 300,
+			gain_control: false,
+			agc_gain: 3,
+			gain_ceiling: 6,
+			black_pixel_correction: false,
+			white_pixel_correction: true,
+			raw_gamma: false,
+			lens_correction: true,
+			horizontal_mirror: false,
+			vertical_flip: false,
+			downsize: true,
+			color_bar: false,
+		}
+	}
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InvalidSensorSetting
+{
+	pub name: &'static str,
+	pub range: RangeInclusive<i32>,
+}
+
+impl Display for InvalidSensorSetting
+{
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result
+	{
+		write!(
+			f,
+			"`{}` must be between {} and {}",
+			self.name,
+			self.range.start(),
+			self.range.end()
+		)
+	}
 }
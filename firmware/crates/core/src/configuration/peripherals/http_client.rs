@@ -0,0 +1,17 @@
+use core::fmt::Debug;
+
+/// Sends requests to other HTTP servers, like the ones of the [`Webhooks`](crate::features::webhooks::Webhooks) and the
+/// one with the firmware updates. It's used by a thread of its own, so it can block until the response arrives (as
+/// long as it has a timeout).
+pub trait HttpClient: Send + 'static
+{
+	type Error: Debug;
+
+	/// Returns the status code of the response.
+	fn post(&mut self, url: &str, content_type: &str, body: &[u8]) -> Result<u16, Self::Error>;
+
+	/// Passes the body of the response to `on_body_chunk` piece by piece as it's received (so that it doesn't need to
+	/// fit in memory), and stops reading it as soon as `on_body_chunk` returns `false`. Returns the status code of the
+	/// response.
+	fn get(&mut self, url: &str, on_body_chunk: &mut dyn FnMut(&[u8]) -> bool) -> Result<u16, Self::Error>;
+}
@@ -1,26 +1,37 @@
 pub mod camera;
+pub mod http_client;
+pub mod mqtt;
+pub mod ota;
+pub mod settings_store;
+pub mod tls;
 
 use core::fmt::Debug;
-use std::net::IpAddr;
+use std::net::{IpAddr, Ipv4Addr};
 
 extern crate alloc;
 use alloc::boxed::Box;
 
 use a13c_embedded::{
-	features::{communication::http::server::HttpServer, storage::embedded_sdmmc::TimeSource},
+	features::{
+		communication::http::server::HttpServer,
+		storage::embedded_sdmmc::{BlockDevice, TimeSource},
+	},
 	peripherals::{
 		time::{real_time::RealTimeClock, system_time::SystemTime},
 		watchdog::WatchdogCreator,
 	},
 };
-use embedded_hal::{
-	delay::DelayNs,
-	digital::{InputPin, OutputPin},
-	spi::SpiDevice,
-};
+use embedded_hal::digital::{InputPin, OutputPin};
 use embedded_svc::wifi::Wifi;
 
-use self::camera::Camera;
+use self::{
+	camera::Camera,
+	http_client::HttpClient,
+	mqtt::{MqttClient, MqttLastWill},
+	ota::OtaFlash,
+	settings_store::SettingsStore,
+	tls::TlsIdentityStore,
+};
 use crate::features::http_server::{stream::PossibleHttpRequest as StreamPossibleHttpRequest, PossibleHttpRequest};
 
 pub trait Peripherals
@@ -32,33 +43,74 @@ pub trait Peripherals
 	type StreamServer: HttpServer<HttpRequest = StreamPossibleHttpRequest>;
 	type ServerError: Debug;
 
-	type SdCardSpi: SpiDevice;
-	type SdCardCS: OutputPin;
-	type SdCardDelay: DelayNs;
-	type SdCardTimeSource: TimeSource;
+	type SdCard: BlockDevice + Send + 'static;
+	type SdCardTimeSource: TimeSource + Send + 'static;
 
 	type PirSensorPin: InputPin;
+	/// Turned on and off with MQTT.
+	type FlashLed: OutputPin;
+	/// Low while the button is pressed.
+	type SetupButtonPin: InputPin;
+
+	type SettingsStore: SettingsStore;
 
 	type WatchdogCreator: WatchdogCreator;
 
 	type RealTimeClock: RealTimeClock;
 
+	type MqttClient: MqttClient;
+	type MqttClientError: Debug;
+
+	type HttpClient: HttpClient;
+
+	type OtaFlash: OtaFlash;
+
+	type TlsIdentityStore: TlsIdentityStore;
+
 	fn take_camera(&mut self) -> Option<Self::Camera>;
 
 	fn take_wifi_driver(&mut self) -> Option<Self::WifiDriver>;
 	fn get_ip_address_from_wifi_driver_function() -> fn(&Self::WifiDriver) -> Option<IpAddr>;
+	fn get_mac_address_from_wifi_driver_function() -> fn(&Self::WifiDriver) -> Option<[u8; 6]>;
+	/// The returned function returns the strength of the signal of the access point in dBm.
+	fn get_rssi_from_wifi_driver_function() -> fn(&Self::WifiDriver) -> Option<i8>;
+	/// The returned function returns the address of the camera in the network of its own access point.
+	fn get_access_point_ip_address_from_wifi_driver_function() -> fn(&Self::WifiDriver) -> Option<Ipv4Addr>;
+	/// The returned function restarts the board, like after a power cycle.
+	fn get_restart_function() -> fn() -> !;
 	fn take_http_server(&mut self) -> Option<Box<dyn FnOnce() -> Result<Self::Server, Self::ServerError>>>;
 	fn take_stream_http_server(&mut self)
 		-> Option<Box<dyn FnOnce() -> Result<Self::StreamServer, Self::ServerError>>>;
 
-	fn take_sd_card_spi(&mut self) -> Option<Self::SdCardSpi>;
-	fn take_sd_card_cs(&mut self) -> Option<Self::SdCardCS>;
-	fn take_sd_card_delay(&mut self) -> Option<Self::SdCardDelay>;
+	fn take_sd_card(&mut self) -> Option<Self::SdCard>;
 	fn take_sd_card_time_source(&mut self) -> Option<Self::SdCardTimeSource>;
 
 	fn take_pir_sensor_pin(&mut self) -> Option<Self::PirSensorPin>;
+	/// `None` if the board doesn't have a flash LED.
+	fn take_flash_led(&mut self) -> Option<Self::FlashLed>;
+	/// `None` if the board doesn't have a button to return to the setup mode (it can still be done with the HTTP API).
+	fn take_setup_button_pin(&mut self) -> Option<Self::SetupButtonPin>;
+
+	fn take_settings_store(&mut self) -> Option<Self::SettingsStore>;
 
 	fn take_watchdog_creator(&mut self) -> Option<Self::WatchdogCreator>;
 
 	fn take_real_time_clock(&mut self) -> Option<Self::RealTimeClock>;
+
+	/// Unlike the other peripherals this one is optional: it's `None` if the camera isn't configured to connect to an
+	/// MQTT broker.
+	fn take_mqtt_client(
+		&mut self,
+	) -> Option<Box<dyn FnOnce(MqttLastWill) -> Result<Self::MqttClient, Self::MqttClientError>>>;
+
+	/// Called once by each feature that sends HTTP requests (the webhooks and the checks for firmware updates). `None` if
+	/// the board can't send them, so those features are disabled.
+	fn take_http_client(&mut self) -> Option<Self::HttpClient>;
+
+	/// `None` if the firmware of the board can't be updated.
+	fn take_ota_flash(&mut self) -> Option<Self::OtaFlash>;
+
+	/// `None` if the certificate of the HTTPS servers can't be changed. It's taken after starting the HTTP servers, which
+	/// may generate the certificate.
+	fn take_tls_identity_store(&mut self) -> Option<Self::TlsIdentityStore>;
 }
@@ -0,0 +1,39 @@
+use core::fmt::Debug;
+
+/// A client of an MQTT broker. The address of the broker and the credentials are chosen by the board, while the last
+/// will is chosen by the [`Mqtt`](crate::features::mqtt::Mqtt) feature when the client is created.
+///
+/// The client must connect (and reconnect after the connection is lost) by itself, and report it with the
+/// [`MqttEvent`]s. The messages are sent in the background, so that the main loop never waits for the network.
+pub trait MqttClient
+{
+	type Error: Debug;
+
+	fn publish(&mut self, topic: &str, payload: &[u8], retain: bool) -> Result<(), Self::Error>;
+	/// The subscriptions can be lost when the client reconnects, so they are made again after each
+	/// [`MqttEvent::Connected`].
+	fn subscribe(&mut self, topic: &str) -> Result<(), Self::Error>;
+	/// Returns the oldest event that hasn't been returned yet.
+	fn poll_event(&mut self) -> Option<MqttEvent>;
+}
+
+/// Published (retained) by the broker when the connection with the client is lost.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MqttLastWill
+{
+	pub topic: String,
+	pub payload: Vec<u8>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MqttEvent
+{
+	Connected,
+	Disconnected,
+	/// A message received on one of the subscribed topics.
+	Message
+	{
+		topic: String,
+		payload: Vec<u8>,
+	},
+}
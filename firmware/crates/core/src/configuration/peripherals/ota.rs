@@ -0,0 +1,27 @@
+use core::fmt::Debug;
+
+/// The flash memory with the firmware, split in two partitions: the one the camera is running from and an inactive one
+/// where the updates are written. An update is booted at the next restart but only stays installed if it's marked
+/// valid, otherwise the board rolls back to the previous firmware at the restart after that.
+pub trait OtaFlash: Send + 'static
+{
+	type Error: Debug;
+
+	/// The version of the firmware the camera is running, like `1.2.0`.
+	fn running_version(&self) -> String;
+	/// Returns `true` if the running firmware is an update that hasn't been marked valid yet.
+	fn is_running_pending_verification(&mut self) -> Result<bool, Self::Error>;
+	/// Keeps the running firmware installed, cancelling the rollback.
+	fn mark_running_valid(&mut self) -> Result<(), Self::Error>;
+
+	/// Erases the inactive partition to write a new image into it. The `size` of the image is `None` if it's unknown, in
+	/// which case the whole partition is erased.
+	fn begin(&mut self, size: Option<usize>) -> Result<(), Self::Error>;
+	/// Appends the `chunk` to the image being written.
+	fn write(&mut self, chunk: &[u8]) -> Result<(), Self::Error>;
+	/// Checks the written image and sets it to be booted at the next restart.
+	fn complete(&mut self) -> Result<(), Self::Error>;
+	/// Stops writing the image, so that [`Self::begin`] can be called again. It does nothing if no image is being
+	/// written.
+	fn abort(&mut self) -> Result<(), Self::Error>;
+}
@@ -0,0 +1,12 @@
+use core::fmt::Debug;
+
+/// A non volatile memory where the [`Settings`](crate::features::settings::Settings) are saved as a single blob, so that
+/// they survive a reboot. The blob is versioned and checksummed by the caller.
+pub trait SettingsStore
+{
+	type Error: Debug;
+
+	/// Returns `None` if nothing has been saved yet.
+	fn read(&mut self) -> Result<Option<Vec<u8>>, Self::Error>;
+	fn write(&mut self, blob: &[u8]) -> Result<(), Self::Error>;
+}
@@ -0,0 +1,18 @@
+use core::fmt::Debug;
+
+use crate::features::http_server::tls::TlsIdentity;
+
+/// A non volatile memory where the certificate and the private key of the HTTPS servers are saved. The board loads them
+/// when it starts the servers, after generating a self-signed identity if none has been saved yet (like at the first
+/// boot).
+pub trait TlsIdentityStore
+{
+	type Error: Debug;
+
+	/// Returns the saved certificate in PEM, or `None` if the servers don't have one (like if they serve plain HTTP).
+	fn certificate(&mut self) -> Result<Option<String>, Self::Error>;
+	/// Returns an error if the board can't use the `identity`, like if its private key doesn't match its certificate.
+	fn check(&mut self, identity: &TlsIdentity) -> Result<(), Self::Error>;
+	/// Saves the `identity`, which the servers use from the next restart.
+	fn write(&mut self, identity: &TlsIdentity) -> Result<(), Self::Error>;
+}
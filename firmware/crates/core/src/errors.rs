@@ -1,8 +1,9 @@
 use a13c_embedded::{
-	features::{communication::http::server::HttpServer, storage::embedded_sdmmc::SdCardError},
+	features::{communication::http::server::HttpServer, storage::embedded_sdmmc::BlockDevice},
 	peripherals::watchdog::{Watchdog, WatchdogCreator},
 };
 use embedded_hal::digital::{ErrorType, InputPin};
+use embedded_svc::wifi::Wifi;
 
 use crate::{
 	configuration::{
@@ -31,7 +32,22 @@ pub enum CreationError<C: Configuration>
 			<<C::Peripherals as Peripherals>::StreamServer as HttpServer>::Error,
 		>,
 	),
-	SdCard(a13c_embedded::features::storage::embedded_sdmmc::Error<SdCardError>),
+	SdCard(
+		a13c_embedded::features::storage::embedded_sdmmc::Error<
+			<<C::Peripherals as Peripherals>::SdCard as BlockDevice>::Error,
+		>,
+	),
+	/// The saved settings couldn't be applied to the camera.
+	Camera(<<C::Peripherals as Peripherals>::Camera as Camera>::Error),
+	StartMqttClient(<C::Peripherals as Peripherals>::MqttClientError),
+	/// The thread that sends the requests to the webhooks couldn't be spawned.
+	StartWebhooks(std::io::Error),
+	/// The thread that checks for firmware updates couldn't be spawned.
+	StartOtaPull(std::io::Error),
+	/// The RTSP server couldn't listen on its port or its thread couldn't be spawned.
+	StartRtspServer(std::io::Error),
+	/// The WiFi couldn't be configured to connect to the saved network or to start the setup access point.
+	Wifi(<<C::Peripherals as Peripherals>::WifiDriver as Wifi>::Error),
 }
 
 impl<C: Configuration> core::fmt::Debug for CreationError<C>
@@ -44,6 +60,12 @@ impl<C: Configuration> core::fmt::Debug for CreationError<C>
 			Self::StartHttpServer(error) => f.debug_tuple("Start HTTP server").field(error).finish(),
 			Self::StartStreamHttpServer(error) => f.debug_tuple("Start stream HTTP server").field(error).finish(),
 			Self::SdCard(error) => f.debug_tuple("SD Card").field(error).finish(),
+			Self::Camera(error) => f.debug_tuple("Camera").field(error).finish(),
+			Self::StartMqttClient(error) => f.debug_tuple("Start MQTT client").field(error).finish(),
+			Self::StartWebhooks(error) => f.debug_tuple("Start webhooks").field(error).finish(),
+			Self::StartOtaPull(error) => f.debug_tuple("Start OTA pull").field(error).finish(),
+			Self::StartRtspServer(error) => f.debug_tuple("Start RTSP server").field(error).finish(),
+			Self::Wifi(error) => f.debug_tuple("WiFi").field(error).finish(),
 			Self::RegisterURIHandlerHttpServer(error) =>
 			{
 				f.debug_tuple("Register URI handler HTTP server").field(error).finish()
@@ -57,6 +79,12 @@ pub enum TickError<C: Configuration>
 	Camera(<<C::Peripherals as Peripherals>::Camera as Camera>::Error),
 	CouldntReadPirSensorPin(<<C::Peripherals as Peripherals>::PirSensorPin as ErrorType>::Error),
 	WatchdogReset(<<<C::Peripherals as Peripherals>::WatchdogCreator as WatchdogCreator>::Watchdog as Watchdog>::Error),
+	/// The image couldn't be stored even after deleting the oldest captures.
+	Storage(
+		a13c_embedded::features::storage::embedded_sdmmc::Error<
+			<<C::Peripherals as Peripherals>::SdCard as BlockDevice>::Error,
+		>,
+	),
 }
 
 impl<C: Configuration> core::fmt::Debug for TickError<C>
@@ -68,6 +96,7 @@ impl<C: Configuration> core::fmt::Debug for TickError<C>
 			Self::Camera(arg0) => f.debug_tuple("Camera").field(arg0).finish(),
 			Self::CouldntReadPirSensorPin(arg0) => f.debug_tuple("CouldntReadPirSensorPin").field(arg0).finish(),
 			Self::WatchdogReset(arg0) => f.debug_tuple("WatchdogReset").field(arg0).finish(),
+			Self::Storage(arg0) => f.debug_tuple("Storage").field(arg0).finish(),
 		}
 	}
 }
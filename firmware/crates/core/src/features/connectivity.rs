@@ -0,0 +1,285 @@
+use core::time::Duration;
+use std::{net::IpAddr, time::Instant};
+
+use serde::Serialize;
+
+use crate::features::provisioning::WifiCredentials;
+
+/// How many networks are saved at most. When another one is saved, the one with the lowest priority is forgotten.
+pub const MAX_SAVED_NETWORKS: usize = 5;
+
+/// Configures how the camera reconnects to the WiFi.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WifiSupervisorSettings
+{
+	/// How long the camera waits for the connection to a network before trying the next one.
+	pub connect_timeout: Duration,
+	/// After all the networks have been tried, the camera waits this long before trying them again. The delay doubles
+	/// each time all the networks fail, up to the `max_retry_delay`.
+	pub first_retry_delay: Duration,
+	pub max_retry_delay: Duration,
+}
+
+/// Keeps the station connected to one of the saved networks: when the connection is lost (for example because the
+/// router has restarted), it tries the networks from the one with the highest priority, and then waits with an
+/// exponential backoff before trying them again.
+pub struct WifiSupervisor
+{
+	/// From the one with the highest priority.
+	networks: Vec<WifiCredentials>,
+	settings: WifiSupervisorSettings,
+	state: SupervisorState,
+	/// How many times in a row all the networks have been tried without connecting to any.
+	failed_rounds: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum SupervisorState
+{
+	Connecting
+	{
+		network: usize, started_at: Instant
+	},
+	Connected
+	{
+		network: usize
+	},
+	WaitingToRetry
+	{
+		until: Instant
+	},
+}
+
+/// Responded to the `/status` request, together with the status of the trigger.
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct WifiStatus
+{
+	pub state: WifiState,
+	/// The network the camera is connected (or connecting) to.
+	pub ssid: Option<String>,
+	pub ip_address: Option<IpAddr>,
+	pub rssi_dbm: Option<i8>,
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum WifiState
+{
+	/// No network is saved, so the setup access point is started.
+	Setup,
+	Connecting,
+	Connected,
+	/// All the networks have failed, so the camera is waiting before trying them again.
+	WaitingToRetry,
+}
+
+impl WifiSupervisor
+{
+	/// The first network is tried at the first [`Self::tick`].
+	pub fn new(networks: Vec<WifiCredentials>, settings: WifiSupervisorSettings, now: Instant) -> Self
+	{
+		Self {
+			networks,
+			settings,
+			state: SupervisorState::WaitingToRetry { until: now },
+			failed_rounds: 0,
+		}
+	}
+
+	/// Must be called periodically with whether the station is connected and has an IP. Returns the network the station
+	/// must start connecting to, if it's time to.
+	pub fn tick(&mut self, is_link_up: bool, now: Instant) -> Option<&WifiCredentials>
+	{
+		if self.networks.is_empty()
+		{
+			return None;
+		}
+
+		match self.state
+		{
+			SupervisorState::Connected { network } if !is_link_up =>
+			{
+				log::warn!(
+					"Lost the connection with the WiFi network {}",
+					self.networks[network].ssid
+				);
+				self.start_attempt(0, now)
+			},
+			SupervisorState::Connected { .. } => None,
+			SupervisorState::Connecting { network, .. } if is_link_up =>
+			{
+				log::info!("Connected to the WiFi network {}", self.networks[network].ssid);
+				self.state = SupervisorState::Connected { network };
+				self.failed_rounds = 0;
+				None
+			},
+			SupervisorState::Connecting { network, started_at }
+				if now >= started_at + self.settings.connect_timeout =>
+			{
+				log::warn!("Couldn't connect to the WiFi network {}", self.networks[network].ssid);
+				if network + 1 < self.networks.len()
+				{
+					return self.start_attempt(network + 1, now);
+				}
+
+				self.failed_rounds += 1;
+				let delay = self
+					.settings
+					.first_retry_delay
+					.saturating_mul(1 << (self.failed_rounds - 1).min(16))
+					.min(self.settings.max_retry_delay);
+				log::warn!("No WiFi network is reachable, trying again in {:?}", delay);
+				self.state = SupervisorState::WaitingToRetry { until: now + delay };
+				None
+			},
+			SupervisorState::Connecting { .. } => None,
+			SupervisorState::WaitingToRetry { until } if now >= until => self.start_attempt(0, now),
+			SupervisorState::WaitingToRetry { .. } => None,
+		}
+	}
+
+	pub fn state(&self) -> WifiState
+	{
+		match self.state
+		{
+			SupervisorState::Connecting { .. } => WifiState::Connecting,
+			SupervisorState::Connected { .. } => WifiState::Connected,
+			SupervisorState::WaitingToRetry { .. } => WifiState::WaitingToRetry,
+		}
+	}
+
+	/// The network the station is connected (or connecting) to.
+	pub fn network(&self) -> Option<&WifiCredentials>
+	{
+		match self.state
+		{
+			SupervisorState::Connecting { network, .. } | SupervisorState::Connected { network } =>
+			{
+				self.networks.get(network)
+			},
+			SupervisorState::WaitingToRetry { .. } => None,
+		}
+	}
+
+	fn start_attempt(&mut self, network: usize, now: Instant) -> Option<&WifiCredentials>
+	{
+		self.state = SupervisorState::Connecting {
+			network,
+			started_at: now,
+		};
+		self.networks.get(network)
+	}
+}
+
+/// Saves the `network` with the highest priority, replacing the saved one with the same SSID. If there are already
+/// [`MAX_SAVED_NETWORKS`], the one with the lowest priority is forgotten.
+pub fn prioritize_network(networks: &mut Vec<WifiCredentials>, network: WifiCredentials)
+{
+	networks.retain(|saved| saved.ssid != network.ssid);
+	networks.insert(0, network);
+	networks.truncate(MAX_SAVED_NETWORKS);
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+	const FIRST_RETRY_DELAY: Duration = Duration::from_secs(5);
+
+	fn network(ssid: &str) -> WifiCredentials
+	{
+		WifiCredentials {
+			ssid: ssid.to_string(),
+			password: String::new(),
+			auth_method: None,
+		}
+	}
+
+	fn supervisor(ssids: &[&str], now: Instant) -> WifiSupervisor
+	{
+		WifiSupervisor::new(
+			ssids.iter().map(|ssid| network(ssid)).collect(),
+			WifiSupervisorSettings {
+				connect_timeout: CONNECT_TIMEOUT,
+				first_retry_delay: FIRST_RETRY_DELAY,
+				max_retry_delay: Duration::from_secs(12),
+			},
+			now,
+		)
+	}
+
+	fn ssid(network: Option<&WifiCredentials>) -> Option<&str>
+	{
+		network.map(|network| network.ssid.as_str())
+	}
+
+	#[test]
+	fn networks_are_tried_by_priority_with_backoff()
+	{
+		let mut now = Instant::now();
+		let mut supervisor = supervisor(&["Home", "Office"], now);
+
+		assert_eq!(ssid(supervisor.tick(false, now)), Some("Home"));
+		assert_eq!(ssid(supervisor.tick(false, now + CONNECT_TIMEOUT / 2)), None);
+		now += CONNECT_TIMEOUT;
+		assert_eq!(ssid(supervisor.tick(false, now)), Some("Office"));
+		now += CONNECT_TIMEOUT;
+		assert_eq!(ssid(supervisor.tick(false, now)), None);
+		assert_eq!(supervisor.state(), WifiState::WaitingToRetry);
+
+		// The delays are 5, 10 and then the max of 12 seconds
+		for delay in [5, 10, 12]
+		{
+			assert_eq!(ssid(supervisor.tick(false, now + Duration::from_secs(delay - 1))), None);
+			now += Duration::from_secs(delay);
+			assert_eq!(ssid(supervisor.tick(false, now)), Some("Home"));
+			now += CONNECT_TIMEOUT;
+			assert_eq!(ssid(supervisor.tick(false, now)), Some("Office"));
+			now += CONNECT_TIMEOUT;
+			assert_eq!(ssid(supervisor.tick(false, now)), None);
+		}
+	}
+
+	#[test]
+	fn lost_connection_is_restored_and_resets_the_backoff()
+	{
+		let mut now = Instant::now();
+		let mut supervisor = supervisor(&["Home"], now);
+
+		assert_eq!(ssid(supervisor.tick(false, now)), Some("Home"));
+		now += CONNECT_TIMEOUT;
+		assert_eq!(ssid(supervisor.tick(false, now)), None);
+		now += FIRST_RETRY_DELAY;
+		assert_eq!(ssid(supervisor.tick(false, now)), Some("Home"));
+		assert_eq!(ssid(supervisor.tick(true, now)), None);
+		assert_eq!(supervisor.state(), WifiState::Connected);
+		assert_eq!(ssid(supervisor.network()), Some("Home"));
+
+		// The router restarts
+		assert_eq!(ssid(supervisor.tick(false, now)), Some("Home"));
+		assert_eq!(supervisor.state(), WifiState::Connecting);
+		now += CONNECT_TIMEOUT;
+		supervisor.tick(false, now);
+		// The first delay again
+		assert_eq!(ssid(supervisor.tick(false, now + FIRST_RETRY_DELAY)), Some("Home"));
+	}
+
+	#[test]
+	fn saved_network_gets_the_highest_priority()
+	{
+		let mut networks = (0..MAX_SAVED_NETWORKS)
+			.map(|index| network(&index.to_string()))
+			.collect();
+
+		prioritize_network(&mut networks, network("2"));
+		assert_eq!(networks.len(), MAX_SAVED_NETWORKS);
+		assert_eq!(networks[0].ssid, "2");
+		assert_eq!(networks[3].ssid, "3");
+
+		prioritize_network(&mut networks, network("New"));
+		let ssids: Vec<_> = networks.iter().map(|network| network.ssid.as_str()).collect();
+		assert_eq!(ssids, ["New", "2", "0", "1", "3"]);
+	}
+}
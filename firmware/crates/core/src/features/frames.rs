@@ -0,0 +1,147 @@
+use core::time::Duration;
+use std::{collections::VecDeque, sync::Arc, time::Instant};
+
+use a13c_embedded::utils::math::micromath::micromath::vector::U16x2;
+use spin::Mutex;
+
+use crate::{
+	configuration::peripherals::camera::{CaptureSettings, Image},
+	features::requests::Requests,
+};
+
+/// A copy of an image captured by the camera, that can outlive the camera's frame buffer.
+#[derive(Clone, Debug)]
+pub struct Frame
+{
+	/// Increases by 1 for each frame published to the [`FrameBroker`].
+	pub sequence_number: u64,
+	pub pixels: Vec<u8>,
+	pub size: U16x2,
+	pub timestamp: Duration,
+	pub published_at: Instant,
+}
+
+/// Keeps the latest frames captured by the camera, so that each consumer (the stream clients, the storage...) can read
+/// them at its own pace. Cloning the broker returns a handle to the same frames.
+#[derive(Clone)]
+pub struct FrameBroker
+{
+	state: Arc<Mutex<FrameBrokerState>>,
+}
+
+struct FrameBrokerState
+{
+	frames: VecDeque<Arc<Frame>>,
+	capacity: usize,
+	next_sequence_number: u64,
+}
+
+impl FrameBroker
+{
+	/// Creates a broker that keeps the latest `capacity` frames (at least 1).
+	pub fn new(capacity: usize) -> Self
+	{
+		let capacity = capacity.max(1);
+		Self {
+			state: Arc::new(Mutex::new(FrameBrokerState {
+				frames: VecDeque::with_capacity(capacity),
+				capacity,
+				next_sequence_number: 0,
+			})),
+		}
+	}
+
+	/// Copies the image in a new frame and returns it. The oldest frame is dropped if the broker is full (the consumers
+	/// that are still using it keep their own reference).
+	pub fn publish(&self, image: &impl Image) -> Arc<Frame>
+	{
+		let frame = self.publish_detached(image);
+
+		let mut state = self.state.lock();
+		if state.frames.len() == state.capacity
+		{
+			state.frames.pop_front();
+		}
+		state.frames.push_back(Arc::clone(&frame));
+
+		frame
+	}
+
+	/// Copies the image in a new frame with a sequence number, without making it available to the subscribers.
+	/// Useful for the images captured with custom settings for a single consumer.
+	pub fn publish_detached(&self, image: &impl Image) -> Arc<Frame>
+	{
+		// The copy is made before locking, so that the consumers aren't blocked by it
+		let mut frame = Frame {
+			sequence_number: 0,
+			pixels: image.get_pixels().to_vec(),
+			size: image.get_size(),
+			timestamp: image.get_timestamp(),
+			published_at: Instant::now(),
+		};
+
+		let mut state = self.state.lock();
+		frame.sequence_number = state.next_sequence_number;
+		state.next_sequence_number += 1;
+
+		Arc::new(frame)
+	}
+
+	pub fn latest_frame(&self) -> Option<Arc<Frame>>
+	{
+		self.state.lock().frames.back().cloned()
+	}
+
+	/// Returns a subscriber that only receives the frames published from now on.
+	pub fn subscribe(&self) -> FrameSubscriber
+	{
+		FrameSubscriber {
+			broker: self.clone(),
+			next_sequence_number: self.state.lock().next_sequence_number,
+		}
+	}
+}
+
+/// Reads the frames of a [`FrameBroker`] without receiving the same frame twice.
+pub struct FrameSubscriber
+{
+	broker: FrameBroker,
+	next_sequence_number: u64,
+}
+
+impl FrameSubscriber
+{
+	/// Returns the oldest frame this subscriber hasn't received yet. If the subscriber is so slow that some frames were
+	/// dropped by the broker, they are skipped.
+	pub fn next_frame(&mut self) -> Option<Arc<Frame>>
+	{
+		let frame = self
+			.broker
+			.state
+			.lock()
+			.frames
+			.iter()
+			.find(|frame| frame.sequence_number >= self.next_sequence_number)
+			.cloned()?;
+		self.next_sequence_number = frame.sequence_number + 1;
+
+		Some(frame)
+	}
+
+	/// Returns the newest frame if this subscriber hasn't received it yet, skipping the older ones (useful for live
+	/// streams, where latency matters more than receiving every frame).
+	pub fn latest_frame(&mut self) -> Option<Arc<Frame>>
+	{
+		let frame = self
+			.broker
+			.latest_frame()
+			.filter(|frame| frame.sequence_number >= self.next_sequence_number)?;
+		self.next_sequence_number = frame.sequence_number + 1;
+
+		Some(frame)
+	}
+}
+
+/// Single captures requested by the HTTP server, each one with its own settings. The frame is `None` if the capture
+/// failed.
+pub type SnapshotRequests = Requests<CaptureSettings, Option<Arc<Frame>>>;
@@ -0,0 +1,79 @@
+use core::time::Duration;
+
+use embedded_svc::http::server::{Connection, Request};
+
+use super::{
+	auth::cors_origin, control::respond_with_error, query, CameraStatus, HttpServerData, BAD_REQUEST_RESPONSE,
+	OK_RESPONSE, SERVICE_UNAVAILABLE_RESPONSE,
+};
+use crate::features::trigger::Arming;
+
+/// How long to wait for the main loop to read or change the arming of the trigger.
+const ARMING_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Arms the image trigger and responds like [`get_status`]. With the `until` query parameter (a date and time of the
+/// camera's clock, like `2024-05-01T18:00`) the trigger disarms itself at that time.
+pub fn arm<C: Connection>(request: Request<&mut C>, data: HttpServerData) -> Result<(), C::Error>
+{
+	log::info!("Start handling `arm` request");
+
+	let arming = match query::query_parameter(request.uri(), "until")
+	{
+		None => Arming::Armed,
+		// The query string isn't percent-decoded, and some clients encode the `:`
+		Some(until) => match until.replace("%3A", ":").replace("%3a", ":").parse()
+		{
+			Ok(until) => Arming::ArmedUntil { until },
+			Err(error) => return respond_with_error(request, BAD_REQUEST_RESPONSE, &error.to_string()),
+		},
+	};
+
+	respond_with_status(request, data, Some(arming))
+}
+
+/// Disarms the image trigger until the next `/arm` request, and responds like [`get_status`].
+pub fn disarm<C: Connection>(request: Request<&mut C>, data: HttpServerData) -> Result<(), C::Error>
+{
+	log::info!("Start handling `disarm` request");
+
+	respond_with_status(request, data, Some(Arming::Disarmed))
+}
+
+/// Responds with the JSON of the [`CameraStatus`].
+///
+/// ```json
+/// {"state":"armed","arming":{"mode":"armed_until","until":"2024-05-01T18:00:00"},"scheduled":true,
+///  "wifi":{"state":"connected","ssid":"Home","ip_address":"192.168.1.20","rssi_dbm":-61},
+///  "tls_certificate_sha256":"3F:B7:2A:...:CE:81"}
+/// ```
+pub fn get_status<C: Connection>(request: Request<&mut C>, data: HttpServerData) -> Result<(), C::Error>
+{
+	log::info!("Start handling `get_status` request");
+
+	respond_with_status(request, data, None)
+}
+
+fn respond_with_status<C: Connection>(
+	request: Request<&mut C>, data: HttpServerData, arming: Option<Arming>,
+) -> Result<(), C::Error>
+{
+	let Some(status) = data.arming_requests.request_and_wait(arming, ARMING_TIMEOUT)
+	else
+	{
+		return respond_with_error(request, SERVICE_UNAVAILABLE_RESPONSE, "The trigger isn't available");
+	};
+
+	let json = serde_json::to_string::<CameraStatus>(&status).unwrap_or_default();
+	let origin = cors_origin(&request);
+	let mut response = request.into_response(
+		OK_RESPONSE,
+		None,
+		&[
+			embedded_svc::http::headers::content_type("application/json"),
+			("Access-Control-Allow-Origin", &origin),
+		],
+	)?;
+	response.write_all(json.as_bytes())?;
+
+	Ok(())
+}
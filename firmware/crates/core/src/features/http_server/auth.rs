@@ -0,0 +1,1004 @@
+use core::time::Duration;
+use std::sync::Arc;
+
+use embedded_svc::http::server::{Connection, Request};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use spin::Mutex;
+
+use super::{
+	control::{read_body, respond_with_error},
+	query, HttpServerData, BAD_REQUEST_RESPONSE, FORBIDDEN_RESPONSE, NO_CONTENT_RESPONSE, SERVICE_UNAVAILABLE_RESPONSE,
+	UNAUTHORIZED_RESPONSE,
+};
+use crate::features::requests::Requests;
+
+/// The PBKDF2 iterations of the new password hashes, so that guessing the passwords from the saved settings is slow.
+/// Checking a password takes about a second on the camera, but only the first request with some credentials is that
+/// slow (check [`MAX_CHECKED_CREDENTIALS`]), and it doesn't block the other requests.
+pub const DEFAULT_PASSWORD_HASH_ITERATIONS: u32 = 50_000;
+/// Less iterations than these would make the passwords too quick to guess.
+const MIN_PASSWORD_HASH_ITERATIONS: u32 = 10_000;
+/// How many of the credentials that passed the password check, and of the ones that failed it, are remembered so that
+/// they aren't hashed again (a client repeating wrong credentials can't keep the camera busy hashing them).
+const MAX_CHECKED_CREDENTIALS: usize = 4;
+const MIN_PASSWORD_LENGTH: usize = 8;
+const MIN_TOKEN_LENGTH: usize = 16;
+/// How long to wait for the main loop to save the new credentials.
+const SAVE_TIMEOUT: Duration = Duration::from_secs(5);
+/// Makes the browsers and the RTSP clients ask for a username and a password.
+pub(crate) const BASIC_CHALLENGE: &str = r#"Basic realm="camera", charset="UTF-8""#;
+
+/// Who can make a request to an endpoint. The requests made by web pages must also come from an allowed origin (check
+/// [`HttpAuthSettings::is_origin_allowed`]).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Access
+{
+	/// Anyone.
+	Public,
+	/// Anyone while the camera is in the setup mode (so only who is connected to its access point), otherwise like
+	/// [`Access::Protected`].
+	Setup,
+	/// Only with the credentials of a user or a token.
+	Protected,
+}
+
+/// The credentials and the CORS policy of the HTTP servers, saved in the settings. While there are no users and no
+/// tokens, the [`Access::Protected`] endpoints refuse all the requests, so the first credentials can only be set from
+/// the setup page (`POST /auth` is [`Access::Setup`]).
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(default)]
+pub struct HttpAuthSettings
+{
+	/// Accepted with `Authorization: Basic <base64 of username:password>`.
+	pub users: Vec<HttpUser>,
+	/// The SHA-256 of the tokens accepted with `Authorization: Bearer <token>` or with the `access_token` query
+	/// parameter (for the clients that can't set headers, like an `<img>` showing the `/stream`).
+	pub token_hashes: Vec<[u8; 32]>,
+	/// The origins of the web pages that can make requests to the camera, like `http://home.local:8123`. The pages
+	/// served by the camera itself are always allowed.
+	pub allowed_origins: Vec<String>,
+	/// The cost of the password hashes made by `POST /auth`. The existing hashes keep the cost they were made with.
+	pub password_hash_iterations: u32,
+	/// The SHA-256 of the last `username:password`s that passed the slow password check. It's never saved, and it's
+	/// emptied when the credentials change because the settings are replaced.
+	#[serde(skip)]
+	pub(crate) verified_credentials: Vec<[u8; 32]>,
+	/// Like `verified_credentials`, but with the ones that failed the password check.
+	#[serde(skip)]
+	pub(crate) failed_credentials: Vec<[u8; 32]>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct HttpUser
+{
+	pub username: String,
+	pub password_hash: PasswordHash,
+}
+
+/// A salted hash of a password, saved with the algorithm and the cost it was made with.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct PasswordHash
+{
+	pub algorithm: PasswordHashAlgorithm,
+	pub iterations: u32,
+	/// Different for each user, so that the same password doesn't have the same hash.
+	pub salt: [u8; 16],
+	pub hash: [u8; 32],
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PasswordHashAlgorithm
+{
+	/// PBKDF2 with HMAC-SHA256 ([RFC 8018](https://www.rfc-editor.org/rfc/rfc8018)).
+	Pbkdf2HmacSha256,
+}
+
+/// The credentials shared between the main loop (that saves them) and the HTTP servers (that check them).
+pub type SharedHttpAuth = Arc<Mutex<HttpAuthSettings>>;
+
+/// Replaces the [`HttpAuthSettings`], which are saved by the main loop.
+pub type HttpAuthRequests = Requests<HttpAuthSettings, ()>;
+
+/// What [`HttpAuthSettings::check_credentials`] can tell while the settings are locked.
+enum CredentialsCheck
+{
+	Done(bool),
+	/// Boxed because it's rare, and the RTSP clients have little stack.
+	Password(Box<PasswordCheck>),
+}
+
+/// The password must be hashed like the `password_hash` of the user, which is slow so it's done without the lock.
+struct PasswordCheck
+{
+	credentials_hash: [u8; 32],
+	username: String,
+	password: String,
+	password_hash: PasswordHash,
+}
+
+#[derive(Deserialize)]
+struct HttpAuthBody
+{
+	#[serde(default)]
+	users: Vec<HttpUserBody>,
+	#[serde(default)]
+	tokens: Vec<String>,
+	#[serde(default)]
+	allowed_origins: Vec<String>,
+	#[serde(default)]
+	password_hash_iterations: Option<u32>,
+}
+
+#[derive(Deserialize)]
+struct HttpUserBody
+{
+	username: String,
+	password: String,
+}
+
+impl Default for HttpAuthSettings
+{
+	fn default() -> Self
+	{
+		Self {
+			users: Vec::new(),
+			token_hashes: Vec::new(),
+			allowed_origins: Vec::new(),
+			password_hash_iterations: DEFAULT_PASSWORD_HASH_ITERATIONS,
+			verified_credentials: Vec::new(),
+			failed_credentials: Vec::new(),
+		}
+	}
+}
+
+impl HttpAuthSettings
+{
+	/// Returns `false` if there are no credentials, so no request is authenticated.
+	pub fn is_enabled(&self) -> bool
+	{
+		!self.users.is_empty() || !self.token_hashes.is_empty()
+	}
+
+	/// Checks the `Authorization` header of a request, or its `access_token` query parameter, except for the passwords
+	/// that haven't been checked yet (check [`is_authenticated`]).
+	fn check_credentials(&self, authorization: Option<&str>, uri: &str) -> CredentialsCheck
+	{
+		let token = match authorization.map(|authorization| authorization.split_once(' '))
+		{
+			Some(Some((scheme, credentials))) if scheme.eq_ignore_ascii_case("Basic") =>
+			{
+				return self.check_user_credentials(credentials.trim());
+			},
+			Some(Some((scheme, token))) if scheme.eq_ignore_ascii_case("Bearer") => token.trim(),
+			Some(_) => return CredentialsCheck::Done(false),
+			None => match query::query_parameter(uri, "access_token")
+			{
+				Some(token) => token,
+				None => return CredentialsCheck::Done(false),
+			},
+		};
+		let token_hash = Sha256::digest(token.as_bytes());
+		CredentialsCheck::Done(
+			self.token_hashes
+				.iter()
+				.any(|expected| bytes_match(&token_hash, expected)),
+		)
+	}
+
+	/// Returns `true` if the request doesn't come from a web page (it has no `Origin` header), or if the page is one of
+	/// the `allowed_origins` or has been served by the camera (its origin has the same host as the request).
+	pub fn is_origin_allowed(&self, origin: Option<&str>, host: Option<&str>) -> bool
+	{
+		let Some(origin) = origin
+		else
+		{
+			return true;
+		};
+
+		let origin_host = origin
+			.strip_prefix("http://")
+			.or_else(|| origin.strip_prefix("https://"));
+		(origin_host.is_some() && origin_host == host) || self.allowed_origins.iter().any(|allowed| allowed == origin)
+	}
+
+	/// Checks the credentials and the origins in the JSON of `POST /auth`, and hashes the passwords and the tokens.
+	fn from_body(body: HttpAuthBody) -> Result<Self, String>
+	{
+		if body.users.is_empty() && body.tokens.is_empty()
+		{
+			return Err("At least a user or a token is needed".to_string());
+		}
+		let password_hash_iterations = body
+			.password_hash_iterations
+			.unwrap_or(DEFAULT_PASSWORD_HASH_ITERATIONS);
+		if password_hash_iterations < MIN_PASSWORD_HASH_ITERATIONS
+		{
+			return Err(format!(
+				"The password hashes need at least {} iterations",
+				MIN_PASSWORD_HASH_ITERATIONS
+			));
+		}
+		let mut users: Vec<HttpUser> = Vec::with_capacity(body.users.len());
+		for user in body.users
+		{
+			if user.username.is_empty() || user.username.contains(':')
+			{
+				return Err(format!("The username {:?} isn't valid", user.username));
+			}
+			if users.iter().any(|other_user| other_user.username == user.username)
+			{
+				return Err(format!("The username {} is repeated", user.username));
+			}
+			if user.password.chars().count() < MIN_PASSWORD_LENGTH
+			{
+				return Err(format!(
+					"The passwords must be at least {} characters long",
+					MIN_PASSWORD_LENGTH
+				));
+			}
+			users.push(HttpUser::new(user.username, &user.password, password_hash_iterations));
+		}
+		if body.tokens.iter().any(|token| token.chars().count() < MIN_TOKEN_LENGTH)
+		{
+			return Err(format!(
+				"The tokens must be at least {} characters long",
+				MIN_TOKEN_LENGTH
+			));
+		}
+		if let Some(origin) = body
+			.allowed_origins
+			.iter()
+			.find(|origin| !(origin.starts_with("http://") || origin.starts_with("https://")) || origin.ends_with('/'))
+		{
+			return Err(format!(
+				"The origin {} isn't valid, it must be like `http://home.local:8123`",
+				origin
+			));
+		}
+
+		Ok(Self {
+			users,
+			token_hashes: body
+				.tokens
+				.iter()
+				.map(|token| Sha256::digest(token.as_bytes()).into())
+				.collect(),
+			allowed_origins: body.allowed_origins,
+			password_hash_iterations,
+			verified_credentials: Vec::new(),
+			failed_credentials: Vec::new(),
+		})
+	}
+
+	fn check_user_credentials(&self, base64_credentials: &str) -> CredentialsCheck
+	{
+		let Some(credentials) = decode_base64(base64_credentials).and_then(|bytes| String::from_utf8(bytes).ok())
+		else
+		{
+			return CredentialsCheck::Done(false);
+		};
+		let credentials_hash: [u8; 32] = Sha256::digest(credentials.as_bytes()).into();
+		let is_hash_of_credentials = |checked: &[u8; 32]| bytes_match(checked, &credentials_hash);
+		if self.verified_credentials.iter().any(is_hash_of_credentials)
+		{
+			return CredentialsCheck::Done(true);
+		}
+		if self.failed_credentials.iter().any(is_hash_of_credentials)
+		{
+			return CredentialsCheck::Done(false);
+		}
+		let Some((username, password)) = credentials.split_once(':')
+		else
+		{
+			return CredentialsCheck::Done(false);
+		};
+
+		match self.users.iter().find(|user| user.username == username)
+		{
+			Some(user) => CredentialsCheck::Password(Box::new(PasswordCheck {
+				credentials_hash,
+				username: username.to_string(),
+				password: password.to_string(),
+				password_hash: user.password_hash.clone(),
+			})),
+			None => CredentialsCheck::Done(false),
+		}
+	}
+
+	/// Remembers the result of a password check, unless the password of the user changed meanwhile.
+	fn remember_password_check(&mut self, check: &PasswordCheck, is_authenticated: bool)
+	{
+		if !self
+			.users
+			.iter()
+			.any(|user| user.username == check.username && user.password_hash == check.password_hash)
+		{
+			return;
+		}
+
+		let checked_credentials = match is_authenticated
+		{
+			true => &mut self.verified_credentials,
+			false => &mut self.failed_credentials,
+		};
+		if checked_credentials.len() == MAX_CHECKED_CREDENTIALS
+		{
+			checked_credentials.remove(0);
+		}
+		checked_credentials.push(check.credentials_hash);
+	}
+}
+
+/// Checks the `Authorization` header of a request, or its `access_token` query parameter (which isn't percent-decoded,
+/// so the tokens should only have URL-safe characters). The `auth` isn't locked while a password is hashed, so the
+/// other requests and the main loop aren't blocked meanwhile.
+pub fn is_authenticated(auth: &SharedHttpAuth, authorization: Option<&str>, uri: &str) -> bool
+{
+	let check = auth.lock().check_credentials(authorization, uri);
+	match check
+	{
+		CredentialsCheck::Done(is_authenticated) => is_authenticated,
+		CredentialsCheck::Password(check) => check.run(auth),
+	}
+}
+
+impl PasswordCheck
+{
+	fn run(self, auth: &SharedHttpAuth) -> bool
+	{
+		let is_authenticated = self.password_hash.matches(&self.password);
+		auth.lock().remember_password_check(&self, is_authenticated);
+
+		is_authenticated
+	}
+}
+
+impl HttpUser
+{
+	/// Hashes the `password` with a new salt and PBKDF2 with the `iterations`.
+	pub fn new(username: String, password: &str, iterations: u32) -> Self
+	{
+		Self {
+			username,
+			password_hash: PasswordHash::new(password, iterations),
+		}
+	}
+}
+
+impl PasswordHash
+{
+	pub fn new(password: &str, iterations: u32) -> Self
+	{
+		let salt = new_salt();
+		Self {
+			algorithm: PasswordHashAlgorithm::Pbkdf2HmacSha256,
+			iterations,
+			hash: pbkdf2_hmac_sha256(password, &salt, iterations),
+			salt,
+		}
+	}
+
+	/// Hashes the `password` like this one was made, and compares the results.
+	pub fn matches(&self, password: &str) -> bool
+	{
+		let hash = match self.algorithm
+		{
+			PasswordHashAlgorithm::Pbkdf2HmacSha256 => pbkdf2_hmac_sha256(password, &self.salt, self.iterations),
+		};
+
+		bytes_match(&hash, &self.hash)
+	}
+}
+
+/// Calls the `handler` if the request comes from an allowed origin and is authenticated for the `access`, otherwise
+/// responds with `403` or `401`.
+pub(super) fn authorize_and_handle<C: Connection>(
+	request: Request<&mut C>, data: HttpServerData, access: Access,
+	handler: fn(Request<&mut C>, HttpServerData) -> Result<(), C::Error>,
+) -> Result<(), C::Error>
+{
+	let is_authentication_required = match access
+	{
+		Access::Public => false,
+		Access::Setup => !data.is_in_setup_mode,
+		Access::Protected => true,
+	};
+	let is_origin_allowed = data
+		.auth
+		.lock()
+		.is_origin_allowed(request.header("Origin"), request.header("Host"));
+	let is_authenticated =
+		!is_authentication_required || is_authenticated(&data.auth, request.header("Authorization"), request.uri());
+
+	if !is_origin_allowed
+	{
+		log::warn!(
+			"Refused a request to {} from the origin {:?}",
+			request.uri(),
+			request.header("Origin")
+		);
+		return respond_with_error(request, FORBIDDEN_RESPONSE, "This origin isn't allowed");
+	}
+	if !is_authenticated
+	{
+		let origin = cors_origin(&request);
+		let mut response = request.into_response(
+			UNAUTHORIZED_RESPONSE,
+			None,
+			&[
+				embedded_svc::http::headers::content_type("application/json"),
+				("WWW-Authenticate", BASIC_CHALLENGE),
+				("Access-Control-Allow-Origin", &origin),
+			],
+		)?;
+		response.write_all(br#"{"error":"Missing or invalid credentials"}"#)?;
+		return Ok(());
+	}
+
+	handler(request, data)
+}
+
+/// The value of the `Access-Control-Allow-Origin` header: the origin of the request, which [`authorize_and_handle`]
+/// has already allowed. The requests without an `Origin` don't come from a web page, so the header is ignored.
+pub(super) fn cors_origin<C: Connection>(request: &Request<&mut C>) -> String
+{
+	request.header("Origin").unwrap_or("*").to_string()
+}
+
+/// Responds to the preflight requests that the browsers make before the cross-origin requests with credentials or a
+/// body, once their origin has been allowed.
+pub fn preflight<C: Connection>(request: Request<&mut C>, _: HttpServerData) -> Result<(), C::Error>
+{
+	let origin = cors_origin(&request);
+	request.into_response(
+		NO_CONTENT_RESPONSE,
+		None,
+		&[
+			("Access-Control-Allow-Origin", &origin),
+			("Access-Control-Allow-Methods", "GET, POST, DELETE"),
+			(
+				"Access-Control-Allow-Headers",
+				"Authorization, Content-Type, X-Image-SHA256",
+			),
+			("Access-Control-Max-Age", "600"),
+		],
+	)?;
+
+	Ok(())
+}
+
+/// Replaces the credentials and the allowed origins with the ones in the JSON of the body, like:
+///
+/// ```json
+/// {"users":[{"username":"admin","password":"..."}],"tokens":["..."],"allowed_origins":["http://home.local:8123"]}
+/// ```
+///
+/// The passwords are hashed with the optional `password_hash_iterations` (at least 10000), otherwise with
+/// [`DEFAULT_PASSWORD_HASH_ITERATIONS`].
+///
+/// At least a user or a token is needed. If the JSON isn't valid nothing is changed, and the response is a `400` with the
+/// JSON `{"error":"..."}`.
+pub fn change_auth<C: Connection>(mut request: Request<&mut C>, data: HttpServerData) -> Result<(), C::Error>
+{
+	log::info!("Start handling `change_auth` request");
+
+	let Some(body) = read_body(&mut request)?
+	else
+	{
+		return respond_with_error(request, BAD_REQUEST_RESPONSE, "The body is too big");
+	};
+	let auth = match serde_json::from_slice(&body)
+		.map_err(|error| error.to_string())
+		.and_then(HttpAuthSettings::from_body)
+	{
+		Ok(auth) => auth,
+		Err(error) => return respond_with_error(request, BAD_REQUEST_RESPONSE, &error),
+	};
+
+	match data.auth_requests.request_and_wait(auth, SAVE_TIMEOUT)
+	{
+		Some(()) =>
+		{
+			let origin = cors_origin(&request);
+			request.into_response(NO_CONTENT_RESPONSE, None, &[("Access-Control-Allow-Origin", &origin)])?;
+			Ok(())
+		},
+		None => respond_with_error(request, SERVICE_UNAVAILABLE_RESPONSE, "Couldn't save the credentials"),
+	}
+}
+
+/// Compares the bytes in a time that doesn't depend on where they differ, so that a secret can't be guessed byte by
+/// byte.
+pub(super) fn bytes_match(bytes: &[u8], expected: &[u8]) -> bool
+{
+	bytes.len() == expected.len()
+		&& bytes
+			.iter()
+			.zip(expected)
+			.fold(0, |difference, (a, b)| difference | (a ^ b))
+			== 0
+}
+
+fn pbkdf2_hmac_sha256(password: &str, salt: &[u8], iterations: u32) -> [u8; 32]
+{
+	let mut hash = [0; 32];
+	pbkdf2::pbkdf2_hmac::<Sha256>(password.as_bytes(), salt, iterations, &mut hash);
+
+	hash
+}
+
+/// Takes the salt from the random number generator of the OS (the one of the hardware on the camera).
+fn new_salt() -> [u8; 16]
+{
+	let mut salt = [0; 16];
+	getrandom::getrandom(&mut salt).expect("The random number generator is always available");
+
+	salt
+}
+
+/// Decodes the standard base64 alphabet, with the padding.
+pub(super) fn decode_base64(base64: &str) -> Option<Vec<u8>>
+{
+	fn sextet(character: u8) -> Option<u32>
+	{
+		let sextet = match character
+		{
+			b'A'..=b'Z' => character - b'A',
+			b'a'..=b'z' => character - b'a' + 26,
+			b'0'..=b'9' => character - b'0' + 52,
+			b'+' => 62,
+			b'/' => 63,
+			_ => return None,
+		};
+		Some(sextet as u32)
+	}
+
+	let groups = base64.as_bytes().chunks_exact(4);
+	if !groups.remainder().is_empty()
+	{
+		return None;
+	}
+	let mut bytes = Vec::with_capacity(base64.len() / 4 * 3);
+	for group in groups
+	{
+		let padding = group.iter().rev().take_while(|&&character| character == b'=').count();
+		if padding > 2
+		{
+			return None;
+		}
+		let mut bits = 0;
+		for &character in &group[..4 - padding]
+		{
+			bits = (bits << 6) | sextet(character)?;
+		}
+		bits <<= 6 * padding;
+		bytes.extend_from_slice(&bits.to_be_bytes()[1..4 - padding]);
+	}
+
+	Some(bytes)
+}
+
+#[cfg(test)]
+mod tests
+{
+	use core::convert::Infallible;
+
+	use embedded_svc::{
+		http::{Headers, Method, Query},
+		io::{ErrorType, Read, Write},
+	};
+
+	use super::*;
+	use crate::features::{
+		frames::FrameBroker,
+		http_server::OK_RESPONSE,
+		storage::{CaptureEntry, CaptureError, CaptureFile, CapturePath, CapturesStorage},
+	};
+
+	const TOKEN: &str = "0123456789abcdef-token";
+
+	struct NoCaptures;
+
+	impl CapturesStorage for NoCaptures
+	{
+		fn capture_directory(&mut self, _: &[String]) -> Result<Vec<CaptureEntry>, CaptureError>
+		{
+			Ok(Vec::new())
+		}
+
+		fn open_capture(&mut self, _: &CapturePath) -> Result<CaptureFile, CaptureError>
+		{
+			Err(CaptureError::NotFound)
+		}
+
+		fn read_capture(&mut self, _: &CaptureFile, _: &mut [u8]) -> Result<usize, CaptureError>
+		{
+			Err(CaptureError::NotFound)
+		}
+
+		fn close_capture(&mut self, _: CaptureFile) -> Result<(), CaptureError>
+		{
+			Ok(())
+		}
+
+		fn delete_capture(&mut self, _: &CapturePath) -> Result<(), CaptureError>
+		{
+			Err(CaptureError::NotFound)
+		}
+	}
+
+	struct FakeHead
+	{
+		uri: String,
+		authorization: Option<String>,
+	}
+
+	impl Query for FakeHead
+	{
+		fn uri(&self) -> &str
+		{
+			&self.uri
+		}
+
+		fn method(&self) -> Method
+		{
+			Method::Get
+		}
+	}
+
+	impl Headers for FakeHead
+	{
+		fn header(&self, name: &str) -> Option<&str>
+		{
+			match name
+			{
+				"Authorization" => self.authorization.as_deref(),
+				_ => None,
+			}
+		}
+	}
+
+	/// An empty body, and a connection that discards what's written to it.
+	struct FakeBody;
+
+	impl ErrorType for FakeBody
+	{
+		type Error = Infallible;
+	}
+
+	impl Read for FakeBody
+	{
+		fn read(&mut self, _: &mut [u8]) -> Result<usize, Self::Error>
+		{
+			Ok(0)
+		}
+	}
+
+	impl Write for FakeBody
+	{
+		fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error>
+		{
+			Ok(buf.len())
+		}
+
+		fn flush(&mut self) -> Result<(), Self::Error>
+		{
+			Ok(())
+		}
+	}
+
+	/// Records the status of the response.
+	struct FakeConnection
+	{
+		head: FakeHead,
+		body: FakeBody,
+		status: Option<u16>,
+	}
+
+	impl FakeConnection
+	{
+		fn new(uri: &str, authorization: Option<&str>) -> Self
+		{
+			Self {
+				head: FakeHead {
+					uri: uri.to_string(),
+					authorization: authorization.map(str::to_string),
+				},
+				body: FakeBody,
+				status: None,
+			}
+		}
+	}
+
+	impl Query for FakeConnection
+	{
+		fn uri(&self) -> &str
+		{
+			self.head.uri()
+		}
+
+		fn method(&self) -> Method
+		{
+			self.head.method()
+		}
+	}
+
+	impl Headers for FakeConnection
+	{
+		fn header(&self, name: &str) -> Option<&str>
+		{
+			self.head.header(name)
+		}
+	}
+
+	impl ErrorType for FakeConnection
+	{
+		type Error = Infallible;
+	}
+
+	impl Read for FakeConnection
+	{
+		fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error>
+		{
+			self.body.read(buf)
+		}
+	}
+
+	impl Write for FakeConnection
+	{
+		fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error>
+		{
+			self.body.write(buf)
+		}
+
+		fn flush(&mut self) -> Result<(), Self::Error>
+		{
+			Ok(())
+		}
+	}
+
+	impl Connection for FakeConnection
+	{
+		type Headers = FakeHead;
+		type Read = FakeBody;
+		type RawConnectionError = Infallible;
+		type RawConnection = FakeBody;
+
+		fn split(&mut self) -> (&Self::Headers, &mut Self::Read)
+		{
+			(&self.head, &mut self.body)
+		}
+
+		fn initiate_response<'a>(
+			&'a mut self, status: u16, _: Option<&'a str>, _: &'a [(&'a str, &'a str)],
+		) -> Result<(), Self::Error>
+		{
+			self.status = Some(status);
+			Ok(())
+		}
+
+		fn is_response_initiated(&self) -> bool
+		{
+			self.status.is_some()
+		}
+
+		fn raw_connection(&mut self) -> Result<&mut Self::RawConnection, Self::Error>
+		{
+			Ok(&mut self.body)
+		}
+	}
+
+	fn data(auth: HttpAuthSettings, is_in_setup_mode: bool) -> HttpServerData
+	{
+		HttpServerData {
+			captures: Arc::new(Mutex::new(NoCaptures)),
+			frame_broker: FrameBroker::new(1),
+			snapshot_requests: Default::default(),
+			sensor_requests: Default::default(),
+			arming_requests: Default::default(),
+			wifi_requests: Default::default(),
+			is_in_setup_mode,
+			ota: None,
+			ota_manifest_requests: Default::default(),
+			mqtt_broker_requests: Default::default(),
+			boot_settings_requests: Default::default(),
+			auth: Arc::new(Mutex::new(auth)),
+			auth_requests: Default::default(),
+			tls_requests: None,
+		}
+	}
+
+	/// Returns the status of the response to a request handled by [`authorize_and_handle`].
+	fn response_status(data: HttpServerData, access: Access, authorization: Option<&str>) -> Option<u16>
+	{
+		fn handler(request: Request<&mut FakeConnection>, _: HttpServerData) -> Result<(), Infallible>
+		{
+			request.into_response(OK_RESPONSE, None, &[])?;
+			Ok(())
+		}
+
+		let mut connection = FakeConnection::new("/captures", authorization);
+		authorize_and_handle(Request::wrap(&mut connection), data, access, handler).unwrap();
+		connection.status
+	}
+
+	fn auth() -> HttpAuthSettings
+	{
+		HttpAuthSettings::from_body(HttpAuthBody {
+			users: vec![HttpUserBody {
+				username: "admin".to_string(),
+				password: "correct horse".to_string(),
+			}],
+			tokens: vec![TOKEN.to_string()],
+			allowed_origins: vec!["http://home.local:8123".to_string()],
+			password_hash_iterations: Some(MIN_PASSWORD_HASH_ITERATIONS),
+		})
+		.unwrap()
+	}
+
+	#[test]
+	fn basic_and_bearer_credentials_are_checked()
+	{
+		let auth = Arc::new(Mutex::new(auth()));
+
+		// "admin:correct horse" and "admin:wrong horse"
+		assert!(is_authenticated(&auth, Some("Basic YWRtaW46Y29ycmVjdCBob3JzZQ=="), "/"));
+		assert!(!is_authenticated(&auth, Some("Basic YWRtaW46d3JvbmcgaG9yc2U="), "/"));
+		assert!(is_authenticated(&auth, Some(&format!("Bearer {}", TOKEN)), "/"));
+		assert!(!is_authenticated(&auth, Some("Bearer 0123456789abcdef"), "/"));
+		assert!(is_authenticated(
+			&auth,
+			None,
+			&format!("/stream?access_token={}", TOKEN)
+		));
+		assert!(!is_authenticated(&auth, None, "/stream"));
+		assert!(!is_authenticated(&auth, Some("Digest username=\"admin\""), "/"));
+
+		let no_auth = Arc::new(Mutex::new(HttpAuthSettings::default()));
+		assert!(!is_authenticated(&no_auth, None, "/"));
+		assert!(!is_authenticated(
+			&no_auth,
+			Some("Basic YWRtaW46Y29ycmVjdCBob3JzZQ=="),
+			"/"
+		));
+	}
+
+	#[test]
+	fn protected_endpoints_are_refused_until_credentials_are_set()
+	{
+		let basic = Some("Basic YWRtaW46Y29ycmVjdCBob3JzZQ==");
+
+		assert_eq!(
+			response_status(data(HttpAuthSettings::default(), false), Access::Protected, None),
+			Some(UNAUTHORIZED_RESPONSE)
+		);
+		assert_eq!(
+			response_status(data(HttpAuthSettings::default(), false), Access::Protected, basic),
+			Some(UNAUTHORIZED_RESPONSE)
+		);
+		assert_eq!(
+			response_status(data(HttpAuthSettings::default(), false), Access::Setup, None),
+			Some(UNAUTHORIZED_RESPONSE)
+		);
+		assert_eq!(
+			response_status(data(HttpAuthSettings::default(), true), Access::Setup, None),
+			Some(OK_RESPONSE)
+		);
+		assert_eq!(
+			response_status(data(auth(), false), Access::Protected, basic),
+			Some(OK_RESPONSE)
+		);
+	}
+
+	#[test]
+	fn passwords_are_salted()
+	{
+		let auth = auth();
+		let user = HttpUser::new("admin".to_string(), "correct horse", MIN_PASSWORD_HASH_ITERATIONS);
+
+		assert_ne!(auth.users[0].password_hash.salt, user.password_hash.salt);
+		assert_ne!(auth.users[0].password_hash.hash, user.password_hash.hash);
+		assert_eq!(user.password_hash.algorithm, PasswordHashAlgorithm::Pbkdf2HmacSha256);
+		assert_eq!(user.password_hash.iterations, MIN_PASSWORD_HASH_ITERATIONS);
+		assert!(user.password_hash.matches("correct horse"));
+		assert!(!user.password_hash.matches("wrong horse"));
+	}
+
+	#[test]
+	fn passwords_are_hashed_with_pbkdf2()
+	{
+		// From RFC 7914, section 11
+		assert_eq!(
+			pbkdf2_hmac_sha256("passwd", b"salt", 1),
+			[
+				0x55, 0xac, 0x04, 0x6e, 0x56, 0xe3, 0x08, 0x9f, 0xec, 0x16, 0x91, 0xc2, 0x25, 0x44, 0xb6, 0x05, 0xf9,
+				0x41, 0x85, 0x21, 0x6d, 0xde, 0x04, 0x65, 0xe6, 0x8b, 0x9d, 0x57, 0xc2, 0x0d, 0xac, 0xbc
+			]
+		);
+	}
+
+	#[test]
+	fn checked_credentials_are_remembered()
+	{
+		let auth = Arc::new(Mutex::new(auth()));
+
+		assert!(is_authenticated(&auth, Some("Basic YWRtaW46Y29ycmVjdCBob3JzZQ=="), "/"));
+		assert!(is_authenticated(&auth, Some("Basic YWRtaW46Y29ycmVjdCBob3JzZQ=="), "/"));
+		assert!(!is_authenticated(&auth, Some("Basic YWRtaW46d3JvbmcgaG9yc2U="), "/"));
+		assert!(!is_authenticated(&auth, Some("Basic YWRtaW46d3JvbmcgaG9yc2U="), "/"));
+		assert_eq!(auth.lock().verified_credentials.len(), 1);
+		assert_eq!(auth.lock().failed_credentials.len(), 1);
+	}
+
+	#[test]
+	fn password_checks_of_replaced_passwords_are_forgotten()
+	{
+		let mut auth = auth();
+		let CredentialsCheck::Password(check) = auth.check_credentials(Some("Basic YWRtaW46Y29ycmVjdCBob3JzZQ=="), "/")
+		else
+		{
+			panic!("The password should be checked");
+		};
+
+		// The settings were replaced while the password was hashed without the lock
+		auth.users[0] = HttpUser::new("admin".to_string(), "battery staple", MIN_PASSWORD_HASH_ITERATIONS);
+		auth.remember_password_check(&check, true);
+		assert!(auth.verified_credentials.is_empty());
+	}
+
+	#[test]
+	fn only_allowed_origins_can_make_requests()
+	{
+		let auth = auth();
+
+		assert!(auth.is_origin_allowed(None, Some("camera.local")));
+		assert!(auth.is_origin_allowed(Some("http://camera.local"), Some("camera.local")));
+		assert!(auth.is_origin_allowed(Some("http://home.local:8123"), Some("camera.local")));
+		assert!(!auth.is_origin_allowed(Some("http://evil.example"), Some("camera.local")));
+		assert!(!auth.is_origin_allowed(Some("null"), None));
+	}
+
+	#[test]
+	fn invalid_credentials_are_refused()
+	{
+		let body = |username: &str, password: &str, token: &str, origin: &str| HttpAuthBody {
+			users: vec![HttpUserBody {
+				username: username.to_string(),
+				password: password.to_string(),
+			}],
+			tokens: vec![token.to_string()],
+			allowed_origins: vec![origin.to_string()],
+			password_hash_iterations: None,
+		};
+
+		assert!(HttpAuthSettings::from_body(body("admin", "long enough", TOKEN, "https://a.local")).is_ok());
+		assert!(HttpAuthSettings::from_body(body("ad:min", "long enough", TOKEN, "https://a.local")).is_err());
+		assert!(HttpAuthSettings::from_body(body("admin", "short", TOKEN, "https://a.local")).is_err());
+		assert!(HttpAuthSettings::from_body(body("admin", "long enough", "short", "https://a.local")).is_err());
+		assert!(HttpAuthSettings::from_body(body("admin", "long enough", TOKEN, "https://a.local/")).is_err());
+		assert!(HttpAuthSettings::from_body(HttpAuthBody {
+			users: Vec::new(),
+			tokens: Vec::new(),
+			allowed_origins: Vec::new(),
+			password_hash_iterations: None,
+		})
+		.is_err());
+		assert!(HttpAuthSettings::from_body(HttpAuthBody {
+			password_hash_iterations: Some(1_000),
+			..body("admin", "long enough", TOKEN, "https://a.local")
+		})
+		.is_err());
+	}
+
+	#[test]
+	fn base64_is_decoded()
+	{
+		assert_eq!(decode_base64("YWRtaW46cGFzcw==").as_deref(), Some(&b"admin:pass"[..]));
+		assert_eq!(decode_base64("YWRtaW46cGFzczE=").as_deref(), Some(&b"admin:pass1"[..]));
+		assert_eq!(decode_base64("YWRtaW46cGFzczEy").as_deref(), Some(&b"admin:pass12"[..]));
+		assert_eq!(decode_base64("YWRtaW46cGFzcw="), None);
+		assert_eq!(decode_base64("YWRt*W46"), None);
+	}
+}
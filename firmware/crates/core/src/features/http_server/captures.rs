@@ -0,0 +1,212 @@
+use core::fmt::Write;
+
+use embedded_svc::http::server::{Connection, Request};
+
+use super::{
+	auth::cors_origin, query, HttpServerData, INTERNAL_SERVER_ERROR_RESPONSE, NOT_FOUND_RESPONSE, NO_CONTENT_RESPONSE,
+	OK_RESPONSE,
+};
+use crate::features::storage::{page_captures, CaptureError, CaptureFile, CapturePath};
+
+const DEFAULT_PAGE_LIMIT: usize = 50;
+const MAX_PAGE_LIMIT: usize = 200;
+/// Size of the chunks in which a capture is read from the SD card and sent to the client.
+const CHUNK_SIZE: usize = 4 * 1024;
+
+/// Responds with a JSON page of the stored captures, from the newest. The page can be selected with the `offset` and
+/// `limit` query parameters.
+///
+/// ```json
+/// {"total":2,"offset":0,"captures":[{"name":"2024/05/01/13/0102_000.JPG","size":51234,"timestamp":"2024-05-01T13:01:02"},{"name":"NOCLOCK/00000000.JPG","size":48001,"timestamp":null}]}
+/// ```
+pub fn list_captures<C: Connection>(request: Request<&mut C>, data: HttpServerData) -> Result<(), C::Error>
+{
+	log::info!("Start handling `list_captures` request");
+
+	let offset = query::query_parameter(request.uri(), "offset")
+		.and_then(|offset| offset.parse().ok())
+		.unwrap_or(0);
+	let limit = query::query_parameter(request.uri(), "limit")
+		.and_then(|limit| limit.parse().ok())
+		.unwrap_or(DEFAULT_PAGE_LIMIT)
+		.min(MAX_PAGE_LIMIT);
+
+	// The lock is released between the directories so that the camera can keep storing images
+	let page = match page_captures(offset, limit, |path| data.captures.lock().capture_directory(path))
+	{
+		Ok(page) => page,
+		Err(error) => return respond_with_error(request, error),
+	};
+
+	let mut json = format!(r#"{{"total":{},"offset":{},"captures":["#, page.total, offset);
+	for (index, capture) in page.captures.iter().enumerate()
+	{
+		if index > 0
+		{
+			json.push(',');
+		}
+		let _ = write!(
+			json,
+			r#"{{"name":"{}","size":{},"timestamp":"#,
+			capture.path, capture.size
+		);
+		let _ = match capture.path
+		{
+			CapturePath::Dated { date, time, .. } => write!(
+				json,
+				r#""{:04}-{:02}-{:02}T{:02}:{:02}:{:02}"}}"#,
+				date.year(),
+				date.month() as u8,
+				date.day(),
+				time.hour(),
+				time.minute(),
+				time.second()
+			),
+			CapturePath::Sequence { .. } => write!(json, "null}}"),
+		};
+	}
+	json.push_str("]}");
+
+	let origin = cors_origin(&request);
+	let mut response = request.into_response(
+		OK_RESPONSE,
+		None,
+		&[
+			embedded_svc::http::headers::content_type("application/json"),
+			("Access-Control-Allow-Origin", &origin),
+		],
+	)?;
+	response.write(json.as_bytes())?;
+
+	Ok(())
+}
+
+/// Sends the capture whose path follows `/captures/` in the URI, reading it from the SD card in chunks.
+pub fn download_capture<C: Connection>(request: Request<&mut C>, data: HttpServerData) -> Result<(), C::Error>
+{
+	log::info!("Start handling `download_capture` request");
+
+	let Some(path) = capture_path(request.uri())
+	else
+	{
+		return respond_with_error(request, CaptureError::NotFound);
+	};
+	let file = match data.captures.lock().open_capture(&path)
+	{
+		Ok(file) => file,
+		Err(error) => return respond_with_error(request, error),
+	};
+	let result = send_capture(request, &data, &path, &file);
+	if let Err(error) = data.captures.lock().close_capture(file)
+	{
+		log::warn!("Couldn't close the capture {}: {:?}", path, error);
+	}
+
+	result
+}
+
+/// Sends the open capture in chunks, releasing the lock between them so that the camera can keep storing images during
+/// the download. The first chunk is read before responding, so that a capture that can't be read gets an error
+/// response. The handlers can't create the error of the connection that would make the server drop it, so if a later
+/// chunk can't be read the response ends early (and the client sees less bytes than the `Content-Length`).
+fn send_capture<C: Connection>(
+	request: Request<&mut C>, data: &HttpServerData, path: &CapturePath, file: &CaptureFile,
+) -> Result<(), C::Error>
+{
+	let mut chunk = vec![0; CHUNK_SIZE];
+	let mut read_bytes = match data.captures.lock().read_capture(file, &mut chunk)
+	{
+		Ok(read_bytes) => read_bytes,
+		Err(error) => return respond_with_error(request, error),
+	};
+
+	let content_length = file.size.to_string();
+	let origin = cors_origin(&request);
+	let mut response = request.into_response(
+		OK_RESPONSE,
+		None,
+		&[
+			embedded_svc::http::headers::content_type("image/jpeg"),
+			("Content-Length", content_length.as_str()),
+			("Access-Control-Allow-Origin", &origin),
+		],
+	)?;
+
+	let mut sent_bytes = 0;
+	loop
+	{
+		response.write_all(&chunk[..read_bytes])?;
+		sent_bytes += read_bytes as u32;
+		if sent_bytes >= file.size
+		{
+			return Ok(());
+		}
+
+		read_bytes = match data.captures.lock().read_capture(file, &mut chunk)
+		{
+			Ok(0) =>
+			{
+				log::error!(
+					"The capture {} ended after {} of its {} bytes",
+					path,
+					sent_bytes,
+					file.size
+				);
+				return Ok(());
+			},
+			Ok(read_bytes) => read_bytes,
+			Err(error) =>
+			{
+				log::error!(
+					"Couldn't read the capture {} after {} of its {} bytes: {:?}",
+					path,
+					sent_bytes,
+					file.size,
+					error
+				);
+				return Ok(());
+			},
+		};
+	}
+}
+
+/// Deletes the capture whose path follows `/captures/` in the URI.
+pub fn delete_capture<C: Connection>(request: Request<&mut C>, data: HttpServerData) -> Result<(), C::Error>
+{
+	log::info!("Start handling `delete_capture` request");
+
+	let result = match capture_path(request.uri())
+	{
+		Some(path) => data.captures.lock().delete_capture(&path),
+		None => Err(CaptureError::NotFound),
+	};
+
+	match result
+	{
+		Ok(()) =>
+		{
+			let origin = cors_origin(&request);
+			request.into_response(NO_CONTENT_RESPONSE, None, &[("Access-Control-Allow-Origin", &origin)])?;
+			Ok(())
+		},
+		Err(error) => respond_with_error(request, error),
+	}
+}
+
+fn capture_path(uri: &str) -> Option<CapturePath>
+{
+	query::uri_path(uri).strip_prefix("/captures/")?.parse().ok()
+}
+
+fn respond_with_error<C: Connection>(request: Request<&mut C>, error: CaptureError) -> Result<(), C::Error>
+{
+	let status = match error
+	{
+		CaptureError::NotFound => NOT_FOUND_RESPONSE,
+		CaptureError::Storage => INTERNAL_SERVER_ERROR_RESPONSE,
+	};
+	let origin = cors_origin(&request);
+	request.into_response(status, None, &[("Access-Control-Allow-Origin", &origin)])?;
+
+	Ok(())
+}
@@ -0,0 +1,128 @@
+use core::time::Duration;
+
+use embedded_svc::http::server::{Connection, Request};
+use serde::Serialize;
+
+use super::{auth::cors_origin, HttpServerData, BAD_REQUEST_RESPONSE, OK_RESPONSE, SERVICE_UNAVAILABLE_RESPONSE};
+
+/// How long to wait for the main loop to read or change the settings of the sensor.
+const SENSOR_TIMEOUT: Duration = Duration::from_secs(5);
+/// The body of a request is refused if it's bigger than this.
+const MAX_BODY_SIZE: usize = 2 * 1024;
+
+/// Responds with the JSON of the current [`SensorSettings`](crate::configuration::peripherals::camera::SensorSettings).
+///
+/// ```json
+/// {"brightness":0,"contrast":0,"saturation":0,"special_effect":0,"white_balance":true,...}
+/// ```
+pub fn get_sensor_settings<C: Connection>(request: Request<&mut C>, data: HttpServerData) -> Result<(), C::Error>
+{
+	log::info!("Start handling `get_sensor_settings` request");
+
+	match data.sensor_requests.request_and_wait(None, SENSOR_TIMEOUT).flatten()
+	{
+		Some(settings) => respond_with_settings(request, &settings),
+		None => respond_with_error(request, SERVICE_UNAVAILABLE_RESPONSE, "The sensor isn't available"),
+	}
+}
+
+/// Changes the settings of the sensor that are in the JSON object of the body (the missing ones keep their current
+/// value) and responds with all the new settings. If a setting is unknown or out of its range nothing is changed, and
+/// the response is a `400` with the JSON `{"error":"..."}`.
+pub fn set_sensor_settings<C: Connection>(mut request: Request<&mut C>, data: HttpServerData) -> Result<(), C::Error>
+{
+	log::info!("Start handling `set_sensor_settings` request");
+
+	let Some(body) = read_body(&mut request)?
+	else
+	{
+		return respond_with_error(request, BAD_REQUEST_RESPONSE, "The body is too big");
+	};
+	let Some(current_settings) = data.sensor_requests.request_and_wait(None, SENSOR_TIMEOUT).flatten()
+	else
+	{
+		return respond_with_error(request, SERVICE_UNAVAILABLE_RESPONSE, "The sensor isn't available");
+	};
+	let settings = match current_settings.with_json_changes(&body)
+	{
+		Ok(settings) => settings,
+		Err(error) => return respond_with_error(request, BAD_REQUEST_RESPONSE, &error),
+	};
+
+	match data
+		.sensor_requests
+		.request_and_wait(Some(settings), SENSOR_TIMEOUT)
+		.flatten()
+	{
+		Some(settings) => respond_with_settings(request, &settings),
+		None => respond_with_error(request, SERVICE_UNAVAILABLE_RESPONSE, "The sensor isn't available"),
+	}
+}
+
+/// Returns `None` if the body is bigger than [`MAX_BODY_SIZE`].
+pub(super) fn read_body<C: Connection>(request: &mut Request<&mut C>) -> Result<Option<Vec<u8>>, C::Error>
+{
+	read_body_up_to(request, MAX_BODY_SIZE)
+}
+
+/// Returns `None` if the body is bigger than `max_size`.
+pub(super) fn read_body_up_to<C: Connection>(
+	request: &mut Request<&mut C>, max_size: usize,
+) -> Result<Option<Vec<u8>>, C::Error>
+{
+	let mut body = vec![0; max_size + 1];
+	let mut length = 0;
+	while length < body.len()
+	{
+		match request.read(&mut body[length..])?
+		{
+			0 => break,
+			read_bytes => length += read_bytes,
+		}
+	}
+	if length > max_size
+	{
+		return Ok(None);
+	}
+	body.truncate(length);
+
+	Ok(Some(body))
+}
+
+pub(super) fn respond_with_settings<C: Connection>(
+	request: Request<&mut C>, settings: &impl Serialize,
+) -> Result<(), C::Error>
+{
+	let json = serde_json::to_string(settings).unwrap_or_default();
+	let origin = cors_origin(&request);
+	let mut response = request.into_response(
+		OK_RESPONSE,
+		None,
+		&[
+			embedded_svc::http::headers::content_type("application/json"),
+			("Access-Control-Allow-Origin", &origin),
+		],
+	)?;
+	response.write_all(json.as_bytes())?;
+
+	Ok(())
+}
+
+pub(super) fn respond_with_error<C: Connection>(
+	request: Request<&mut C>, status: u16, error: &str,
+) -> Result<(), C::Error>
+{
+	let json = serde_json::json!({ "error": error }).to_string();
+	let origin = cors_origin(&request);
+	let mut response = request.into_response(
+		status,
+		None,
+		&[
+			embedded_svc::http::headers::content_type("application/json"),
+			("Access-Control-Allow-Origin", &origin),
+		],
+	)?;
+	response.write_all(json.as_bytes())?;
+
+	Ok(())
+}
@@ -1,56 +1,74 @@
-use core::time::Duration;
 use std::sync::Arc;
 
+use serde::Serialize;
 use spin::Mutex;
 
-use crate::configuration::peripherals::camera::Image;
+use super::{
+	auth::{HttpAuthRequests, SharedHttpAuth},
+	tls::TlsRequests,
+};
+use crate::{
+	configuration::peripherals::camera::SensorSettings,
+	features::{
+		connectivity::WifiStatus,
+		frames::{FrameBroker, SnapshotRequests},
+		mqtt::MqttBrokerRequests,
+		ota::{ManifestUrlRequests, SharedOtaWriter},
+		provisioning::WifiRequests,
+		requests::Requests,
+		settings::BootSettings,
+		storage::CapturesStorage,
+		trigger::{Arming, TriggerStatus},
+	},
+};
 
-pub struct HttpServerData
-{
-	image: Arc<Mutex<(*const u8, usize, Duration)>>,
-}
+/// The storage of the camera, shared between the main loop (that stores the captures) and the HTTP server.
+pub type SharedCaptures = Arc<Mutex<dyn CapturesStorage>>;
+
+/// Reads (with `None`) or changes (with `Some`) the settings of the image sensor, that is owned by the main loop. The
+/// response is the current settings, or `None` if the sensor couldn't be read or written.
+pub type SensorRequests = Requests<Option<SensorSettings>, Option<SensorSettings>>;
 
-impl Clone for HttpServerData
+/// Reads (with `None`) or saves (with `Some`) the [`BootSettings`], that are owned by the main loop. The response is
+/// the current settings.
+pub type BootSettingsRequests = Requests<Option<BootSettings>, BootSettings>;
+
+/// Reads (with `None`) or changes (with `Some`) the arming of the image trigger, that is owned by the main loop. The
+/// response is the status of the camera after the change.
+pub type ArmingRequests = Requests<Option<Arming>, CameraStatus>;
+
+/// Responded to the `/status` request.
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct CameraStatus
 {
-	fn clone(&self) -> Self
-	{
-		Self {
-			image: Arc::clone(&self.image),
-		}
-	}
+	#[serde(flatten)]
+	pub trigger: TriggerStatus,
+	pub wifi: WifiStatus,
+	/// The SHA-256 fingerprint of the certificate of the HTTPS servers (check
+	/// [`certificate_fingerprint`](super::tls::certificate_fingerprint)), `None` if the board serves plain HTTP.
+	pub tls_certificate_sha256: Option<String>,
 }
 
-impl HttpServerData
+/// What the handlers of the HTTP servers share with the main loop.
+#[derive(Clone)]
+pub struct HttpServerData
 {
-	pub fn new() -> Self
-	{
-		Self {
-			image: Arc::new(Mutex::new((core::ptr::null(), 0, Duration::default()))),
-		}
-	}
-
-	pub fn read_image_bytes<T>(&mut self, callback: impl FnOnce(Option<(&[u8], Duration)>) -> T) -> T
-	{
-		let mut image_guard = self.image.lock();
-		if image_guard.0.is_null() || image_guard.1 == 0
-		{
-			(callback)(None)
-		}
-		else
-		{
-			let image_bytes = unsafe { core::slice::from_raw_parts(image_guard.0, image_guard.1) };
-			let return_value = (callback)(Some((image_bytes, image_guard.2)));
-			image_guard.0 = core::ptr::null();
-			image_guard.1 = 0;
-			return_value
-		}
-	}
-
-	pub fn write_image(&mut self, image: &[u8], timestamp: Duration)
-	{
-		let mut image_guard = self.image.lock();
-		*image_guard = (image as *const [u8] as *const u8, image.len(), timestamp);
-	}
+	pub captures: SharedCaptures,
+	pub frame_broker: FrameBroker,
+	pub snapshot_requests: SnapshotRequests,
+	pub sensor_requests: SensorRequests,
+	pub arming_requests: ArmingRequests,
+	pub wifi_requests: WifiRequests,
+	/// If the camera isn't connected to a network but is waiting to be set up through its access point.
+	pub is_in_setup_mode: bool,
+	/// `None` if the firmware of the board can't be updated.
+	pub ota: Option<SharedOtaWriter>,
+	pub ota_manifest_requests: ManifestUrlRequests,
+	pub mqtt_broker_requests: MqttBrokerRequests,
+	pub boot_settings_requests: BootSettingsRequests,
+	/// Checked before calling each handler.
+	pub auth: SharedHttpAuth,
+	pub auth_requests: HttpAuthRequests,
+	/// `None` if the certificate of the board can't be changed.
+	pub tls_requests: Option<TlsRequests>,
 }
-
-unsafe impl Send for HttpServerData {}
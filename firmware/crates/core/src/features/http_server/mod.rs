@@ -1,29 +1,76 @@
+mod arming;
+pub mod auth;
+mod captures;
+mod control;
 mod data;
-
-use a13c_embedded::{features::communication::http::server::HttpServer, impl_http_requests};
+mod mqtt;
+mod ota;
+mod provisioning;
+pub mod query;
+mod settings;
+mod snapshot;
+pub mod tls;
+
+use a13c_embedded::features::communication::http::server::HttpServer;
 use embedded_svc::http::{
 	server::{Connection, Request},
 	Method,
 };
 use strum::{EnumCount, IntoEnumIterator};
 
-pub use self::data::HttpServerData;
+pub use self::data::*;
+use self::{
+	arming::*,
+	auth::{change_auth, preflight, Access},
+	captures::*,
+	control::*,
+	mqtt::*,
+	ota::*,
+	provisioning::*,
+	settings::*,
+	snapshot::*,
+	tls::upload_tls_identity,
+};
 
 pub const STACK_SIZE: usize = 1_000;
 
+/// Like `impl_http_requests!`, but each handler is called only if the request is authorized for its [`Access`]
+/// (check [`auth::authorize_and_handle`]), otherwise the response is a `401` or a `403`.
+macro_rules! impl_authorized_http_requests {
+	($data:ty, $($name:ident => $method:expr => $uri:literal => $access:expr => $handler:ident),* $(,)?) => {
+		mod authorized
+		{
+			use a13c_embedded::impl_http_requests;
+
+			use super::*;
+
+			$(
+				fn $handler<C: Connection>(request: Request<&mut C>, data: $data) -> Result<(), C::Error>
+				{
+					auth::authorize_and_handle(request, data, $access, super::$handler)
+				}
+			)*
+
+			impl_http_requests!($data, $($name => $method => $uri => $handler),*);
+		}
+
+		pub use self::authorized::{http_request_handlers_count, PossibleHttpRequest};
+	};
+}
+
 pub fn register_all_requests<
 	S: HttpServer<Error = E, HttpRequest = PossibleHttpRequest>,
 	StreamS: HttpServer<Error = StreamE, HttpRequest = stream::PossibleHttpRequest>,
 	E,
 	StreamE,
 >(
-	http_server: &mut S, stream_http_server: &mut StreamS, data: <stream::PossibleHttpRequest as HttpRequest>::Data,
+	http_server: &mut S, stream_http_server: &mut StreamS, data: HttpServerData,
 ) -> Result<(), RegisterError<E, StreamE>>
 {
 	for possible_request in PossibleHttpRequest::iter()
 	{
 		http_server
-			.register_request(possible_request, ())
+			.register_request(possible_request, data.clone())
 			.map_err(RegisterError::Main)?;
 	}
 	for possible_request in stream::PossibleHttpRequest::iter()
@@ -43,16 +90,46 @@ pub enum RegisterError<E, StreamE>
 	Stream(StreamE),
 }
 
-impl_http_requests!((),
-	Index => Method::Get => "/" => index
+impl_authorized_http_requests!(HttpServerData,
+	Index => Method::Get => "/" => Access::Setup => index,
+	Capture => Method::Get => "/capture" => Access::Protected => capture,
+	GetSensorSettings => Method::Get => "/control" => Access::Protected => get_sensor_settings,
+	SetSensorSettings => Method::Post => "/control" => Access::Protected => set_sensor_settings,
+	ListCaptures => Method::Get => "/captures" => Access::Protected => list_captures,
+	DownloadCapture => Method::Get => "/captures/*" => Access::Protected => download_capture,
+	DeleteCapture => Method::Delete => "/captures/*" => Access::Protected => delete_capture,
+	Arm => Method::Post => "/arm" => Access::Protected => arm,
+	Disarm => Method::Post => "/disarm" => Access::Protected => disarm,
+	GetStatus => Method::Get => "/status" => Access::Protected => get_status,
+	Setup => Method::Get => "/setup" => Access::Setup => setup,
+	ListWifiNetworks => Method::Get => "/wifi/networks" => Access::Setup => list_wifi_networks,
+	SaveWifiCredentials => Method::Post => "/wifi" => Access::Setup => save_wifi_credentials,
+	EnterWifiSetup => Method::Post => "/wifi/setup" => Access::Protected => enter_wifi_setup,
+	UploadFirmware => Method::Post => "/ota" => Access::Protected => upload_firmware,
+	ChangeOtaManifest => Method::Post => "/ota/manifest" => Access::Protected => change_ota_manifest,
+	ChangeMqttBroker => Method::Post => "/mqtt/broker" => Access::Protected => change_mqtt_broker,
+	GetSettings => Method::Get => "/settings" => Access::Protected => get_settings,
+	ChangeSettings => Method::Post => "/settings" => Access::Protected => change_settings,
+	// The first credentials are set from the setup page
+	ChangeAuth => Method::Post => "/auth" => Access::Setup => change_auth,
+	UploadTlsIdentity => Method::Post => "/tls" => Access::Protected => upload_tls_identity,
+	Preflight => Method::Options => "/*" => Access::Public => preflight,
+	// It must be the last one, because the URIs are matched in the order they're registered
+	NotFound => Method::Get => "/*" => Access::Public => not_found
 );
 
-fn index<C: Connection>(mut request: Request<&mut C>, _: ()) -> Result<(), C::Error>
+fn index<C: Connection>(mut request: Request<&mut C>, data: HttpServerData) -> Result<(), C::Error>
 {
 	log::info!("Start handling `index` request");
 
+	if data.is_in_setup_mode
+	{
+		return redirect_to_setup(request);
+	}
+
 	const INDEX_HTML: &'static [u8] = include_bytes!("../../../../../../website/index.html");
-	let mut response = request.into_response(OK_RESPONSE, None, &[("Access-Control-Allow-Origin", "*")])?;
+	let origin = auth::cors_origin(&request);
+	let mut response = request.into_response(OK_RESPONSE, None, &[("Access-Control-Allow-Origin", &origin)])?;
 
 	response.write(INDEX_HTML)?;
 
@@ -60,22 +137,40 @@ fn index<C: Connection>(mut request: Request<&mut C>, _: ()) -> Result<(), C::Er
 }
 
 const OK_RESPONSE: u16 = 200;
+const NO_CONTENT_RESPONSE: u16 = 204;
+const BAD_REQUEST_RESPONSE: u16 = 400;
+const UNAUTHORIZED_RESPONSE: u16 = 401;
+const FORBIDDEN_RESPONSE: u16 = 403;
+const NOT_FOUND_RESPONSE: u16 = 404;
+const CONFLICT_RESPONSE: u16 = 409;
+const INTERNAL_SERVER_ERROR_RESPONSE: u16 = 500;
+const SERVICE_UNAVAILABLE_RESPONSE: u16 = 503;
+
+/// Formats the timestamp of a frame for the `X-Timestamp` header.
+fn timestamp_header(timestamp: core::time::Duration) -> String
+{
+	format!("{}.{:06}", timestamp.as_secs(), timestamp.subsec_micros())
+}
 
 pub mod stream
 {
 	use super::*;
 
-	impl_http_requests!(HttpServerData,
-		Stream => Method::Get => "/stream" => stream
+	/// How long the stream waits before checking again if there's a new frame.
+	const FRAME_POLL_PERIOD: core::time::Duration = core::time::Duration::from_millis(10);
+
+	impl_authorized_http_requests!(HttpServerData,
+		Stream => Method::Get => "/stream" => Access::Protected => stream
 	);
 
 	// Check this: https://stackoverflow.com/questions/47729941/mjpeg-over-http-specification
-	fn stream<C: Connection>(request: Request<&mut C>, mut data: HttpServerData) -> Result<(), C::Error>
+	fn stream<C: Connection>(request: Request<&mut C>, data: HttpServerData) -> Result<(), C::Error>
 	{
 		log::info!("Start handling `stream` request");
 
 		const BOUNDARY: &'static str = "\r\n--123456789000000000000987654321\r\n";
 
+		let origin = auth::cors_origin(&request);
 		let mut response = request.into_response(
 			OK_RESPONSE,
 			None,
@@ -83,37 +178,26 @@ pub mod stream
 				embedded_svc::http::headers::content_type(
 					"multipart/x-mixed-replace;boundary=123456789000000000000987654321",
 				),
-				("Access-Control-Allow-Origin", "*"),
+				("Access-Control-Allow-Origin", &origin),
 				("X-Framerate", "60"),
 			],
 		)?;
 
+		let mut frames = data.frame_broker.subscribe();
 		loop
 		{
-			let _ = data.read_image_bytes(|image| {
-				if let Some((image, timestamp)) = image
-				{
-					let mut result = response.write(BOUNDARY.as_bytes())?;
-					result += response.write(b"Content-Type: image/jpeg\r\n")?;
-					response.write(format!("Content-Length: {}\r\n", image.len()).as_bytes())?;
-					result += response.write(
-						format!(
-							"X-Timestamp: {}.{:06}\r\n\r\n",
-							timestamp.as_secs(),
-							timestamp.subsec_micros()
-						)
-						.as_bytes(),
-					)?;
-					result += response.write(image)?;
-					Ok(result)
-				}
-				else
-				{
-					Ok(0)
-				}
-			})?;
+			let Some(frame) = frames.latest_frame()
+			else
+			{
+				std::thread::sleep(FRAME_POLL_PERIOD);
+				continue;
+			};
+
+			response.write_all(BOUNDARY.as_bytes())?;
+			response.write_all(b"Content-Type: image/jpeg\r\n")?;
+			response.write_all(format!("Content-Length: {}\r\n", frame.pixels.len()).as_bytes())?;
+			response.write_all(format!("X-Timestamp: {}\r\n\r\n", timestamp_header(frame.timestamp)).as_bytes())?;
+			response.write_all(&frame.pixels)?;
 		}
-
-		Ok(())
 	}
 }
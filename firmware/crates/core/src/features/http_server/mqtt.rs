@@ -0,0 +1,65 @@
+use core::time::Duration;
+
+use embedded_svc::http::server::{Connection, Request};
+
+use super::{
+	auth::cors_origin,
+	control::{read_body, respond_with_error},
+	HttpServerData, BAD_REQUEST_RESPONSE, OK_RESPONSE, SERVICE_UNAVAILABLE_RESPONSE,
+};
+use crate::features::mqtt::MqttBroker;
+
+/// How long to wait for the main loop to save the broker.
+const SAVE_TIMEOUT: Duration = Duration::from_secs(5);
+/// The schemes of the broker URLs the MQTT clients of the boards support.
+const BROKER_URL_SCHEMES: [&str; 4] = ["mqtt://", "mqtts://", "ws://", "wss://"];
+
+/// Saves the MQTT broker in the JSON of the body, like
+/// `{"url":"mqtt://192.168.1.2:1883","username":"camera","password":"..."}` (the credentials are optional), and
+/// restarts the camera to connect to it. With `null` the camera stops using MQTT. If the URL isn't valid the response
+/// is a `400` with the JSON `{"error":"..."}`.
+pub fn change_mqtt_broker<C: Connection>(mut request: Request<&mut C>, data: HttpServerData) -> Result<(), C::Error>
+{
+	log::info!("Start handling `change_mqtt_broker` request");
+
+	let Some(body) = read_body(&mut request)?
+	else
+	{
+		return respond_with_error(request, BAD_REQUEST_RESPONSE, "The body is too big");
+	};
+	let broker = match serde_json::from_slice::<Option<MqttBroker>>(&body)
+	{
+		Ok(broker) => broker,
+		Err(error) => return respond_with_error(request, BAD_REQUEST_RESPONSE, &error.to_string()),
+	};
+	if broker
+		.as_ref()
+		.is_some_and(|broker| !BROKER_URL_SCHEMES.iter().any(|scheme| broker.url.starts_with(scheme)))
+	{
+		return respond_with_error(
+			request,
+			BAD_REQUEST_RESPONSE,
+			"The broker URL must be MQTT or WebSocket",
+		);
+	}
+
+	match data.mqtt_broker_requests.request_and_wait(broker, SAVE_TIMEOUT)
+	{
+		Some(()) =>
+		{
+			let origin = cors_origin(&request);
+			let mut response = request.into_response(
+				OK_RESPONSE,
+				None,
+				&[
+					embedded_svc::http::headers::content_type("application/json"),
+					("Access-Control-Allow-Origin", &origin),
+				],
+			)?;
+			response.write_all(br#"{"restarting":true}"#)?;
+
+			Ok(())
+		},
+		None => respond_with_error(request, SERVICE_UNAVAILABLE_RESPONSE, "Couldn't save the MQTT broker"),
+	}
+}
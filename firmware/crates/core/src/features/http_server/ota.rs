@@ -0,0 +1,168 @@
+use core::time::Duration;
+
+use embedded_svc::http::server::{Connection, Request};
+use serde::Deserialize;
+
+use super::{
+	auth::cors_origin,
+	control::{read_body, respond_with_error},
+	HttpServerData, BAD_REQUEST_RESPONSE, CONFLICT_RESPONSE, FORBIDDEN_RESPONSE, INTERNAL_SERVER_ERROR_RESPONSE,
+	OK_RESPONSE, SERVICE_UNAVAILABLE_RESPONSE,
+};
+use crate::features::ota::{parse_sha256, signature::ImageError, OtaError};
+
+/// The image is read from the body and written to the flash in chunks of this size.
+const CHUNK_SIZE: usize = 4 * 1024;
+/// How long to wait for the main loop to save the manifest URL.
+const SAVE_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Deserialize)]
+struct ManifestBody
+{
+	manifest_url: Option<String>,
+}
+
+/// Installs the firmware image in the body and restarts the camera to boot it. The image must have been signed with
+/// `firmware-signer` by one of the trusted keys, for this board and with a newer version. The request must have the header
+/// `X-Image-SHA256` with the SHA-256 of the image in hexadecimal, like:
+///
+/// ```sh
+/// curl --data-binary @firmware.signed -u admin \
+///     -H "X-Image-SHA256: $(sha256sum firmware.signed | cut -d ' ' -f 1)" http://camera.local/ota
+/// ```
+///
+/// If the image is received correctly the response is `{"restarting":true}`, otherwise it's discarded and the response
+/// is an error with the JSON `{"error":"..."}`.
+pub fn upload_firmware<C: Connection>(mut request: Request<&mut C>, data: HttpServerData) -> Result<(), C::Error>
+{
+	log::info!("Start handling `upload_firmware` request");
+
+	let Some(ota) = data.ota
+	else
+	{
+		return respond_with_error(request, SERVICE_UNAVAILABLE_RESPONSE, "The firmware can't be updated");
+	};
+	let Some(sha256) = request.header("X-Image-SHA256").and_then(parse_sha256)
+	else
+	{
+		return respond_with_error(
+			request,
+			BAD_REQUEST_RESPONSE,
+			"Missing or invalid `X-Image-SHA256` header",
+		);
+	};
+	let size = match request.header("Content-Length").map(str::parse)
+	{
+		Some(Ok(size)) => Some(size),
+		Some(Err(_)) => return respond_with_error(request, BAD_REQUEST_RESPONSE, "Invalid `Content-Length` header"),
+		None => None,
+	};
+
+	if let Err(error) = ota.lock().begin(sha256, size)
+	{
+		return respond_with_ota_error(request, error);
+	}
+	let mut chunk = vec![0; CHUNK_SIZE];
+	loop
+	{
+		let read_bytes = match request.read(&mut chunk)
+		{
+			Ok(0) => break,
+			Ok(read_bytes) => read_bytes,
+			Err(error) =>
+			{
+				ota.lock().abort();
+				return Err(error);
+			},
+		};
+		// The lock is released between the chunks, so that the main loop can check if the update has been installed
+		if let Err(error) = ota.lock().write(&chunk[..read_bytes])
+		{
+			return respond_with_ota_error(request, error);
+		}
+	}
+	if let Err(error) = ota.lock().finish()
+	{
+		return respond_with_ota_error(request, error);
+	}
+
+	let origin = cors_origin(&request);
+	let mut response = request.into_response(
+		OK_RESPONSE,
+		None,
+		&[
+			embedded_svc::http::headers::content_type("application/json"),
+			("Access-Control-Allow-Origin", &origin),
+		],
+	)?;
+	response.write_all(br#"{"restarting":true}"#)?;
+
+	Ok(())
+}
+
+/// Saves the manifest URL in the JSON of the body, like `{"manifest_url":"https://example.com/camera/manifest.json"}`,
+/// and restarts the camera to check it periodically (check [`OtaPullSettings`](crate::features::ota::OtaPullSettings)).
+/// With `{"manifest_url":null}` the camera stops checking for updates. If the URL isn't valid the response is a `400`
+/// with the JSON `{"error":"..."}`.
+pub fn change_ota_manifest<C: Connection>(mut request: Request<&mut C>, data: HttpServerData) -> Result<(), C::Error>
+{
+	log::info!("Start handling `change_ota_manifest` request");
+
+	if data.ota.is_none()
+	{
+		return respond_with_error(request, SERVICE_UNAVAILABLE_RESPONSE, "The firmware can't be updated");
+	}
+	let Some(body) = read_body(&mut request)?
+	else
+	{
+		return respond_with_error(request, BAD_REQUEST_RESPONSE, "The body is too big");
+	};
+	let manifest_url = match serde_json::from_slice::<ManifestBody>(&body)
+	{
+		Ok(body) => body.manifest_url,
+		Err(error) => return respond_with_error(request, BAD_REQUEST_RESPONSE, &error.to_string()),
+	};
+	if manifest_url
+		.as_ref()
+		.is_some_and(|url| !(url.starts_with("http://") || url.starts_with("https://")))
+	{
+		return respond_with_error(request, BAD_REQUEST_RESPONSE, "The manifest URL must be HTTP or HTTPS");
+	}
+
+	match data.ota_manifest_requests.request_and_wait(manifest_url, SAVE_TIMEOUT)
+	{
+		Some(()) =>
+		{
+			let origin = cors_origin(&request);
+			let mut response = request.into_response(
+				OK_RESPONSE,
+				None,
+				&[
+					embedded_svc::http::headers::content_type("application/json"),
+					("Access-Control-Allow-Origin", &origin),
+				],
+			)?;
+			response.write_all(br#"{"restarting":true}"#)?;
+
+			Ok(())
+		},
+		None => respond_with_error(request, SERVICE_UNAVAILABLE_RESPONSE, "Couldn't save the manifest URL"),
+	}
+}
+
+fn respond_with_ota_error<C: Connection>(request: Request<&mut C>, error: OtaError) -> Result<(), C::Error>
+{
+	let status = match error
+	{
+		OtaError::Busy
+		| OtaError::RunningNotValid
+		| OtaError::InvalidImage(ImageError::Downgrade { .. } | ImageError::BelowRollbackFloor { .. }) => CONFLICT_RESPONSE,
+		OtaError::InvalidImage(ImageError::UntrustedSignature) => FORBIDDEN_RESPONSE,
+		OtaError::SizeMismatch { .. }
+		| OtaError::HashMismatch
+		| OtaError::InvalidImage(ImageError::NotSigned | ImageError::WrongBoard { .. }) => BAD_REQUEST_RESPONSE,
+		OtaError::NotStarted | OtaError::Flash(_) => INTERNAL_SERVER_ERROR_RESPONSE,
+	};
+
+	respond_with_error(request, status, &error.to_string())
+}
@@ -0,0 +1,161 @@
+use core::time::Duration;
+
+use embedded_svc::http::server::{Connection, Request};
+use serde::Deserialize;
+
+use super::{
+	auth::cors_origin,
+	control::{read_body, respond_with_error},
+	HttpServerData, BAD_REQUEST_RESPONSE, NOT_FOUND_RESPONSE, OK_RESPONSE, SERVICE_UNAVAILABLE_RESPONSE,
+};
+use crate::features::provisioning::{WifiAuthMethod, WifiCredentials, WifiRequest, WifiResponse};
+
+/// How long to wait for the main loop to scan the networks, which takes a few seconds.
+const SCAN_TIMEOUT: Duration = Duration::from_secs(15);
+/// How long to wait for the main loop to save the credentials or to forget them.
+const SAVE_TIMEOUT: Duration = Duration::from_secs(5);
+const FOUND_RESPONSE: u16 = 302;
+
+#[derive(Deserialize)]
+struct WifiCredentialsBody
+{
+	ssid: String,
+	#[serde(default)]
+	password: String,
+	#[serde(default)]
+	auth_method: Option<WifiAuthMethod>,
+}
+
+/// Responds with the page that lists the networks and saves the chosen one.
+pub fn setup<C: Connection>(request: Request<&mut C>, _: HttpServerData) -> Result<(), C::Error>
+{
+	log::info!("Start handling `setup` request");
+
+	const SETUP_HTML: &[u8] = include_bytes!("../../../../../../website/setup.html");
+	let mut response = request.into_response(
+		OK_RESPONSE,
+		None,
+		&[embedded_svc::http::headers::content_type("text/html; charset=utf-8")],
+	)?;
+
+	response.write_all(SETUP_HTML)?;
+
+	Ok(())
+}
+
+/// Scans the networks and responds with the JSON of the [`WifiNetwork`](crate::features::provisioning::WifiNetwork)s
+/// found, from the strongest signal.
+///
+/// ```json
+/// [{"ssid":"Home","rssi_dbm":-52,"secure":true},{"ssid":"Guest","rssi_dbm":-70,"secure":false}]
+/// ```
+pub fn list_wifi_networks<C: Connection>(request: Request<&mut C>, data: HttpServerData) -> Result<(), C::Error>
+{
+	log::info!("Start handling `list_wifi_networks` request");
+
+	let networks = match data.wifi_requests.request_and_wait(WifiRequest::Scan, SCAN_TIMEOUT)
+	{
+		Some(WifiResponse::Networks(networks)) => networks,
+		_ => return respond_with_error(request, SERVICE_UNAVAILABLE_RESPONSE, "Couldn't scan the networks"),
+	};
+
+	let json = serde_json::to_string(&networks).unwrap_or_default();
+	let origin = cors_origin(&request);
+	let mut response = request.into_response(
+		OK_RESPONSE,
+		None,
+		&[
+			embedded_svc::http::headers::content_type("application/json"),
+			("Access-Control-Allow-Origin", &origin),
+		],
+	)?;
+	response.write_all(json.as_bytes())?;
+
+	Ok(())
+}
+
+/// Saves the network in the JSON of the body, like `{"ssid":"Home","password":"...","auth_method":"wpa3_personal"}`,
+/// with the highest priority, and restarts the camera to connect to it. The password can be omitted if the network is
+/// open, and the `auth_method` to pick it from the password. If the SSID or the password aren't valid the response is
+/// a `400` with the JSON `{"error":"..."}`.
+pub fn save_wifi_credentials<C: Connection>(mut request: Request<&mut C>, data: HttpServerData)
+	-> Result<(), C::Error>
+{
+	log::info!("Start handling `save_wifi_credentials` request");
+
+	let Some(body) = read_body(&mut request)?
+	else
+	{
+		return respond_with_error(request, BAD_REQUEST_RESPONSE, "The body is too big");
+	};
+	let credentials = match serde_json::from_slice::<WifiCredentialsBody>(&body)
+	{
+		Ok(body) => WifiCredentials {
+			ssid: body.ssid,
+			password: body.password,
+			auth_method: body.auth_method,
+		},
+		Err(error) => return respond_with_error(request, BAD_REQUEST_RESPONSE, &error.to_string()),
+	};
+	if let Err(error) = credentials.validate()
+	{
+		return respond_with_error(request, BAD_REQUEST_RESPONSE, &error.to_string());
+	}
+
+	respond_with_restart(request, data, WifiRequest::SaveCredentials(credentials))
+}
+
+/// Forgets all the saved networks and restarts the camera in the setup mode.
+pub fn enter_wifi_setup<C: Connection>(request: Request<&mut C>, data: HttpServerData) -> Result<(), C::Error>
+{
+	log::info!("Start handling `enter_wifi_setup` request");
+
+	respond_with_restart(request, data, WifiRequest::EnterSetupMode)
+}
+
+/// Handles the URIs that no other handler does. In the setup mode it redirects to the setup page, so that the devices
+/// that check if they're behind a captive portal (by requesting a URI of theirs) show it.
+pub fn not_found<C: Connection>(request: Request<&mut C>, data: HttpServerData) -> Result<(), C::Error>
+{
+	if data.is_in_setup_mode
+	{
+		return redirect_to_setup(request);
+	}
+
+	let origin = cors_origin(&request);
+	request.into_response(NOT_FOUND_RESPONSE, None, &[("Access-Control-Allow-Origin", &origin)])?;
+
+	Ok(())
+}
+
+pub(super) fn redirect_to_setup<C: Connection>(request: Request<&mut C>) -> Result<(), C::Error>
+{
+	request.into_response(FOUND_RESPONSE, None, &[("Location", "/setup")])?;
+
+	Ok(())
+}
+
+fn respond_with_restart<C: Connection>(
+	request: Request<&mut C>, data: HttpServerData, wifi_request: WifiRequest,
+) -> Result<(), C::Error>
+{
+	match data.wifi_requests.request_and_wait(wifi_request, SAVE_TIMEOUT)
+	{
+		Some(WifiResponse::Restarting) =>
+		{
+			let origin = cors_origin(&request);
+			let mut response = request.into_response(
+				OK_RESPONSE,
+				None,
+				&[
+					embedded_svc::http::headers::content_type("application/json"),
+					("Access-Control-Allow-Origin", &origin),
+				],
+			)?;
+			response.write_all(br#"{"restarting":true}"#)?;
+
+			Ok(())
+		},
+		_ => respond_with_error(request, SERVICE_UNAVAILABLE_RESPONSE, "The WiFi isn't available"),
+	}
+}
@@ -0,0 +1,16 @@
+/// Returns the path of the `uri`, without the query string.
+pub fn uri_path(uri: &str) -> &str
+{
+	uri.split_once('?').map(|(path, _)| path).unwrap_or(uri)
+}
+
+/// Returns the value of the parameter called `name` in the query string of the `uri` (it's not percent-decoded).
+pub fn query_parameter<'a>(uri: &'a str, name: &str) -> Option<&'a str>
+{
+	let (_, query) = uri.split_once('?')?;
+	query
+		.split('&')
+		.filter_map(|parameter| parameter.split_once('='))
+		.find(|(parameter_name, _)| *parameter_name == name)
+		.map(|(_, value)| value)
+}
@@ -0,0 +1,80 @@
+use core::time::Duration;
+
+use embedded_svc::http::server::{Connection, Request};
+
+use super::{
+	auth::cors_origin,
+	control::{read_body, respond_with_error, respond_with_settings},
+	HttpServerData, BAD_REQUEST_RESPONSE, OK_RESPONSE, SERVICE_UNAVAILABLE_RESPONSE,
+};
+
+/// How long to wait for the main loop to read or save the settings.
+const SETTINGS_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Responds with the JSON of the current [`BootSettings`](crate::features::settings::BootSettings), where `null` means
+/// the default of the board.
+///
+/// ```json
+/// {"camera":{"width":800,"height":600,"pixel_format":"Jpeg","jpeg_quality":80},"trigger":"Always",...}
+/// ```
+pub fn get_settings<C: Connection>(request: Request<&mut C>, data: HttpServerData) -> Result<(), C::Error>
+{
+	log::info!("Start handling `get_settings` request");
+
+	match data.boot_settings_requests.request_and_wait(None, SETTINGS_TIMEOUT)
+	{
+		Some(settings) => respond_with_settings(request, &settings),
+		None => respond_with_error(request, SERVICE_UNAVAILABLE_RESPONSE, "Couldn't read the settings"),
+	}
+}
+
+/// Saves the settings that are in the JSON object of the body (the missing ones keep their current value, and the ones
+/// set to `null` return to the default) and restarts the camera to apply them, if they changed. If a setting is
+/// unknown or out of its range nothing is changed, and the response is a `400` with the JSON `{"error":"..."}`.
+pub fn change_settings<C: Connection>(mut request: Request<&mut C>, data: HttpServerData) -> Result<(), C::Error>
+{
+	log::info!("Start handling `change_settings` request");
+
+	let Some(body) = read_body(&mut request)?
+	else
+	{
+		return respond_with_error(request, BAD_REQUEST_RESPONSE, "The body is too big");
+	};
+	let Some(current_settings) = data.boot_settings_requests.request_and_wait(None, SETTINGS_TIMEOUT)
+	else
+	{
+		return respond_with_error(request, SERVICE_UNAVAILABLE_RESPONSE, "Couldn't read the settings");
+	};
+	let settings = match current_settings.with_json_changes(&body)
+	{
+		Ok(settings) => settings,
+		Err(error) => return respond_with_error(request, BAD_REQUEST_RESPONSE, &error),
+	};
+	let is_restarting = settings != current_settings;
+
+	match data
+		.boot_settings_requests
+		.request_and_wait(Some(settings), SETTINGS_TIMEOUT)
+	{
+		Some(_) =>
+		{
+			let origin = cors_origin(&request);
+			let mut response = request.into_response(
+				OK_RESPONSE,
+				None,
+				&[
+					embedded_svc::http::headers::content_type("application/json"),
+					("Access-Control-Allow-Origin", &origin),
+				],
+			)?;
+			response.write_all(
+				serde_json::json!({ "restarting": is_restarting })
+					.to_string()
+					.as_bytes(),
+			)?;
+
+			Ok(())
+		},
+		None => respond_with_error(request, SERVICE_UNAVAILABLE_RESPONSE, "Couldn't save the settings"),
+	}
+}
@@ -0,0 +1,95 @@
+use core::time::Duration;
+
+use a13c_embedded::utils::math::micromath::micromath::vector::U16x2;
+use embedded_svc::http::server::{Connection, Request};
+
+use super::{
+	auth::cors_origin, query, timestamp_header, HttpServerData, BAD_REQUEST_RESPONSE, OK_RESPONSE,
+	SERVICE_UNAVAILABLE_RESPONSE,
+};
+use crate::configuration::peripherals::camera::CaptureSettings;
+
+/// The latest frame of the camera is sent only if it's more recent than this, otherwise a new frame is captured.
+const MAX_LATEST_FRAME_AGE: Duration = Duration::from_secs(1);
+/// How long to wait for the main loop to capture a new frame.
+const CAPTURE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Responds with a single JPEG image. The optional `resolution` (like `640x480`) and `quality` (from 0 to 100) query
+/// parameters are applied only to this capture.
+pub fn capture<C: Connection>(request: Request<&mut C>, data: HttpServerData) -> Result<(), C::Error>
+{
+	log::info!("Start handling `capture` request");
+
+	let origin = cors_origin(&request);
+	let Some(settings) = capture_settings(request.uri())
+	else
+	{
+		request.into_response(BAD_REQUEST_RESPONSE, None, &[("Access-Control-Allow-Origin", &origin)])?;
+		return Ok(());
+	};
+
+	let latest_frame = if settings.is_empty()
+	{
+		data.frame_broker
+			.latest_frame()
+			.filter(|frame| frame.published_at.elapsed() < MAX_LATEST_FRAME_AGE)
+	}
+	else
+	{
+		None
+	};
+	let Some(frame) = latest_frame.or_else(|| {
+		data.snapshot_requests
+			.request_and_wait(settings, CAPTURE_TIMEOUT)
+			.flatten()
+	})
+	else
+	{
+		request.into_response(
+			SERVICE_UNAVAILABLE_RESPONSE,
+			None,
+			&[("Access-Control-Allow-Origin", &origin)],
+		)?;
+		return Ok(());
+	};
+
+	let content_length = frame.pixels.len().to_string();
+	let timestamp = timestamp_header(frame.timestamp);
+	let mut response = request.into_response(
+		OK_RESPONSE,
+		None,
+		&[
+			embedded_svc::http::headers::content_type("image/jpeg"),
+			("Content-Length", content_length.as_str()),
+			("X-Timestamp", timestamp.as_str()),
+			("Access-Control-Allow-Origin", &origin),
+		],
+	)?;
+	response.write_all(&frame.pixels)?;
+
+	Ok(())
+}
+
+/// Returns `None` if one of the query parameters is invalid.
+fn capture_settings(uri: &str) -> Option<CaptureSettings>
+{
+	let resolution = match query::query_parameter(uri, "resolution")
+	{
+		Some(resolution) =>
+		{
+			let (width, height) = resolution.split_once('x')?;
+			Some(U16x2 {
+				x: width.parse().ok()?,
+				y: height.parse().ok()?,
+			})
+		},
+		None => None,
+	};
+	let quality = match query::query_parameter(uri, "quality")
+	{
+		Some(quality) => Some(quality.parse().ok().filter(|quality| *quality <= 100)?),
+		None => None,
+	};
+
+	Some(CaptureSettings { resolution, quality })
+}
@@ -1,3 +1,14 @@
+pub mod connectivity;
+pub mod frames;
 pub mod http_server;
+pub mod mqtt;
+pub mod ota;
+pub mod provisioning;
+pub mod recording;
+pub mod requests;
+pub mod rtsp;
+pub mod settings;
 pub mod storage;
+pub mod timelapse;
 pub mod trigger;
+pub mod webhooks;
@@ -0,0 +1,236 @@
+use serde_json::{json, Value};
+
+use super::MqttTopics;
+
+/// Configures the discovery of the camera by Home Assistant.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HomeAssistantSettings
+{
+	/// The `discovery_prefix` of the MQTT integration of Home Assistant, which is `homeassistant` by default.
+	pub discovery_prefix: String,
+	/// The name of the device in Home Assistant, which the names of its entities are prefixed with.
+	pub device_name: String,
+}
+
+/// What Home Assistant needs to know about the camera that isn't in the [`HomeAssistantSettings`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HomeAssistantDevice
+{
+	/// The MAC address of the WiFi interface, from which the identifiers of the device and of its entities are derived
+	/// (so that they don't change when the settings do).
+	pub mac_address: [u8; 6],
+	/// Without it the flash LED switch isn't discovered.
+	pub has_flash_led: bool,
+}
+
+/// A retained message that makes Home Assistant create (or update) an entity.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DiscoveryConfig
+{
+	pub topic: String,
+	pub payload: Value,
+}
+
+impl HomeAssistantDevice
+{
+	/// Like `camera_a1b2c3d4e5f6`.
+	pub fn node_id(&self) -> String
+	{
+		let mac_address: String = self.mac_address.iter().map(|byte| format!("{:02x}", byte)).collect();
+		format!("camera_{}", mac_address)
+	}
+
+	/// Returns the configs of all the entities of the camera:
+	/// - `camera`: the JPEG published when a motion event starts or after a `command/snapshot`
+	/// - `binary_sensor` motion
+	/// - `switch` armed and flash LED
+	/// - `sensor` SD free space, RSSI and uptime
+	pub fn discovery_configs(&self, settings: &HomeAssistantSettings, topics: &MqttTopics) -> Vec<DiscoveryConfig>
+	{
+		let mut configs = vec![
+			self.config(
+				settings,
+				topics,
+				"camera",
+				"camera",
+				json!({
+					"name": "Snapshot",
+					"topic": topics.topic(MqttTopics::SNAPSHOT),
+				}),
+			),
+			self.config(
+				settings,
+				topics,
+				"binary_sensor",
+				"motion",
+				json!({
+					"name": "Motion",
+					"device_class": "motion",
+					"state_topic": topics.topic(MqttTopics::MOTION),
+				}),
+			),
+			self.config(
+				settings,
+				topics,
+				"switch",
+				"armed",
+				json!({
+					"name": "Armed",
+					"icon": "mdi:shield-home",
+					"command_topic": topics.command(MqttTopics::ARMED_COMMAND),
+					"state_topic": topics.topic(MqttTopics::STATUS),
+					"value_template": "{{ 'OFF' if value_json.arming.mode == 'disarmed' else 'ON' }}",
+				}),
+			),
+			self.config(
+				settings,
+				topics,
+				"sensor",
+				"sd_free_space",
+				json!({
+					"name": "SD free space",
+					"device_class": "data_size",
+					"state_class": "measurement",
+					"unit_of_measurement": "B",
+					"entity_category": "diagnostic",
+					"state_topic": topics.topic(MqttTopics::STORAGE),
+					"value_template": "{{ value_json.free_bytes }}",
+				}),
+			),
+			self.config(
+				settings,
+				topics,
+				"sensor",
+				"rssi",
+				json!({
+					"name": "WiFi signal",
+					"device_class": "signal_strength",
+					"state_class": "measurement",
+					"unit_of_measurement": "dBm",
+					"entity_category": "diagnostic",
+					"state_topic": topics.topic(MqttTopics::SYSTEM),
+					"value_template": "{{ value_json.rssi_dbm }}",
+				}),
+			),
+			self.config(
+				settings,
+				topics,
+				"sensor",
+				"uptime",
+				json!({
+					"name": "Uptime",
+					"device_class": "duration",
+					"state_class": "total_increasing",
+					"unit_of_measurement": "s",
+					"entity_category": "diagnostic",
+					"state_topic": topics.topic(MqttTopics::SYSTEM),
+					"value_template": "{{ value_json.uptime_seconds }}",
+				}),
+			),
+		];
+		if self.has_flash_led
+		{
+			configs.push(self.config(
+				settings,
+				topics,
+				"switch",
+				"flash_led",
+				json!({
+					"name": "Flash LED",
+					"icon": "mdi:flashlight",
+					"command_topic": topics.command(MqttTopics::FLASH_LED_COMMAND),
+					"state_topic": topics.topic(MqttTopics::FLASH_LED),
+				}),
+			));
+		}
+
+		configs
+	}
+
+	/// Adds the fields shared by all the entities to the `entity` ones.
+	fn config(
+		&self, settings: &HomeAssistantSettings, topics: &MqttTopics, component: &str, object_id: &str,
+		mut entity: Value,
+	) -> DiscoveryConfig
+	{
+		let node_id = self.node_id();
+		let mac_address = self
+			.mac_address
+			.iter()
+			.map(|byte| format!("{:02x}", byte))
+			.collect::<Vec<_>>()
+			.join(":");
+
+		entity["unique_id"] = json!(format!("{}_{}", node_id, object_id));
+		entity["availability_topic"] = json!(topics.topic(MqttTopics::AVAILABILITY));
+		entity["device"] = json!({
+			"identifiers": [node_id],
+			"connections": [["mac", mac_address]],
+			"name": settings.device_name,
+		});
+
+		DiscoveryConfig {
+			topic: format!(
+				"{}/{}/{}/{}/config",
+				settings.discovery_prefix, component, node_id, object_id
+			),
+			payload: entity,
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	fn device(has_flash_led: bool) -> HomeAssistantDevice
+	{
+		HomeAssistantDevice {
+			mac_address: [0xA1, 0xB2, 0xC3, 0x04, 0x05, 0x06],
+			has_flash_led,
+		}
+	}
+
+	fn settings() -> HomeAssistantSettings
+	{
+		HomeAssistantSettings {
+			discovery_prefix: "homeassistant".to_string(),
+			device_name: "Garden camera".to_string(),
+		}
+	}
+
+	#[test]
+	fn identifiers_are_derived_from_the_mac_address()
+	{
+		let configs = device(true).discovery_configs(&settings(), &MqttTopics::new("home/camera"));
+		let motion = configs
+			.iter()
+			.find(|config| config.topic == "homeassistant/binary_sensor/camera_a1b2c3040506/motion/config")
+			.unwrap();
+
+		assert_eq!(motion.payload["unique_id"], "camera_a1b2c3040506_motion");
+		assert_eq!(motion.payload["state_topic"], "home/camera/motion");
+		assert_eq!(motion.payload["availability_topic"], "home/camera/availability");
+		assert_eq!(motion.payload["device"]["identifiers"], json!(["camera_a1b2c3040506"]));
+		assert_eq!(
+			motion.payload["device"]["connections"],
+			json!([["mac", "a1:b2:c3:04:05:06"]])
+		);
+	}
+
+	#[test]
+	fn flash_led_is_discovered_only_if_the_board_has_it()
+	{
+		let topics = MqttTopics::new("home/camera");
+		let is_flash_led = |config: &DiscoveryConfig| config.topic.ends_with("/flash_led/config");
+
+		let configs = device(true).discovery_configs(&settings(), &topics);
+		assert_eq!(configs.len(), 7);
+		assert!(configs.iter().any(is_flash_led));
+
+		let configs = device(false).discovery_configs(&settings(), &topics);
+		assert_eq!(configs.len(), 6);
+		assert!(!configs.iter().any(is_flash_led));
+	}
+}
@@ -0,0 +1,719 @@
+mod home_assistant;
+
+use core::{
+	fmt::{Debug, Formatter},
+	time::Duration,
+};
+use std::{net::IpAddr, time::Instant};
+
+pub use home_assistant::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+	configuration::peripherals::{
+		camera::SensorSettings,
+		mqtt::{MqttClient, MqttEvent, MqttLastWill},
+	},
+	features::{
+		requests::Requests,
+		storage::StorageStats,
+		trigger::{Arming, MotionEvent, TriggerState, TriggerStatus},
+	},
+};
+
+/// Changes (with `Some`) or removes (with `None`) the broker saved in the
+/// [`Settings`](crate::features::settings::Settings). The camera restarts to connect to it.
+pub type MqttBrokerRequests = Requests<Option<MqttBroker>, ()>;
+
+/// Configures the [`Mqtt`] feature.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MqttSettings
+{
+	/// The prefix of all the topics (check [`MqttTopics`]), like `home/camera`.
+	pub base_topic: String,
+	/// How often the [`StorageStats`] and the [`SystemStats`] are published.
+	pub stats_period: Duration,
+	/// `None` if the camera isn't discovered by Home Assistant.
+	pub home_assistant: Option<HomeAssistantSettings>,
+}
+
+/// The broker the camera connects to, saved in the [`Settings`](crate::features::settings::Settings) with
+/// `POST /mqtt/broker` and applied by the board when it creates the MQTT client.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct MqttBroker
+{
+	/// Like `mqtt://192.168.1.2:1883`.
+	pub url: String,
+	#[serde(default)]
+	pub username: Option<String>,
+	#[serde(default)]
+	pub password: Option<String>,
+}
+
+/// The topics of the camera, all under the `base_topic` of the [`MqttSettings`]. The ones marked with (R) are retained.
+/// - `availability` (R): `online`, or `offline` when the camera disconnects (it's the last will)
+/// - `status` (R): the JSON of the [`TriggerStatus`]
+/// - `motion` (R): `ON` while the trigger is [`TriggerState::Triggered`], `OFF` otherwise
+/// - `event`: the JSON of each [`MotionEvent`]
+/// - `storage` (R): the JSON of the [`StorageStats`], every `stats_period`
+/// - `system` (R): the JSON of the [`SystemStats`], every `stats_period`
+/// - `snapshot_url` (R): the URL from which a JPEG can be downloaded over HTTP
+/// - `snapshot`: the JPEG captured when a [`MotionEvent`] starts or after a `command/snapshot`
+/// - `settings` (R): the JSON of the [`SensorSettings`] after a `command/settings`
+/// - `flash_led` (R): `ON` or `OFF`, if the board has a flash LED
+///
+/// The commands are received on:
+/// - `command/arm`: arms the trigger, until the date and time in the payload if it isn't empty (like
+///   `2024-05-01T18:00`)
+/// - `command/disarm`
+/// - `command/armed`: arms the trigger if the payload is `ON`, disarms it if it's `OFF` (like a Home Assistant switch)
+/// - `command/snapshot`
+/// - `command/settings`: changes the settings of the sensor in the JSON object of the payload, like the `/control`
+///   request
+/// - `command/flash_led`: turns the flash LED on if the payload is `ON`, off if it's `OFF`
+///
+/// If the [`HomeAssistantSettings`] are set, the discovery configs are published (retained) too after each connection.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MqttTopics
+{
+	base: String,
+}
+
+/// A command received with MQTT, executed by the main loop (check [`MqttTopics`]).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MqttCommand
+{
+	SetArming(Arming),
+	Snapshot,
+	/// The JSON object with the settings to change.
+	ChangeSensorSettings(Vec<u8>),
+	SetFlashLed(bool),
+}
+
+/// Published on the `system` topic.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SystemStats
+{
+	/// Since the camera was created.
+	pub uptime_seconds: u64,
+	/// The strength of the signal of the WiFi access point, or `None` if it isn't known.
+	pub rssi_dbm: Option<i8>,
+}
+
+/// Publishes the state of the camera to an MQTT broker and receives the commands for it (check [`MqttTopics`]).
+/// Nothing is published while the client is disconnected, and everything retained is published again when it
+/// reconnects.
+pub struct Mqtt<M: MqttClient>
+{
+	client: M,
+	settings: MqttSettings,
+	topics: MqttTopics,
+	is_connected: bool,
+	/// `None` if it has to be published again, like after a reconnection.
+	published_status: Option<TriggerStatus>,
+	/// `None` if the stats haven't been published since the last connection.
+	stats_published_at: Option<Instant>,
+	/// `None` if the camera isn't discovered by Home Assistant (because it's disabled or because the MAC address isn't
+	/// known).
+	home_assistant_device: Option<HomeAssistantDevice>,
+	/// `None` if the board doesn't have a flash LED (so it's never been published). It's published again after each
+	/// reconnection.
+	flash_led_on: Option<bool>,
+	created_at: Instant,
+}
+
+impl Debug for MqttBroker
+{
+	fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result
+	{
+		f.debug_struct("MqttBroker")
+			.field("url", &self.url)
+			.field("username", &self.username)
+			.field("password", &self.password.as_ref().map(|_| "***"))
+			.finish()
+	}
+}
+
+impl MqttTopics
+{
+	pub const AVAILABILITY: &str = "availability";
+	pub const STATUS: &str = "status";
+	pub const MOTION: &str = "motion";
+	pub const EVENT: &str = "event";
+	pub const STORAGE: &str = "storage";
+	pub const SYSTEM: &str = "system";
+	pub const SNAPSHOT_URL: &str = "snapshot_url";
+	pub const SNAPSHOT: &str = "snapshot";
+	pub const SETTINGS: &str = "settings";
+	pub const FLASH_LED: &str = "flash_led";
+
+	pub const ARM_COMMAND: &str = "arm";
+	pub const DISARM_COMMAND: &str = "disarm";
+	pub const ARMED_COMMAND: &str = "armed";
+	pub const SNAPSHOT_COMMAND: &str = "snapshot";
+	pub const SETTINGS_COMMAND: &str = "settings";
+	pub const FLASH_LED_COMMAND: &str = "flash_led";
+	const COMMANDS: [&str; 6] = [
+		Self::ARM_COMMAND,
+		Self::DISARM_COMMAND,
+		Self::ARMED_COMMAND,
+		Self::SNAPSHOT_COMMAND,
+		Self::SETTINGS_COMMAND,
+		Self::FLASH_LED_COMMAND,
+	];
+
+	pub fn new(base_topic: &str) -> Self
+	{
+		Self {
+			base: base_topic.trim_end_matches('/').to_string(),
+		}
+	}
+
+	pub fn topic(&self, name: &str) -> String
+	{
+		format!("{}/{}", self.base, name)
+	}
+
+	pub fn command(&self, command: &str) -> String
+	{
+		format!("{}/command/{}", self.base, command)
+	}
+}
+
+impl<M: MqttClient> Mqtt<M>
+{
+	/// The last will that the client must be created with.
+	pub fn last_will(settings: &MqttSettings) -> MqttLastWill
+	{
+		MqttLastWill {
+			topic: MqttTopics::new(&settings.base_topic).topic(MqttTopics::AVAILABILITY),
+			payload: b"offline".to_vec(),
+		}
+	}
+
+	/// `home_assistant_device` is ignored if the [`HomeAssistantSettings`] aren't set.
+	pub fn new(client: M, settings: MqttSettings, home_assistant_device: Option<HomeAssistantDevice>) -> Self
+	{
+		if settings.home_assistant.is_some() && home_assistant_device.is_none()
+		{
+			log::warn!("The camera won't be discovered by Home Assistant because its MAC address isn't known");
+		}
+
+		Self {
+			client,
+			topics: MqttTopics::new(&settings.base_topic),
+			home_assistant_device: home_assistant_device.filter(|_| settings.home_assistant.is_some()),
+			settings,
+			is_connected: false,
+			published_status: None,
+			stats_published_at: None,
+			flash_led_on: None,
+			created_at: Instant::now(),
+		}
+	}
+
+	pub fn topics(&self) -> &MqttTopics
+	{
+		&self.topics
+	}
+
+	/// Handles the events of the client and returns the commands received since the last call. After each connection
+	/// it subscribes to the commands and publishes the availability and the `snapshot_url` (if the `ip_address` is
+	/// known).
+	pub fn poll(&mut self, ip_address: Option<IpAddr>) -> Vec<MqttCommand>
+	{
+		let mut commands = Vec::new();
+		while let Some(event) = self.client.poll_event()
+		{
+			match event
+			{
+				MqttEvent::Connected => self.on_connected(ip_address),
+				MqttEvent::Disconnected =>
+				{
+					log::warn!("Disconnected from the MQTT broker");
+					self.is_connected = false;
+				},
+				MqttEvent::Message { topic, payload } => commands.extend(self.parse_command(&topic, payload)),
+			}
+		}
+
+		commands
+	}
+
+	fn on_connected(&mut self, ip_address: Option<IpAddr>)
+	{
+		log::info!("Connected to the MQTT broker");
+		self.is_connected = true;
+		self.published_status = None;
+		self.stats_published_at = None;
+
+		for command in MqttTopics::COMMANDS
+		{
+			let topic = self.topics.command(command);
+			if let Err(error) = self.client.subscribe(&topic)
+			{
+				log::warn!("Couldn't subscribe to {}: {:?}", topic, error);
+			}
+		}
+		self.publish(MqttTopics::AVAILABILITY, b"online", true);
+		if let Some(ip_address) = ip_address
+		{
+			self.publish(
+				MqttTopics::SNAPSHOT_URL,
+				format!("http://{}/capture", ip_address).as_bytes(),
+				true,
+			);
+		}
+		if let Some(on) = self.flash_led_on
+		{
+			self.publish_flash_led(on);
+		}
+		self.publish_discovery_configs();
+	}
+
+	fn publish_discovery_configs(&mut self)
+	{
+		let (Some(device), Some(settings)) = (&self.home_assistant_device, &self.settings.home_assistant)
+		else
+		{
+			return;
+		};
+
+		for config in device.discovery_configs(settings, &self.topics)
+		{
+			let payload = serde_json::to_vec(&config.payload).expect("A JSON `Value` can always be serialized");
+			if let Err(error) = self.client.publish(&config.topic, &payload, true)
+			{
+				log::warn!("Couldn't publish to {}: {:?}", config.topic, error);
+			}
+		}
+	}
+
+	fn parse_command(&self, topic: &str, payload: Vec<u8>) -> Option<MqttCommand>
+	{
+		let command = topic.strip_prefix(&self.topics.command(""))?;
+		let command = match command
+		{
+			MqttTopics::ARM_COMMAND => match core::str::from_utf8(&payload).map(str::trim)
+			{
+				Ok("") => MqttCommand::SetArming(Arming::Armed),
+				Ok(until) => match until.parse()
+				{
+					Ok(until) => MqttCommand::SetArming(Arming::ArmedUntil { until }),
+					Err(error) =>
+					{
+						log::warn!("Invalid MQTT command {}: {}", topic, error);
+						return None;
+					},
+				},
+				Err(error) =>
+				{
+					log::warn!("Invalid MQTT command {}: {}", topic, error);
+					return None;
+				},
+			},
+			MqttTopics::DISARM_COMMAND => MqttCommand::SetArming(Arming::Disarmed),
+			MqttTopics::ARMED_COMMAND => match parse_switch(topic, &payload)?
+			{
+				true => MqttCommand::SetArming(Arming::Armed),
+				false => MqttCommand::SetArming(Arming::Disarmed),
+			},
+			MqttTopics::SNAPSHOT_COMMAND => MqttCommand::Snapshot,
+			MqttTopics::SETTINGS_COMMAND => MqttCommand::ChangeSensorSettings(payload),
+			MqttTopics::FLASH_LED_COMMAND => MqttCommand::SetFlashLed(parse_switch(topic, &payload)?),
+			_ =>
+			{
+				log::warn!("Unknown MQTT command {}", topic);
+				return None;
+			},
+		};
+
+		Some(command)
+	}
+
+	/// Publishes the status of the trigger, if it changed since the last time.
+	pub fn publish_status(&mut self, status: TriggerStatus)
+	{
+		if !self.is_connected || self.published_status == Some(status)
+		{
+			return;
+		}
+
+		let json = serde_json::to_vec(&status).expect("`TriggerStatus` can always be serialized");
+		let motion: &[u8] = match status.state
+		{
+			TriggerState::Triggered => b"ON",
+			_ => b"OFF",
+		};
+		if self.publish(MqttTopics::STATUS, &json, true) && self.publish(MqttTopics::MOTION, motion, true)
+		{
+			self.published_status = Some(status);
+		}
+	}
+
+	/// Publishes the `event` and, if the frame that started it was captured, its `snapshot`.
+	pub fn publish_motion_event(&mut self, event: &MotionEvent, snapshot: Option<&[u8]>)
+	{
+		let json = serde_json::to_vec(event).expect("`MotionEvent` can always be serialized");
+		self.publish(MqttTopics::EVENT, &json, false);
+		if let Some(snapshot) = snapshot
+		{
+			self.publish_snapshot(snapshot);
+		}
+	}
+
+	/// Returns `true` if it's time to call [`Self::publish_stats`].
+	pub fn needs_stats(&self) -> bool
+	{
+		match self.stats_published_at
+		{
+			_ if !self.is_connected => false,
+			Some(published_at) => published_at.elapsed() >= self.settings.stats_period,
+			None => true,
+		}
+	}
+
+	/// Publishes the `storage` stats and the [`SystemStats`].
+	pub fn publish_stats(&mut self, storage: StorageStats, rssi_dbm: Option<i8>)
+	{
+		let system = SystemStats {
+			uptime_seconds: self.created_at.elapsed().as_secs(),
+			rssi_dbm,
+		};
+
+		let json = serde_json::to_vec(&storage).expect("`StorageStats` can always be serialized");
+		self.publish(MqttTopics::STORAGE, &json, true);
+		let json = serde_json::to_vec(&system).expect("`SystemStats` can always be serialized");
+		self.publish(MqttTopics::SYSTEM, &json, true);
+		// Even if it failed, so that it's not retried at each tick
+		self.stats_published_at = Some(Instant::now());
+	}
+
+	/// Publishes the state of the flash LED, which is published again after each reconnection (so it must be called
+	/// when the camera is created too).
+	pub fn publish_flash_led(&mut self, on: bool)
+	{
+		self.flash_led_on = Some(on);
+		self.publish(MqttTopics::FLASH_LED, if on { b"ON" } else { b"OFF" }, true);
+	}
+
+	pub fn publish_snapshot(&mut self, jpeg: &[u8])
+	{
+		self.publish(MqttTopics::SNAPSHOT, jpeg, false);
+	}
+
+	pub fn publish_sensor_settings(&mut self, settings: &SensorSettings)
+	{
+		let json = serde_json::to_vec(settings).expect("`SensorSettings` can always be serialized");
+		self.publish(MqttTopics::SETTINGS, &json, true);
+	}
+
+	/// Returns `false` if the message couldn't be published (the error is logged).
+	fn publish(&mut self, name: &str, payload: &[u8], retain: bool) -> bool
+	{
+		if !self.is_connected
+		{
+			return false;
+		}
+
+		let topic = self.topics.topic(name);
+		match self.client.publish(&topic, payload, retain)
+		{
+			Ok(()) => true,
+			Err(error) =>
+			{
+				log::warn!("Couldn't publish to {}: {:?}", topic, error);
+				false
+			},
+		}
+	}
+}
+
+/// Parses the payload of the commands of a Home Assistant switch (`ON` or `OFF`).
+fn parse_switch(topic: &str, payload: &[u8]) -> Option<bool>
+{
+	match payload
+	{
+		b"ON" => Some(true),
+		b"OFF" => Some(false),
+		_ =>
+		{
+			log::warn!("Invalid MQTT command {}: the payload must be ON or OFF", topic);
+			None
+		},
+	}
+}
+
+#[cfg(test)]
+mod tests
+{
+	use std::{collections::VecDeque, net::Ipv4Addr};
+
+	use super::*;
+	use crate::features::trigger::TriggerSource;
+
+	/// Stands in for the broker: it records what the client publishes and subscribes to, and delivers the events that
+	/// the test queues.
+	#[derive(Default)]
+	struct FakeBroker
+	{
+		is_connected: bool,
+		events: VecDeque<MqttEvent>,
+		subscriptions: Vec<String>,
+		/// The topic, payload and retain flag of each message.
+		published: Vec<(String, Vec<u8>, bool)>,
+	}
+
+	impl FakeBroker
+	{
+		fn connect(&mut self)
+		{
+			self.is_connected = true;
+			self.events.push_back(MqttEvent::Connected);
+		}
+
+		fn disconnect(&mut self)
+		{
+			self.is_connected = false;
+			self.events.push_back(MqttEvent::Disconnected);
+		}
+
+		fn send(&mut self, topic: &str, payload: &[u8])
+		{
+			self.events.push_back(MqttEvent::Message {
+				topic: topic.to_string(),
+				payload: payload.to_vec(),
+			});
+		}
+
+		fn published_to(&self, topic: &str) -> Vec<&[u8]>
+		{
+			self.published
+				.iter()
+				.filter(|(published_topic, _, _)| published_topic == topic)
+				.map(|(_, payload, _)| payload.as_slice())
+				.collect()
+		}
+	}
+
+	impl MqttClient for FakeBroker
+	{
+		type Error = ();
+
+		fn publish(&mut self, topic: &str, payload: &[u8], retain: bool) -> Result<(), Self::Error>
+		{
+			if !self.is_connected
+			{
+				return Err(());
+			}
+			self.published.push((topic.to_string(), payload.to_vec(), retain));
+			Ok(())
+		}
+
+		fn subscribe(&mut self, topic: &str) -> Result<(), Self::Error>
+		{
+			self.subscriptions.push(topic.to_string());
+			Ok(())
+		}
+
+		fn poll_event(&mut self) -> Option<MqttEvent>
+		{
+			self.events.pop_front()
+		}
+	}
+
+	fn settings() -> MqttSettings
+	{
+		MqttSettings {
+			base_topic: "home/camera/".to_string(),
+			stats_period: Duration::from_secs(60),
+			home_assistant: None,
+		}
+	}
+
+	fn connected_mqtt() -> Mqtt<FakeBroker>
+	{
+		let mut mqtt = Mqtt::new(FakeBroker::default(), settings(), None);
+		mqtt.client.connect();
+		mqtt.poll(None);
+		mqtt.client.published.clear();
+		mqtt
+	}
+
+	fn status(state: TriggerState) -> TriggerStatus
+	{
+		TriggerStatus {
+			state,
+			arming: Arming::Armed,
+			scheduled: true,
+		}
+	}
+
+	#[test]
+	fn last_will_marks_the_camera_offline()
+	{
+		assert_eq!(
+			Mqtt::<FakeBroker>::last_will(&settings()),
+			MqttLastWill {
+				topic: "home/camera/availability".to_string(),
+				payload: b"offline".to_vec(),
+			}
+		);
+	}
+
+	#[test]
+	fn connection_subscribes_to_the_commands_and_publishes_the_availability()
+	{
+		let mut mqtt = Mqtt::new(FakeBroker::default(), settings(), None);
+		mqtt.client.connect();
+		mqtt.poll(Some(IpAddr::V4(Ipv4Addr::new(192, 168, 1, 10))));
+
+		assert_eq!(
+			mqtt.client.subscriptions,
+			[
+				"home/camera/command/arm",
+				"home/camera/command/disarm",
+				"home/camera/command/armed",
+				"home/camera/command/snapshot",
+				"home/camera/command/settings",
+				"home/camera/command/flash_led"
+			]
+		);
+		assert_eq!(
+			mqtt.client.published,
+			[
+				("home/camera/availability".to_string(), b"online".to_vec(), true),
+				(
+					"home/camera/snapshot_url".to_string(),
+					b"http://192.168.1.10/capture".to_vec(),
+					true
+				),
+			]
+		);
+	}
+
+	#[test]
+	fn commands_are_parsed()
+	{
+		let mut mqtt = connected_mqtt();
+		mqtt.client.send("home/camera/command/arm", b"");
+		mqtt.client.send("home/camera/command/arm", b"2024-05-01T18:00");
+		mqtt.client.send("home/camera/command/arm", b"tomorrow");
+		mqtt.client.send("home/camera/command/disarm", b"");
+		mqtt.client.send("home/camera/command/armed", b"ON");
+		mqtt.client.send("home/camera/command/armed", b"off");
+		mqtt.client.send("home/camera/command/snapshot", b"");
+		mqtt.client.send("home/camera/command/settings", br#"{"brightness":1}"#);
+		mqtt.client.send("home/camera/command/flash_led", b"OFF");
+		mqtt.client.send("home/camera/command/reboot", b"");
+
+		assert_eq!(
+			mqtt.poll(None),
+			[
+				MqttCommand::SetArming(Arming::Armed),
+				MqttCommand::SetArming(Arming::ArmedUntil {
+					until: "2024-05-01T18:00".parse().unwrap()
+				}),
+				MqttCommand::SetArming(Arming::Disarmed),
+				MqttCommand::SetArming(Arming::Armed),
+				MqttCommand::Snapshot,
+				MqttCommand::ChangeSensorSettings(br#"{"brightness":1}"#.to_vec()),
+				MqttCommand::SetFlashLed(false),
+			]
+		);
+	}
+
+	#[test]
+	fn motion_events_are_published_with_their_snapshot()
+	{
+		let mut mqtt = connected_mqtt();
+		let event = MotionEvent {
+			id: "20240501130102".to_string(),
+			timestamp: "2024-05-01T13:01:02".parse().unwrap(),
+			trigger_source: TriggerSource::Pir,
+		};
+		mqtt.publish_motion_event(&event, Some(b"jpeg"));
+		mqtt.publish_motion_event(&event, None);
+
+		assert_eq!(mqtt.client.published_to("home/camera/event").len(), 2);
+		assert_eq!(mqtt.client.published_to("home/camera/snapshot"), [b"jpeg".as_slice()]);
+	}
+
+	#[test]
+	fn status_is_published_when_it_changes_and_after_reconnecting()
+	{
+		let mut mqtt = connected_mqtt();
+		mqtt.publish_status(status(TriggerState::Armed));
+		mqtt.publish_status(status(TriggerState::Armed));
+		mqtt.publish_status(status(TriggerState::Triggered));
+		assert_eq!(
+			mqtt.client.published_to("home/camera/motion"),
+			[b"OFF".as_slice(), b"ON"]
+		);
+
+		mqtt.client.disconnect();
+		mqtt.poll(None);
+		mqtt.publish_status(status(TriggerState::Triggered));
+		mqtt.client.connect();
+		mqtt.poll(None);
+		mqtt.publish_status(status(TriggerState::Triggered));
+		assert_eq!(
+			mqtt.client.published_to("home/camera/motion"),
+			[b"OFF".as_slice(), b"ON", b"ON"]
+		);
+		assert_eq!(mqtt.client.published_to("home/camera/status").len(), 3);
+	}
+
+	#[test]
+	fn stats_are_published_periodically()
+	{
+		let mut mqtt = connected_mqtt();
+		assert!(mqtt.needs_stats());
+
+		mqtt.publish_stats(
+			StorageStats {
+				used_bytes: 10,
+				free_bytes: 90,
+				capacity_bytes: 100,
+			},
+			Some(-60),
+		);
+		assert!(!mqtt.needs_stats());
+		assert_eq!(
+			mqtt.client.published_to("home/camera/storage"),
+			[br#"{"used_bytes":10,"free_bytes":90,"capacity_bytes":100}"#]
+		);
+		assert_eq!(
+			mqtt.client.published_to("home/camera/system"),
+			[br#"{"uptime_seconds":0,"rssi_dbm":-60}"#]
+		);
+	}
+
+	#[test]
+	fn discovery_configs_and_flash_led_are_published_after_connecting()
+	{
+		let mut settings = settings();
+		settings.home_assistant = Some(HomeAssistantSettings {
+			discovery_prefix: "homeassistant".to_string(),
+			device_name: "Garden camera".to_string(),
+		});
+		let device = HomeAssistantDevice {
+			mac_address: [0xA1, 0xB2, 0xC3, 0x04, 0x05, 0x06],
+			has_flash_led: true,
+		};
+		let mut mqtt = Mqtt::new(FakeBroker::default(), settings, Some(device));
+		mqtt.publish_flash_led(false);
+		mqtt.client.connect();
+		mqtt.poll(None);
+
+		assert_eq!(mqtt.client.published_to("home/camera/flash_led"), [b"OFF"]);
+		let discovery_configs: Vec<_> = mqtt
+			.client
+			.published
+			.iter()
+			.filter(|(topic, _, _)| topic.starts_with("homeassistant/"))
+			.collect();
+		assert_eq!(discovery_configs.len(), 7);
+		assert!(discovery_configs.iter().all(|(_, _, retain)| *retain));
+	}
+}
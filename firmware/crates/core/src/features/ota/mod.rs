@@ -0,0 +1,686 @@
+mod pull;
+pub mod signature;
+
+use core::{fmt::Display, time::Duration};
+use std::sync::Arc;
+
+use sha2::{Digest, Sha256};
+use spin::Mutex;
+
+pub use self::pull::start_pull;
+use self::signature::{FirmwareVersion, ImageError, ImageMetadata, ImagePolicy, SIGNED_HEADER_SIZE};
+use crate::{configuration::peripherals::ota::OtaFlash, features::requests::Requests};
+
+/// Configures the firmware updates (OTA).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OtaSettings
+{
+	/// How often the camera checks the manifest saved in the settings (check
+	/// [`Settings::ota_manifest_url`](crate::features::settings::Settings::ota_manifest_url)).
+	pub check_period: Duration,
+	/// The board the images must have been built for, like `esp32-cam`.
+	pub board: String,
+	/// The Ed25519 public keys whose signatures are accepted. If there are none all the images are refused.
+	pub trusted_public_keys: Vec<[u8; 32]>,
+	/// How many ticks of the main loop must complete after booting an update before it's marked valid. If the camera
+	/// restarts before (because it crashes or the watchdog resets it), the previous firmware is booted.
+	pub ticks_before_valid: u32,
+}
+
+/// Configures the checks for updates made by the camera.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OtaPullSettings
+{
+	/// The URL of a JSON like `{"version":"1.2.0","url":"http://...","sha256":"...","size":1234567}` (the `size` is
+	/// optional). The signed image at the `url` is installed if the `version` is newer than the running one.
+	pub manifest_url: String,
+	pub check_period: Duration,
+}
+
+/// Replaces (with `Some`) or removes (with `None`) the manifest URL of the settings, which is saved by the main loop
+/// and checked from the next restart.
+pub type ManifestUrlRequests = Requests<Option<String>, ()>;
+
+/// Writes the updates to the [`OtaFlash`]. The flash isn't touched until the signed header of the image has been
+/// received and checked with the [`ImagePolicy`], and the image isn't booted unless its binary matches the header.
+pub struct OtaUpdater<F: OtaFlash>
+{
+	flash: F,
+	policy: ImagePolicy,
+	running_version: FirmwareVersion,
+	state: UpdateState,
+	/// `false` while the running firmware is an update that hasn't been marked valid yet.
+	is_running_valid: bool,
+}
+
+enum UpdateState
+{
+	Idle,
+	/// The signed header is being received.
+	ReceivingHeader
+	{
+		file: ReceivedFile,
+		header: Vec<u8>,
+	},
+	/// The header has been checked and the binary is being written to the flash.
+	WritingBinary
+	{
+		file: ReceivedFile,
+		binary: ReceivedBinary,
+	},
+	/// The image has been written and will be booted at the next restart.
+	Installed(ImageMetadata),
+}
+
+/// What has been received of the whole image (the header and the binary).
+struct ReceivedFile
+{
+	hasher: Sha256,
+	size: usize,
+	expected_size: Option<usize>,
+	expected_sha256: [u8; 32],
+}
+
+struct ReceivedBinary
+{
+	metadata: ImageMetadata,
+	hasher: Sha256,
+	size: usize,
+}
+
+/// Writes the images uploaded to the HTTP server or downloaded by [`start_pull`], without depending on the type of the
+/// flash.
+pub trait OtaWriter: Send
+{
+	fn running_version(&self) -> FirmwareVersion;
+	/// Starts receiving a signed image (check [`SIGNED_HEADER_SIZE`]) whose SHA-256 must be `expected_sha256`. The
+	/// `expected_size` is `None` if it's unknown.
+	fn begin(&mut self, expected_sha256: [u8; 32], expected_size: Option<usize>) -> Result<(), OtaError>;
+	/// Appends the `chunk` to the image. If it fails the update is aborted.
+	fn write(&mut self, chunk: &[u8]) -> Result<(), OtaError>;
+	/// Checks the size and the SHA-256 of the image and sets it to be booted at the next restart. If it fails the
+	/// update is aborted.
+	fn finish(&mut self) -> Result<(), OtaError>;
+	/// Stops writing the image (if it's being written), for example because the connection has been lost.
+	fn abort(&mut self);
+}
+
+/// The updater shared between the main loop (that marks the updates valid and restarts to boot them), the HTTP server
+/// and the thread of [`start_pull`].
+pub type SharedOtaWriter = Arc<Mutex<dyn OtaWriter>>;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum OtaError
+{
+	/// Another image is being written, or one has already been installed and the camera is about to restart.
+	Busy,
+	/// [`OtaWriter::begin`] hasn't been called.
+	NotStarted,
+	/// The running firmware must be marked valid before installing another one, otherwise the rollback wouldn't work.
+	RunningNotValid,
+	SizeMismatch
+	{
+		expected: usize,
+		received: usize,
+	},
+	HashMismatch,
+	/// The signed header of the image has been refused.
+	InvalidImage(ImageError),
+	/// The error of the [`OtaFlash`].
+	Flash(String),
+}
+
+impl<F: OtaFlash> OtaUpdater<F>
+{
+	pub fn new(mut flash: F, policy: ImagePolicy) -> Self
+	{
+		let running_version = flash.running_version().parse().unwrap_or_else(|()| {
+			log::warn!(
+				"The running version {} isn't valid, only the updates from {} are accepted",
+				flash.running_version(),
+				policy.rollback_floor
+			);
+			policy.rollback_floor
+		});
+		let is_running_valid = match flash.is_running_pending_verification()
+		{
+			Ok(is_pending_verification) => !is_pending_verification,
+			Err(error) =>
+			{
+				log::warn!("Couldn't check if the running firmware is valid: {:?}", error);
+				true
+			},
+		};
+		if !is_running_valid
+		{
+			log::info!(
+				"Running the update {}, it will be marked valid if the camera works",
+				running_version
+			);
+		}
+
+		Self {
+			flash,
+			policy,
+			running_version,
+			state: UpdateState::Idle,
+			is_running_valid,
+		}
+	}
+
+	pub fn is_running_valid(&self) -> bool
+	{
+		self.is_running_valid
+	}
+
+	/// Keeps the running firmware installed. If it fails it's tried again at the next call.
+	pub fn mark_running_valid(&mut self)
+	{
+		match self.flash.mark_running_valid()
+		{
+			Ok(()) =>
+			{
+				log::info!("Marked the firmware {} valid", self.running_version);
+				self.is_running_valid = true;
+			},
+			Err(error) => log::warn!("Couldn't mark the running firmware valid: {:?}", error),
+		}
+	}
+
+	/// Returns the metadata of the update that has been installed (so the camera must restart to boot it), if any.
+	pub fn installed_image(&self) -> Option<&ImageMetadata>
+	{
+		match &self.state
+		{
+			UpdateState::Installed(metadata) => Some(metadata),
+			_ => None,
+		}
+	}
+
+	fn receive_header(&mut self, file: ReceivedFile, mut header: Vec<u8>, chunk: &[u8])
+		-> Result<UpdateState, OtaError>
+	{
+		let header_length = chunk.len().min(SIGNED_HEADER_SIZE - header.len());
+		header.extend_from_slice(&chunk[..header_length]);
+		let Ok(header) = <[u8; SIGNED_HEADER_SIZE]>::try_from(header.as_slice())
+		else
+		{
+			return Ok(UpdateState::ReceivingHeader { file, header });
+		};
+
+		let metadata = ImageMetadata::verify(&header, &self.policy.trusted_keys).map_err(OtaError::InvalidImage)?;
+		self.policy
+			.check(&metadata, self.running_version)
+			.map_err(OtaError::InvalidImage)?;
+		log::info!("Receiving the signed firmware {}", metadata.version);
+
+		self.flash
+			.begin(Some(metadata.binary_size as usize))
+			.map_err(OtaError::flash)?;
+		let binary = ReceivedBinary {
+			metadata,
+			hasher: Sha256::new(),
+			size: 0,
+		};
+		self.write_binary(file, binary, &chunk[header_length..])
+	}
+
+	fn write_binary(
+		&mut self, file: ReceivedFile, mut binary: ReceivedBinary, chunk: &[u8],
+	) -> Result<UpdateState, OtaError>
+	{
+		let expected = binary.metadata.binary_size as usize;
+		let received = binary.size + chunk.len();
+		if received > expected
+		{
+			return Err(OtaError::SizeMismatch { expected, received });
+		}
+		binary.hasher.update(chunk);
+		binary.size = received;
+		self.flash.write(chunk).map_err(OtaError::flash)?;
+
+		Ok(UpdateState::WritingBinary { file, binary })
+	}
+
+	fn install(&mut self, file: ReceivedFile, binary: ReceivedBinary) -> Result<UpdateState, OtaError>
+	{
+		file.check()?;
+		let expected = binary.metadata.binary_size as usize;
+		if binary.size != expected
+		{
+			return Err(OtaError::SizeMismatch {
+				expected,
+				received: binary.size,
+			});
+		}
+		if binary.hasher.finalize().as_slice() != binary.metadata.binary_sha256
+		{
+			return Err(OtaError::HashMismatch);
+		}
+		self.flash.complete().map_err(OtaError::flash)?;
+
+		log::info!(
+			"Installed the firmware {}, it will be booted at the next restart",
+			binary.metadata.version
+		);
+		Ok(UpdateState::Installed(binary.metadata))
+	}
+
+	fn abort_flash(&mut self)
+	{
+		if let Err(error) = self.flash.abort()
+		{
+			log::warn!("Couldn't abort the update: {:?}", error);
+		}
+	}
+}
+
+impl<F: OtaFlash> OtaWriter for OtaUpdater<F>
+{
+	fn running_version(&self) -> FirmwareVersion
+	{
+		self.running_version
+	}
+
+	fn begin(&mut self, expected_sha256: [u8; 32], expected_size: Option<usize>) -> Result<(), OtaError>
+	{
+		if !matches!(self.state, UpdateState::Idle)
+		{
+			return Err(OtaError::Busy);
+		}
+		if !self.is_running_valid
+		{
+			return Err(OtaError::RunningNotValid);
+		}
+
+		self.state = UpdateState::ReceivingHeader {
+			file: ReceivedFile {
+				hasher: Sha256::new(),
+				size: 0,
+				expected_size,
+				expected_sha256,
+			},
+			header: Vec::with_capacity(SIGNED_HEADER_SIZE),
+		};
+
+		Ok(())
+	}
+
+	fn write(&mut self, chunk: &[u8]) -> Result<(), OtaError>
+	{
+		let state = match core::mem::replace(&mut self.state, UpdateState::Idle)
+		{
+			UpdateState::ReceivingHeader { mut file, header } => file
+				.receive(chunk)
+				.and_then(|()| self.receive_header(file, header, chunk)),
+			UpdateState::WritingBinary { mut file, binary } => file
+				.receive(chunk)
+				.and_then(|()| self.write_binary(file, binary, chunk)),
+			state =>
+			{
+				self.state = state;
+				return Err(OtaError::NotStarted);
+			},
+		};
+
+		match state
+		{
+			Ok(state) =>
+			{
+				self.state = state;
+				Ok(())
+			},
+			Err(error) =>
+			{
+				self.abort_flash();
+				Err(error)
+			},
+		}
+	}
+
+	fn finish(&mut self) -> Result<(), OtaError>
+	{
+		match core::mem::replace(&mut self.state, UpdateState::Idle)
+		{
+			// The image is shorter than its header
+			UpdateState::ReceivingHeader { .. } => Err(OtaError::InvalidImage(ImageError::NotSigned)),
+			UpdateState::WritingBinary { file, binary } => match self.install(file, binary)
+			{
+				Ok(state) =>
+				{
+					self.state = state;
+					Ok(())
+				},
+				Err(error) =>
+				{
+					self.abort_flash();
+					Err(error)
+				},
+			},
+			state =>
+			{
+				self.state = state;
+				Err(OtaError::NotStarted)
+			},
+		}
+	}
+
+	fn abort(&mut self)
+	{
+		match core::mem::replace(&mut self.state, UpdateState::Idle)
+		{
+			UpdateState::ReceivingHeader { .. } => (),
+			UpdateState::WritingBinary { .. } => self.abort_flash(),
+			state => self.state = state,
+		}
+	}
+}
+
+impl ReceivedFile
+{
+	fn receive(&mut self, chunk: &[u8]) -> Result<(), OtaError>
+	{
+		self.size += chunk.len();
+		if let Some(expected) = self.expected_size
+		{
+			if self.size > expected
+			{
+				return Err(OtaError::SizeMismatch {
+					expected,
+					received: self.size,
+				});
+			}
+		}
+		self.hasher.update(chunk);
+
+		Ok(())
+	}
+
+	fn check(self) -> Result<(), OtaError>
+	{
+		if let Some(expected) = self.expected_size
+		{
+			if self.size != expected
+			{
+				return Err(OtaError::SizeMismatch {
+					expected,
+					received: self.size,
+				});
+			}
+		}
+		if self.hasher.finalize().as_slice() != self.expected_sha256
+		{
+			return Err(OtaError::HashMismatch);
+		}
+
+		Ok(())
+	}
+}
+
+impl OtaError
+{
+	fn flash(error: impl core::fmt::Debug) -> Self
+	{
+		Self::Flash(format!("{:?}", error))
+	}
+}
+
+impl Display for OtaError
+{
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result
+	{
+		match self
+		{
+			Self::Busy => write!(f, "Another update is in progress"),
+			Self::NotStarted => write!(f, "The update hasn't been started"),
+			Self::RunningNotValid => write!(f, "The running firmware hasn't been marked valid yet"),
+			Self::SizeMismatch { expected, received } =>
+			{
+				write!(
+					f,
+					"The image should be {} bytes, but {} have been received",
+					expected, received
+				)
+			},
+			Self::HashMismatch => write!(f, "The SHA-256 of the image doesn't match"),
+			Self::InvalidImage(error) => write!(f, "{}", error),
+			Self::Flash(error) => write!(f, "Couldn't write the image: {}", error),
+		}
+	}
+}
+
+/// Parses a SHA-256 written as 64 hexadecimal digits.
+pub fn parse_sha256(hex: &str) -> Option<[u8; 32]>
+{
+	parse_hex(hex)
+}
+
+/// Parses `N` bytes written as `2 * N` hexadecimal digits, like an Ed25519 public key.
+pub fn parse_hex<const N: usize>(hex: &str) -> Option<[u8; N]>
+{
+	if hex.len() != 2 * N || !hex.is_ascii()
+	{
+		return None;
+	}
+
+	let mut bytes = [0; N];
+	for (byte, digits) in bytes.iter_mut().zip(hex.as_bytes().chunks(2))
+	{
+		*byte = u8::from_str_radix(core::str::from_utf8(digits).ok()?, 16).ok()?;
+	}
+
+	Some(bytes)
+}
+
+#[cfg(test)]
+pub(crate) mod tests
+{
+	use ed25519_dalek::SigningKey;
+
+	use super::*;
+	use crate::features::ota::signature::tests::{signed_image, signing_key, version, BOARD};
+
+	/// Keeps the image in memory.
+	#[derive(Default)]
+	pub(crate) struct FakeFlash
+	{
+		pub running_version: String,
+		pub is_pending_verification: bool,
+		/// `None` if no image is being written.
+		pub written: Option<Vec<u8>>,
+		/// The image set to be booted at the next restart.
+		pub installed: Option<Vec<u8>>,
+		/// How many times the inactive partition has been erased.
+		pub erases: usize,
+	}
+
+	impl OtaFlash for FakeFlash
+	{
+		type Error = &'static str;
+
+		fn running_version(&self) -> String
+		{
+			self.running_version.clone()
+		}
+
+		fn is_running_pending_verification(&mut self) -> Result<bool, Self::Error>
+		{
+			Ok(self.is_pending_verification)
+		}
+
+		fn mark_running_valid(&mut self) -> Result<(), Self::Error>
+		{
+			self.is_pending_verification = false;
+			Ok(())
+		}
+
+		fn begin(&mut self, _: Option<usize>) -> Result<(), Self::Error>
+		{
+			self.erases += 1;
+			self.written = Some(Vec::new());
+			Ok(())
+		}
+
+		fn write(&mut self, chunk: &[u8]) -> Result<(), Self::Error>
+		{
+			self.written.as_mut().ok_or("Not started")?.extend_from_slice(chunk);
+			Ok(())
+		}
+
+		fn complete(&mut self) -> Result<(), Self::Error>
+		{
+			self.installed = Some(self.written.take().ok_or("Not started")?);
+			Ok(())
+		}
+
+		fn abort(&mut self) -> Result<(), Self::Error>
+		{
+			self.written = None;
+			Ok(())
+		}
+	}
+
+	pub(crate) fn sha256(image: &[u8]) -> [u8; 32]
+	{
+		Sha256::digest(image).into()
+	}
+
+	/// Accepts the images of [`signing_key`] from the version 1.0.0.
+	pub(crate) fn updater(running_version: &str) -> OtaUpdater<FakeFlash>
+	{
+		updater_with_flash(FakeFlash {
+			running_version: running_version.to_string(),
+			..Default::default()
+		})
+	}
+
+	pub(crate) fn updater_with_flash(flash: FakeFlash) -> OtaUpdater<FakeFlash>
+	{
+		OtaUpdater::new(
+			flash,
+			ImagePolicy {
+				board: BOARD.to_string(),
+				trusted_keys: vec![signing_key().verifying_key()],
+				rollback_floor: version("1.0.0"),
+			},
+		)
+	}
+
+	/// Writes the `image` in chunks that don't line up with the header.
+	fn upload(updater: &mut OtaUpdater<FakeFlash>, image: &[u8]) -> Result<(), OtaError>
+	{
+		updater.begin(sha256(image), Some(image.len()))?;
+		for chunk in image.chunks(50)
+		{
+			updater.write(chunk)?;
+		}
+		updater.finish()
+	}
+
+	#[test]
+	fn signed_image_is_installed_without_its_header()
+	{
+		let binary = vec![0xE9; 1000];
+		let image = signed_image(&binary, "1.1.0", &signing_key());
+		let mut updater = updater("1.0.0");
+
+		updater.begin(sha256(&image), Some(image.len())).unwrap();
+		assert_eq!(updater.begin(sha256(&image), None), Err(OtaError::Busy));
+		for chunk in image.chunks(50)
+		{
+			updater.write(chunk).unwrap();
+		}
+		updater.finish().unwrap();
+
+		assert_eq!(
+			updater.installed_image().map(|metadata| metadata.version),
+			Some(version("1.1.0"))
+		);
+		assert_eq!(updater.flash.installed, Some(binary));
+		assert_eq!(updater.begin(sha256(&image), None), Err(OtaError::Busy));
+	}
+
+	#[test]
+	fn corrupted_image_is_aborted()
+	{
+		let mut image = signed_image(&[0xE9; 1000], "1.1.0", &signing_key());
+		let mut updater = updater("1.0.0");
+
+		// The binary is changed after it has been signed
+		*image.last_mut().unwrap() = 0;
+		assert_eq!(upload(&mut updater, &image), Err(OtaError::HashMismatch));
+		assert_eq!(updater.flash.written, None);
+		assert_eq!(updater.flash.installed, None);
+
+		updater.begin(sha256(&image), Some(image.len() - 1)).unwrap();
+		assert_eq!(
+			updater.write(&image),
+			Err(OtaError::SizeMismatch {
+				expected: image.len() - 1,
+				received: image.len()
+			})
+		);
+		assert_eq!(updater.write(&image), Err(OtaError::NotStarted));
+		assert!(updater.installed_image().is_none());
+	}
+
+	#[test]
+	fn unsigned_or_downgraded_images_never_touch_the_flash()
+	{
+		let mut updater = updater("1.2.0");
+
+		assert_eq!(
+			upload(&mut updater, &[0xE9; 1000]),
+			Err(OtaError::InvalidImage(ImageError::NotSigned))
+		);
+		assert_eq!(
+			upload(&mut updater, &signed_image(b"binary", "1.1.0", &signing_key())),
+			Err(OtaError::InvalidImage(ImageError::Downgrade {
+				version: version("1.1.0"),
+				running_version: version("1.2.0")
+			}))
+		);
+		let other_key = SigningKey::from_bytes(&[8; 32]);
+		assert_eq!(
+			upload(&mut updater, &signed_image(b"binary", "1.3.0", &other_key)),
+			Err(OtaError::InvalidImage(ImageError::UntrustedSignature))
+		);
+		// Shorter than the header
+		assert_eq!(
+			upload(&mut updater, &[0xE9; 10]),
+			Err(OtaError::InvalidImage(ImageError::NotSigned))
+		);
+
+		assert_eq!(updater.flash.erases, 0);
+		upload(&mut updater, &signed_image(b"binary", "1.3.0", &signing_key())).unwrap();
+		assert_eq!(updater.flash.erases, 1);
+	}
+
+	#[test]
+	fn update_is_refused_until_the_running_one_is_valid()
+	{
+		let mut updater = updater_with_flash(FakeFlash {
+			running_version: "1.0.0".to_string(),
+			is_pending_verification: true,
+			..Default::default()
+		});
+
+		assert!(!updater.is_running_valid());
+		assert_eq!(updater.begin([0; 32], None), Err(OtaError::RunningNotValid));
+
+		updater.mark_running_valid();
+		assert!(updater.is_running_valid());
+		assert!(!updater.flash.is_pending_verification);
+		updater.begin([0; 32], None).unwrap();
+	}
+
+	#[test]
+	fn sha256_is_parsed_from_hex()
+	{
+		let hex = "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08";
+
+		assert_eq!(parse_sha256(hex), Some(sha256(b"test")));
+		assert_eq!(parse_sha256(&hex[1..]), None);
+		assert_eq!(parse_sha256(&hex.replace('f', "g")), None);
+	}
+}
@@ -0,0 +1,304 @@
+use core::time::Duration;
+
+use serde::Deserialize;
+
+use super::{parse_sha256, signature::FirmwareVersion, OtaError, OtaPullSettings, SharedOtaWriter};
+use crate::configuration::peripherals::http_client::HttpClient;
+
+/// The first check is delayed, so that the running firmware has usually been marked valid (otherwise the update is
+/// refused, and checked again after [`RETRY_DELAY`]).
+const FIRST_CHECK_DELAY: Duration = Duration::from_secs(60);
+/// How long to wait before checking again when the updater couldn't start the update yet.
+const RETRY_DELAY: Duration = Duration::from_secs(30);
+/// The TLS handshake of the HTTPS requests needs most of it.
+const STACK_SIZE: usize = 10 * 1024;
+/// The manifest is refused if it's bigger than this.
+const MAX_MANIFEST_SIZE: usize = 2 * 1024;
+const OK_STATUS: u16 = 200;
+
+#[derive(Deserialize, Debug)]
+struct Manifest
+{
+	version: String,
+	url: String,
+	sha256: String,
+	#[serde(default)]
+	size: Option<usize>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum CheckError
+{
+	/// The running firmware hasn't been marked valid yet, or another update is being received, so the check is
+	/// repeated after [`RETRY_DELAY`] instead of the check period.
+	NotReady(OtaError),
+	Failed(String),
+}
+
+impl core::fmt::Display for CheckError
+{
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result
+	{
+		match self
+		{
+			Self::NotReady(error) => write!(f, "{}", error),
+			Self::Failed(error) => f.write_str(error),
+		}
+	}
+}
+
+impl From<String> for CheckError
+{
+	fn from(error: String) -> Self
+	{
+		Self::Failed(error)
+	}
+}
+
+impl From<&str> for CheckError
+{
+	fn from(error: &str) -> Self
+	{
+		Self::Failed(error.to_string())
+	}
+}
+
+/// Starts a thread that periodically downloads the manifest of the [`OtaPullSettings`] with the `client`, and installs
+/// the update it points to if its version is newer than the running one. The main loop then restarts to boot it.
+pub fn start_pull<H: HttpClient>(
+	mut client: H, ota: SharedOtaWriter, settings: OtaPullSettings,
+) -> Result<(), std::io::Error>
+{
+	std::thread::Builder::new()
+		.name("ota-pull".to_string())
+		.stack_size(STACK_SIZE)
+		.spawn(move || {
+			std::thread::sleep(FIRST_CHECK_DELAY);
+			loop
+			{
+				let delay = match check_for_update(&mut client, &ota, &settings.manifest_url)
+				{
+					Ok(_) => settings.check_period,
+					Err(CheckError::NotReady(error)) =>
+					{
+						log::info!("Checking for firmware updates again soon: {}", error);
+						RETRY_DELAY.min(settings.check_period)
+					},
+					Err(error) =>
+					{
+						log::warn!("Couldn't check for firmware updates: {}", error);
+						settings.check_period
+					},
+				};
+				std::thread::sleep(delay);
+			}
+		})?;
+
+	Ok(())
+}
+
+/// Returns `Ok(true)` if an update has been installed.
+fn check_for_update<H: HttpClient>(
+	client: &mut H, ota: &SharedOtaWriter, manifest_url: &str,
+) -> Result<bool, CheckError>
+{
+	let mut manifest = Vec::new();
+	let status = client
+		.get(manifest_url, &mut |chunk| {
+			manifest.extend_from_slice(chunk);
+			manifest.len() <= MAX_MANIFEST_SIZE
+		})
+		.map_err(|error| format!("{:?}", error))?;
+	if status != OK_STATUS
+	{
+		return Err(CheckError::Failed(format!(
+			"The manifest request responded with status {}",
+			status
+		)));
+	}
+	if manifest.len() > MAX_MANIFEST_SIZE
+	{
+		return Err("The manifest is too big".into());
+	}
+	let manifest: Manifest = serde_json::from_slice(&manifest).map_err(|error| error.to_string())?;
+
+	let version: FirmwareVersion = manifest
+		.version
+		.parse()
+		.map_err(|()| format!("The version {} of the manifest isn't valid", manifest.version))?;
+	let running_version = ota.lock().running_version();
+	if version <= running_version
+	{
+		log::debug!("The firmware {} is up to date", running_version);
+		return Ok(false);
+	}
+	let sha256 = parse_sha256(&manifest.sha256).ok_or("The SHA-256 of the manifest isn't valid")?;
+
+	log::info!("Downloading the firmware {} from {}", version, manifest.url);
+	ota.lock().begin(sha256, manifest.size).map_err(|error| match error
+	{
+		OtaError::RunningNotValid | OtaError::Busy => CheckError::NotReady(error),
+		error => CheckError::Failed(error.to_string()),
+	})?;
+	let mut write_result = Ok(());
+	let status = client.get(&manifest.url, &mut |chunk| {
+		// The lock is released between the chunks, so that the main loop can check if the update has been installed
+		write_result = ota.lock().write(chunk);
+		write_result.is_ok()
+	});
+	let result = match (status, write_result)
+	{
+		(Err(error), _) => Err(format!("{:?}", error)),
+		(Ok(status), _) if status != OK_STATUS => Err(format!("The image request responded with status {}", status)),
+		(Ok(_), Err(error)) => Err(error.to_string()),
+		(Ok(_), Ok(())) => ota.lock().finish().map_err(|error| error.to_string()),
+	};
+	if result.is_err()
+	{
+		ota.lock().abort();
+	}
+
+	result.map(|()| true).map_err(CheckError::Failed)
+}
+
+#[cfg(test)]
+mod tests
+{
+	use std::{collections::HashMap, sync::Arc};
+
+	use spin::Mutex;
+
+	use super::*;
+	use crate::features::ota::{
+		signature::tests::{signed_image, signing_key},
+		tests::{sha256, updater, updater_with_flash, FakeFlash},
+		OtaUpdater,
+	};
+
+	const MANIFEST_URL: &str = "http://updates.local/manifest.json";
+	const IMAGE_URL: &str = "http://updates.local/firmware-1.1.0.bin";
+	const BINARY: &[u8] = b"the firmware 1.1.0";
+
+	/// Responds with the bodies of the URLs, in chunks of 4 bytes.
+	struct FakeServer(HashMap<&'static str, Vec<u8>>);
+
+	impl HttpClient for FakeServer
+	{
+		type Error = ();
+
+		/// The updates are only downloaded with GET requests.
+		fn post(&mut self, _: &str, _: &str, _: &[u8]) -> Result<u16, Self::Error>
+		{
+			Err(())
+		}
+
+		fn get(&mut self, url: &str, on_body_chunk: &mut dyn FnMut(&[u8]) -> bool) -> Result<u16, Self::Error>
+		{
+			let Some(body) = self.0.get(url)
+			else
+			{
+				return Ok(404);
+			};
+			for chunk in body.chunks(4)
+			{
+				if !on_body_chunk(chunk)
+				{
+					break;
+				}
+			}
+			Ok(200)
+		}
+	}
+
+	fn image() -> Vec<u8>
+	{
+		signed_image(BINARY, "1.1.0", &signing_key())
+	}
+
+	fn server(image_sha256: [u8; 32]) -> FakeServer
+	{
+		let image = image();
+		let hex: String = image_sha256.iter().map(|byte| format!("{:02x}", byte)).collect();
+		let manifest = format!(
+			r#"{{"version":"1.1.0","url":"{}","sha256":"{}","size":{}}}"#,
+			IMAGE_URL,
+			hex,
+			image.len()
+		);
+		FakeServer(HashMap::from([
+			(MANIFEST_URL, manifest.into_bytes()),
+			(IMAGE_URL, image),
+		]))
+	}
+
+	fn ota(running_version: &str) -> (Arc<Mutex<OtaUpdater<FakeFlash>>>, SharedOtaWriter)
+	{
+		let updater = Arc::new(Mutex::new(updater(running_version)));
+		let writer: SharedOtaWriter = updater.clone();
+		(updater, writer)
+	}
+
+	#[test]
+	fn newer_version_is_installed()
+	{
+		let (updater, writer) = ota("1.0.0");
+
+		assert_eq!(
+			check_for_update(&mut server(sha256(&image())), &writer, MANIFEST_URL),
+			Ok(true)
+		);
+		assert!(updater.lock().installed_image().is_some());
+		assert_eq!(updater.lock().flash.installed.as_deref(), Some(BINARY));
+	}
+
+	#[test]
+	fn same_or_older_version_is_skipped()
+	{
+		for running_version in ["1.1.0", "1.2.0"]
+		{
+			let (updater, writer) = ota(running_version);
+
+			assert_eq!(
+				check_for_update(&mut server(sha256(&image())), &writer, MANIFEST_URL),
+				Ok(false)
+			);
+			assert_eq!(updater.lock().flash.erases, 0);
+		}
+	}
+
+	#[test]
+	fn update_is_retried_until_the_running_firmware_is_valid()
+	{
+		let updater = Arc::new(Mutex::new(updater_with_flash(FakeFlash {
+			running_version: "1.0.0".to_string(),
+			is_pending_verification: true,
+			..Default::default()
+		})));
+		let writer: SharedOtaWriter = updater.clone();
+
+		assert_eq!(
+			check_for_update(&mut server(sha256(&image())), &writer, MANIFEST_URL),
+			Err(CheckError::NotReady(OtaError::RunningNotValid))
+		);
+		updater.lock().mark_running_valid();
+		assert_eq!(
+			check_for_update(&mut server(sha256(&image())), &writer, MANIFEST_URL),
+			Ok(true)
+		);
+	}
+
+	#[test]
+	fn image_with_another_hash_is_aborted()
+	{
+		let (updater, writer) = ota("1.0.0");
+
+		assert!(check_for_update(&mut server([0; 32]), &writer, MANIFEST_URL).is_err());
+		assert!(updater.lock().installed_image().is_none());
+		assert_eq!(updater.lock().flash.written, None);
+		// The next check can start again
+		assert_eq!(
+			check_for_update(&mut server(sha256(&image())), &writer, MANIFEST_URL),
+			Ok(true)
+		);
+	}
+}
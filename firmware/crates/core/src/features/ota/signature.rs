@@ -0,0 +1,399 @@
+use core::{fmt::Display, str::FromStr};
+
+use ed25519_dalek::{Signature, Signer};
+pub use ed25519_dalek::{SigningKey, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// Identifies the header of a signed image.
+const MAGIC: [u8; 8] = *b"CAMFWSG1";
+/// The name of the board is padded with zeros to this size.
+const BOARD_CAPACITY: usize = 32;
+const VERSION_SIZE: usize = 6;
+/// Everything before the signature, which is what's signed.
+const SIGNED_SIZE: usize = MAGIC.len() + BOARD_CAPACITY + 2 * VERSION_SIZE + 4 + 32;
+/// A signed image is this header followed by the binary to write to the flash.
+pub const SIGNED_HEADER_SIZE: usize = SIGNED_SIZE + Signature::BYTE_SIZE;
+
+/// A version like `1.2.0`, compared field by field.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FirmwareVersion
+{
+	pub major: u16,
+	pub minor: u16,
+	pub patch: u16,
+}
+
+/// What the signature of an image guarantees about its binary.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ImageMetadata
+{
+	/// The board the binary has been built for, like `esp32-cam`.
+	pub board: String,
+	pub version: FirmwareVersion,
+	/// Once this image has been installed, the images older than this version are refused.
+	pub min_rollback_version: FirmwareVersion,
+	pub binary_size: u32,
+	pub binary_sha256: [u8; 32],
+}
+
+/// Decides which signed images can be installed on this camera.
+pub struct ImagePolicy
+{
+	/// The board the camera is running on.
+	pub board: String,
+	/// The images must be signed with one of these keys.
+	pub trusted_keys: Vec<VerifyingKey>,
+	/// The highest `min_rollback_version` of the images installed so far.
+	pub rollback_floor: FirmwareVersion,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ImageError
+{
+	/// The image doesn't start with a signed header.
+	NotSigned,
+	/// The signature isn't valid for any of the trusted keys.
+	UntrustedSignature,
+	WrongBoard
+	{
+		board: String
+	},
+	/// The image is older than the running firmware.
+	Downgrade
+	{
+		version: FirmwareVersion,
+		running_version: FirmwareVersion,
+	},
+	/// The image is older than the `min_rollback_version` of an image that has been installed.
+	BelowRollbackFloor
+	{
+		version: FirmwareVersion,
+		rollback_floor: FirmwareVersion,
+	},
+}
+
+impl ImageMetadata
+{
+	/// The metadata of the `binary`, whose size and SHA-256 are computed.
+	pub fn of_binary(
+		board: String, version: FirmwareVersion, min_rollback_version: FirmwareVersion, binary: &[u8],
+	) -> Option<Self>
+	{
+		Some(Self {
+			board,
+			version,
+			min_rollback_version,
+			binary_size: binary.len().try_into().ok()?,
+			binary_sha256: Sha256::digest(binary).into(),
+		})
+	}
+
+	/// Returns the header that must precede the binary in the signed image. Returns `None` if the name of the board is
+	/// longer than 32 bytes.
+	pub fn sign(&self, key: &SigningKey) -> Option<[u8; SIGNED_HEADER_SIZE]>
+	{
+		let signed = self.signed_bytes()?;
+		let mut header = [0; SIGNED_HEADER_SIZE];
+		header[..SIGNED_SIZE].copy_from_slice(&signed);
+		header[SIGNED_SIZE..].copy_from_slice(&key.sign(&signed).to_bytes());
+
+		Some(header)
+	}
+
+	/// Returns the metadata in the `header` if it's signed by one of the `trusted_keys`.
+	pub fn verify(header: &[u8; SIGNED_HEADER_SIZE], trusted_keys: &[VerifyingKey]) -> Result<Self, ImageError>
+	{
+		let (signed, signature) = header.split_at(SIGNED_SIZE);
+		if signed[..MAGIC.len()] != MAGIC
+		{
+			return Err(ImageError::NotSigned);
+		}
+		let signature = Signature::from_slice(signature).map_err(|_| ImageError::UntrustedSignature)?;
+		if !trusted_keys
+			.iter()
+			.any(|key| key.verify_strict(signed, &signature).is_ok())
+		{
+			return Err(ImageError::UntrustedSignature);
+		}
+
+		let mut offset = MAGIC.len();
+		let mut take = move |size: usize| {
+			offset += size;
+			&signed[offset - size..offset]
+		};
+		let board = take(BOARD_CAPACITY);
+		let board_length = board.iter().position(|&byte| byte == 0).unwrap_or(BOARD_CAPACITY);
+		let version = FirmwareVersion::from_bytes(take(VERSION_SIZE));
+		let min_rollback_version = FirmwareVersion::from_bytes(take(VERSION_SIZE));
+		let binary_size = u32::from_le_bytes(take(4).try_into().expect("The size is 4 bytes"));
+		let binary_sha256 = take(32).try_into().expect("The SHA-256 is 32 bytes");
+
+		Ok(Self {
+			// The signature guarantees it has been written by `sign`
+			board: String::from_utf8_lossy(&board[..board_length]).into_owned(),
+			version,
+			min_rollback_version,
+			binary_size,
+			binary_sha256,
+		})
+	}
+
+	fn signed_bytes(&self) -> Option<[u8; SIGNED_SIZE]>
+	{
+		if self.board.len() > BOARD_CAPACITY
+		{
+			return None;
+		}
+
+		let mut board = [0; BOARD_CAPACITY];
+		board[..self.board.len()].copy_from_slice(self.board.as_bytes());
+		let mut signed = Vec::with_capacity(SIGNED_SIZE);
+		signed.extend_from_slice(&MAGIC);
+		signed.extend_from_slice(&board);
+		signed.extend_from_slice(&self.version.to_bytes());
+		signed.extend_from_slice(&self.min_rollback_version.to_bytes());
+		signed.extend_from_slice(&self.binary_size.to_le_bytes());
+		signed.extend_from_slice(&self.binary_sha256);
+
+		signed.try_into().ok()
+	}
+}
+
+impl ImagePolicy
+{
+	/// Checks that the image can replace the running firmware.
+	pub fn check(&self, metadata: &ImageMetadata, running_version: FirmwareVersion) -> Result<(), ImageError>
+	{
+		if metadata.board != self.board
+		{
+			return Err(ImageError::WrongBoard {
+				board: metadata.board.clone(),
+			});
+		}
+		if metadata.version < running_version
+		{
+			return Err(ImageError::Downgrade {
+				version: metadata.version,
+				running_version,
+			});
+		}
+		if metadata.version < self.rollback_floor
+		{
+			return Err(ImageError::BelowRollbackFloor {
+				version: metadata.version,
+				rollback_floor: self.rollback_floor,
+			});
+		}
+
+		Ok(())
+	}
+}
+
+impl FirmwareVersion
+{
+	fn to_bytes(self) -> [u8; VERSION_SIZE]
+	{
+		let mut bytes = [0; VERSION_SIZE];
+		for (bytes, field) in bytes.chunks_exact_mut(2).zip([self.major, self.minor, self.patch])
+		{
+			bytes.copy_from_slice(&field.to_le_bytes());
+		}
+		bytes
+	}
+
+	fn from_bytes(bytes: &[u8]) -> Self
+	{
+		let field = |index: usize| u16::from_le_bytes([bytes[2 * index], bytes[2 * index + 1]]);
+		Self {
+			major: field(0),
+			minor: field(1),
+			patch: field(2),
+		}
+	}
+}
+
+impl FromStr for FirmwareVersion
+{
+	type Err = ();
+
+	/// Parses a version like `1.2.0`.
+	fn from_str(version: &str) -> Result<Self, Self::Err>
+	{
+		let mut fields = version.split('.').map(|field| field.parse().map_err(|_| ()));
+		let version = Self {
+			major: fields.next().ok_or(())??,
+			minor: fields.next().ok_or(())??,
+			patch: fields.next().ok_or(())??,
+		};
+		match fields.next()
+		{
+			Some(_) => Err(()),
+			None => Ok(version),
+		}
+	}
+}
+
+impl Display for FirmwareVersion
+{
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result
+	{
+		write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+	}
+}
+
+impl Display for ImageError
+{
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result
+	{
+		match self
+		{
+			Self::NotSigned => write!(f, "The image isn't signed"),
+			Self::UntrustedSignature => write!(f, "The image isn't signed with a trusted key"),
+			Self::WrongBoard { board } => write!(f, "The image has been built for another board: {}", board),
+			Self::Downgrade {
+				version,
+				running_version,
+			} => write!(
+				f,
+				"The image {} is older than the running firmware {}",
+				version, running_version
+			),
+			Self::BelowRollbackFloor {
+				version,
+				rollback_floor,
+			} => write!(
+				f,
+				"The image {} is older than the minimum allowed {}",
+				version, rollback_floor
+			),
+		}
+	}
+}
+
+#[cfg(test)]
+pub(crate) mod tests
+{
+	use super::*;
+
+	pub(crate) const BOARD: &str = "esp32-cam";
+
+	pub(crate) fn signing_key() -> SigningKey
+	{
+		SigningKey::from_bytes(&[7; 32])
+	}
+
+	pub(crate) fn version(version: &str) -> FirmwareVersion
+	{
+		version.parse().unwrap()
+	}
+
+	/// Returns the signed image of the `binary`.
+	pub(crate) fn signed_image(binary: &[u8], image_version: &str, key: &SigningKey) -> Vec<u8>
+	{
+		let metadata =
+			ImageMetadata::of_binary(BOARD.to_string(), version(image_version), version("1.0.0"), binary).unwrap();
+		let mut image = metadata.sign(key).unwrap().to_vec();
+		image.extend_from_slice(binary);
+		image
+	}
+
+	fn header(image: &[u8]) -> &[u8; SIGNED_HEADER_SIZE]
+	{
+		image[..SIGNED_HEADER_SIZE].try_into().unwrap()
+	}
+
+	#[test]
+	fn signed_metadata_is_verified()
+	{
+		let image = signed_image(b"binary", "1.2.3", &signing_key());
+
+		let metadata = ImageMetadata::verify(header(&image), &[signing_key().verifying_key()]).unwrap();
+
+		assert_eq!(metadata.board, BOARD);
+		assert_eq!(metadata.version, version("1.2.3"));
+		assert_eq!(metadata.min_rollback_version, version("1.0.0"));
+		assert_eq!(metadata.binary_size, 6);
+		assert_eq!(metadata.binary_sha256, <[u8; 32]>::from(Sha256::digest(b"binary")));
+	}
+
+	#[test]
+	fn tampered_or_untrusted_images_are_refused()
+	{
+		let trusted_keys = [signing_key().verifying_key()];
+		let mut image = signed_image(b"binary", "1.2.3", &signing_key());
+
+		let other_key = SigningKey::from_bytes(&[8; 32]);
+		let untrusted_image = signed_image(b"binary", "1.2.3", &other_key);
+		assert_eq!(
+			ImageMetadata::verify(header(&untrusted_image), &trusted_keys),
+			Err(ImageError::UntrustedSignature)
+		);
+
+		// The version is changed to 2.2.3
+		image[MAGIC.len() + BOARD_CAPACITY] = 2;
+		assert_eq!(
+			ImageMetadata::verify(header(&image), &trusted_keys),
+			Err(ImageError::UntrustedSignature)
+		);
+
+		image[0] = b'X';
+		assert_eq!(
+			ImageMetadata::verify(header(&image), &trusted_keys),
+			Err(ImageError::NotSigned)
+		);
+	}
+
+	#[test]
+	fn policy_refuses_other_boards_and_downgrades()
+	{
+		let policy = ImagePolicy {
+			board: BOARD.to_string(),
+			trusted_keys: Vec::new(),
+			rollback_floor: version("1.1.0"),
+		};
+		let metadata = |board: &str, image_version: &str| {
+			ImageMetadata::of_binary(board.to_string(), version(image_version), version("1.0.0"), b"").unwrap()
+		};
+
+		assert_eq!(policy.check(&metadata(BOARD, "1.2.0"), version("1.2.0")), Ok(()));
+		assert_eq!(policy.check(&metadata(BOARD, "1.10.0"), version("1.2.0")), Ok(()));
+		assert_eq!(
+			policy.check(&metadata("esp32-s3", "1.3.0"), version("1.2.0")),
+			Err(ImageError::WrongBoard {
+				board: "esp32-s3".to_string()
+			})
+		);
+		assert_eq!(
+			policy.check(&metadata(BOARD, "1.1.9"), version("1.2.0")),
+			Err(ImageError::Downgrade {
+				version: version("1.1.9"),
+				running_version: version("1.2.0")
+			})
+		);
+		// After a rollback to 1.0.0
+		assert_eq!(
+			policy.check(&metadata(BOARD, "1.0.5"), version("1.0.0")),
+			Err(ImageError::BelowRollbackFloor {
+				version: version("1.0.5"),
+				rollback_floor: version("1.1.0")
+			})
+		);
+	}
+
+	#[test]
+	fn version_is_parsed()
+	{
+		assert_eq!(
+			"1.20.3".parse(),
+			Ok(FirmwareVersion {
+				major: 1,
+				minor: 20,
+				patch: 3
+			})
+		);
+		assert_eq!("1.2".parse::<FirmwareVersion>(), Err(()));
+		assert_eq!("1.2.3.4".parse::<FirmwareVersion>(), Err(()));
+		assert_eq!("1.2.x".parse::<FirmwareVersion>(), Err(()));
+	}
+}
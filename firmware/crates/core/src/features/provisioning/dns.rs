@@ -0,0 +1,150 @@
+use std::{
+	io,
+	net::{Ipv4Addr, UdpSocket},
+};
+
+const PORT: u16 = 53;
+const STACK_SIZE: usize = 6 * 1024;
+/// Big enough for any query made over UDP.
+const MAX_PACKET_SIZE: usize = 512;
+const HEADER_SIZE: usize = 12;
+/// How long the clients can cache the answers, short so that they stop using them soon after the camera leaves the
+/// setup mode.
+const TTL_SECONDS: u32 = 10;
+
+const TYPE_A: u16 = 1;
+const CLASS_IN: u16 = 1;
+
+/// Starts a DNS server that answers every query for an IPv4 address with `ip` (the one of the setup access point), so
+/// that the devices connected to the access point open the setup page whatever address they try to reach.
+pub fn start_captive_dns(ip: Ipv4Addr) -> io::Result<()>
+{
+	let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, PORT))?;
+	std::thread::Builder::new()
+		.name("captive-dns".to_string())
+		.stack_size(STACK_SIZE)
+		.spawn(move || {
+			let mut buffer = [0; MAX_PACKET_SIZE];
+			loop
+			{
+				match socket.recv_from(&mut buffer)
+				{
+					Ok((length, sender)) =>
+					{
+						if let Some(response) = answer(&buffer[..length], ip)
+						{
+							if let Err(error) = socket.send_to(&response, sender)
+							{
+								log::warn!("Couldn't answer a DNS query: {}", error);
+							}
+						}
+					},
+					Err(error) => log::warn!("Couldn't receive a DNS query: {}", error),
+				}
+			}
+		})?;
+
+	Ok(())
+}
+
+/// Returns the response to the `query`, or `None` if it isn't a standard query with a single question. The questions
+/// about anything other than an IPv4 address are answered without records.
+fn answer(query: &[u8], ip: Ipv4Addr) -> Option<Vec<u8>>
+{
+	let header = query.get(..HEADER_SIZE)?;
+	let is_response = header[2] & 0x80 != 0;
+	let opcode = (header[2] >> 3) & 0x0F;
+	let questions_count = u16::from_be_bytes([header[4], header[5]]);
+	if is_response || opcode != 0 || questions_count != 1
+	{
+		return None;
+	}
+
+	// The name is a sequence of labels, each prefixed with its length, ending with an empty one
+	let mut question_end = HEADER_SIZE;
+	loop
+	{
+		let label_length = *query.get(question_end)? as usize;
+		question_end += 1 + label_length;
+		if label_length == 0
+		{
+			break;
+		}
+	}
+	let question_type = u16::from_be_bytes([*query.get(question_end)?, *query.get(question_end + 1)?]);
+	let question_class = u16::from_be_bytes([*query.get(question_end + 2)?, *query.get(question_end + 3)?]);
+	question_end += 4;
+	let is_answered = question_type == TYPE_A && question_class == CLASS_IN;
+
+	let mut response = Vec::with_capacity(question_end + 16);
+	// Same ID
+	response.extend_from_slice(&header[..2]);
+	// Response, authoritative answer, same "recursion desired" and "recursion available"
+	response.extend_from_slice(&[0x84 | (header[2] & 0x01), 0x80]);
+	response.extend_from_slice(&1_u16.to_be_bytes());
+	response.extend_from_slice(&(is_answered as u16).to_be_bytes());
+	response.extend_from_slice(&[0, 0, 0, 0]);
+	response.extend_from_slice(&query[HEADER_SIZE..question_end]);
+
+	if is_answered
+	{
+		// The name is a pointer to the one of the question
+		response.extend_from_slice(&[0xC0, HEADER_SIZE as u8]);
+		response.extend_from_slice(&TYPE_A.to_be_bytes());
+		response.extend_from_slice(&CLASS_IN.to_be_bytes());
+		response.extend_from_slice(&TTL_SECONDS.to_be_bytes());
+		response.extend_from_slice(&4_u16.to_be_bytes());
+		response.extend_from_slice(&ip.octets());
+	}
+
+	Some(response)
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	const IP: Ipv4Addr = Ipv4Addr::new(192, 168, 71, 1);
+
+	fn query(question_type: u16) -> Vec<u8>
+	{
+		let mut query = vec![0x12, 0x34, 0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0];
+		query.extend_from_slice(b"\x07example\x03com\x00");
+		query.extend_from_slice(&question_type.to_be_bytes());
+		query.extend_from_slice(&CLASS_IN.to_be_bytes());
+		query
+	}
+
+	#[test]
+	fn ipv4_queries_are_answered_with_the_access_point_address()
+	{
+		let query = query(TYPE_A);
+		let response = answer(&query, IP).unwrap();
+
+		assert_eq!(response[..2], [0x12, 0x34]);
+		assert_eq!(response[2] & 0x80, 0x80);
+		// 1 question and 1 answer
+		assert_eq!(response[4..8], [0, 1, 0, 1]);
+		assert_eq!(response[HEADER_SIZE..query.len()], query[HEADER_SIZE..]);
+		assert_eq!(response[response.len() - 4..], IP.octets());
+	}
+
+	#[test]
+	fn other_queries_are_answered_without_records()
+	{
+		// AAAA
+		let response = answer(&query(28), IP).unwrap();
+		assert_eq!(response[4..8], [0, 1, 0, 0]);
+		assert_eq!(response.len(), query(28).len());
+	}
+
+	#[test]
+	fn malformed_queries_are_ignored()
+	{
+		let query = query(TYPE_A);
+
+		assert_eq!(answer(&query[..HEADER_SIZE + 5], IP), None);
+		assert_eq!(answer(&query[..4], IP), None);
+	}
+}
@@ -0,0 +1,693 @@
+mod dns;
+
+use core::{
+	fmt::{Debug, Formatter},
+	time::Duration,
+};
+use std::time::Instant;
+
+pub use dns::start_captive_dns;
+use embedded_hal::digital::InputPin;
+use embedded_svc::wifi::{
+	AccessPointConfiguration, AccessPointInfo, AuthMethod, ClientConfiguration, Configuration, Wifi,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::features::{connectivity::prioritize_network, requests::Requests};
+
+const SSID_CAPACITY: usize = 32;
+const PASSWORD_CAPACITY: usize = 64;
+/// How long the networks found by a scan are returned to the next scan requests, because a scan blocks the main loop
+/// (that owns the WiFi driver) for a few seconds.
+const SCAN_CACHE_DURATION: Duration = Duration::from_secs(15);
+
+/// Configures how the camera is set up when it doesn't know which WiFi network to connect to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProvisioningSettings
+{
+	/// The SSID of the setup access point is this followed by the last 2 bytes of the MAC address, like
+	/// `ESP32-CAM-setup-0A1B`.
+	pub access_point_ssid_prefix: String,
+	/// `None` if the setup access point is open.
+	pub access_point_password: Option<String>,
+	/// How long the setup button must be held to return to the setup mode.
+	pub button_long_press: Duration,
+}
+
+/// A WiFi network the camera connects to, saved in the [`Settings`](crate::features::settings::Settings) by the
+/// setup page.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct WifiCredentials
+{
+	pub ssid: String,
+	/// Empty if the network is open.
+	pub password: String,
+	/// `None` picks it from the password: open if it's empty, otherwise at least WPA2.
+	#[serde(default)]
+	pub auth_method: Option<WifiAuthMethod>,
+}
+
+/// The security of a network, which is the minimum one accepted when connecting to it.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum WifiAuthMethod
+{
+	Open,
+	Wpa2Personal,
+	Wpa3Personal,
+	/// WPA2 and WPA3 in transition mode.
+	Wpa2Wpa3Personal,
+}
+
+/// Made by the setup page to the main loop, which owns the WiFi driver.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum WifiRequest
+{
+	Scan,
+	/// Saves the network with the highest priority and restarts in station mode.
+	SaveCredentials(WifiCredentials),
+	/// Forgets all the networks and restarts in setup mode.
+	EnterSetupMode,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum WifiResponse
+{
+	/// Sorted from the strongest signal, without duplicates.
+	Networks(Vec<WifiNetwork>),
+	ScanFailed,
+	Restarting,
+}
+
+/// A network found by a scan.
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct WifiNetwork
+{
+	pub ssid: String,
+	pub rssi_dbm: i8,
+	/// `false` if the network doesn't need a password.
+	pub secure: bool,
+}
+
+pub type WifiRequests = Requests<WifiRequest, WifiResponse>;
+
+/// Executes the [`WifiRequest`]s for the main loop.
+#[derive(Default)]
+pub struct WifiProvisioning
+{
+	/// The networks found by the last successful scan, and when it was made.
+	last_scan: Option<(Instant, Vec<WifiNetwork>)>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum InvalidWifiCredentials
+{
+	/// It must be from 1 to 32 bytes long.
+	Ssid,
+	/// It must be empty or from 8 to 64 bytes long.
+	Password,
+	/// The password must be empty if and only if the network is open.
+	AuthMethod,
+}
+
+/// Reports when the setup button has been held for the long press.
+pub struct SetupButton<P: InputPin>
+{
+	/// It's low while the button is pressed.
+	pin: P,
+	long_press: Duration,
+	/// `None` if the button isn't pressed.
+	pressed_since: Option<Instant>,
+	/// If the current press has already been reported, so that holding the button reports it once.
+	has_reported: bool,
+}
+
+impl WifiCredentials
+{
+	pub fn validate(&self) -> Result<(), InvalidWifiCredentials>
+	{
+		if self.ssid.is_empty() || self.ssid.len() > SSID_CAPACITY
+		{
+			return Err(InvalidWifiCredentials::Ssid);
+		}
+		if !self.password.is_empty() && !(8..=PASSWORD_CAPACITY).contains(&self.password.len())
+		{
+			return Err(InvalidWifiCredentials::Password);
+		}
+		if let Some(auth_method) = self.auth_method
+		{
+			if (auth_method == WifiAuthMethod::Open) != self.password.is_empty()
+			{
+				return Err(InvalidWifiCredentials::AuthMethod);
+			}
+		}
+
+		Ok(())
+	}
+}
+
+// Implemented manually so that the password is never logged
+impl Debug for WifiCredentials
+{
+	fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result
+	{
+		f.debug_struct("WifiCredentials")
+			.field("ssid", &self.ssid)
+			.field("password", &"***")
+			.field("auth_method", &self.auth_method)
+			.finish()
+	}
+}
+
+impl core::fmt::Display for InvalidWifiCredentials
+{
+	fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result
+	{
+		match self
+		{
+			Self::Ssid => write!(f, "The SSID must be from 1 to 32 bytes long"),
+			Self::Password => write!(f, "The password must be empty or from 8 to 64 bytes long"),
+			Self::AuthMethod => write!(f, "Only the open networks can have an empty password"),
+		}
+	}
+}
+
+impl WifiNetwork
+{
+	/// Returns the networks found by a scan, sorted from the strongest signal. The hidden networks are skipped, and the
+	/// networks with more access points are listed once.
+	pub fn from_scan(access_points: &[AccessPointInfo]) -> Vec<Self>
+	{
+		let mut networks: Vec<Self> = Vec::new();
+		for access_point in access_points
+			.iter()
+			.filter(|access_point| !access_point.ssid.is_empty())
+		{
+			let network = Self {
+				ssid: access_point.ssid.to_string(),
+				rssi_dbm: access_point.signal_strength,
+				secure: !matches!(access_point.auth_method, None | Some(AuthMethod::None)),
+			};
+			match networks.iter_mut().find(|known| known.ssid == network.ssid)
+			{
+				Some(known) if known.rssi_dbm < network.rssi_dbm => *known = network,
+				Some(_) => (),
+				None => networks.push(network),
+			}
+		}
+		networks.sort_by_key(|network| core::cmp::Reverse(network.rssi_dbm));
+
+		networks
+	}
+}
+
+impl WifiProvisioning
+{
+	/// Executes the `request`, changing the saved `wifi_networks` if needed. If the response is
+	/// [`WifiResponse::Restarting`], the networks must be saved and the camera restarted.
+	pub fn execute<W: Wifi>(
+		&mut self, request: WifiRequest, wifi_driver: &mut W, wifi_networks: &mut Vec<WifiCredentials>, now: Instant,
+	) -> WifiResponse
+	{
+		match request
+		{
+			WifiRequest::Scan => self.scan(wifi_driver, now),
+			WifiRequest::SaveCredentials(credentials) =>
+			{
+				log::info!("Saving the WiFi network {}", credentials.ssid);
+				prioritize_network(wifi_networks, credentials);
+				WifiResponse::Restarting
+			},
+			WifiRequest::EnterSetupMode =>
+			{
+				wifi_networks.clear();
+				WifiResponse::Restarting
+			},
+		}
+	}
+
+	/// Returns the networks of the last scan if it's recent enough, otherwise scans them again.
+	fn scan<W: Wifi>(&mut self, wifi_driver: &mut W, now: Instant) -> WifiResponse
+	{
+		if let Some((scanned_at, networks)) = &self.last_scan
+		{
+			if now.saturating_duration_since(*scanned_at) < SCAN_CACHE_DURATION
+			{
+				return WifiResponse::Networks(networks.clone());
+			}
+		}
+
+		match wifi_driver.scan()
+		{
+			Ok(access_points) =>
+			{
+				let networks = WifiNetwork::from_scan(&access_points);
+				self.last_scan = Some((now, networks.clone()));
+				WifiResponse::Networks(networks)
+			},
+			Err(error) =>
+			{
+				log::warn!("Couldn't scan the WiFi networks: {:?}", error);
+				WifiResponse::ScanFailed
+			},
+		}
+	}
+}
+
+impl<P: InputPin> SetupButton<P>
+{
+	pub fn new(pin: P, long_press: Duration) -> Self
+	{
+		Self {
+			pin,
+			long_press,
+			pressed_since: None,
+			has_reported: false,
+		}
+	}
+
+	/// Returns `true` once for each press that lasts at least the long press.
+	pub fn tick(&mut self) -> Result<bool, P::Error>
+	{
+		if !self.pin.is_low()?
+		{
+			self.pressed_since = None;
+			self.has_reported = false;
+			return Ok(false);
+		}
+
+		let pressed_since = *self.pressed_since.get_or_insert_with(Instant::now);
+		if !self.has_reported && pressed_since.elapsed() >= self.long_press
+		{
+			self.has_reported = true;
+			return Ok(true);
+		}
+
+		Ok(false)
+	}
+}
+
+/// Connects to the network of the `credentials`, without waiting for the connection to be established. The attempt in
+/// progress (if any) is stopped.
+pub fn connect_to_network<W: Wifi>(wifi_driver: &mut W, credentials: &WifiCredentials) -> Result<(), W::Error>
+{
+	log::info!("Connecting to the WiFi network {}", credentials.ssid);
+
+	// It fails if the station isn't started or connecting, which doesn't matter
+	let _ = wifi_driver.disconnect();
+
+	wifi_driver.set_configuration(&Configuration::Client(client_configuration(credentials)))?;
+	wifi_driver.start()?;
+	wifi_driver.connect()
+}
+
+/// Starts the setup access point. The station is started too (without connecting it), so that the networks can be
+/// scanned.
+pub fn start_setup_access_point<W: Wifi>(
+	wifi_driver: &mut W, settings: &ProvisioningSettings, mac_address: Option<[u8; 6]>,
+) -> Result<(), W::Error>
+{
+	let ssid = match mac_address
+	{
+		Some(mac_address) => format!(
+			"{}-{:02X}{:02X}",
+			settings.access_point_ssid_prefix, mac_address[4], mac_address[5]
+		),
+		None => settings.access_point_ssid_prefix.clone(),
+	};
+	log::info!("Starting the setup access point {}", ssid);
+
+	let password = settings.access_point_password.as_deref().unwrap_or_default();
+	wifi_driver.set_configuration(&Configuration::Mixed(
+		ClientConfiguration::default(),
+		AccessPointConfiguration {
+			ssid: truncated(&ssid, SSID_CAPACITY),
+			auth_method: match password.is_empty()
+			{
+				true => AuthMethod::None,
+				false => AuthMethod::WPA2Personal,
+			},
+			password: truncated(password, PASSWORD_CAPACITY),
+			channel: 1,
+			max_connections: 4,
+			..Default::default()
+		},
+	))?;
+	wifi_driver.start()
+}
+
+impl From<WifiAuthMethod> for AuthMethod
+{
+	fn from(auth_method: WifiAuthMethod) -> Self
+	{
+		match auth_method
+		{
+			WifiAuthMethod::Open => Self::None,
+			WifiAuthMethod::Wpa2Personal => Self::WPA2Personal,
+			WifiAuthMethod::Wpa3Personal => Self::WPA3Personal,
+			WifiAuthMethod::Wpa2Wpa3Personal => Self::WPA2WPA3Personal,
+		}
+	}
+}
+
+fn client_configuration(credentials: &WifiCredentials) -> ClientConfiguration
+{
+	ClientConfiguration {
+		ssid: truncated(&credentials.ssid, SSID_CAPACITY),
+		auth_method: match (credentials.auth_method, credentials.password.is_empty())
+		{
+			(Some(auth_method), _) => auth_method.into(),
+			(None, true) => AuthMethod::None,
+			(None, false) => AuthMethod::WPA2Personal,
+		},
+		password: truncated(&credentials.password, PASSWORD_CAPACITY),
+		..Default::default()
+	}
+}
+
+/// Converts the `string` to a fixed capacity one (like the SSID of a [`ClientConfiguration`]), dropping the
+/// characters that don't fit in `capacity` bytes. The credentials are validated before they're saved, so only the SSID
+/// of the access point could be too long.
+fn truncated<T: for<'a> TryFrom<&'a str> + Default>(string: &str, capacity: usize) -> T
+{
+	let mut length = string.len().min(capacity);
+	while !string.is_char_boundary(length)
+	{
+		length -= 1;
+	}
+
+	string[..length].try_into().unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests
+{
+	use std::convert::Infallible;
+
+	use embedded_hal::digital::ErrorType;
+	use embedded_svc::wifi::Capability;
+	use enumset::EnumSet;
+
+	use super::*;
+
+	struct ButtonPin(bool);
+
+	impl ErrorType for ButtonPin
+	{
+		type Error = Infallible;
+	}
+
+	impl InputPin for ButtonPin
+	{
+		fn is_high(&mut self) -> Result<bool, Self::Error>
+		{
+			Ok(!self.0)
+		}
+
+		fn is_low(&mut self) -> Result<bool, Self::Error>
+		{
+			Ok(self.0)
+		}
+	}
+
+	/// Finds the `access_points`, or fails to scan if there are none, and counts the scans.
+	#[derive(Default)]
+	struct FakeWifi
+	{
+		configuration: Configuration,
+		is_started: bool,
+		access_points: Vec<AccessPointInfo>,
+		scans: usize,
+	}
+
+	impl Wifi for FakeWifi
+	{
+		type Error = ();
+
+		fn get_capabilities(&self) -> Result<EnumSet<Capability>, Self::Error>
+		{
+			Ok(Capability::Client | Capability::AccessPoint | Capability::Mixed)
+		}
+
+		fn get_configuration(&self) -> Result<Configuration, Self::Error>
+		{
+			Ok(self.configuration.clone())
+		}
+
+		fn set_configuration(&mut self, conf: &Configuration) -> Result<(), Self::Error>
+		{
+			self.configuration = conf.clone();
+			Ok(())
+		}
+
+		fn start(&mut self) -> Result<(), Self::Error>
+		{
+			self.is_started = true;
+			Ok(())
+		}
+
+		fn stop(&mut self) -> Result<(), Self::Error>
+		{
+			self.is_started = false;
+			Ok(())
+		}
+
+		fn connect(&mut self) -> Result<(), Self::Error>
+		{
+			Ok(())
+		}
+
+		fn disconnect(&mut self) -> Result<(), Self::Error>
+		{
+			Ok(())
+		}
+
+		fn is_started(&self) -> Result<bool, Self::Error>
+		{
+			Ok(self.is_started)
+		}
+
+		fn is_connected(&self) -> Result<bool, Self::Error>
+		{
+			Ok(false)
+		}
+
+		fn scan_n<const N: usize>(&mut self) -> Result<(heapless::Vec<AccessPointInfo, N>, usize), Self::Error>
+		{
+			Err(())
+		}
+
+		fn scan(&mut self) -> Result<Vec<AccessPointInfo>, Self::Error>
+		{
+			self.scans += 1;
+			match self.access_points.is_empty()
+			{
+				true => Err(()),
+				false => Ok(self.access_points.clone()),
+			}
+		}
+	}
+
+	fn credentials(ssid: &str) -> WifiCredentials
+	{
+		WifiCredentials {
+			ssid: ssid.to_string(),
+			password: "password".to_string(),
+			auth_method: None,
+		}
+	}
+
+	fn access_point(ssid: &str, signal_strength: i8, auth_method: AuthMethod) -> AccessPointInfo
+	{
+		AccessPointInfo {
+			ssid: truncated(ssid, SSID_CAPACITY),
+			signal_strength,
+			auth_method: Some(auth_method),
+			..Default::default()
+		}
+	}
+
+	#[test]
+	fn credentials_are_validated()
+	{
+		let credentials = |ssid: &str, password: &str| WifiCredentials {
+			ssid: ssid.to_string(),
+			password: password.to_string(),
+			auth_method: None,
+		};
+
+		assert_eq!(credentials("Home", "password").validate(), Ok(()));
+		assert_eq!(credentials("Home", "").validate(), Ok(()));
+		assert_eq!(
+			credentials("", "password").validate(),
+			Err(InvalidWifiCredentials::Ssid)
+		);
+		assert_eq!(
+			credentials(&"a".repeat(33), "password").validate(),
+			Err(InvalidWifiCredentials::Ssid)
+		);
+		assert_eq!(
+			credentials("Home", "short").validate(),
+			Err(InvalidWifiCredentials::Password)
+		);
+		assert!(!format!("{:?}", credentials("Home", "password")).contains("password\""));
+
+		let with_auth_method = |password: &str, auth_method| WifiCredentials {
+			auth_method: Some(auth_method),
+			..credentials("Home", password)
+		};
+		assert_eq!(with_auth_method("", WifiAuthMethod::Open).validate(), Ok(()));
+		assert_eq!(
+			with_auth_method("password", WifiAuthMethod::Wpa3Personal).validate(),
+			Ok(())
+		);
+		assert_eq!(
+			with_auth_method("password", WifiAuthMethod::Open).validate(),
+			Err(InvalidWifiCredentials::AuthMethod)
+		);
+		assert_eq!(
+			with_auth_method("", WifiAuthMethod::Wpa2Personal).validate(),
+			Err(InvalidWifiCredentials::AuthMethod)
+		);
+	}
+
+	#[test]
+	fn scanned_networks_are_deduplicated_and_sorted()
+	{
+		let networks = WifiNetwork::from_scan(&[
+			access_point("Home", -70, AuthMethod::WPA2Personal),
+			access_point("", -30, AuthMethod::WPA2Personal),
+			access_point("Guest", -60, AuthMethod::None),
+			access_point("Home", -50, AuthMethod::WPA2Personal),
+		]);
+
+		assert_eq!(
+			networks,
+			[
+				WifiNetwork {
+					ssid: "Home".to_string(),
+					rssi_dbm: -50,
+					secure: true,
+				},
+				WifiNetwork {
+					ssid: "Guest".to_string(),
+					rssi_dbm: -60,
+					secure: false,
+				},
+			]
+		);
+	}
+
+	#[test]
+	fn long_press_is_reported_once()
+	{
+		let mut button = SetupButton::new(ButtonPin(false), Duration::ZERO);
+		assert_eq!(button.tick(), Ok(false));
+
+		button.pin.0 = true;
+		assert_eq!(button.tick(), Ok(true));
+		assert_eq!(button.tick(), Ok(false));
+
+		button.pin.0 = false;
+		assert_eq!(button.tick(), Ok(false));
+		button.pin.0 = true;
+		assert_eq!(button.tick(), Ok(true));
+	}
+
+	#[test]
+	fn scans_are_reused_for_a_while()
+	{
+		let mut provisioning = WifiProvisioning::default();
+		let mut wifi = FakeWifi {
+			access_points: vec![access_point("Home", -50, AuthMethod::WPA2Personal)],
+			..Default::default()
+		};
+		let mut networks = Vec::new();
+		let now = Instant::now();
+
+		let response = provisioning.execute(WifiRequest::Scan, &mut wifi, &mut networks, now);
+		assert_eq!(
+			response,
+			WifiResponse::Networks(WifiNetwork::from_scan(&wifi.access_points))
+		);
+		wifi.access_points.push(access_point("Guest", -40, AuthMethod::None));
+		let later = now + SCAN_CACHE_DURATION / 2;
+		assert_eq!(
+			provisioning.execute(WifiRequest::Scan, &mut wifi, &mut networks, later),
+			response
+		);
+		assert_eq!(wifi.scans, 1);
+
+		let after_the_cache = now + SCAN_CACHE_DURATION;
+		let response = provisioning.execute(WifiRequest::Scan, &mut wifi, &mut networks, after_the_cache);
+		assert_eq!(wifi.scans, 2);
+		let WifiResponse::Networks(scanned_networks) = response
+		else
+		{
+			panic!("The scan failed");
+		};
+		assert_eq!(scanned_networks[0].ssid, "Guest");
+	}
+
+	#[test]
+	fn failed_scans_are_retried()
+	{
+		let mut provisioning = WifiProvisioning::default();
+		let mut wifi = FakeWifi::default();
+		let mut networks = Vec::new();
+		let now = Instant::now();
+
+		assert_eq!(
+			provisioning.execute(WifiRequest::Scan, &mut wifi, &mut networks, now),
+			WifiResponse::ScanFailed
+		);
+		wifi.access_points
+			.push(access_point("Home", -50, AuthMethod::WPA2Personal));
+		assert!(matches!(
+			provisioning.execute(WifiRequest::Scan, &mut wifi, &mut networks, now),
+			WifiResponse::Networks(_)
+		));
+		assert_eq!(wifi.scans, 2);
+	}
+
+	#[test]
+	fn setup_saves_the_networks_until_the_setup_mode_is_entered_again()
+	{
+		let settings = ProvisioningSettings {
+			access_point_ssid_prefix: "CAM-setup".to_string(),
+			access_point_password: None,
+			button_long_press: Duration::from_secs(5),
+		};
+		let mut wifi = FakeWifi::default();
+		start_setup_access_point(&mut wifi, &settings, Some([0, 0, 0, 0, 0x0A, 0x1B])).unwrap();
+		assert!(wifi.is_started);
+		let Configuration::Mixed(_, access_point) = &wifi.configuration
+		else
+		{
+			panic!("The access point isn't started: {:?}", wifi.configuration);
+		};
+		assert_eq!(access_point.ssid, "CAM-setup-0A1B");
+		assert_eq!(access_point.auth_method, AuthMethod::None);
+
+		let mut provisioning = WifiProvisioning::default();
+		let mut networks = Vec::new();
+		let now = Instant::now();
+		for ssid in ["Home", "Office", "Home"]
+		{
+			let request = WifiRequest::SaveCredentials(credentials(ssid));
+			assert_eq!(
+				provisioning.execute(request, &mut wifi, &mut networks, now),
+				WifiResponse::Restarting
+			);
+		}
+		let ssids: Vec<&str> = networks.iter().map(|network| network.ssid.as_str()).collect();
+		assert_eq!(ssids, ["Home", "Office"]);
+
+		assert_eq!(
+			provisioning.execute(WifiRequest::EnterSetupMode, &mut wifi, &mut networks, now),
+			WifiResponse::Restarting
+		);
+		assert!(networks.is_empty());
+		assert_eq!(wifi.scans, 0);
+	}
+}
@@ -0,0 +1,422 @@
+mod pre_trigger;
+
+use core::time::Duration;
+use std::sync::Arc;
+
+use a13c_embedded::peripherals::time::real_time::time::{Date, PrimitiveDateTime, Time};
+pub use pre_trigger::*;
+use serde::Serialize;
+
+use crate::features::{
+	frames::Frame,
+	storage::{CapturePath, EventPath, EventStorage},
+	trigger::TriggerSource,
+};
+
+/// Configures the events recorded by the [`EventRecorder`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EventRecordingSettings
+{
+	/// How long before the trigger the frames are kept in memory, to be saved at the start of the event.
+	pub pre_trigger: Duration,
+	/// How long the recording goes on after the last motion.
+	pub post_roll: Duration,
+	/// The event is finished after this even if there's still motion (the motion will start a new event).
+	pub max_duration: Duration,
+	/// Limits the memory used by the frames kept before the trigger. On the ESP32 allocations this big are placed in
+	/// the PSRAM, so this should fit in it together with the frame buffers of the camera.
+	pub max_pre_trigger_bytes: usize,
+}
+
+/// Describes an event, saved in its directory next to the video (check [`EventPath`]).
+///
+/// ```json
+/// {"start":"2024-05-01T13:01:00","end":"2024-05-01T13:01:25","trigger_source":"pir","frame_count":250,"pre_trigger_frame_count":30}
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct EventManifest
+{
+	/// Date and time of the first frame (`None` if the real time clock wasn't set).
+	pub start: Option<String>,
+	/// Date and time of the last frame (`None` if the real time clock wasn't set).
+	pub end: Option<String>,
+	pub trigger_source: TriggerSource,
+	pub frame_count: u32,
+	/// How many of the frames were captured before the trigger.
+	pub pre_trigger_frame_count: u32,
+}
+
+/// Records the frames around each trigger as an event: the frames captured in the `pre_trigger` period are kept in
+/// a [`PreTriggerBuffer`] and saved when the trigger happens, then the frames are saved until `post_roll` after the
+/// last motion, and finally the [`EventManifest`] is saved.
+pub struct EventRecorder
+{
+	settings: EventRecordingSettings,
+	pre_trigger_buffer: PreTriggerBuffer,
+	/// `None` while waiting for a trigger.
+	event: Option<RecordingEvent>,
+}
+
+struct RecordingEvent
+{
+	path: EventPath,
+	trigger_source: TriggerSource,
+	/// The date and time of the trigger and the timestamp of the frame captured in that moment, from which the date and
+	/// time of the other frames are calculated. `None` if the real time clock isn't set.
+	trigger_date_and_time: Option<PrimitiveDateTime>,
+	trigger_timestamp: Duration,
+	first_timestamp: Option<Duration>,
+	last_timestamp: Duration,
+	last_motion_timestamp: Duration,
+	frame_count: u32,
+	pre_trigger_frame_count: u32,
+}
+
+impl EventRecorder
+{
+	pub fn new(settings: EventRecordingSettings) -> Self
+	{
+		Self {
+			settings,
+			pre_trigger_buffer: PreTriggerBuffer::new(settings.pre_trigger, settings.max_pre_trigger_bytes),
+			event: None,
+		}
+	}
+
+	/// Must be called with each frame captured while the [`crate::features::trigger::ImageTrigger`] is enabled, with
+	/// the source of the motion detected in the same tick (if any).
+	pub fn record<S: EventStorage>(
+		&mut self, frame: Arc<Frame>, motion_source: Option<TriggerSource>, date_and_time: Option<(Date, Time)>,
+		storage: &mut S,
+	) -> Result<(), S::Error>
+	{
+		let Some(event) = self.event.as_mut()
+		else
+		{
+			return match motion_source
+			{
+				Some(trigger_source) => self.start_event(frame, trigger_source, date_and_time, storage),
+				None =>
+				{
+					self.pre_trigger_buffer.push(frame);
+					Ok(())
+				},
+			};
+		};
+
+		if motion_source.is_some()
+		{
+			event.last_motion_timestamp = frame.timestamp;
+		}
+		let result = event.store_frame(&frame, storage);
+
+		let is_over = frame.timestamp.saturating_sub(event.last_motion_timestamp) >= self.settings.post_roll
+			|| event.first_timestamp.is_some_and(|first_timestamp| {
+				frame.timestamp.saturating_sub(first_timestamp) >= self.settings.max_duration
+			});
+		if is_over
+		{
+			self.finish_event(storage)?;
+		}
+
+		result
+	}
+
+	/// Saves the manifest of the event being recorded, if any. It's also called when the frames stop being captured,
+	/// so that an event isn't left open.
+	pub fn finish_event<S: EventStorage>(&mut self, storage: &mut S) -> Result<(), S::Error>
+	{
+		let Some(event) = self.event.take()
+		else
+		{
+			return Ok(());
+		};
+
+		let manifest = event.manifest();
+		let json = serde_json::to_vec(&manifest).expect("`EventManifest` can always be serialized");
+		storage.finish_event(&event.path, &json)?;
+		log::info!("Finished event {} with {} frames", event.path, manifest.frame_count);
+
+		Ok(())
+	}
+
+	pub fn is_recording(&self) -> bool
+	{
+		self.event.is_some()
+	}
+
+	fn start_event<S: EventStorage>(
+		&mut self, frame: Arc<Frame>, trigger_source: TriggerSource, date_and_time: Option<(Date, Time)>,
+		storage: &mut S,
+	) -> Result<(), S::Error>
+	{
+		let path = storage.start_event(date_and_time)?;
+		log::info!("Started event {} (triggered by {:?})", path, trigger_source);
+
+		// The storage decides if the clock is valid
+		let trigger_date_and_time = match (path.0, date_and_time)
+		{
+			(CapturePath::Dated { .. }, Some((date, time))) => Some(PrimitiveDateTime::new(date, time)),
+			_ => None,
+		};
+		let event = self.event.insert(RecordingEvent {
+			path,
+			trigger_source,
+			trigger_date_and_time,
+			trigger_timestamp: frame.timestamp,
+			first_timestamp: None,
+			last_timestamp: frame.timestamp,
+			last_motion_timestamp: frame.timestamp,
+			frame_count: 0,
+			pre_trigger_frame_count: 0,
+		});
+
+		for pre_trigger_frame in self.pre_trigger_buffer.take()
+		{
+			event.store_frame(&pre_trigger_frame, storage)?;
+			event.pre_trigger_frame_count += 1;
+		}
+		event.store_frame(&frame, storage)
+	}
+}
+
+impl RecordingEvent
+{
+	fn store_frame<S: EventStorage>(&mut self, frame: &Frame, storage: &mut S) -> Result<(), S::Error>
+	{
+		storage.store_event_frame(&self.path, frame)?;
+
+		self.frame_count += 1;
+		self.first_timestamp.get_or_insert(frame.timestamp);
+		self.last_timestamp = frame.timestamp;
+
+		Ok(())
+	}
+
+	fn manifest(&self) -> EventManifest
+	{
+		EventManifest {
+			start: self
+				.first_timestamp
+				.and_then(|first_timestamp| self.date_and_time_of(first_timestamp)),
+			end: self.date_and_time_of(self.last_timestamp),
+			trigger_source: self.trigger_source,
+			frame_count: self.frame_count,
+			pre_trigger_frame_count: self.pre_trigger_frame_count,
+		}
+	}
+
+	/// Returns the date and time of the frame with this `timestamp` as `YYYY-MM-DDTHH:MM:SS`.
+	fn date_and_time_of(&self, timestamp: Duration) -> Option<String>
+	{
+		let trigger_date_and_time = self.trigger_date_and_time?;
+		let date_and_time = if timestamp >= self.trigger_timestamp
+		{
+			trigger_date_and_time.checked_add((timestamp - self.trigger_timestamp).try_into().ok()?)?
+		}
+		else
+		{
+			trigger_date_and_time.checked_sub((self.trigger_timestamp - timestamp).try_into().ok()?)?
+		};
+
+		Some(format!(
+			"{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+			date_and_time.year(),
+			date_and_time.month() as u8,
+			date_and_time.day(),
+			date_and_time.hour(),
+			date_and_time.minute(),
+			date_and_time.second()
+		))
+	}
+}
+
+#[cfg(test)]
+mod tests
+{
+	use std::time::Instant;
+
+	use a13c_embedded::{peripherals::time::real_time::time::Month, utils::math::micromath::micromath::vector::U16x2};
+
+	use super::*;
+
+	/// Keeps the events in memory: the frames are identified by their timestamp in seconds.
+	#[derive(Default)]
+	struct FakeStorage
+	{
+		events: Vec<(EventPath, Vec<u64>, Option<serde_json::Value>)>,
+	}
+
+	impl EventStorage for FakeStorage
+	{
+		type Error = ();
+
+		fn start_event(&mut self, date_and_time: Option<(Date, Time)>) -> Result<EventPath, Self::Error>
+		{
+			let path = match date_and_time
+			{
+				Some((date, time)) => CapturePath::dated(date, time),
+				None => CapturePath::Sequence {
+					number: self.events.len() as u32,
+				},
+			};
+			self.events.push((EventPath(path), Vec::new(), None));
+
+			Ok(EventPath(path))
+		}
+
+		fn store_event_frame(&mut self, event: &EventPath, frame: &Frame) -> Result<(), Self::Error>
+		{
+			let (path, frames, _) = self.events.last_mut().unwrap();
+			assert_eq!(path, event);
+			frames.push(frame.timestamp.as_secs());
+
+			Ok(())
+		}
+
+		fn finish_event(&mut self, event: &EventPath, manifest: &[u8]) -> Result<(), Self::Error>
+		{
+			let (path, _, saved_manifest) = self.events.last_mut().unwrap();
+			assert_eq!(path, event);
+			*saved_manifest = Some(serde_json::from_slice(manifest).unwrap());
+
+			Ok(())
+		}
+	}
+
+	const SETTINGS: EventRecordingSettings = EventRecordingSettings {
+		pre_trigger: Duration::from_secs(3),
+		post_roll: Duration::from_secs(2),
+		max_duration: Duration::from_secs(10),
+		max_pre_trigger_bytes: 1024,
+	};
+
+	fn frame(second: u64, size: usize) -> Arc<Frame>
+	{
+		Arc::new(Frame {
+			sequence_number: second,
+			pixels: vec![0; size],
+			size: U16x2 { x: 8, y: 8 },
+			timestamp: Duration::from_secs(second),
+			published_at: Instant::now(),
+		})
+	}
+
+	fn date_and_time(second: u8) -> Option<(Date, Time)>
+	{
+		Some((
+			Date::from_calendar_date(2024, Month::May, 1).unwrap(),
+			Time::from_hms(13, 0, second).unwrap(),
+		))
+	}
+
+	/// Records a frame per second, with motion in the `motion_seconds`.
+	fn record(settings: EventRecordingSettings, seconds: u64, motion_seconds: &[u64]) -> FakeStorage
+	{
+		let mut recorder = EventRecorder::new(settings);
+		let mut storage = FakeStorage::default();
+		for second in 0..seconds
+		{
+			let motion_source = motion_seconds.contains(&second).then_some(TriggerSource::Pir);
+			recorder
+				.record(
+					frame(second, 100),
+					motion_source,
+					date_and_time(second as u8),
+					&mut storage,
+				)
+				.unwrap();
+		}
+
+		storage
+	}
+
+	#[test]
+	fn pre_trigger_buffer_drops_old_frames()
+	{
+		let mut buffer = PreTriggerBuffer::new(Duration::from_secs(3), 1024);
+		for second in 0..10
+		{
+			buffer.push(frame(second, 100));
+		}
+
+		let timestamps: Vec<u64> = buffer.take().iter().map(|frame| frame.timestamp.as_secs()).collect();
+		assert_eq!(timestamps, [7, 8, 9]);
+		assert!(buffer.is_empty());
+		assert_eq!(buffer.bytes(), 0);
+	}
+
+	#[test]
+	fn pre_trigger_buffer_respects_the_memory_limit()
+	{
+		let mut buffer = PreTriggerBuffer::new(Duration::from_secs(3), 250);
+		for second in 0..10
+		{
+			buffer.push(frame(second, 100));
+		}
+
+		assert_eq!(buffer.len(), 2);
+		assert_eq!(buffer.bytes(), 200);
+	}
+
+	#[test]
+	fn nothing_is_recorded_without_motion()
+	{
+		assert!(record(SETTINGS, 20, &[]).events.is_empty());
+	}
+
+	#[test]
+	fn event_contains_pre_trigger_and_post_roll_frames()
+	{
+		let storage = record(SETTINGS, 20, &[5, 6]);
+
+		assert_eq!(storage.events.len(), 1);
+		let (path, frames, manifest) = &storage.events[0];
+		assert_eq!(path.to_string(), "2024/05/01/13/0005_000.EVT");
+		assert_eq!(frames, &[2, 3, 4, 5, 6, 7, 8]);
+		assert_eq!(
+			manifest.as_ref().unwrap(),
+			&serde_json::json!({
+				"start": "2024-05-01T13:00:02",
+				"end": "2024-05-01T13:00:08",
+				"trigger_source": "pir",
+				"frame_count": 7,
+				"pre_trigger_frame_count": 3,
+			})
+		);
+	}
+
+	#[test]
+	fn long_motion_is_split_in_events()
+	{
+		let storage = record(SETTINGS, 20, &Vec::from_iter(5..20));
+
+		assert_eq!(storage.events.len(), 2);
+		assert_eq!(storage.events[0].1, Vec::from_iter(2..=12));
+		assert!(storage.events[0].2.is_some());
+		assert_eq!(storage.events[1].1, Vec::from_iter(13..20));
+		assert!(storage.events[1].2.is_none());
+	}
+
+	#[test]
+	fn manifest_has_no_dates_without_clock()
+	{
+		let mut recorder = EventRecorder::new(SETTINGS);
+		let mut storage = FakeStorage::default();
+		recorder
+			.record(frame(0, 100), Some(TriggerSource::FrameDifference), None, &mut storage)
+			.unwrap();
+		recorder.finish_event(&mut storage).unwrap();
+
+		assert_eq!(
+			storage.events[0].2.as_ref().unwrap(),
+			&serde_json::json!({
+				"start": null,
+				"end": null,
+				"trigger_source": "frame_difference",
+				"frame_count": 1,
+				"pre_trigger_frame_count": 0,
+			})
+		);
+	}
+}
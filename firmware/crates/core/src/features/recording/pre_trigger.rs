@@ -0,0 +1,73 @@
+use core::time::Duration;
+use std::{collections::VecDeque, sync::Arc};
+
+use crate::features::frames::Frame;
+
+/// The latest frames captured before a trigger, so that an event also shows what happened before the motion was
+/// detected. The frames are shared with the [`crate::features::frames::FrameBroker`], so they aren't copied again.
+pub struct PreTriggerBuffer
+{
+	frames: VecDeque<Arc<Frame>>,
+	max_age: Duration,
+	max_bytes: usize,
+	bytes: usize,
+}
+
+impl PreTriggerBuffer
+{
+	pub fn new(max_age: Duration, max_bytes: usize) -> Self
+	{
+		Self {
+			frames: VecDeque::new(),
+			max_age,
+			max_bytes,
+			bytes: 0,
+		}
+	}
+
+	/// Adds the frame, then drops the oldest frames until all of them are less than `max_age` older than it and their
+	/// size fits in `max_bytes`.
+	pub fn push(&mut self, frame: Arc<Frame>)
+	{
+		let timestamp = frame.timestamp;
+		self.bytes += frame.pixels.len();
+		self.frames.push_back(frame);
+
+		while let Some(oldest) = self.frames.front()
+		{
+			// A timestamp that goes backward means that the camera restarted, so the older frames are dropped too
+			let is_recent = timestamp
+				.checked_sub(oldest.timestamp)
+				.is_some_and(|age| age < self.max_age);
+			if is_recent && self.bytes <= self.max_bytes
+			{
+				break;
+			}
+
+			self.bytes -= oldest.pixels.len();
+			self.frames.pop_front();
+		}
+	}
+
+	/// Removes all the frames and returns them, from the oldest.
+	pub fn take(&mut self) -> VecDeque<Arc<Frame>>
+	{
+		self.bytes = 0;
+		core::mem::take(&mut self.frames)
+	}
+
+	pub fn len(&self) -> usize
+	{
+		self.frames.len()
+	}
+
+	pub fn is_empty(&self) -> bool
+	{
+		self.frames.is_empty()
+	}
+
+	pub fn bytes(&self) -> usize
+	{
+		self.bytes
+	}
+}
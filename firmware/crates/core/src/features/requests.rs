@@ -0,0 +1,102 @@
+use core::time::Duration;
+use std::{collections::VecDeque, sync::Arc, time::Instant};
+
+use spin::Mutex;
+
+/// Requests made by the HTTP server and fulfilled by the main loop, which is the only one that owns the peripherals
+/// (like the camera). Cloning it returns a handle to the same requests.
+pub struct Requests<Request, Response>
+{
+	state: Arc<Mutex<RequestsState<Request, Response>>>,
+}
+
+struct RequestsState<Request, Response>
+{
+	next_id: u64,
+	pending: VecDeque<(u64, Request)>,
+	/// The requests whose HTTP handler is still waiting (the ones that timed out aren't fulfilled).
+	waiting: Vec<u64>,
+	fulfilled: Vec<(u64, Response)>,
+}
+
+impl<Request, Response> Requests<Request, Response>
+{
+	/// How long [`Self::request_and_wait`] waits before checking again if the request has been fulfilled.
+	const POLL_PERIOD: Duration = Duration::from_millis(10);
+
+	/// Makes the request and waits until the main loop fulfills it. Returns `None` if it isn't fulfilled before the
+	/// `timeout`.
+	pub fn request_and_wait(&self, request: Request, timeout: Duration) -> Option<Response>
+	{
+		let id = {
+			let mut state = self.state.lock();
+			let id = state.next_id;
+			state.next_id += 1;
+			state.pending.push_back((id, request));
+			state.waiting.push(id);
+			id
+		};
+
+		let requested_at = Instant::now();
+		loop
+		{
+			{
+				let mut state = self.state.lock();
+				if let Some(index) = state.fulfilled.iter().position(|(fulfilled_id, _)| *fulfilled_id == id)
+				{
+					state.waiting.retain(|waiting_id| *waiting_id != id);
+					return Some(state.fulfilled.swap_remove(index).1);
+				}
+				if requested_at.elapsed() >= timeout
+				{
+					state.pending.retain(|(pending_id, _)| *pending_id != id);
+					state.waiting.retain(|waiting_id| *waiting_id != id);
+					return None;
+				}
+			}
+
+			std::thread::sleep(Self::POLL_PERIOD);
+		}
+	}
+
+	/// Returns the oldest request that hasn't been fulfilled yet. The caller must then call [`Self::fulfill`].
+	pub fn take_pending(&self) -> Option<(u64, Request)>
+	{
+		self.state.lock().pending.pop_front()
+	}
+
+	pub fn fulfill(&self, id: u64, response: Response)
+	{
+		let mut state = self.state.lock();
+		if state.waiting.contains(&id)
+		{
+			state.fulfilled.push((id, response));
+		}
+	}
+}
+
+// Implemented manually because the derive would require `Request: Clone` and `Response: Clone`
+impl<Request, Response> Clone for Requests<Request, Response>
+{
+	fn clone(&self) -> Self
+	{
+		Self {
+			state: Arc::clone(&self.state),
+		}
+	}
+}
+
+impl<Request, Response> Default for Requests<Request, Response>
+{
+	fn default() -> Self
+	{
+		Self {
+			state: Arc::new(Mutex::new(RequestsState {
+				next_id: 0,
+				pending: VecDeque::new(),
+				waiting: Vec::new(),
+				fulfilled: Vec::new(),
+			})),
+		}
+	}
+}
@@ -0,0 +1,444 @@
+//! RTP payload format for JPEG ([RFC 2435](https://www.rfc-editor.org/rfc/rfc2435)). Only the headers the receivers
+//! can't guess are sent: the size, the sampling, the quantization tables and the restart interval. The receivers
+//! rebuild the rest of the JPEG with the standard Huffman tables, which are the ones used by the camera's encoder.
+
+use std::io;
+
+/// The static payload type of JPEG.
+pub(super) const PAYLOAD_TYPE: u8 = 26;
+/// The RTP timestamps of video count in 90 kHz.
+pub(super) const CLOCK_RATE: u32 = 90_000;
+/// Small enough that the UDP datagrams aren't fragmented on an Ethernet or WiFi network.
+const MAX_PACKET_SIZE: usize = 1400;
+const QUANTIZATION_TABLE_SIZE: usize = 64;
+/// The quantization tables are sent in the first packet of each frame instead of being derived from a quality factor.
+const DYNAMIC_QUANTIZATION_TABLES: u8 = 255;
+/// Added to the type when the frame has restart markers.
+const RESTART_MARKERS_TYPE_OFFSET: u8 = 64;
+/// The width and the height are sent as multiples of 8 pixels, in a byte.
+const MAX_DIMENSION: u16 = 255 * 8;
+/// The offset of a fragment is 24 bits long.
+const MAX_SCAN_SIZE: usize = 1 << 24;
+
+const START_OF_IMAGE: u8 = 0xD8;
+const END_OF_IMAGE: u8 = 0xD9;
+const BASELINE_START_OF_FRAME: u8 = 0xC0;
+const DEFINE_HUFFMAN_TABLE: u8 = 0xC4;
+const DEFINE_ARITHMETIC_CODING: u8 = 0xCC;
+const JPEG_EXTENSION: u8 = 0xC8;
+const DEFINE_QUANTIZATION_TABLE: u8 = 0xDB;
+const DEFINE_RESTART_INTERVAL: u8 = 0xDD;
+const START_OF_SCAN: u8 = 0xDA;
+
+/// Why a JPEG can't be sent with RFC 2435.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum JpegError
+{
+	/// The image doesn't start with a JPEG marker, or one of its segments is cut.
+	Malformed,
+	/// Only baseline JPEGs can be sent.
+	NotBaseline,
+	/// Only YUV 4:2:2 and 4:2:0 can be sent.
+	UnsupportedSampling,
+	/// Only the tables with 8-bit values can be sent.
+	UnsupportedQuantizationTable,
+	/// A component uses a quantization table that isn't defined.
+	MissingQuantizationTable,
+	/// Wider or higher than 2040 pixels, or with a scan bigger than 16 MiB.
+	TooLarge,
+}
+
+impl core::fmt::Display for JpegError
+{
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result
+	{
+		f.write_str(match self
+		{
+			Self::Malformed => "the image isn't a valid JPEG",
+			Self::NotBaseline => "the JPEG isn't baseline",
+			Self::UnsupportedSampling => "the JPEG isn't YUV 4:2:2 or 4:2:0",
+			Self::UnsupportedQuantizationTable => "the JPEG has 16-bit quantization tables",
+			Self::MissingQuantizationTable => "the JPEG uses a quantization table it doesn't define",
+			Self::TooLarge => "the JPEG is too large",
+		})
+	}
+}
+
+/// The parts of a JPEG sent over RTP.
+#[derive(Debug, PartialEq, Eq)]
+pub(super) struct JpegFrame<'a>
+{
+	/// 0 for 4:2:2 and 1 for 4:2:0, plus 64 if the frame has restart markers.
+	jpeg_type: u8,
+	/// In multiples of 8 pixels.
+	width: u8,
+	height: u8,
+	restart_interval: u16,
+	/// The table of the luminance followed by the one of the chrominance, in zigzag order.
+	quantization_tables: [u8; 2 * QUANTIZATION_TABLE_SIZE],
+	/// The entropy-coded data, without the end of image marker.
+	scan: &'a [u8],
+}
+
+impl<'a> JpegFrame<'a>
+{
+	pub(super) fn parse(jpeg: &'a [u8]) -> Result<Self, JpegError>
+	{
+		if jpeg.get(..2) != Some(&[0xFF, START_OF_IMAGE])
+		{
+			return Err(JpegError::Malformed);
+		}
+
+		let mut tables: [Option<&[u8]>; 4] = [None; 4];
+		// The sampling and the quantization table of each component
+		let mut components = None;
+		let mut restart_interval = 0;
+		let mut size = (0, 0);
+		let mut position = 2;
+		loop
+		{
+			// A marker can be preceded by any number of fill bytes
+			while jpeg.get(position) == Some(&0xFF) && jpeg.get(position + 1) == Some(&0xFF)
+			{
+				position += 1;
+			}
+			let (Some(&0xFF), Some(&marker)) = (jpeg.get(position), jpeg.get(position + 1))
+			else
+			{
+				return Err(JpegError::Malformed);
+			};
+			let length = jpeg
+				.get(position + 2..position + 4)
+				.map(|length| u16::from_be_bytes([length[0], length[1]]) as usize)
+				.filter(|&length| length >= 2)
+				.ok_or(JpegError::Malformed)?;
+			let segment = jpeg
+				.get(position + 4..position + 2 + length)
+				.ok_or(JpegError::Malformed)?;
+			position += 2 + length;
+
+			match marker
+			{
+				DEFINE_QUANTIZATION_TABLE =>
+				{
+					let mut definitions = segment;
+					while let Some((&precision_and_id, rest)) = definitions.split_first()
+					{
+						if precision_and_id >> 4 != 0
+						{
+							return Err(JpegError::UnsupportedQuantizationTable);
+						}
+						let table = rest.get(..QUANTIZATION_TABLE_SIZE).ok_or(JpegError::Malformed)?;
+						*tables.get_mut(precision_and_id as usize).ok_or(JpegError::Malformed)? = Some(table);
+						definitions = &rest[QUANTIZATION_TABLE_SIZE..];
+					}
+				},
+				BASELINE_START_OF_FRAME =>
+				{
+					// The precision, the height, the width, the count of components and 3 bytes per component
+					let [8, height_high, height_low, width_high, width_low, 3, ref components_bytes @ ..] = *segment
+					else
+					{
+						return Err(JpegError::UnsupportedSampling);
+					};
+					let [_, luminance_sampling, luminance_table, _, blue_sampling, blue_table, _, red_sampling, red_table] =
+						*components_bytes
+					else
+					{
+						return Err(JpegError::Malformed);
+					};
+					if blue_sampling != 0x11 || red_sampling != 0x11 || blue_table != red_table
+					{
+						return Err(JpegError::UnsupportedSampling);
+					}
+					size = (
+						u16::from_be_bytes([width_high, width_low]),
+						u16::from_be_bytes([height_high, height_low]),
+					);
+					components = Some((luminance_sampling, luminance_table, blue_table));
+				},
+				0xC1..=0xCF
+					if marker != DEFINE_HUFFMAN_TABLE
+						&& marker != JPEG_EXTENSION
+						&& marker != DEFINE_ARITHMETIC_CODING =>
+				{
+					return Err(JpegError::NotBaseline);
+				},
+				DEFINE_RESTART_INTERVAL =>
+				{
+					let [high, low] = *segment
+					else
+					{
+						return Err(JpegError::Malformed);
+					};
+					restart_interval = u16::from_be_bytes([high, low]);
+				},
+				START_OF_SCAN => break,
+				// The Huffman tables are the standard ones, and the receivers don't need the metadata
+				_ => (),
+			}
+		}
+
+		let (luminance_sampling, luminance_table, chrominance_table) = components.ok_or(JpegError::Malformed)?;
+		let jpeg_type = match luminance_sampling
+		{
+			0x21 => 0,
+			0x22 => 1,
+			_ => return Err(JpegError::UnsupportedSampling),
+		};
+		let jpeg_type = match restart_interval
+		{
+			0 => jpeg_type,
+			_ => jpeg_type + RESTART_MARKERS_TYPE_OFFSET,
+		};
+		let (width, height) = size;
+		if width == 0 || height == 0 || width > MAX_DIMENSION || height > MAX_DIMENSION
+		{
+			return Err(JpegError::TooLarge);
+		}
+
+		let mut quantization_tables = [0; 2 * QUANTIZATION_TABLE_SIZE];
+		for (destination, id) in quantization_tables
+			.chunks_exact_mut(QUANTIZATION_TABLE_SIZE)
+			.zip([luminance_table, chrominance_table])
+		{
+			let table = tables
+				.get(id as usize)
+				.copied()
+				.flatten()
+				.ok_or(JpegError::MissingQuantizationTable)?;
+			destination.copy_from_slice(table);
+		}
+
+		// The entropy-coded data can't contain the end of image marker (its 0xFF bytes are followed by 0x00 or by a
+		// restart marker), and the camera's buffers can have padding after it
+		let scan = &jpeg[position..];
+		let scan = match scan.windows(2).position(|bytes| bytes == [0xFF, END_OF_IMAGE])
+		{
+			Some(end) => &scan[..end],
+			None => scan,
+		};
+		if scan.is_empty()
+		{
+			return Err(JpegError::Malformed);
+		}
+		if scan.len() > MAX_SCAN_SIZE
+		{
+			return Err(JpegError::TooLarge);
+		}
+
+		Ok(Self {
+			jpeg_type,
+			width: width.div_ceil(8) as u8,
+			height: height.div_ceil(8) as u8,
+			restart_interval,
+			quantization_tables,
+			scan,
+		})
+	}
+}
+
+/// Splits the frames in RTP packets for a single stream.
+pub(super) struct JpegPacketizer
+{
+	ssrc: u32,
+	sequence_number: u16,
+}
+
+impl JpegPacketizer
+{
+	/// The SSRC and the first sequence number should be random, so that a new stream can't be mistaken for an old one.
+	pub(super) fn new(ssrc: u32, sequence_number: u16) -> Self
+	{
+		Self { ssrc, sequence_number }
+	}
+
+	/// The sequence number of the next packet.
+	pub(super) fn sequence_number(&self) -> u16
+	{
+		self.sequence_number
+	}
+
+	/// Calls `send` with each RTP packet of the frame, stopping at the first error. The last packet has the marker bit.
+	pub(super) fn packetize(
+		&mut self, frame: &JpegFrame, timestamp: u32, mut send: impl FnMut(&[u8]) -> io::Result<()>,
+	) -> io::Result<()>
+	{
+		let mut packet = Vec::with_capacity(MAX_PACKET_SIZE);
+		let mut offset = 0;
+		while offset < frame.scan.len()
+		{
+			packet.clear();
+			// The marker bit and the sequence number are set once the size of the fragment is known
+			packet.extend_from_slice(&[0x80, 0, 0, 0]);
+			packet.extend_from_slice(&timestamp.to_be_bytes());
+			packet.extend_from_slice(&self.ssrc.to_be_bytes());
+
+			packet.push(0);
+			packet.extend_from_slice(&(offset as u32).to_be_bytes()[1..]);
+			packet.extend_from_slice(&[frame.jpeg_type, DYNAMIC_QUANTIZATION_TABLES, frame.width, frame.height]);
+			if frame.restart_interval != 0
+			{
+				packet.extend_from_slice(&frame.restart_interval.to_be_bytes());
+				// The fragments aren't aligned on the restart intervals: first and last with a count of 0x3FFF
+				packet.extend_from_slice(&[0xFF, 0xFF]);
+			}
+			if offset == 0
+			{
+				packet.extend_from_slice(&[0, 0]);
+				packet.extend_from_slice(&(frame.quantization_tables.len() as u16).to_be_bytes());
+				packet.extend_from_slice(&frame.quantization_tables);
+			}
+
+			let fragment_size = (MAX_PACKET_SIZE - packet.len()).min(frame.scan.len() - offset);
+			packet.extend_from_slice(&frame.scan[offset..offset + fragment_size]);
+			offset += fragment_size;
+
+			let is_last = offset == frame.scan.len();
+			packet[1] = ((is_last as u8) << 7) | PAYLOAD_TYPE;
+			packet[2..4].copy_from_slice(&self.sequence_number.to_be_bytes());
+			self.sequence_number = self.sequence_number.wrapping_add(1);
+
+			send(&packet)?;
+		}
+
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+pub(super) mod tests
+{
+	use super::*;
+
+	/// A JPEG of 320x240 pixels with the segments the parser reads, a comment, and padding after the end.
+	pub(in crate::features::rtsp) fn jpeg(luminance_sampling: u8, restart_interval: u16, scan_size: usize) -> Vec<u8>
+	{
+		let mut jpeg = vec![0xFF, START_OF_IMAGE];
+		jpeg.extend_from_slice(&[0xFF, 0xFE, 0, 6]);
+		jpeg.extend_from_slice(b"test");
+		// Both tables in the same segment
+		jpeg.extend_from_slice(&[0xFF, DEFINE_QUANTIZATION_TABLE, 0, 2 + 2 * 65, 0]);
+		jpeg.extend_from_slice(&[1; 64]);
+		jpeg.push(1);
+		jpeg.extend_from_slice(&[2; 64]);
+		jpeg.extend_from_slice(&[0xFF, BASELINE_START_OF_FRAME, 0, 17, 8, 0, 240, 1, 64, 3]);
+		jpeg.extend_from_slice(&[1, luminance_sampling, 0, 2, 0x11, 1, 3, 0x11, 1]);
+		jpeg.extend_from_slice(&[0xFF, DEFINE_HUFFMAN_TABLE, 0, 3, 0]);
+		if restart_interval != 0
+		{
+			jpeg.extend_from_slice(&[0xFF, DEFINE_RESTART_INTERVAL, 0, 4]);
+			jpeg.extend_from_slice(&restart_interval.to_be_bytes());
+		}
+		jpeg.extend_from_slice(&[0xFF, START_OF_SCAN, 0, 12, 3, 1, 0, 2, 0x11, 3, 0x11, 0, 63, 0]);
+		jpeg.extend((0..scan_size).map(|index| (index % 251) as u8));
+		jpeg.extend_from_slice(&[0xFF, END_OF_IMAGE, 0, 0]);
+		jpeg
+	}
+
+	#[test]
+	fn headers_are_parsed()
+	{
+		let jpeg = jpeg(0x22, 0, 100);
+		let frame = JpegFrame::parse(&jpeg).unwrap();
+
+		assert_eq!(frame.jpeg_type, 1);
+		assert_eq!((frame.width, frame.height), (40, 30));
+		assert_eq!(frame.quantization_tables[..64], [1; 64]);
+		assert_eq!(frame.quantization_tables[64..], [2; 64]);
+		assert_eq!(frame.scan, &jpeg[jpeg.len() - 104..jpeg.len() - 4]);
+
+		let jpeg = self::jpeg(0x21, 20, 100);
+		let frame = JpegFrame::parse(&jpeg).unwrap();
+		assert_eq!(frame.jpeg_type, 64);
+		assert_eq!(frame.restart_interval, 20);
+	}
+
+	#[test]
+	fn unsupported_jpegs_are_refused()
+	{
+		assert_eq!(JpegFrame::parse(b"\x89PNG"), Err(JpegError::Malformed));
+		assert_eq!(
+			JpegFrame::parse(&jpeg(0x11, 0, 100)),
+			Err(JpegError::UnsupportedSampling)
+		);
+
+		let mut progressive = jpeg(0x22, 0, 100);
+		let start_of_frame = progressive
+			.windows(2)
+			.position(|bytes| bytes == [0xFF, BASELINE_START_OF_FRAME])
+			.unwrap();
+		progressive[start_of_frame + 1] = 0xC2;
+		assert_eq!(JpegFrame::parse(&progressive), Err(JpegError::NotBaseline));
+
+		let jpeg = jpeg(0x22, 0, 100);
+		assert_eq!(JpegFrame::parse(&jpeg[..50]), Err(JpegError::Malformed));
+	}
+
+	#[test]
+	fn frames_are_split_in_packets()
+	{
+		let jpeg = jpeg(0x22, 0, 3000);
+		let frame = JpegFrame::parse(&jpeg).unwrap();
+		let mut packetizer = JpegPacketizer::new(0x12345678, 0xFFFF);
+
+		let mut packets = Vec::new();
+		packetizer
+			.packetize(&frame, 90_000, |packet| {
+				packets.push(packet.to_vec());
+				Ok(())
+			})
+			.unwrap();
+
+		assert_eq!(packets.len(), 3);
+		assert_eq!(packetizer.sequence_number(), 2);
+		let mut scan = Vec::new();
+		for (index, packet) in packets.iter().enumerate()
+		{
+			assert!(packet.len() <= MAX_PACKET_SIZE);
+			assert_eq!(packet[0], 0x80);
+			assert_eq!(packet[1], (((index == 2) as u8) << 7) | PAYLOAD_TYPE);
+			assert_eq!(packet[2..4], (0xFFFF_u16.wrapping_add(index as u16)).to_be_bytes());
+			assert_eq!(packet[4..8], 90_000_u32.to_be_bytes());
+			assert_eq!(packet[8..12], 0x12345678_u32.to_be_bytes());
+
+			let offset = u32::from_be_bytes([0, packet[13], packet[14], packet[15]]) as usize;
+			assert_eq!(offset, scan.len());
+			assert_eq!(packet[16..20], [1, DYNAMIC_QUANTIZATION_TABLES, 40, 30]);
+			let payload = if index == 0
+			{
+				assert_eq!(packet[20..24], [0, 0, 0, 128]);
+				assert_eq!(packet[24..152], frame.quantization_tables);
+				&packet[152..]
+			}
+			else
+			{
+				&packet[20..]
+			};
+			scan.extend_from_slice(payload);
+		}
+		assert_eq!(scan, frame.scan);
+	}
+
+	#[test]
+	fn restart_markers_header_is_in_every_packet()
+	{
+		let jpeg = jpeg(0x21, 8, 2000);
+		let frame = JpegFrame::parse(&jpeg).unwrap();
+
+		let mut packets = Vec::new();
+		JpegPacketizer::new(1, 0)
+			.packetize(&frame, 0, |packet| {
+				packets.push(packet.to_vec());
+				Ok(())
+			})
+			.unwrap();
+
+		assert_eq!(packets.len(), 2);
+		for packet in packets
+		{
+			assert_eq!(packet[16], 64);
+			assert_eq!(packet[20..24], [0, 8, 0xFF, 0xFF]);
+		}
+	}
+}
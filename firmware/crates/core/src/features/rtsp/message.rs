@@ -0,0 +1,294 @@
+//! The requests and the responses of RTSP 1.0 ([RFC 2326](https://www.rfc-editor.org/rfc/rfc2326)), which look like
+//! the ones of HTTP/1.1. With the TCP transport, the RTP and RTCP packets are interleaved with them on the connection.
+
+/// A request (with its body) or an interleaved packet bigger than this closes the connection.
+const MAX_MESSAGE_SIZE: usize = 4 * 1024;
+/// Starts a packet interleaved in the connection, followed by the channel and the length of the packet.
+pub(super) const INTERLEAVED_MARKER: u8 = b'$';
+
+pub(super) const OK_RESPONSE: u16 = 200;
+pub(super) const BAD_REQUEST_RESPONSE: u16 = 400;
+pub(super) const UNAUTHORIZED_RESPONSE: u16 = 401;
+pub(super) const SESSION_NOT_FOUND_RESPONSE: u16 = 454;
+pub(super) const METHOD_NOT_VALID_IN_THIS_STATE_RESPONSE: u16 = 455;
+pub(super) const UNSUPPORTED_TRANSPORT_RESPONSE: u16 = 461;
+pub(super) const INTERNAL_SERVER_ERROR_RESPONSE: u16 = 500;
+pub(super) const NOT_IMPLEMENTED_RESPONSE: u16 = 501;
+pub(super) const SERVICE_UNAVAILABLE_RESPONSE: u16 = 503;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(super) struct RtspRequest
+{
+	pub method: String,
+	pub uri: String,
+	pub headers: Vec<(String, String)>,
+}
+
+impl RtspRequest
+{
+	/// Returns the value of the first header called `name`, ignoring the case.
+	pub(super) fn header(&self, name: &str) -> Option<&str>
+	{
+		self.headers
+			.iter()
+			.find(|(header, _)| header.eq_ignore_ascii_case(name))
+			.map(|(_, value)| value.as_str())
+	}
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub(super) enum Message
+{
+	Request(RtspRequest),
+	/// An RTCP packet sent by the client on the TCP connection (its receiver reports are ignored).
+	Interleaved,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum MessageError
+{
+	TooLarge,
+	Malformed,
+}
+
+impl core::fmt::Display for MessageError
+{
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result
+	{
+		f.write_str(match self
+		{
+			Self::TooLarge => "the request is too large",
+			Self::Malformed => "the request isn't a valid RTSP request",
+		})
+	}
+}
+
+/// Removes the first complete message from the `buffer` and returns it, or returns `None` if the client hasn't sent all
+/// of it yet. The body of a request is skipped.
+pub(super) fn take_message(buffer: &mut Vec<u8>) -> Result<Option<Message>, MessageError>
+{
+	if buffer.first() == Some(&INTERLEAVED_MARKER)
+	{
+		let Some(length) = buffer
+			.get(2..4)
+			.map(|length| u16::from_be_bytes([length[0], length[1]]) as usize)
+		else
+		{
+			return Ok(None);
+		};
+		if buffer.len() < 4 + length
+		{
+			return Ok(None);
+		}
+
+		buffer.drain(..4 + length);
+		return Ok(Some(Message::Interleaved));
+	}
+
+	let Some(header_end) = buffer.windows(4).position(|bytes| bytes == b"\r\n\r\n")
+	else
+	{
+		if buffer.len() > MAX_MESSAGE_SIZE
+		{
+			return Err(MessageError::TooLarge);
+		}
+		return Ok(None);
+	};
+	let head = core::str::from_utf8(&buffer[..header_end]).map_err(|_| MessageError::Malformed)?;
+	let mut lines = head.split("\r\n");
+
+	let mut request_line = lines.next().unwrap_or_default().split(' ');
+	let (Some(method), Some(uri), Some(version), None) = (
+		request_line.next(),
+		request_line.next(),
+		request_line.next(),
+		request_line.next(),
+	)
+	else
+	{
+		return Err(MessageError::Malformed);
+	};
+	if method.is_empty() || !version.starts_with("RTSP/1.")
+	{
+		return Err(MessageError::Malformed);
+	}
+	let headers = lines
+		.map(|line| {
+			line.split_once(':')
+				.map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+				.ok_or(MessageError::Malformed)
+		})
+		.collect::<Result<Vec<_>, _>>()?;
+	let request = RtspRequest {
+		method: method.to_string(),
+		uri: uri.to_string(),
+		headers,
+	};
+
+	let body_length = match request.header("Content-Length")
+	{
+		Some(length) => length.parse::<usize>().map_err(|_| MessageError::Malformed)?,
+		None => 0,
+	};
+	let message_length = header_end + 4 + body_length;
+	if message_length > MAX_MESSAGE_SIZE
+	{
+		return Err(MessageError::TooLarge);
+	}
+	if buffer.len() < message_length
+	{
+		return Ok(None);
+	}
+
+	buffer.drain(..message_length);
+	Ok(Some(Message::Request(request)))
+}
+
+/// Builds a response, which is sent with the `CSeq` of its request.
+#[derive(Clone, Debug)]
+pub(super) struct RtspResponse
+{
+	pub status: u16,
+	headers: Vec<(&'static str, String)>,
+	body: Option<(&'static str, String)>,
+}
+
+impl RtspResponse
+{
+	pub(super) fn new(status: u16) -> Self
+	{
+		Self {
+			status,
+			headers: Vec::new(),
+			body: None,
+		}
+	}
+
+	pub(super) fn header(mut self, name: &'static str, value: impl Into<String>) -> Self
+	{
+		self.headers.push((name, value.into()));
+		self
+	}
+
+	pub(super) fn body(mut self, content_type: &'static str, body: String) -> Self
+	{
+		self.body = Some((content_type, body));
+		self
+	}
+
+	pub(super) fn to_bytes(&self, cseq: Option<&str>) -> Vec<u8>
+	{
+		let mut response = format!("RTSP/1.0 {} {}\r\n", self.status, reason_phrase(self.status));
+		if let Some(cseq) = cseq
+		{
+			response.push_str(&format!("CSeq: {}\r\n", cseq));
+		}
+		for (name, value) in &self.headers
+		{
+			response.push_str(&format!("{}: {}\r\n", name, value));
+		}
+		match &self.body
+		{
+			Some((content_type, body)) =>
+			{
+				response.push_str(&format!(
+					"Content-Type: {}\r\nContent-Length: {}\r\n\r\n{}",
+					content_type,
+					body.len(),
+					body
+				));
+			},
+			None => response.push_str("\r\n"),
+		}
+
+		response.into_bytes()
+	}
+}
+
+fn reason_phrase(status: u16) -> &'static str
+{
+	match status
+	{
+		OK_RESPONSE => "OK",
+		BAD_REQUEST_RESPONSE => "Bad Request",
+		UNAUTHORIZED_RESPONSE => "Unauthorized",
+		SESSION_NOT_FOUND_RESPONSE => "Session Not Found",
+		METHOD_NOT_VALID_IN_THIS_STATE_RESPONSE => "Method Not Valid in This State",
+		UNSUPPORTED_TRANSPORT_RESPONSE => "Unsupported Transport",
+		INTERNAL_SERVER_ERROR_RESPONSE => "Internal Server Error",
+		NOT_IMPLEMENTED_RESPONSE => "Not Implemented",
+		SERVICE_UNAVAILABLE_RESPONSE => "Service Unavailable",
+		_ => "Unknown",
+	}
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	#[test]
+	fn requests_are_parsed_once_complete()
+	{
+		let request = b"SETUP rtsp://camera/stream/track0 RTSP/1.0\r\nCSeq: 3\r\ntransport:RTP/AVP;unicast;client_port=5000-5001\r\n\r\nOPTIONS";
+		let mut buffer = request[..20].to_vec();
+		assert_eq!(take_message(&mut buffer), Ok(None));
+
+		buffer = request.to_vec();
+		let Ok(Some(Message::Request(request))) = take_message(&mut buffer)
+		else
+		{
+			panic!("The request wasn't parsed");
+		};
+		assert_eq!(request.method, "SETUP");
+		assert_eq!(request.uri, "rtsp://camera/stream/track0");
+		assert_eq!(request.header("CSeq"), Some("3"));
+		assert_eq!(
+			request.header("Transport"),
+			Some("RTP/AVP;unicast;client_port=5000-5001")
+		);
+		assert_eq!(buffer, b"OPTIONS");
+	}
+
+	#[test]
+	fn bodies_and_interleaved_packets_are_skipped()
+	{
+		let mut buffer = b"$\x01\x00\x03abcSET_PARAMETER * RTSP/1.0\r\nCSeq: 4\r\nContent-Length: 5\r\n\r\nab".to_vec();
+
+		assert_eq!(take_message(&mut buffer), Ok(Some(Message::Interleaved)));
+		assert_eq!(take_message(&mut buffer), Ok(None));
+		buffer.extend_from_slice(b"cde");
+		assert!(matches!(take_message(&mut buffer), Ok(Some(Message::Request(_)))));
+		assert!(buffer.is_empty());
+	}
+
+	#[test]
+	fn invalid_requests_are_refused()
+	{
+		assert_eq!(
+			take_message(&mut b"GET / HTTP/1.1\r\n\r\n".to_vec()),
+			Err(MessageError::Malformed)
+		);
+		assert_eq!(
+			take_message(&mut b"OPTIONS * RTSP/1.0\r\nCSeq\r\n\r\n".to_vec()),
+			Err(MessageError::Malformed)
+		);
+		assert_eq!(
+			take_message(&mut vec![b'A'; MAX_MESSAGE_SIZE + 1]),
+			Err(MessageError::TooLarge)
+		);
+	}
+
+	#[test]
+	fn responses_echo_the_sequence_number()
+	{
+		let response = RtspResponse::new(OK_RESPONSE)
+			.header("Session", "1234")
+			.body("application/sdp", "v=0\r\n".to_string());
+
+		assert_eq!(
+			String::from_utf8(response.to_bytes(Some("7"))).unwrap(),
+			"RTSP/1.0 200 OK\r\nCSeq: 7\r\nSession: 1234\r\nContent-Type: application/sdp\r\nContent-Length: 5\r\n\r\nv=0\r\n"
+		);
+	}
+}
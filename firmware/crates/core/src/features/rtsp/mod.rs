@@ -0,0 +1,404 @@
+//! An RTSP server streaming the frames of the camera as RTP/JPEG, for the NVRs (Frigate, Blue Iris, ZoneMinder...)
+//! that don't read the `multipart/x-mixed-replace` stream. Any URL is the stream, like `rtsp://<camera>/stream`, with
+//! the same credentials as the HTTP servers. The RTP packets are sent over UDP or interleaved on the RTSP connection.
+//!
+//! Like the HTTP stream, it only sends the frames captured while the image trigger is enabled.
+
+mod jpeg;
+mod message;
+mod session;
+
+use core::time::Duration;
+use std::{
+	io::{self, Read, Write},
+	net::{Ipv4Addr, TcpListener, TcpStream},
+	sync::{
+		atomic::{AtomicUsize, Ordering},
+		Arc,
+	},
+	time::Instant,
+};
+
+use self::{
+	message::{take_message, Message, RtspResponse, BAD_REQUEST_RESPONSE, SERVICE_UNAVAILABLE_RESPONSE},
+	session::{RtspSession, SESSION_TIMEOUT},
+};
+use crate::features::{frames::FrameBroker, http_server::auth::SharedHttpAuth};
+
+const STACK_SIZE: usize = 8 * 1024;
+/// How long a client waits for a request before checking again if there's a new frame.
+const POLL_PERIOD: Duration = Duration::from_millis(10);
+/// A client that doesn't read the stream for this long is disconnected.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+const READ_BUFFER_SIZE: usize = 512;
+/// How long a refused client has to send its first request, whose `CSeq` the `503` response must repeat. The other
+/// clients wait for it to be accepted.
+const REFUSAL_TIMEOUT: Duration = Duration::from_secs(1);
+
+#[derive(Clone, Debug)]
+pub struct RtspSettings
+{
+	/// Usually 554.
+	pub port: u16,
+	/// Each client has its own thread and copies the frames in its own packets, so the other ones are refused.
+	pub max_clients: usize,
+}
+
+/// Starts a thread accepting the RTSP clients on all the interfaces, each one served by its own thread.
+pub fn start_rtsp_server(settings: RtspSettings, frame_broker: FrameBroker, auth: SharedHttpAuth) -> io::Result<()>
+{
+	let listener = TcpListener::bind((Ipv4Addr::UNSPECIFIED, settings.port))?;
+	log::info!("RTSP server listening on port {}", settings.port);
+
+	serve(listener, settings.max_clients, frame_broker, auth)
+}
+
+fn serve(listener: TcpListener, max_clients: usize, frame_broker: FrameBroker, auth: SharedHttpAuth) -> io::Result<()>
+{
+	let clients_count = Arc::new(AtomicUsize::new(0));
+	std::thread::Builder::new()
+		.name("rtsp".to_string())
+		.stack_size(STACK_SIZE)
+		.spawn(move || {
+			for connection in listener.incoming()
+			{
+				let connection = match connection
+				{
+					Ok(connection) => connection,
+					Err(error) =>
+					{
+						log::warn!("Couldn't accept an RTSP client: {}", error);
+						continue;
+					},
+				};
+				if clients_count.load(Ordering::Relaxed) >= max_clients
+				{
+					log::warn!("Refusing an RTSP client, {} are already connected", max_clients);
+					if let Err(error) = refuse_client(connection)
+					{
+						log::warn!("Couldn't refuse an RTSP client: {}", error);
+					}
+					continue;
+				}
+
+				clients_count.fetch_add(1, Ordering::Relaxed);
+				let client_clients_count = clients_count.clone();
+				let frame_broker = frame_broker.clone();
+				let auth = auth.clone();
+				let spawned = std::thread::Builder::new()
+					.name("rtsp-client".to_string())
+					.stack_size(STACK_SIZE)
+					.spawn(move || {
+						if let Err(error) = serve_client(connection, frame_broker, auth)
+						{
+							log::warn!("RTSP client disconnected: {}", error);
+						}
+						client_clients_count.fetch_sub(1, Ordering::Relaxed);
+					});
+				if let Err(error) = spawned
+				{
+					log::warn!("Couldn't start the thread of an RTSP client: {}", error);
+					clients_count.fetch_sub(1, Ordering::Relaxed);
+				}
+			}
+		})?;
+
+	Ok(())
+}
+
+/// Responds to the first request of the client with a `503`, then closes the connection.
+fn refuse_client(mut connection: TcpStream) -> io::Result<()>
+{
+	connection.set_read_timeout(Some(REFUSAL_TIMEOUT))?;
+	connection.set_write_timeout(Some(REFUSAL_TIMEOUT))?;
+
+	let started_at = Instant::now();
+	let mut input = Vec::new();
+	let mut buffer = [0; READ_BUFFER_SIZE];
+	let cseq = loop
+	{
+		match take_message(&mut input)
+		{
+			Ok(Some(Message::Request(request))) => break request.header("CSeq").map(str::to_string),
+			Ok(None | Some(Message::Interleaved)) if started_at.elapsed() < REFUSAL_TIMEOUT => (),
+			_ => break None,
+		}
+		match connection.read(&mut buffer)?
+		{
+			0 => return Ok(()),
+			length => input.extend_from_slice(&buffer[..length]),
+		}
+	};
+
+	connection.write_all(&RtspResponse::new(SERVICE_UNAVAILABLE_RESPONSE).to_bytes(cseq.as_deref()))
+}
+
+/// Answers the requests of the client and sends it the latest frames while it's playing, until it closes the
+/// connection or tears the session down.
+fn serve_client(mut connection: TcpStream, frame_broker: FrameBroker, auth: SharedHttpAuth) -> io::Result<()>
+{
+	connection.set_nodelay(true)?;
+	connection.set_read_timeout(Some(POLL_PERIOD))?;
+	connection.set_write_timeout(Some(WRITE_TIMEOUT))?;
+	let peer_ip = connection.peer_addr()?.ip();
+	log::info!("RTSP client {} connected", peer_ip);
+
+	let mut session = RtspSession::new(auth, connection.local_addr()?.ip(), peer_ip);
+	let mut frames = frame_broker.subscribe();
+	let mut input = Vec::new();
+	let mut buffer = [0; READ_BUFFER_SIZE];
+	let mut last_request_at = Instant::now();
+	loop
+	{
+		match connection.read(&mut buffer)
+		{
+			Ok(0) => return Ok(()),
+			Ok(length) => input.extend_from_slice(&buffer[..length]),
+			Err(error) if matches!(error.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => (),
+			Err(error) => return Err(error),
+		}
+
+		loop
+		{
+			match take_message(&mut input)
+			{
+				Ok(Some(Message::Request(request))) =>
+				{
+					last_request_at = Instant::now();
+					let response = session.handle(&request);
+					connection.write_all(&response.to_bytes(request.header("CSeq")))?;
+				},
+				// The RTCP reports also keep the session alive
+				Ok(Some(Message::Interleaved)) => last_request_at = Instant::now(),
+				Ok(None) => break,
+				Err(error) =>
+				{
+					connection.write_all(&RtspResponse::new(BAD_REQUEST_RESPONSE).to_bytes(None))?;
+					return Err(io::Error::new(io::ErrorKind::InvalidData, error.to_string()));
+				},
+			}
+		}
+
+		if session.is_torn_down()
+		{
+			log::info!("RTSP client {} tore down its session", peer_ip);
+			return Ok(());
+		}
+		if session.uses_udp() && last_request_at.elapsed() > SESSION_TIMEOUT
+		{
+			log::info!("The RTSP session of {} timed out", peer_ip);
+			return Ok(());
+		}
+		if session.is_playing()
+		{
+			if let Some(frame) = frames.latest_frame()
+			{
+				session.send_frame(&frame, &mut connection)?;
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests
+{
+	use std::net::{SocketAddr, UdpSocket};
+
+	use a13c_embedded::utils::math::micromath::micromath::vector::U16x2;
+	use sha2::{Digest, Sha256};
+	use spin::Mutex;
+
+	use super::*;
+	use crate::{configuration::peripherals::camera::Image, features::http_server::auth::HttpAuthSettings};
+
+	const TOKEN: &str = "0123456789abcdef-token";
+
+	struct JpegImage(Vec<u8>);
+
+	impl Image for JpegImage
+	{
+		fn get_pixels(&self) -> &[u8]
+		{
+			&self.0
+		}
+
+		fn get_size(&self) -> U16x2
+		{
+			U16x2 { x: 320, y: 240 }
+		}
+
+		fn get_timestamp(&self) -> Duration
+		{
+			Duration::ZERO
+		}
+	}
+
+	/// Starts a server accepting a single client on a free port of the loopback interface.
+	fn start_server() -> (SocketAddr, FrameBroker)
+	{
+		let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+		let address = listener.local_addr().unwrap();
+		let frame_broker = FrameBroker::new(1);
+		let auth = Arc::new(Mutex::new(HttpAuthSettings {
+			token_hashes: vec![Sha256::digest(TOKEN).into()],
+			..Default::default()
+		}));
+		serve(listener, 1, frame_broker.clone(), auth).unwrap();
+
+		(address, frame_broker)
+	}
+
+	/// A frame of 4:2:0 JPEG split in 2 packets.
+	fn publish_frame(frame_broker: &FrameBroker)
+	{
+		frame_broker.publish(&JpegImage(jpeg::tests::jpeg(0x22, 0, 2000)));
+	}
+
+	/// Sends the request with the token of [`start_server`] and returns the response, skipping the interleaved packets
+	/// received before it.
+	fn request(connection: &mut TcpStream, request: &str) -> String
+	{
+		let request = request.replacen("\r\n", &format!("\r\nAuthorization: Bearer {}\r\n", TOKEN), 1);
+		connection.write_all(request.as_bytes()).unwrap();
+
+		let mut response = Vec::new();
+		let mut byte = [0];
+		while !response.ends_with(b"\r\n\r\n")
+		{
+			connection.read_exact(&mut byte).unwrap();
+			if response.is_empty() && byte[0] == b'$'
+			{
+				let mut header = [0; 3];
+				connection.read_exact(&mut header).unwrap();
+				let mut packet = vec![0; u16::from_be_bytes([header[1], header[2]]) as usize];
+				connection.read_exact(&mut packet).unwrap();
+				continue;
+			}
+			response.push(byte[0]);
+		}
+		let mut response = String::from_utf8(response).unwrap();
+		if let Some(length) = response
+			.lines()
+			.find_map(|line| line.strip_prefix("Content-Length: "))
+			.map(|length| length.parse().unwrap())
+		{
+			let mut body = vec![0; length];
+			connection.read_exact(&mut body).unwrap();
+			response.push_str(&String::from_utf8(body).unwrap());
+		}
+
+		response
+	}
+
+	fn session_id(response: &str) -> &str
+	{
+		response
+			.lines()
+			.find_map(|line| line.strip_prefix("Session: "))
+			.and_then(|session| session.split(';').next())
+			.unwrap()
+	}
+
+	#[test]
+	fn frames_are_streamed_interleaved()
+	{
+		let (address, frame_broker) = start_server();
+		let mut connection = TcpStream::connect(address).unwrap();
+		connection.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+
+		let response = request(
+			&mut connection,
+			"DESCRIBE rtsp://camera/stream RTSP/1.0\r\nCSeq: 1\r\n\r\n",
+		);
+		assert!(response.starts_with("RTSP/1.0 200 OK\r\nCSeq: 1\r\n"));
+		assert!(response.contains("a=rtpmap:26 JPEG/90000"));
+
+		let response = request(
+			&mut connection,
+			"SETUP rtsp://camera/stream/track0 RTSP/1.0\r\nCSeq: 2\r\nTransport: RTP/AVP/TCP;unicast;interleaved=0-1\r\n\r\n",
+		);
+		assert!(response.contains("Transport: RTP/AVP/TCP;unicast;interleaved=0-1;ssrc="));
+		let session = session_id(&response).to_string();
+
+		let response = request(
+			&mut connection,
+			&format!(
+				"PLAY rtsp://camera/stream RTSP/1.0\r\nCSeq: 3\r\nSession: {}\r\n\r\n",
+				session
+			),
+		);
+		assert!(response.starts_with("RTSP/1.0 200 OK\r\nCSeq: 3\r\n"));
+		publish_frame(&frame_broker);
+
+		let mut header = [0; 4];
+		connection.read_exact(&mut header).unwrap();
+		assert_eq!(header[..2], [b'$', 0]);
+		let mut packet = vec![0; u16::from_be_bytes([header[2], header[3]]) as usize];
+		connection.read_exact(&mut packet).unwrap();
+		assert_eq!(packet[0], 0x80);
+		assert_eq!(packet[1] & 0x7F, 26);
+
+		let response = request(
+			&mut connection,
+			&format!(
+				"TEARDOWN rtsp://camera/stream RTSP/1.0\r\nCSeq: 4\r\nSession: {}\r\n\r\n",
+				session
+			),
+		);
+		assert!(response.starts_with("RTSP/1.0 200 OK\r\nCSeq: 4\r\n"));
+		assert_eq!(connection.read(&mut [0]).unwrap(), 0);
+	}
+
+	#[test]
+	fn frames_are_streamed_over_udp()
+	{
+		let (address, frame_broker) = start_server();
+		let mut connection = TcpStream::connect(address).unwrap();
+		connection.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+		let rtp_socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+		rtp_socket.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+		let rtp_port = rtp_socket.local_addr().unwrap().port();
+
+		let response = request(
+			&mut connection,
+			&format!(
+				"SETUP rtsp://camera/stream/track0 RTSP/1.0\r\nCSeq: 1\r\nTransport: RTP/AVP;unicast;client_port={}-{}\r\n\r\n",
+				rtp_port,
+				rtp_port + 1
+			),
+		);
+		assert!(response.contains(";server_port="));
+		let session = session_id(&response).to_string();
+		request(
+			&mut connection,
+			&format!(
+				"PLAY rtsp://camera/stream RTSP/1.0\r\nCSeq: 2\r\nSession: {}\r\n\r\n",
+				session
+			),
+		);
+		publish_frame(&frame_broker);
+
+		let mut packet = [0; 1500];
+		let length = rtp_socket.recv(&mut packet).unwrap();
+		assert_eq!(packet[1], 26);
+		let length = length + rtp_socket.recv(&mut packet).unwrap();
+		assert_eq!(packet[1], 0x80 | 26);
+		assert!(length > 2000);
+	}
+
+	#[test]
+	fn clients_over_the_limit_are_refused()
+	{
+		let (address, _) = start_server();
+		let mut first = TcpStream::connect(address).unwrap();
+		first.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+		assert!(request(&mut first, "OPTIONS * RTSP/1.0\r\nCSeq: 1\r\n\r\n").contains("Public: "));
+
+		let mut second = TcpStream::connect(address).unwrap();
+		second.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+		let response = request(&mut second, "OPTIONS * RTSP/1.0\r\nCSeq: 1\r\n\r\n");
+		assert!(response.starts_with("RTSP/1.0 503 Service Unavailable\r\n"));
+		assert!(response.contains("CSeq: 1\r\n"));
+		assert!(second.read(&mut [0]).map_or(true, |length| length == 0));
+	}
+}
@@ -0,0 +1,582 @@
+use core::time::Duration;
+use std::{
+	io::{self, Write},
+	net::{IpAddr, SocketAddr, UdpSocket},
+	time::Instant,
+};
+
+use super::{
+	jpeg::{JpegFrame, JpegPacketizer, CLOCK_RATE, PAYLOAD_TYPE},
+	message::{
+		RtspRequest, RtspResponse, BAD_REQUEST_RESPONSE, INTERLEAVED_MARKER, INTERNAL_SERVER_ERROR_RESPONSE,
+		METHOD_NOT_VALID_IN_THIS_STATE_RESPONSE, NOT_IMPLEMENTED_RESPONSE, OK_RESPONSE, SESSION_NOT_FOUND_RESPONSE,
+		UNAUTHORIZED_RESPONSE, UNSUPPORTED_TRANSPORT_RESPONSE,
+	},
+};
+use crate::features::{
+	frames::Frame,
+	http_server::auth::{is_authenticated, SharedHttpAuth, BASIC_CHALLENGE},
+};
+
+/// A session using UDP ends if the client doesn't send any request for this long (the clients send keep-alive requests
+/// like `GET_PARAMETER` more often). The sessions using TCP end with their connection.
+pub(super) const SESSION_TIMEOUT: Duration = Duration::from_secs(60);
+const PUBLIC_METHODS: &str = "OPTIONS, DESCRIBE, SETUP, PLAY, PAUSE, TEARDOWN, GET_PARAMETER, SET_PARAMETER";
+/// The control URL of the only track, relative to the one of the stream.
+const TRACK_CONTROL: &str = "track0";
+
+/// How the RTP packets are sent to the client.
+enum Transport
+{
+	/// The RTCP reports of the client are ignored, so there's no socket for them.
+	Udp
+	{
+		socket: UdpSocket
+	},
+	/// On the RTSP connection.
+	Interleaved
+	{
+		rtp_channel: u8
+	},
+}
+
+/// What the client asked for in the `Transport` header of a `SETUP`.
+#[derive(Debug, PartialEq, Eq)]
+enum TransportRequest
+{
+	Udp
+	{
+		rtp_port: u16, rtcp_port: u16
+	},
+	Interleaved
+	{
+		rtp_channel: u8, rtcp_channel: u8
+	},
+}
+
+/// The state of the only session of an RTSP connection, which streams the frames of the camera as a single video track.
+pub(super) struct RtspSession
+{
+	id: String,
+	auth: SharedHttpAuth,
+	/// The address of the camera the client connected to, used in the SDP.
+	local_ip: IpAddr,
+	peer_ip: IpAddr,
+	transport: Option<Transport>,
+	is_playing: bool,
+	is_torn_down: bool,
+	packetizer: JpegPacketizer,
+	ssrc: u32,
+	/// The RTP timestamps are counted from this instant, starting at a random value.
+	created_at: Instant,
+	first_timestamp: u32,
+	has_skipped_frames: bool,
+}
+
+impl RtspSession
+{
+	pub(super) fn new(auth: SharedHttpAuth, local_ip: IpAddr, peer_ip: IpAddr) -> Self
+	{
+		let ssrc = random() as u32;
+		Self {
+			id: format!("{:016X}", random()),
+			auth,
+			local_ip,
+			peer_ip,
+			transport: None,
+			is_playing: false,
+			is_torn_down: false,
+			packetizer: JpegPacketizer::new(ssrc, random() as u16),
+			ssrc,
+			created_at: Instant::now(),
+			first_timestamp: random() as u32,
+			has_skipped_frames: false,
+		}
+	}
+
+	pub(super) fn is_playing(&self) -> bool
+	{
+		self.is_playing
+	}
+
+	/// Returns `true` after a `TEARDOWN`, when the connection should be closed.
+	pub(super) fn is_torn_down(&self) -> bool
+	{
+		self.is_torn_down
+	}
+
+	/// Returns `true` if the session can time out (check [`SESSION_TIMEOUT`]).
+	pub(super) fn uses_udp(&self) -> bool
+	{
+		matches!(self.transport, Some(Transport::Udp { .. }))
+	}
+
+	/// Returns the response to the `request`, without its `CSeq`.
+	pub(super) fn handle(&mut self, request: &RtspRequest) -> RtspResponse
+	{
+		log::debug!("Handling RTSP {} {}", request.method, request.uri);
+
+		if request.method != "OPTIONS" && !is_authenticated(&self.auth, request.header("Authorization"), &request.uri)
+		{
+			return RtspResponse::new(UNAUTHORIZED_RESPONSE).header("WWW-Authenticate", BASIC_CHALLENGE);
+		}
+
+		match request.method.as_str()
+		{
+			"OPTIONS" => RtspResponse::new(OK_RESPONSE).header("Public", PUBLIC_METHODS),
+			"DESCRIBE" => self.describe(request),
+			"SETUP" => self.setup(request),
+			"PLAY" | "PAUSE" => match self.check_session(request)
+			{
+				Err(response) => response,
+				Ok(()) if request.method == "PAUSE" =>
+				{
+					self.is_playing = false;
+					self.session_response()
+				},
+				Ok(()) =>
+				{
+					self.is_playing = true;
+					let rtp_info = format!(
+						"url={};seq={};rtptime={}",
+						request.uri,
+						self.packetizer.sequence_number(),
+						self.timestamp(Instant::now())
+					);
+					self.session_response()
+						.header("Range", "npt=0.000-")
+						.header("RTP-Info", rtp_info)
+				},
+			},
+			"TEARDOWN" => match self.check_session(request)
+			{
+				Err(response) => response,
+				Ok(()) =>
+				{
+					self.is_playing = false;
+					self.is_torn_down = true;
+					RtspResponse::new(OK_RESPONSE)
+				},
+			},
+			// Sent as keep-alive
+			"GET_PARAMETER" | "SET_PARAMETER" if self.transport.is_some() => self.session_response(),
+			"GET_PARAMETER" | "SET_PARAMETER" => RtspResponse::new(OK_RESPONSE),
+			_ => RtspResponse::new(NOT_IMPLEMENTED_RESPONSE).header("Public", PUBLIC_METHODS),
+		}
+	}
+
+	/// Sends the frame if it's a JPEG that can be streamed, otherwise it's skipped.
+	pub(super) fn send_frame(&mut self, frame: &Frame, connection: &mut impl Write) -> io::Result<()>
+	{
+		let jpeg = match JpegFrame::parse(&frame.pixels)
+		{
+			Ok(jpeg) => jpeg,
+			Err(error) =>
+			{
+				if !self.has_skipped_frames
+				{
+					log::warn!("The frames can't be streamed over RTSP: {}", error);
+					self.has_skipped_frames = true;
+				}
+				return Ok(());
+			},
+		};
+		let timestamp = self.timestamp(frame.published_at);
+
+		match &self.transport
+		{
+			Some(Transport::Udp { socket }) => self
+				.packetizer
+				.packetize(&jpeg, timestamp, |packet| socket.send(packet).map(|_| ())),
+			Some(Transport::Interleaved { rtp_channel }) =>
+			{
+				let mut interleaved = Vec::new();
+				self.packetizer.packetize(&jpeg, timestamp, |packet| {
+					interleaved.clear();
+					interleaved.extend_from_slice(&[INTERLEAVED_MARKER, *rtp_channel]);
+					interleaved.extend_from_slice(&(packet.len() as u16).to_be_bytes());
+					interleaved.extend_from_slice(packet);
+					connection.write_all(&interleaved)
+				})
+			},
+			None => Ok(()),
+		}
+	}
+
+	fn describe(&self, request: &RtspRequest) -> RtspResponse
+	{
+		let address_type = match self.local_ip
+		{
+			IpAddr::V4(_) => "IP4",
+			IpAddr::V6(_) => "IP6",
+		};
+		let sdp = format!(
+			"v=0\r\n\
+			o=- {id} 1 IN {address_type} {local_ip}\r\n\
+			s=Camera\r\n\
+			c=IN {address_type} {unspecified}\r\n\
+			t=0 0\r\n\
+			a=control:*\r\n\
+			m=video 0 RTP/AVP {PAYLOAD_TYPE}\r\n\
+			a=rtpmap:{PAYLOAD_TYPE} JPEG/{CLOCK_RATE}\r\n\
+			a=control:{TRACK_CONTROL}\r\n",
+			id = self.ssrc,
+			local_ip = self.local_ip,
+			unspecified = if self.local_ip.is_ipv4() { "0.0.0.0" } else { "::" },
+		);
+
+		// The relative control URLs are resolved against it
+		let content_base = if request.uri.ends_with('/')
+		{
+			request.uri.clone()
+		}
+		else
+		{
+			format!("{}/", request.uri)
+		};
+		RtspResponse::new(OK_RESPONSE)
+			.header("Content-Base", content_base)
+			.body("application/sdp", sdp)
+	}
+
+	fn setup(&mut self, request: &RtspRequest) -> RtspResponse
+	{
+		if self.transport.is_some() && !self.has_session(request)
+		{
+			return RtspResponse::new(SESSION_NOT_FOUND_RESPONSE);
+		}
+		let Some(transport_header) = request.header("Transport")
+		else
+		{
+			return RtspResponse::new(BAD_REQUEST_RESPONSE);
+		};
+		// The client can propose several transports, in its order of preference
+		let Some(transport_request) = transport_header.split(',').find_map(parse_transport)
+		else
+		{
+			return RtspResponse::new(UNSUPPORTED_TRANSPORT_RESPONSE);
+		};
+
+		let (transport, transport_response) = match transport_request
+		{
+			TransportRequest::Udp { rtp_port, rtcp_port } =>
+			{
+				let (socket, server_port) = match self.open_rtp_socket(rtp_port)
+				{
+					Ok(socket) => socket,
+					Err(error) =>
+					{
+						log::warn!("Couldn't open the RTP socket of an RTSP session: {}", error);
+						return RtspResponse::new(INTERNAL_SERVER_ERROR_RESPONSE);
+					},
+				};
+				(
+					Transport::Udp { socket },
+					format!(
+						"RTP/AVP;unicast;client_port={}-{};server_port={}-{};ssrc={:08X}",
+						rtp_port,
+						rtcp_port,
+						server_port,
+						server_port.wrapping_add(1),
+						self.ssrc
+					),
+				)
+			},
+			TransportRequest::Interleaved {
+				rtp_channel,
+				rtcp_channel,
+			} => (
+				Transport::Interleaved { rtp_channel },
+				format!(
+					"RTP/AVP/TCP;unicast;interleaved={}-{};ssrc={:08X}",
+					rtp_channel, rtcp_channel, self.ssrc
+				),
+			),
+		};
+
+		log::info!(
+			"RTSP client {} set up the stream with {}",
+			self.peer_ip,
+			transport_response
+		);
+		self.transport = Some(transport);
+		self.session_response().header("Transport", transport_response)
+	}
+
+	/// Returns the socket sending the RTP packets to the client, and its port.
+	fn open_rtp_socket(&self, client_port: u16) -> io::Result<(UdpSocket, u16)>
+	{
+		let socket = UdpSocket::bind(SocketAddr::new(self.local_ip, 0))?;
+		socket.connect(SocketAddr::new(self.peer_ip, client_port))?;
+		let port = socket.local_addr()?.port();
+
+		Ok((socket, port))
+	}
+
+	/// Returns an error response if the request isn't about the session that has been set up.
+	fn check_session(&self, request: &RtspRequest) -> Result<(), RtspResponse>
+	{
+		if self.transport.is_none()
+		{
+			return Err(RtspResponse::new(METHOD_NOT_VALID_IN_THIS_STATE_RESPONSE));
+		}
+		if !self.has_session(request)
+		{
+			return Err(RtspResponse::new(SESSION_NOT_FOUND_RESPONSE));
+		}
+
+		Ok(())
+	}
+
+	fn has_session(&self, request: &RtspRequest) -> bool
+	{
+		request
+			.header("Session")
+			.and_then(|session| session.split(';').next())
+			.is_some_and(|id| id.trim() == self.id)
+	}
+
+	fn session_response(&self) -> RtspResponse
+	{
+		RtspResponse::new(OK_RESPONSE).header("Session", format!("{};timeout={}", self.id, SESSION_TIMEOUT.as_secs()))
+	}
+
+	/// The RTP timestamp of a frame published at `instant`, in ticks of the 90 kHz clock (it wraps around).
+	fn timestamp(&self, instant: Instant) -> u32
+	{
+		let ticks = instant.saturating_duration_since(self.created_at).as_micros() * CLOCK_RATE as u128 / 1_000_000;
+		self.first_timestamp.wrapping_add(ticks as u32)
+	}
+}
+
+/// Parses one of the transports of a `Transport` header, like `RTP/AVP;unicast;client_port=5000-5001` or
+/// `RTP/AVP/TCP;unicast;interleaved=0-1`. Returns `None` if it isn't supported (like multicast).
+fn parse_transport(transport: &str) -> Option<TransportRequest>
+{
+	let mut parameters = transport.trim().split(';').map(str::trim);
+	let is_tcp = match parameters.next()?
+	{
+		"RTP/AVP" | "RTP/AVP/UDP" => false,
+		"RTP/AVP/TCP" => true,
+		_ => return None,
+	};
+
+	let mut ports = None;
+	let mut channels = None;
+	for parameter in parameters
+	{
+		let (name, value) = parameter.split_once('=').unwrap_or((parameter, ""));
+		match name
+		{
+			"multicast" => return None,
+			"client_port" => ports = Some(parse_range::<u16>(value)?),
+			"interleaved" => channels = Some(parse_range::<u8>(value)?),
+			_ => (),
+		}
+	}
+
+	if is_tcp
+	{
+		// The first channels if the client lets the server choose
+		let (rtp_channel, rtcp_channel) = channels.unwrap_or((0, 1));
+		return Some(TransportRequest::Interleaved {
+			rtp_channel,
+			rtcp_channel,
+		});
+	}
+
+	let (rtp_port, rtcp_port) = ports?;
+	Some(TransportRequest::Udp { rtp_port, rtcp_port })
+}
+
+/// Parses `first-second` or `first` (then the second is the next one).
+fn parse_range<T: core::str::FromStr + Copy + TryFrom<u32> + Into<u32>>(range: &str) -> Option<(T, T)>
+{
+	match range.split_once('-')
+	{
+		Some((first, second)) => Some((first.parse().ok()?, second.parse().ok()?)),
+		None =>
+		{
+			let first: T = range.parse().ok()?;
+			Some((first, T::try_from(first.into() + 1).ok()?))
+		},
+	}
+}
+
+/// The session IDs, the SSRCs and the first sequence numbers and timestamps must be unpredictable, so they're taken
+/// from the random number generator of the OS (like the salts of the passwords).
+fn random() -> u64
+{
+	let mut bytes = [0; 8];
+	getrandom::getrandom(&mut bytes).expect("The random number generator is always available");
+
+	u64::from_le_bytes(bytes)
+}
+
+#[cfg(test)]
+mod tests
+{
+	use std::{net::Ipv4Addr, sync::Arc};
+
+	use spin::Mutex;
+
+	use super::*;
+	use crate::features::http_server::auth::{HttpAuthSettings, HttpUser};
+
+	const LOCALHOST: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+	/// admin:password
+	const AUTHORIZATION: &str = "Basic YWRtaW46cGFzc3dvcmQ=";
+
+	fn auth() -> SharedHttpAuth
+	{
+		Arc::new(Mutex::new(HttpAuthSettings {
+			users: vec![HttpUser::new("admin".to_string(), "password", 1_000)],
+			..Default::default()
+		}))
+	}
+
+	fn request(method: &str, headers: &[(&str, &str)]) -> RtspRequest
+	{
+		RtspRequest {
+			method: method.to_string(),
+			uri: "rtsp://127.0.0.1/stream".to_string(),
+			headers: headers
+				.iter()
+				.map(|(name, value)| (name.to_string(), value.to_string()))
+				.collect(),
+		}
+	}
+
+	/// Like [`request`], with the credentials of the user of [`auth`].
+	fn authorized_request(method: &str, headers: &[(&str, &str)]) -> RtspRequest
+	{
+		let mut request = request(method, headers);
+		request
+			.headers
+			.push(("Authorization".to_string(), AUTHORIZATION.to_string()));
+		request
+	}
+
+	fn session_id(response: &RtspResponse) -> String
+	{
+		let response = String::from_utf8(response.to_bytes(None)).unwrap();
+		let session = response
+			.lines()
+			.find_map(|line| line.strip_prefix("Session: "))
+			.unwrap();
+		session.split(';').next().unwrap().to_string()
+	}
+
+	#[test]
+	fn transports_are_parsed()
+	{
+		assert_eq!(
+			parse_transport("RTP/AVP;unicast;client_port=5000-5001"),
+			Some(TransportRequest::Udp {
+				rtp_port: 5000,
+				rtcp_port: 5001
+			})
+		);
+		assert_eq!(
+			parse_transport(" RTP/AVP/TCP;unicast;interleaved=2-3"),
+			Some(TransportRequest::Interleaved {
+				rtp_channel: 2,
+				rtcp_channel: 3
+			})
+		);
+		assert_eq!(
+			parse_transport("RTP/AVP/TCP;unicast"),
+			Some(TransportRequest::Interleaved {
+				rtp_channel: 0,
+				rtcp_channel: 1
+			})
+		);
+		assert_eq!(parse_transport("RTP/AVP;multicast;client_port=5000-5001"), None);
+		assert_eq!(parse_transport("RTP/AVP;unicast"), None);
+		assert_eq!(parse_transport("RAW/RAW/UDP;unicast;client_port=5000"), None);
+	}
+
+	#[test]
+	fn session_is_set_up_then_played()
+	{
+		let mut session = RtspSession::new(auth(), LOCALHOST, LOCALHOST);
+
+		let response = session.handle(&authorized_request("DESCRIBE", &[]));
+		let response = String::from_utf8(response.to_bytes(Some("2"))).unwrap();
+		assert!(response.contains("Content-Base: rtsp://127.0.0.1/stream/\r\n"));
+		assert!(response.contains("m=video 0 RTP/AVP 26\r\na=rtpmap:26 JPEG/90000\r\na=control:track0\r\n"));
+
+		assert_eq!(
+			session.handle(&authorized_request("PLAY", &[])).status,
+			METHOD_NOT_VALID_IN_THIS_STATE_RESPONSE
+		);
+		let response = session.handle(&authorized_request(
+			"SETUP",
+			&[("Transport", "RTP/AVP;multicast, RTP/AVP/TCP;unicast;interleaved=0-1")],
+		));
+		assert_eq!(response.status, OK_RESPONSE);
+		let id = session_id(&response);
+		assert!(!session.uses_udp());
+
+		assert_eq!(
+			session
+				.handle(&authorized_request("PLAY", &[("Session", "unknown")]))
+				.status,
+			SESSION_NOT_FOUND_RESPONSE
+		);
+		assert_eq!(
+			session.handle(&authorized_request("PLAY", &[("Session", &id)])).status,
+			OK_RESPONSE
+		);
+		assert!(session.is_playing());
+		assert_eq!(
+			session
+				.handle(&authorized_request("TEARDOWN", &[("Session", &id)]))
+				.status,
+			OK_RESPONSE
+		);
+		assert!(session.is_torn_down());
+	}
+
+	#[test]
+	fn udp_transport_opens_a_socket()
+	{
+		let mut session = RtspSession::new(auth(), LOCALHOST, LOCALHOST);
+
+		let response = session.handle(&authorized_request(
+			"SETUP",
+			&[("Transport", "RTP/AVP;unicast;client_port=5000-5001")],
+		));
+		let response = String::from_utf8(response.to_bytes(None)).unwrap();
+		assert!(response.contains("Transport: RTP/AVP;unicast;client_port=5000-5001;server_port="));
+		assert!(session.uses_udp());
+		assert_eq!(
+			session
+				.handle(&authorized_request("SETUP", &[("Transport", "RTP/AVP;multicast")]))
+				.status,
+			SESSION_NOT_FOUND_RESPONSE
+		);
+	}
+
+	#[test]
+	fn only_options_are_allowed_without_credentials()
+	{
+		let mut session = RtspSession::new(auth(), LOCALHOST, LOCALHOST);
+
+		assert_eq!(session.handle(&request("OPTIONS", &[])).status, OK_RESPONSE);
+		let response = session.handle(&request("DESCRIBE", &[]));
+		assert_eq!(response.status, UNAUTHORIZED_RESPONSE);
+		assert!(String::from_utf8(response.to_bytes(None))
+			.unwrap()
+			.contains("WWW-Authenticate: Basic realm=\"camera\""));
+		assert_eq!(session.handle(&authorized_request("DESCRIBE", &[])).status, OK_RESPONSE);
+
+		let mut session = RtspSession::new(Arc::default(), LOCALHOST, LOCALHOST);
+		assert_eq!(
+			session
+				.handle(&request("DESCRIBE", &[("Authorization", AUTHORIZATION)]))
+				.status,
+			UNAUTHORIZED_RESPONSE
+		);
+	}
+}
@@ -0,0 +1,418 @@
+use core::{fmt::Display, ops::RangeInclusive};
+
+use a13c_embedded::peripherals::time::real_time::time::Time;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+	configuration::peripherals::{
+		camera::{InvalidSensorSetting, PixelFormat, SensorSettings},
+		settings_store::SettingsStore,
+	},
+	features::{
+		http_server::auth::HttpAuthSettings,
+		mqtt::MqttBroker,
+		ota::signature::FirmwareVersion,
+		provisioning::WifiCredentials,
+		trigger::{Arming, EnableOnConditions},
+	},
+};
+
+/// Identifies a blob written by [`Settings::save`].
+const MAGIC: [u8; 4] = *b"CSET";
+/// The version of the current [`Settings`] layout. When the layout changes in a way that the serde defaults can't
+/// handle, increase it and add the function that converts the previous version to [`MIGRATIONS`].
+const CURRENT_VERSION: u16 = 2;
+/// `MIGRATIONS[i]` converts the JSON of version `i + 1` to the JSON of version `i + 2`.
+const MIGRATIONS: [fn(&mut Value); CURRENT_VERSION as usize - 1] = [wifi_network_to_list];
+/// Magic number, version and CRC32 of the JSON.
+const HEADER_SIZE: usize = MAGIC.len() + 2 + 4;
+/// The largest frame of the OV2640.
+const MAX_FRAME_SIZE: (i32, i32) = (1600, 1200);
+const SECONDS_PER_DAY: i32 = 24 * 60 * 60;
+/// From UTC-12 to UTC+14, the offsets used around the world.
+const UTC_OFFSET_RANGE: RangeInclusive<i32> = -12 * 60 * 60..=14 * 60 * 60;
+
+/// Everything that can be tuned at runtime and must survive a reboot. A `None` field uses the default of the board or
+/// of the [`Customization`](crate::configuration::customization::Customization).
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct Settings
+{
+	/// Applied by the board when it creates the camera, changed with `POST /settings`.
+	pub camera: Option<CameraSettings>,
+	pub sensor: SensorSettings,
+	/// Changed with `POST /settings`.
+	pub trigger: Option<TriggerSchedule>,
+	pub arming: Arming,
+	/// Applied by the board when it creates the real time clock, changed with `POST /settings`.
+	pub utc_offset_seconds: Option<i32>,
+	/// Saved by the setup page, from the one with the highest priority. If it's empty the camera starts the setup
+	/// access point.
+	pub wifi_networks: Vec<WifiCredentials>,
+	/// The highest minimum rollback version of the firmware updates installed so far. Older images are refused even if
+	/// they are signed, so that a known vulnerable firmware can't be installed again.
+	pub ota_rollback_floor: FirmwareVersion,
+	/// The credentials and the allowed origins of the HTTP servers, changed with `POST /auth`.
+	pub http_auth: HttpAuthSettings,
+	/// The manifest of the firmware updates the camera checks by itself (check
+	/// [`OtaPullSettings`](crate::features::ota::OtaPullSettings)), changed with `POST /ota/manifest`. If it's `None`
+	/// the updates must be uploaded to `POST /ota`.
+	pub ota_manifest_url: Option<String>,
+	/// Applied by the board when it creates the MQTT client, changed with `POST /mqtt/broker`. If it's `None` the
+	/// camera doesn't use MQTT.
+	pub mqtt_broker: Option<MqttBroker>,
+}
+
+/// The [`Settings`] applied when the camera is created, read with `GET /settings` and changed with `POST /settings`
+/// (which restarts the camera to apply them).
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct BootSettings
+{
+	pub camera: Option<CameraSettings>,
+	pub trigger: Option<TriggerSchedule>,
+	pub utc_offset_seconds: Option<i32>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CameraSettings
+{
+	pub width: u16,
+	pub height: u16,
+	pub pixel_format: PixelFormat,
+	/// From 0 (smallest file) to 100 (best image).
+	pub jpeg_quality: u8,
+}
+
+/// When the image trigger is enabled (check [`EnableOnConditions`]).
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum TriggerSchedule
+{
+	Always,
+	Never,
+	TimeWindows(Vec<TimeWindow>),
+}
+
+/// A range of the day, with both ends included.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TimeWindow
+{
+	/// Seconds since midnight.
+	pub start: u32,
+	/// Seconds since midnight.
+	pub end: u32,
+}
+
+impl Settings
+{
+	/// Reads the settings from the `store`. Returns `Ok(None)` if they have never been saved.
+	pub fn load<S: SettingsStore>(store: &mut S) -> Result<Option<Self>, LoadSettingsError<S::Error>>
+	{
+		let Some(blob) = store.read().map_err(LoadSettingsError::Store)?
+		else
+		{
+			return Ok(None);
+		};
+
+		Self::decode(&blob).map(Some)
+	}
+
+	/// Like [`Self::load`], but returns the default settings (and logs why) if they can't be loaded.
+	pub fn load_or_default<S: SettingsStore>(store: &mut S) -> Self
+	{
+		match Self::load(store)
+		{
+			Ok(settings) => settings.unwrap_or_default(),
+			Err(error) =>
+			{
+				log::error!("Couldn't load the settings, the default ones are used: {:?}", error);
+				Self::default()
+			},
+		}
+	}
+
+	pub fn save<S: SettingsStore>(&self, store: &mut S) -> Result<(), S::Error>
+	{
+		store.write(&self.encode())
+	}
+
+	fn encode(&self) -> Vec<u8>
+	{
+		let json = serde_json::to_vec(self).expect("`Settings` can always be serialized");
+
+		let mut blob = Vec::with_capacity(HEADER_SIZE + json.len());
+		blob.extend_from_slice(&MAGIC);
+		blob.extend_from_slice(&CURRENT_VERSION.to_le_bytes());
+		blob.extend_from_slice(&crc32fast::hash(&json).to_le_bytes());
+		blob.extend_from_slice(&json);
+
+		blob
+	}
+
+	fn decode<E>(blob: &[u8]) -> Result<Self, LoadSettingsError<E>>
+	{
+		if blob.len() < HEADER_SIZE || blob[..MAGIC.len()] != MAGIC
+		{
+			return Err(LoadSettingsError::Corrupted);
+		}
+		let version = u16::from_le_bytes([blob[4], blob[5]]);
+		let checksum = u32::from_le_bytes([blob[6], blob[7], blob[8], blob[9]]);
+		let json = &blob[HEADER_SIZE..];
+		if crc32fast::hash(json) != checksum
+		{
+			return Err(LoadSettingsError::Corrupted);
+		}
+		if version == 0 || version > CURRENT_VERSION
+		{
+			return Err(LoadSettingsError::UnsupportedVersion(version));
+		}
+
+		let mut json = serde_json::from_slice(json).map_err(LoadSettingsError::Invalid)?;
+		for migration in &MIGRATIONS[version as usize - 1..]
+		{
+			migration(&mut json);
+		}
+
+		let settings: Self = serde_json::from_value(json).map_err(LoadSettingsError::Invalid)?;
+		settings.sensor.validate().map_err(LoadSettingsError::OutOfRange)?;
+
+		Ok(settings)
+	}
+}
+
+impl BootSettings
+{
+	pub fn validate(&self) -> Result<(), InvalidBootSetting>
+	{
+		let mut ranges = Vec::new();
+		if let Some(camera) = &self.camera
+		{
+			ranges.push(("camera.width", camera.width as i32, 1..=MAX_FRAME_SIZE.0));
+			ranges.push(("camera.height", camera.height as i32, 1..=MAX_FRAME_SIZE.1));
+			ranges.push(("camera.jpeg_quality", camera.jpeg_quality as i32, 0..=100));
+		}
+		if let Some(TriggerSchedule::TimeWindows(windows)) = &self.trigger
+		{
+			for window in windows
+			{
+				// Saturated so that a value too big for an `i32` is still out of the range
+				let seconds = |seconds: u32| seconds.min(i32::MAX as u32) as i32;
+				ranges.push(("trigger.start", seconds(window.start), 0..=SECONDS_PER_DAY - 1));
+				ranges.push(("trigger.end", seconds(window.end), 0..=SECONDS_PER_DAY - 1));
+			}
+		}
+		if let Some(utc_offset_seconds) = self.utc_offset_seconds
+		{
+			ranges.push(("utc_offset_seconds", utc_offset_seconds, UTC_OFFSET_RANGE));
+		}
+
+		match ranges.into_iter().find(|(_, value, range)| !range.contains(value))
+		{
+			Some((name, _, range)) => Err(InvalidBootSetting { name, range }),
+			None => Ok(()),
+		}
+	}
+
+	/// Returns these settings with the changes in the JSON object `changes` applied (the settings missing from it keep
+	/// their current value, and the ones set to `null` return to the default). If a setting is unknown or out of its
+	/// range, the error describes it.
+	pub fn with_json_changes(&self, changes: &[u8]) -> Result<Self, String>
+	{
+		let changes = match serde_json::from_slice::<Value>(changes)
+		{
+			Ok(Value::Object(changes)) => changes,
+			Ok(_) => return Err("The settings must be a JSON object".to_string()),
+			Err(error) => return Err(error.to_string()),
+		};
+
+		let Ok(Value::Object(mut settings)) = serde_json::to_value(self)
+		else
+		{
+			unreachable!("`BootSettings` is serialized as a JSON object");
+		};
+		settings.extend(changes);
+		let settings = serde_json::from_value::<Self>(Value::Object(settings)).map_err(|error| error.to_string())?;
+		settings.validate().map_err(|error| error.to_string())?;
+
+		Ok(settings)
+	}
+}
+
+impl Settings
+{
+	pub fn boot_settings(&self) -> BootSettings
+	{
+		BootSettings {
+			camera: self.camera,
+			trigger: self.trigger.clone(),
+			utc_offset_seconds: self.utc_offset_seconds,
+		}
+	}
+
+	pub fn set_boot_settings(&mut self, boot_settings: BootSettings)
+	{
+		self.camera = boot_settings.camera;
+		self.trigger = boot_settings.trigger;
+		self.utc_offset_seconds = boot_settings.utc_offset_seconds;
+	}
+}
+
+impl TriggerSchedule
+{
+	pub fn to_enable_on_conditions(&self) -> EnableOnConditions<Vec<RangeInclusive<Time>>>
+	{
+		match self
+		{
+			TriggerSchedule::Always => EnableOnConditions::Always,
+			TriggerSchedule::Never => EnableOnConditions::Never,
+			TriggerSchedule::TimeWindows(windows) => EnableOnConditions::TimeWindows {
+				ranges: windows
+					.iter()
+					.filter_map(|window| Some(seconds_to_time(window.start)?..=seconds_to_time(window.end)?))
+					.collect(),
+			},
+		}
+	}
+}
+
+/// Version 1 had a single `wifi` network, version 2 has the `wifi_networks` list.
+fn wifi_network_to_list(json: &mut Value)
+{
+	if let Some(settings) = json.as_object_mut()
+	{
+		let network = settings.remove("wifi").filter(|network| !network.is_null());
+		settings.insert("wifi_networks".to_string(), Value::Array(network.into_iter().collect()));
+	}
+}
+
+fn seconds_to_time(seconds: u32) -> Option<Time>
+{
+	Time::from_hms((seconds / 3600) as u8, (seconds / 60 % 60) as u8, (seconds % 60) as u8).ok()
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InvalidBootSetting
+{
+	pub name: &'static str,
+	pub range: RangeInclusive<i32>,
+}
+
+impl Display for InvalidBootSetting
+{
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result
+	{
+		write!(
+			f,
+			"`{}` must be between {} and {}",
+			self.name,
+			self.range.start(),
+			self.range.end()
+		)
+	}
+}
+
+#[derive(Debug)]
+pub enum LoadSettingsError<E>
+{
+	Store(E),
+	/// The magic number or the checksum don't match, so the blob wasn't completely written (or it's not settings).
+	Corrupted,
+	/// The settings were saved by a newer firmware.
+	UnsupportedVersion(u16),
+	Invalid(serde_json::Error),
+	OutOfRange(InvalidSensorSetting),
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	fn blob(version: u16, json: &str) -> Vec<u8>
+	{
+		let mut blob = MAGIC.to_vec();
+		blob.extend_from_slice(&version.to_le_bytes());
+		blob.extend_from_slice(&crc32fast::hash(json.as_bytes()).to_le_bytes());
+		blob.extend_from_slice(json.as_bytes());
+		blob
+	}
+
+	#[test]
+	fn single_wifi_network_of_version_1_is_migrated()
+	{
+		let settings = Settings::decode::<()>(&blob(1, r#"{"wifi":{"ssid":"Home","password":"password"}}"#)).unwrap();
+		assert_eq!(settings.wifi_networks.len(), 1);
+		assert_eq!(settings.wifi_networks[0].ssid, "Home");
+
+		let settings = Settings::decode::<()>(&blob(1, r#"{"wifi":null}"#)).unwrap();
+		assert!(settings.wifi_networks.is_empty());
+	}
+
+	#[test]
+	fn saved_settings_are_decoded()
+	{
+		let settings = Settings {
+			utc_offset_seconds: Some(3600),
+			mqtt_broker: Some(MqttBroker {
+				url: "mqtt://192.168.1.2:1883".to_string(),
+				username: Some("camera".to_string()),
+				password: None,
+			}),
+			..Default::default()
+		};
+
+		assert_eq!(Settings::decode::<()>(&settings.encode()).unwrap(), settings);
+	}
+
+	#[test]
+	fn boot_settings_are_changed_with_json()
+	{
+		let settings = BootSettings {
+			utc_offset_seconds: Some(3600),
+			..Default::default()
+		};
+
+		let changed = settings
+			.with_json_changes(
+				br#"{"camera":{"width":800,"height":600,"pixel_format":"Jpeg","jpeg_quality":80},
+				"trigger":{"TimeWindows":[{"start":79200,"end":21600}]}}"#,
+			)
+			.unwrap();
+		assert_eq!(changed.camera.map(|camera| camera.width), Some(800));
+		assert_eq!(
+			changed.trigger,
+			Some(TriggerSchedule::TimeWindows(vec![TimeWindow {
+				start: 79200,
+				end: 21600,
+			}]))
+		);
+		assert_eq!(changed.utc_offset_seconds, Some(3600));
+
+		let reset = changed.with_json_changes(br#"{"utc_offset_seconds":null}"#).unwrap();
+		assert_eq!(reset.utc_offset_seconds, None);
+		assert_eq!(reset.camera, changed.camera);
+	}
+
+	#[test]
+	fn invalid_boot_settings_are_refused()
+	{
+		let settings = BootSettings::default();
+
+		assert!(settings.with_json_changes(br#"{"timezone":3600}"#).is_err());
+		assert!(settings.with_json_changes(br#"[]"#).is_err());
+		assert_eq!(
+			settings.with_json_changes(br#"{"utc_offset_seconds":54000}"#),
+			Err("`utc_offset_seconds` must be between -43200 and 50400".to_string())
+		);
+		assert!(settings
+			.with_json_changes(br#"{"trigger":{"TimeWindows":[{"start":0,"end":86400}]}}"#)
+			.is_err());
+		assert!(settings
+			.with_json_changes(br#"{"camera":{"width":0,"height":600,"pixel_format":"Jpeg","jpeg_quality":80}}"#)
+			.is_err());
+		assert!(settings
+			.with_json_changes(br#"{"camera":{"width":800,"height":600,"pixel_format":"Jpeg","jpeg_quality":101}}"#)
+			.is_err());
+	}
+}
@@ -0,0 +1,631 @@
+use core::time::Duration;
+
+use a13c_embedded::utils::math::micromath::micromath::vector::U16x2;
+
+/// Bytes before the first frame: the `RIFF` header, the `hdrl` list and the header of the `movi` list.
+pub const AVI_HEADER_SIZE: u32 = 224;
+/// Offset of the `movi` FourCC, from which the offsets in the `idx1` index start.
+const MOVI_OFFSET: u32 = 220;
+/// Offset of `dwTotalFrames` in the `avih` header, which is `0` until the file is finished.
+const TOTAL_FRAMES_OFFSET: usize = 48;
+const MICROSECONDS_PER_FRAME_OFFSET: usize = 32;
+const WIDTH_OFFSET: usize = 64;
+const HEIGHT_OFFSET: usize = 68;
+/// Used until the frame rate can be calculated from the timestamps of the frames.
+const DEFAULT_MICROSECONDS_PER_FRAME: u32 = 100_000;
+const FRAME_CHUNK_ID: &[u8; 4] = b"00dc";
+const INDEX_ENTRY_SIZE: u32 = 16;
+const AVIF_HASINDEX: u32 = 0x10;
+const AVIIF_KEYFRAME: u32 = 0x10;
+
+/// A file an AVI can be written to, which must support writing at any offset (to patch the header when it's
+/// finished).
+pub trait AviFile
+{
+	type Error;
+
+	fn length(&mut self) -> Result<u32, Self::Error>;
+	/// Reads from `offset` until `buffer` is full or the file ends, and returns the number of bytes read.
+	fn read_at(&mut self, offset: u32, buffer: &mut [u8]) -> Result<usize, Self::Error>;
+	/// Writes `bytes` at `offset`, which can be at most the length of the file.
+	fn write_at(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error>;
+}
+
+/// Writes the JPEG frames in an AVI file with an MJPG stream, while they are captured.
+///
+/// The frames are written right away, while the `idx1` index and the sizes in the header are written by
+/// [`Self::finish`]. Until then the total number of frames in the header is `0`, which is how [`repair_avi`]
+/// recognizes a file whose writing was interrupted. More frames can be written after finishing the file, and then it
+/// must be finished again.
+#[derive(Clone, Debug, Default)]
+pub struct AviWriter
+{
+	width: u16,
+	height: u16,
+	index: Vec<IndexEntry>,
+	/// Bytes of the frame chunks in the `movi` list.
+	movi_size: u32,
+	max_frame_size: u32,
+	first_timestamp: Option<Duration>,
+	last_timestamp: Duration,
+	/// How long each frame is shown, if it's not calculated from the timestamps.
+	frame_duration: Option<Duration>,
+	is_finished: bool,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct IndexEntry
+{
+	/// From the `movi` FourCC.
+	offset: u32,
+	size: u32,
+}
+
+impl AviWriter
+{
+	pub fn new() -> Self
+	{
+		Self::default()
+	}
+
+	/// Creates a writer that shows each frame for `frame_duration`, ignoring the timestamps (like for a timelapse).
+	pub fn with_frame_duration(frame_duration: Duration) -> Self
+	{
+		Self {
+			frame_duration: Some(frame_duration),
+			..Self::default()
+		}
+	}
+
+	/// Continues writing a file written by a writer created with [`Self::with_frame_duration`], finished or not: the new
+	/// frames are written after the last complete one. Returns `None` if the file isn't an AVI (like if it's empty).
+	pub fn resume<F: AviFile>(file: &mut F, frame_duration: Duration) -> Result<Option<Self>, F::Error>
+	{
+		let header = match read_header(file)
+		{
+			Ok(header) => header,
+			Err(AviRepairError::File(error)) => return Err(error),
+			Err(AviRepairError::NotAnAvi) => return Ok(None),
+		};
+		let frames = scan_frames(file)?;
+
+		Ok(Some(Self {
+			width: read_u32(&header, WIDTH_OFFSET) as u16,
+			height: read_u32(&header, HEIGHT_OFFSET) as u16,
+			index: frames.index,
+			movi_size: frames.movi_size,
+			max_frame_size: frames.max_frame_size,
+			first_timestamp: None,
+			last_timestamp: Duration::ZERO,
+			frame_duration: Some(frame_duration),
+			// So that the next frame writes the header of an unfinished file again, before overwriting the index
+			is_finished: read_u32(&header, TOTAL_FRAMES_OFFSET) != 0,
+		}))
+	}
+
+	pub fn frame_count(&self) -> u32
+	{
+		self.index.len() as u32
+	}
+
+	/// Forgets the frames written so far if the file is shorter than them (like if it was deleted and created again),
+	/// so that the next frame starts a new video with its header. Returns whether the writer was restarted.
+	pub fn restart_if_truncated<F: AviFile>(&mut self, file: &mut F) -> Result<bool, F::Error>
+	{
+		if self.index.is_empty() || file.length()? >= AVI_HEADER_SIZE + self.movi_size
+		{
+			return Ok(false);
+		}
+
+		*self = Self {
+			frame_duration: self.frame_duration,
+			..Self::default()
+		};
+		Ok(true)
+	}
+
+	/// Appends the JPEG as a new frame (the header is written with the first one). If an error is returned the frame
+	/// isn't part of the video, and it can be written again.
+	pub fn write_frame<F: AviFile>(
+		&mut self, file: &mut F, jpeg: &[u8], size: U16x2, timestamp: Duration,
+	) -> Result<(), F::Error>
+	{
+		if self.index.is_empty()
+		{
+			self.width = size.x;
+			self.height = size.y;
+		}
+		if self.index.is_empty() || self.is_finished
+		{
+			file.write_at(0, &header(&self.header_values(false)))?;
+			self.is_finished = false;
+		}
+
+		let offset = AVI_HEADER_SIZE + self.movi_size;
+		let size = jpeg.len() as u32;
+		write_frame_chunk(file, offset, jpeg)?;
+
+		self.index.push(IndexEntry {
+			offset: offset - MOVI_OFFSET,
+			size,
+		});
+		self.movi_size += chunk_size(size);
+		self.max_frame_size = self.max_frame_size.max(size);
+		self.first_timestamp.get_or_insert(timestamp);
+		self.last_timestamp = timestamp;
+
+		Ok(())
+	}
+
+	/// Writes the `idx1` index after the frames and the final header, with the frame rate calculated from the
+	/// timestamps. Nothing is written if there are no frames.
+	pub fn finish<F: AviFile>(&mut self, file: &mut F) -> Result<(), F::Error>
+	{
+		if self.index.is_empty()
+		{
+			return Ok(());
+		}
+
+		file.write_at(AVI_HEADER_SIZE + self.movi_size, &index_chunk(&self.index))?;
+		file.write_at(0, &header(&self.header_values(true)))?;
+		self.is_finished = true;
+
+		Ok(())
+	}
+
+	/// Returns the values of the header of a file that has been `finished` (or not).
+	fn header_values(&self, finished: bool) -> HeaderValues
+	{
+		let frame_duration = match (self.frame_duration, self.first_timestamp)
+		{
+			(Some(frame_duration), _) => Some(frame_duration),
+			(None, Some(first_timestamp)) if self.index.len() > 1 =>
+			{
+				Some(self.last_timestamp.saturating_sub(first_timestamp) / (self.index.len() as u32 - 1))
+			},
+			_ => None,
+		};
+		let microseconds_per_frame = frame_duration.map_or(DEFAULT_MICROSECONDS_PER_FRAME, |frame_duration| {
+			frame_duration.as_micros().clamp(1, u32::MAX as u128) as u32
+		});
+
+		let (frame_count, index_size) = match finished
+		{
+			true => (self.frame_count(), 8 + self.frame_count() * INDEX_ENTRY_SIZE),
+			false => (0, 0),
+		};
+
+		HeaderValues {
+			width: self.width,
+			height: self.height,
+			microseconds_per_frame,
+			frame_count,
+			max_frame_size: self.max_frame_size,
+			movi_size: self.movi_size,
+			riff_end: AVI_HEADER_SIZE + self.movi_size + index_size,
+		}
+	}
+}
+
+/// Why [`repair_avi`] couldn't repair a file.
+#[derive(Debug)]
+pub enum AviRepairError<E>
+{
+	File(E),
+	/// The file doesn't start with a header written by [`AviWriter`].
+	NotAnAvi,
+}
+
+/// Makes playable an AVI file written by [`AviWriter`] whose writing was interrupted before [`AviWriter::finish`] (like
+/// by a power loss): the index is rebuilt from the complete frames, the incomplete frame is dropped and the sizes in the
+/// header are fixed. The frame rate stays the default one, because the timestamps of the frames aren't saved.
+///
+/// Returns the number of frames recovered, or `None` if the file was already finished.
+pub fn repair_avi<F: AviFile>(file: &mut F) -> Result<Option<u32>, AviRepairError<F::Error>>
+{
+	let header_bytes = read_header(file)?;
+	if read_u32(&header_bytes, TOTAL_FRAMES_OFFSET) != 0
+	{
+		return Ok(None);
+	}
+	let ScannedFrames {
+		index,
+		movi_size,
+		max_frame_size,
+		length,
+	} = scan_frames(file).map_err(AviRepairError::File)?;
+	let offset = AVI_HEADER_SIZE + movi_size;
+
+	// The filesystem can't truncate the file, so the bytes of the incomplete frame are covered by a `JUNK` chunk
+	let index_chunk = index_chunk(&index);
+	let index_end = offset + index_chunk.len() as u32;
+	file.write_at(offset, &index_chunk).map_err(AviRepairError::File)?;
+	let mut riff_end = index_end;
+	if length > index_end
+	{
+		let junk_size = (length - index_end).saturating_sub(8);
+		let mut junk_header = *b"JUNK\0\0\0\0";
+		junk_header[4..].copy_from_slice(&junk_size.to_le_bytes());
+		file.write_at(index_end, &junk_header).map_err(AviRepairError::File)?;
+		riff_end = index_end + 8 + junk_size;
+		if junk_size % 2 == 1
+		{
+			file.write_at(riff_end, &[0]).map_err(AviRepairError::File)?;
+			riff_end += 1;
+		}
+	}
+
+	let frame_count = index.len() as u32;
+	file.write_at(
+		0,
+		&header(&HeaderValues {
+			width: read_u32(&header_bytes, WIDTH_OFFSET) as u16,
+			height: read_u32(&header_bytes, HEIGHT_OFFSET) as u16,
+			microseconds_per_frame: read_u32(&header_bytes, MICROSECONDS_PER_FRAME_OFFSET),
+			frame_count,
+			max_frame_size,
+			movi_size,
+			riff_end,
+		}),
+	)
+	.map_err(AviRepairError::File)?;
+
+	Ok(Some(frame_count))
+}
+
+/// Reads the header of a file written by [`AviWriter`].
+fn read_header<F: AviFile>(file: &mut F) -> Result<[u8; AVI_HEADER_SIZE as usize], AviRepairError<F::Error>>
+{
+	let mut header = [0; AVI_HEADER_SIZE as usize];
+	let read_bytes = file.read_at(0, &mut header).map_err(AviRepairError::File)?;
+	if read_bytes < header.len()
+		|| &header[0..4] != b"RIFF"
+		|| &header[8..12] != b"AVI "
+		|| &header[MOVI_OFFSET as usize..AVI_HEADER_SIZE as usize] != b"movi"
+	{
+		return Err(AviRepairError::NotAnAvi);
+	}
+
+	Ok(header)
+}
+
+struct ScannedFrames
+{
+	index: Vec<IndexEntry>,
+	movi_size: u32,
+	max_frame_size: u32,
+	/// The length of the file.
+	length: u32,
+}
+
+/// Finds the complete frames that follow the header, without relying on the index or on the sizes in the header.
+fn scan_frames<F: AviFile>(file: &mut F) -> Result<ScannedFrames, F::Error>
+{
+	let length = file.length()?;
+	let mut index = Vec::new();
+	let mut max_frame_size = 0;
+	let mut offset = AVI_HEADER_SIZE;
+	while offset + 8 <= length
+	{
+		let mut chunk_header = [0; 8];
+		file.read_at(offset, &mut chunk_header)?;
+		let size = read_u32(&chunk_header, 4);
+		if &chunk_header[0..4] != FRAME_CHUNK_ID || offset as u64 + chunk_size(size) as u64 > length as u64
+		{
+			break;
+		}
+
+		index.push(IndexEntry {
+			offset: offset - MOVI_OFFSET,
+			size,
+		});
+		max_frame_size = max_frame_size.max(size);
+		offset += chunk_size(size);
+	}
+
+	Ok(ScannedFrames {
+		index,
+		movi_size: offset - AVI_HEADER_SIZE,
+		max_frame_size,
+		length,
+	})
+}
+
+struct HeaderValues
+{
+	width: u16,
+	height: u16,
+	microseconds_per_frame: u32,
+	frame_count: u32,
+	max_frame_size: u32,
+	movi_size: u32,
+	/// Offset of the end of the `RIFF` chunk.
+	riff_end: u32,
+}
+
+/// Returns the bytes of the file before the first frame (check [`AVI_HEADER_SIZE`]).
+fn header(values: &HeaderValues) -> Vec<u8>
+{
+	let mut header = Vec::with_capacity(AVI_HEADER_SIZE as usize);
+	let width = values.width as u32;
+	let height = values.height as u32;
+	let frames_per_second = (1_000_000 / values.microseconds_per_frame.max(1)).max(1);
+
+	header.extend_from_slice(b"RIFF");
+	header.extend_from_slice(&(values.riff_end - 8).to_le_bytes());
+	header.extend_from_slice(b"AVI ");
+
+	header.extend_from_slice(b"LIST");
+	header.extend_from_slice(&192_u32.to_le_bytes());
+	header.extend_from_slice(b"hdrl");
+
+	header.extend_from_slice(b"avih");
+	header.extend_from_slice(&56_u32.to_le_bytes());
+	for value in [
+		values.microseconds_per_frame,
+		values.max_frame_size.saturating_mul(frames_per_second),
+		0,
+		AVIF_HASINDEX,
+		values.frame_count,
+		0,
+		1,
+		values.max_frame_size,
+		width,
+		height,
+		0,
+		0,
+		0,
+		0,
+	]
+	{
+		header.extend_from_slice(&value.to_le_bytes());
+	}
+
+	header.extend_from_slice(b"LIST");
+	header.extend_from_slice(&116_u32.to_le_bytes());
+	header.extend_from_slice(b"strl");
+
+	header.extend_from_slice(b"strh");
+	header.extend_from_slice(&56_u32.to_le_bytes());
+	header.extend_from_slice(b"vids");
+	header.extend_from_slice(b"MJPG");
+	// Flags, priority and language, initial frames, scale (the rate is 1_000_000 / scale frames per second), rate,
+	// start, length, suggested buffer size, quality (-1 is the default one), sample size
+	for value in [
+		0,
+		0,
+		0,
+		values.microseconds_per_frame,
+		1_000_000,
+		0,
+		values.frame_count,
+		values.max_frame_size,
+		u32::MAX,
+		0,
+	]
+	{
+		header.extend_from_slice(&value.to_le_bytes());
+	}
+	// The rectangle of the frame (left, top, right, bottom)
+	for value in [0, 0, values.width, values.height]
+	{
+		header.extend_from_slice(&value.to_le_bytes());
+	}
+
+	header.extend_from_slice(b"strf");
+	header.extend_from_slice(&40_u32.to_le_bytes());
+	header.extend_from_slice(&40_u32.to_le_bytes());
+	header.extend_from_slice(&width.to_le_bytes());
+	header.extend_from_slice(&height.to_le_bytes());
+	header.extend_from_slice(&1_u16.to_le_bytes());
+	header.extend_from_slice(&24_u16.to_le_bytes());
+	header.extend_from_slice(b"MJPG");
+	for value in [width * height * 3, 0, 0, 0, 0]
+	{
+		header.extend_from_slice(&value.to_le_bytes());
+	}
+
+	header.extend_from_slice(b"LIST");
+	header.extend_from_slice(&(4 + values.movi_size).to_le_bytes());
+	header.extend_from_slice(b"movi");
+
+	debug_assert_eq!(header.len(), AVI_HEADER_SIZE as usize);
+	header
+}
+
+/// Writes the chunk of a frame, padded to an even size as required by RIFF.
+fn write_frame_chunk<F: AviFile>(file: &mut F, offset: u32, jpeg: &[u8]) -> Result<(), F::Error>
+{
+	let size = jpeg.len() as u32;
+	let mut chunk_header = [0; 8];
+	chunk_header[..4].copy_from_slice(FRAME_CHUNK_ID);
+	chunk_header[4..].copy_from_slice(&size.to_le_bytes());
+
+	file.write_at(offset, &chunk_header)?;
+	file.write_at(offset + 8, jpeg)?;
+	if size % 2 == 1
+	{
+		file.write_at(offset + 8 + size, &[0])?;
+	}
+
+	Ok(())
+}
+
+fn index_chunk(index: &[IndexEntry]) -> Vec<u8>
+{
+	let mut chunk = Vec::with_capacity(8 + index.len() * INDEX_ENTRY_SIZE as usize);
+	chunk.extend_from_slice(b"idx1");
+	chunk.extend_from_slice(&(index.len() as u32 * INDEX_ENTRY_SIZE).to_le_bytes());
+	for entry in index
+	{
+		chunk.extend_from_slice(FRAME_CHUNK_ID);
+		chunk.extend_from_slice(&AVIIF_KEYFRAME.to_le_bytes());
+		chunk.extend_from_slice(&entry.offset.to_le_bytes());
+		chunk.extend_from_slice(&entry.size.to_le_bytes());
+	}
+
+	chunk
+}
+
+/// Returns the size of the chunk of a frame of `size` bytes, including its header and padding.
+fn chunk_size(size: u32) -> u32
+{
+	8 + size + size % 2
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32
+{
+	u32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]])
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	/// A file in memory.
+	impl AviFile for Vec<u8>
+	{
+		type Error = core::convert::Infallible;
+
+		fn length(&mut self) -> Result<u32, Self::Error>
+		{
+			Ok(self.len() as u32)
+		}
+
+		fn read_at(&mut self, offset: u32, buffer: &mut [u8]) -> Result<usize, Self::Error>
+		{
+			let bytes = self.get(offset as usize..).unwrap_or_default();
+			let read_bytes = bytes.len().min(buffer.len());
+			buffer[..read_bytes].copy_from_slice(&bytes[..read_bytes]);
+			Ok(read_bytes)
+		}
+
+		fn write_at(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error>
+		{
+			let offset = offset as usize;
+			assert!(offset <= self.len());
+			let end = offset + bytes.len();
+			if end > self.len()
+			{
+				self.resize(end, 0);
+			}
+			self[offset..end].copy_from_slice(bytes);
+			Ok(())
+		}
+	}
+
+	const SIZE: U16x2 = U16x2 { x: 320, y: 240 };
+
+	/// Writes frames of the given sizes, 200 ms apart.
+	fn write_frames(frame_sizes: &[usize]) -> (Vec<u8>, AviWriter)
+	{
+		let mut file = Vec::new();
+		let mut writer = AviWriter::new();
+		for (index, frame_size) in frame_sizes.iter().enumerate()
+		{
+			let jpeg = vec![index as u8; *frame_size];
+			writer
+				.write_frame(&mut file, &jpeg, SIZE, Duration::from_millis(200 * index as u64))
+				.unwrap();
+		}
+
+		(file, writer)
+	}
+
+	/// Checks that the sizes in the header match the file, and returns the frames listed in the index.
+	fn indexed_frames(file: &[u8]) -> Vec<Vec<u8>>
+	{
+		assert_eq!(read_u32(file, 4) as usize, file.len() - 8);
+		let movi_end = MOVI_OFFSET + read_u32(file, 216);
+		assert_eq!(&file[movi_end as usize..movi_end as usize + 4], b"idx1");
+
+		let frame_count = read_u32(file, TOTAL_FRAMES_OFFSET) as usize;
+		assert_eq!(read_u32(file, movi_end as usize + 4) as usize, frame_count * 16);
+		(0..frame_count)
+			.map(|index| {
+				let entry = movi_end as usize + 8 + index * 16;
+				assert_eq!(&file[entry..entry + 4], FRAME_CHUNK_ID);
+				let offset = (MOVI_OFFSET + read_u32(file, entry + 8)) as usize;
+				let size = read_u32(file, entry + 12) as usize;
+				assert_eq!(read_u32(file, offset + 4) as usize, size);
+				file[offset + 8..offset + 8 + size].to_vec()
+			})
+			.collect()
+	}
+
+	#[test]
+	fn finished_file_has_index_and_frame_rate()
+	{
+		let (mut file, mut writer) = write_frames(&[100, 51, 80]);
+		writer.finish(&mut file).unwrap();
+
+		assert_eq!(indexed_frames(&file), [vec![0; 100], vec![1; 51], vec![2; 80]]);
+		assert_eq!(read_u32(&file, MICROSECONDS_PER_FRAME_OFFSET), 200_000);
+		assert_eq!(read_u32(&file, WIDTH_OFFSET), 320);
+		assert_eq!(read_u32(&file, HEIGHT_OFFSET), 240);
+		assert_eq!(repair_avi(&mut file).unwrap(), None);
+	}
+
+	#[test]
+	fn truncated_file_is_repaired()
+	{
+		let (mut file, _) = write_frames(&[100, 51, 80]);
+		file.truncate(file.len() - 30);
+
+		assert_eq!(repair_avi(&mut file).unwrap(), Some(2));
+		assert_eq!(indexed_frames(&file), [vec![0; 100], vec![1; 51]]);
+		assert_eq!(&file[file.len() - 18..file.len() - 14], b"JUNK");
+	}
+
+	#[test]
+	fn resumed_file_keeps_the_previous_frames()
+	{
+		let mut file = Vec::new();
+		let mut writer = AviWriter::with_frame_duration(Duration::from_millis(40));
+		writer.write_frame(&mut file, &[1; 10], SIZE, Duration::ZERO).unwrap();
+		writer.finish(&mut file).unwrap();
+
+		let mut writer = AviWriter::resume(&mut file, Duration::from_millis(40))
+			.unwrap()
+			.unwrap();
+		writer
+			.write_frame(&mut file, &[2; 21], SIZE, Duration::from_secs(300))
+			.unwrap();
+
+		// If the power is lost before it's finished again, the file can still be repaired
+		let mut cut_file = file.clone();
+		assert_eq!(repair_avi(&mut cut_file).unwrap(), Some(2));
+		assert_eq!(indexed_frames(&cut_file), [vec![1; 10], vec![2; 21]]);
+
+		writer.finish(&mut file).unwrap();
+		assert_eq!(indexed_frames(&file), [vec![1; 10], vec![2; 21]]);
+		assert_eq!(read_u32(&file, MICROSECONDS_PER_FRAME_OFFSET), 40_000);
+		assert!(AviWriter::resume(&mut Vec::new(), Duration::from_millis(40))
+			.unwrap()
+			.is_none());
+	}
+
+	#[test]
+	fn deleted_file_is_started_again()
+	{
+		let (mut file, mut writer) = write_frames(&[100, 51]);
+		assert!(!writer.restart_if_truncated(&mut file).unwrap());
+
+		let mut file = Vec::new();
+		assert!(writer.restart_if_truncated(&mut file).unwrap());
+		writer.write_frame(&mut file, &[2; 80], SIZE, Duration::ZERO).unwrap();
+		writer.finish(&mut file).unwrap();
+
+		assert_eq!(indexed_frames(&file), [vec![2; 80]]);
+	}
+
+	#[test]
+	fn file_cut_inside_the_header_is_not_an_avi()
+	{
+		let (mut file, _) = write_frames(&[100]);
+		file.truncate(100);
+
+		assert!(matches!(repair_avi(&mut file), Err(AviRepairError::NotAnAvi)));
+	}
+}
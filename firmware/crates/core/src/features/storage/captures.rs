@@ -0,0 +1,271 @@
+use a13c_embedded::features::storage::embedded_sdmmc::*;
+
+use super::{retention::DATED_DIRECTORIES_DEPTH, CapturePath, Storage, NO_CLOCK_DIRECTORY_NAME};
+
+/// A capture stored in the SD card, as returned by [`Storage::captures`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CaptureInfo
+{
+	pub path: CapturePath,
+	pub size: u32,
+}
+
+/// An entry of a directory of captures, as returned by [`CapturesStorage::capture_directory`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CaptureEntry
+{
+	/// The name of a subdirectory with more captures.
+	Directory(String),
+	Capture(CaptureInfo),
+}
+
+/// A capture opened by [`CapturesStorage::open_capture`], which must be closed with
+/// [`CapturesStorage::close_capture`].
+#[derive(Debug, PartialEq, Eq)]
+pub struct CaptureFile
+{
+	file: RawFile,
+	pub size: u32,
+}
+
+/// A page of the captures stored in the SD card, from the newest to the oldest.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CapturesPage
+{
+	pub captures: Vec<CaptureInfo>,
+	/// Number of captures in the SD card (not only the ones in this page).
+	pub total: usize,
+}
+
+/// The error returned by [`CapturesStorage`], which hides the type of the block device.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CaptureError
+{
+	NotFound,
+	/// The details are logged when the error happens.
+	Storage,
+}
+
+/// Read and delete access to the captures of a [`Storage`], usable as a trait object by the HTTP server. Each method
+/// does a small amount of work, so that the lock of the storage can be released between the calls.
+pub trait CapturesStorage: Send
+{
+	/// Lists a single directory (check [`page_captures`]).
+	fn capture_directory(&mut self, path: &[String]) -> Result<Vec<CaptureEntry>, CaptureError>;
+	fn open_capture(&mut self, path: &CapturePath) -> Result<CaptureFile, CaptureError>;
+	/// Reads the next bytes of the capture, and returns how many were read (`0` at the end of the file).
+	fn read_capture(&mut self, file: &CaptureFile, buffer: &mut [u8]) -> Result<usize, CaptureError>;
+	fn close_capture(&mut self, file: CaptureFile) -> Result<(), CaptureError>;
+	fn delete_capture(&mut self, path: &CapturePath) -> Result<(), CaptureError>;
+}
+
+/// Returns at most `limit` captures, skipping the newest `offset` ones. The directories are listed one at a time with
+/// `capture_directory`, from the root one. The captures stored while the real time clock wasn't set are listed after
+/// all the dated ones.
+pub fn page_captures<E>(
+	offset: usize, limit: usize, mut capture_directory: impl FnMut(&[String]) -> Result<Vec<CaptureEntry>, E>,
+) -> Result<CapturesPage, E>
+{
+	enum Pending
+	{
+		Directory(Vec<String>),
+		Capture(CaptureInfo),
+	}
+
+	let mut page = CapturesPage::default();
+	// The next one is the last
+	let mut pending = vec![
+		Pending::Directory(vec![NO_CLOCK_DIRECTORY_NAME.to_string()]),
+		Pending::Directory(Vec::new()),
+	];
+	while let Some(next) = pending.pop()
+	{
+		match next
+		{
+			Pending::Directory(path) =>
+			{
+				for entry in capture_directory(&path)?.into_iter().rev()
+				{
+					pending.push(match entry
+					{
+						CaptureEntry::Directory(name) =>
+						{
+							let mut subdirectory_path = path.clone();
+							subdirectory_path.push(name);
+							Pending::Directory(subdirectory_path)
+						},
+						CaptureEntry::Capture(capture) => Pending::Capture(capture),
+					});
+				}
+			},
+			Pending::Capture(capture) =>
+			{
+				if page.total >= offset && page.captures.len() < limit
+				{
+					page.captures.push(capture);
+				}
+				page.total += 1;
+			},
+		}
+	}
+
+	Ok(page)
+}
+
+impl<D: BlockDevice, T: TimeSource, const MAX_DIRS: usize, const MAX_FILES: usize, const MAX_VOLUMES: usize>
+	Storage<D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>
+{
+	/// Returns at most `limit` captures, skipping the newest `offset` ones (check [`page_captures`]).
+	pub fn captures(&mut self, offset: usize, limit: usize) -> Result<CapturesPage, Error<D::Error>>
+	{
+		page_captures(offset, limit, |path| self.capture_directory(path))
+	}
+
+	/// Returns the captures and the dated subdirectories in the directory at `path`, from the newest. A directory that
+	/// doesn't exist is empty.
+	pub fn capture_directory(&mut self, path: &[String]) -> Result<Vec<CaptureEntry>, Error<D::Error>>
+	{
+		let entries = match self.directory_entries(path)
+		{
+			Ok(entries) => entries,
+			Err(Error::NotFound) => return Ok(Vec::new()),
+			Err(error) => return Err(error),
+		};
+
+		let mut entries: Vec<(String, DirEntry)> = entries
+			.into_iter()
+			.map(|entry| (entry.name.to_string(), entry))
+			.collect();
+		entries.sort_by(|(name, _), (other_name, _)| other_name.cmp(name));
+
+		Ok(entries
+			.into_iter()
+			.filter_map(|(name, entry)| {
+				if entry.attributes.is_directory()
+				{
+					(path.len() < DATED_DIRECTORIES_DEPTH && name.bytes().all(|byte| byte.is_ascii_digit()))
+						.then_some(CaptureEntry::Directory(name))
+				}
+				else
+				{
+					let capture_path = format!("{}/{}", path.join("/"), name).parse().ok()?;
+					Some(CaptureEntry::Capture(CaptureInfo {
+						path: capture_path,
+						size: entry.size,
+					}))
+				}
+			})
+			.collect())
+	}
+
+	pub fn capture_size(&mut self, path: &CapturePath) -> Result<u32, Error<D::Error>>
+	{
+		let directory = self.open_existing_directories(&path.directories())?;
+		let entry = self
+			.volume_manager
+			.find_directory_entry(directory, path.file_name().as_str());
+		self.close_directory(directory)?;
+
+		Ok(entry?.size)
+	}
+
+	/// Opens the capture to read it in chunks with [`Self::read_capture`]. It takes one of the `MAX_FILES` until it's
+	/// closed with [`Self::close_capture`], and the retention policy can't delete it meanwhile.
+	pub fn open_capture(&mut self, path: &CapturePath) -> Result<CaptureFile, Error<D::Error>>
+	{
+		let directory = self.open_existing_directories(&path.directories())?;
+		let file = self
+			.volume_manager
+			.open_file_in_dir(directory, path.file_name().as_str(), Mode::ReadOnly);
+		self.close_directory(directory)?;
+
+		let file = file?;
+		match self.volume_manager.file_length(file)
+		{
+			Ok(size) => Ok(CaptureFile { file, size }),
+			Err(error) =>
+			{
+				self.volume_manager.close_file(file)?;
+				Err(error)
+			},
+		}
+	}
+
+	/// Reads the next bytes of the capture and returns how many bytes were read (`0` at the end of the file).
+	pub fn read_capture(&mut self, file: &CaptureFile, buffer: &mut [u8]) -> Result<usize, Error<D::Error>>
+	{
+		self.volume_manager.read(file.file, buffer)
+	}
+
+	pub fn close_capture(&mut self, file: CaptureFile) -> Result<(), Error<D::Error>>
+	{
+		self.volume_manager.close_file(file.file)
+	}
+
+	pub fn delete_capture(&mut self, path: &CapturePath) -> Result<(), Error<D::Error>>
+	{
+		let directory = self.open_existing_directories(&path.directories())?;
+		let file_name = path.file_name();
+		let result = self
+			.volume_manager
+			.find_directory_entry(directory, file_name.as_str())
+			.and_then(|entry| {
+				self.volume_manager
+					.delete_file_in_dir(directory, file_name.as_str())
+					.map(|()| entry.size)
+			});
+		self.close_directory(directory)?;
+
+		self.count_deleted_bytes(result? as u64);
+		log::info!("Deleted capture: {}", path);
+
+		Ok(())
+	}
+}
+
+impl<
+		D: BlockDevice + Send,
+		T: TimeSource + Send,
+		const MAX_DIRS: usize,
+		const MAX_FILES: usize,
+		const MAX_VOLUMES: usize,
+	> CapturesStorage for Storage<D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>
+{
+	fn capture_directory(&mut self, path: &[String]) -> Result<Vec<CaptureEntry>, CaptureError>
+	{
+		Storage::capture_directory(self, path).map_err(to_capture_error)
+	}
+
+	fn open_capture(&mut self, path: &CapturePath) -> Result<CaptureFile, CaptureError>
+	{
+		Storage::open_capture(self, path).map_err(to_capture_error)
+	}
+
+	fn read_capture(&mut self, file: &CaptureFile, buffer: &mut [u8]) -> Result<usize, CaptureError>
+	{
+		Storage::read_capture(self, file, buffer).map_err(to_capture_error)
+	}
+
+	fn close_capture(&mut self, file: CaptureFile) -> Result<(), CaptureError>
+	{
+		Storage::close_capture(self, file).map_err(to_capture_error)
+	}
+
+	fn delete_capture(&mut self, path: &CapturePath) -> Result<(), CaptureError>
+	{
+		Storage::delete_capture(self, path).map_err(to_capture_error)
+	}
+}
+
+fn to_capture_error<E: core::fmt::Debug>(error: Error<E>) -> CaptureError
+{
+	match error
+	{
+		Error::NotFound => CaptureError::NotFound,
+		error =>
+		{
+			log::error!("Couldn't access the captures: {:?}", error);
+			CaptureError::Storage
+		},
+	}
+}
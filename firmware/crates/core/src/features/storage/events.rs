@@ -0,0 +1,271 @@
+use a13c_embedded::{
+	features::storage::embedded_sdmmc::*,
+	peripherals::time::real_time::time::{Date, Time},
+};
+
+use super::{
+	repair_avi, AviFile, AviWriter, CapturePath, EventPath, SdCardFile, Storage, AVI_HEADER_SIZE,
+	EVENT_MANIFEST_FILE_NAME, EVENT_VIDEO_FILE_NAME, FIRST_VALID_YEAR,
+};
+use crate::features::frames::Frame;
+
+/// Contains the path of the event being recorded, so that its video can be repaired if the camera is turned off
+/// before it's finished.
+const EVENT_IN_PROGRESS_FILE_NAME: &str = "EVENT.TXT";
+
+/// Where the [`crate::features::recording::EventRecorder`] writes the events, implemented by [`Storage`] and by fakes
+/// in the tests.
+pub trait EventStorage
+{
+	type Error: core::fmt::Debug;
+
+	/// Creates the directory of a new event and returns its path.
+	fn start_event(&mut self, date_and_time: Option<(Date, Time)>) -> Result<EventPath, Self::Error>;
+	/// Appends a frame to the event.
+	fn store_event_frame(&mut self, event: &EventPath, frame: &Frame) -> Result<(), Self::Error>;
+	/// Writes the manifest of the event, after its last frame.
+	fn finish_event(&mut self, event: &EventPath, manifest: &[u8]) -> Result<(), Self::Error>;
+}
+
+impl<D: BlockDevice, T: TimeSource, const MAX_DIRS: usize, const MAX_FILES: usize, const MAX_VOLUMES: usize>
+	Storage<D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>
+{
+	/// Creates the directory of a new event (check [`EventPath`]). Like for [`Self::store_image`], the path is based on
+	/// `date_and_time` if the real time clock is set, otherwise on the sequence number, and an existing event is never
+	/// reused.
+	pub fn start_event(&mut self, date_and_time: Option<(Date, Time)>) -> Result<EventPath, Error<D::Error>>
+	{
+		let mut path = match date_and_time.filter(|(date, _)| date.year() >= FIRST_VALID_YEAR)
+		{
+			Some((date, time)) => CapturePath::dated(date, time),
+			None => CapturePath::Sequence { number: 0 },
+		};
+
+		let directory = self.open_directories(&path.directories())?;
+		let result = self.make_new_event_directory(directory, &mut path);
+		self.close_directory(directory)?;
+		result?;
+
+		let event = EventPath(path);
+		let root_directory = self.raw_root_dir.ok_or(Error::BadHandle)?;
+		self.write_file(
+			root_directory,
+			EVENT_IN_PROGRESS_FILE_NAME,
+			event.to_string().as_bytes(),
+		)?;
+		self.event_video = Some((event, AviWriter::new()));
+
+		Ok(event)
+	}
+
+	fn make_new_event_directory(
+		&mut self, directory: RawDirectory, path: &mut CapturePath,
+	) -> Result<(), Error<D::Error>>
+	{
+		if let CapturePath::Sequence { number } = path
+		{
+			*number = self.next_sequence_number(directory)?;
+		}
+
+		loop
+		{
+			match self
+				.volume_manager
+				.make_dir_in_dir(directory, EventPath(*path).directory_name().as_str())
+			{
+				Err(Error::DirAlreadyExists) if path.advance() => continue,
+				result => result?,
+			}
+			break;
+		}
+
+		if let CapturePath::Sequence { number } = path
+		{
+			self.save_next_sequence_number(directory, *number + 1)?;
+		}
+
+		Ok(())
+	}
+
+	/// Appends a frame to the video of the event, enforcing the retention policy like [`Self::store_image`].
+	pub fn store_event_frame(&mut self, event: &EventPath, frame: &Frame) -> Result<(), Error<D::Error>>
+	{
+		let today = match event.0
+		{
+			CapturePath::Dated { date, .. } => Some(date),
+			CapturePath::Sequence { .. } => None,
+		};
+		self.enforce_retention_policy(frame.pixels.len() as u64, today)?;
+
+		match self.write_event_frame(event, frame)
+		{
+			Err(Error::DiskFull | Error::NotEnoughSpace) =>
+			{
+				log::warn!("The SD card is full, deleting the oldest captures");
+				self.delete_oldest_captures()?;
+				self.write_event_frame(event, frame)
+			},
+			result => result,
+		}
+	}
+
+	/// Finishes the video of the event (check [`AviWriter::finish`]) and writes the manifest next to it.
+	pub fn finish_event(&mut self, event: &EventPath, manifest: &[u8]) -> Result<(), Error<D::Error>>
+	{
+		if let Some((_, mut writer)) = self.event_video.take().filter(|(path, _)| path == event)
+		{
+			self.with_event_video(event, |file| {
+				restart_if_deleted(event, &mut writer, file)?;
+				writer.finish(file)
+			})?;
+			if writer.frame_count() > 0
+			{
+				self.count_written_bytes(8 + writer.frame_count() as u64 * 16);
+			}
+		}
+
+		self.write_event_file(event, EVENT_MANIFEST_FILE_NAME, manifest)?;
+		self.count_written_bytes(manifest.len() as u64);
+
+		self.delete_event_in_progress_file()
+	}
+
+	/// Repairs the video of the event that was being recorded when the camera was turned off, if any (check
+	/// [`repair_avi`]). Errors are only logged, because they shouldn't stop the camera from starting.
+	pub(super) fn repair_interrupted_event(&mut self)
+	{
+		let mut buffer = [0; 64];
+		let event = match self.read_root_file(EVENT_IN_PROGRESS_FILE_NAME, &mut buffer)
+		{
+			Ok(read_bytes) => core::str::from_utf8(&buffer[..read_bytes])
+				.ok()
+				.and_then(|path| path.trim().parse::<EventPath>().ok()),
+			Err(Error::NotFound) => return,
+			Err(error) =>
+			{
+				log::warn!("Couldn't check if an event was interrupted: {:?}", error);
+				return;
+			},
+		};
+
+		match event.map(|event| (self.with_event_video(&event, |file| Ok(repair_avi(file))), event))
+		{
+			Some((Ok(Ok(Some(frame_count))), event)) =>
+			{
+				log::info!("Repaired event {} with {} frames", event, frame_count)
+			},
+			Some((Ok(Ok(None)), _)) => (),
+			Some((result, event)) => log::warn!("Couldn't repair event {}: {:?}", event, result),
+			None => log::warn!("The path of the interrupted event is invalid"),
+		}
+
+		if let Err(error) = self.delete_event_in_progress_file()
+		{
+			log::warn!("Couldn't delete {}: {:?}", EVENT_IN_PROGRESS_FILE_NAME, error);
+		}
+	}
+
+	fn delete_event_in_progress_file(&mut self) -> Result<(), Error<D::Error>>
+	{
+		let root_directory = self.raw_root_dir.ok_or(Error::BadHandle)?;
+		match self
+			.volume_manager
+			.delete_file_in_dir(root_directory, EVENT_IN_PROGRESS_FILE_NAME)
+		{
+			Ok(()) | Err(Error::NotFound) => Ok(()),
+			Err(error) => Err(error),
+		}
+	}
+
+	fn write_event_frame(&mut self, event: &EventPath, frame: &Frame) -> Result<(), Error<D::Error>>
+	{
+		let mut writer = match self.event_video.take()
+		{
+			Some((path, writer)) if path == *event => writer,
+			_ => AviWriter::new(),
+		};
+		let mut header_size = 0;
+		let result = self.with_event_video(event, |file| {
+			restart_if_deleted(event, &mut writer, file)?;
+			if writer.frame_count() == 0
+			{
+				header_size = AVI_HEADER_SIZE as u64;
+			}
+			writer.write_frame(file, &frame.pixels, frame.size, frame.timestamp)
+		});
+		if result.is_ok()
+		{
+			self.count_written_bytes(header_size + 8 + frame.pixels.len() as u64);
+		}
+		self.event_video = Some((*event, writer));
+
+		result
+	}
+
+	/// Opens the video of the event (creating the missing directories and the file) and calls `action` with it.
+	fn with_event_video<R>(
+		&mut self, event: &EventPath,
+		action: impl FnOnce(&mut SdCardFile<D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>) -> Result<R, Error<D::Error>>,
+	) -> Result<R, Error<D::Error>>
+	{
+		self.with_file(&event.directories(), EVENT_VIDEO_FILE_NAME, action)
+	}
+
+	/// The event directory is created again if the retention policy deleted it while the event was being recorded.
+	fn write_event_file(&mut self, event: &EventPath, file_name: &str, bytes: &[u8]) -> Result<(), Error<D::Error>>
+	{
+		let directory = self.open_directories(&event.directories())?;
+		let result = self.write_file(directory, file_name, bytes);
+		self.close_directory(directory)?;
+
+		result
+	}
+
+	fn read_root_file(&mut self, file_name: &str, buffer: &mut [u8]) -> Result<usize, Error<D::Error>>
+	{
+		let root_directory = self.raw_root_dir.ok_or(Error::BadHandle)?;
+		let file = self
+			.volume_manager
+			.open_file_in_dir(root_directory, file_name, Mode::ReadOnly)?;
+		let result = self.volume_manager.read(file, buffer);
+		self.volume_manager.close_file(file)?;
+
+		result
+	}
+}
+
+/// Restarts the video if the retention policy deleted the event while it was being recorded: the file was created
+/// again empty, so the frames that were written before are lost and the header must be written again.
+fn restart_if_deleted<F: AviFile>(event: &EventPath, writer: &mut AviWriter, file: &mut F) -> Result<(), F::Error>
+{
+	if writer.restart_if_truncated(file)?
+	{
+		log::warn!(
+			"The video of event {} was deleted while it was recorded, starting it again",
+			event
+		);
+	}
+
+	Ok(())
+}
+
+impl<D: BlockDevice, T: TimeSource, const MAX_DIRS: usize, const MAX_FILES: usize, const MAX_VOLUMES: usize>
+	EventStorage for Storage<D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>
+{
+	type Error = Error<D::Error>;
+
+	fn start_event(&mut self, date_and_time: Option<(Date, Time)>) -> Result<EventPath, Self::Error>
+	{
+		Storage::start_event(self, date_and_time)
+	}
+
+	fn store_event_frame(&mut self, event: &EventPath, frame: &Frame) -> Result<(), Self::Error>
+	{
+		Storage::store_event_frame(self, event, frame)
+	}
+
+	fn finish_event(&mut self, event: &EventPath, manifest: &[u8]) -> Result<(), Self::Error>
+	{
+		Storage::finish_event(self, event, manifest)
+	}
+}
@@ -0,0 +1,257 @@
+use a13c_embedded::features::storage::embedded_sdmmc::*;
+
+const PARTITION1_LBA_START_OFFSET: usize = 446 + 8;
+/// Clusters `0` and `1` are reserved, so the first cluster of the data region is `2`.
+const FIRST_CLUSTER: u32 = 2;
+/// Volumes with fewer clusters are FAT12 (which the filesystem library doesn't support), and with more are FAT32.
+const MIN_FAT16_CLUSTERS: u32 = 4085;
+const MIN_FAT32_CLUSTERS: u32 = 65525;
+/// Blocks of the FAT read at a time while counting the free clusters.
+const BLOCKS_PER_READ: usize = 8;
+
+/// Where the FAT of the first partition of the card is, read from its boot sector.
+///
+/// The filesystem library keeps the number of free clusters to itself (and the one saved in the FAT32 info sector is
+/// only updated when a file is closed), so the [`super::Storage`] counts the free entries of the FAT itself.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) struct FatLayout
+{
+	fat_start: u32,
+	cluster_count: u32,
+	bytes_per_cluster: u32,
+	is_fat32: bool,
+}
+
+impl FatLayout
+{
+	pub fn read<D: BlockDevice>(block_device: &D) -> Result<Self, Error<D::Error>>
+	{
+		let mut blocks = [Block::new()];
+		block_device
+			.read(&mut blocks, BlockIdx(0), "read_mbr")
+			.map_err(Error::DeviceError)?;
+		let lba_start = read_u32(&blocks[0].contents, PARTITION1_LBA_START_OFFSET);
+
+		block_device
+			.read(&mut blocks, BlockIdx(lba_start), "read_bpb")
+			.map_err(Error::DeviceError)?;
+		Self::from_boot_sector(&blocks[0].contents, lba_start)
+	}
+
+	fn from_boot_sector<E: core::fmt::Debug>(boot_sector: &[u8; Block::LEN], lba_start: u32) -> Result<Self, Error<E>>
+	{
+		if boot_sector[510..] != [0x55, 0xAA] || read_u16(boot_sector, 11) as usize != Block::LEN
+		{
+			return Err(Error::FormatError("Bad boot sector"));
+		}
+
+		let blocks_per_cluster = boot_sector[13] as u32;
+		let reserved_blocks = read_u16(boot_sector, 14) as u32;
+		let fat_count = boot_sector[16] as u32;
+		let root_directory_blocks = (read_u16(boot_sector, 17) as u32 * 32).div_ceil(Block::LEN_U32);
+		let total_blocks = match read_u16(boot_sector, 19)
+		{
+			0 => read_u32(boot_sector, 32),
+			total_blocks => total_blocks as u32,
+		};
+		let fat_size = match read_u16(boot_sector, 22)
+		{
+			0 => read_u32(boot_sector, 36),
+			fat_size => fat_size as u32,
+		};
+
+		let data_blocks = total_blocks
+			.checked_sub(reserved_blocks + fat_count * fat_size + root_directory_blocks)
+			.ok_or(Error::FormatError("Bad boot sector"))?;
+		let cluster_count = data_blocks
+			.checked_div(blocks_per_cluster)
+			.ok_or(Error::FormatError("Bad boot sector"))?;
+		if cluster_count < MIN_FAT16_CLUSTERS
+		{
+			return Err(Error::FormatError("FAT12 is unsupported"));
+		}
+
+		Ok(Self {
+			fat_start: lba_start + reserved_blocks,
+			cluster_count,
+			bytes_per_cluster: blocks_per_cluster * Block::LEN_U32,
+			is_fat32: cluster_count >= MIN_FAT32_CLUSTERS,
+		})
+	}
+
+	/// The size of the data region of the volume.
+	pub fn capacity_bytes(&self) -> u64
+	{
+		self.cluster_count as u64 * self.bytes_per_cluster as u64
+	}
+
+	/// Rounds `bytes` up to whole clusters, which is the space a file of that size takes.
+	pub fn allocated_bytes(&self, bytes: u64) -> u64
+	{
+		bytes.div_ceil(self.bytes_per_cluster as u64) * self.bytes_per_cluster as u64
+	}
+
+	/// Counts the free clusters in the FAT, and returns their size.
+	pub fn free_bytes<D: BlockDevice>(&self, block_device: &D) -> Result<u64, Error<D::Error>>
+	{
+		let entry_size = if self.is_fat32 { 4 } else { 2 };
+		let entries_per_block = Block::LEN as u32 / entry_size;
+		let end = FIRST_CLUSTER + self.cluster_count;
+
+		let mut blocks: [Block; BLOCKS_PER_READ] = core::array::from_fn(|_| Block::new());
+		let mut free_clusters = 0;
+		let mut cluster = 0;
+		while cluster < end
+		{
+			let block_index = cluster / entries_per_block;
+			block_device
+				.read(&mut blocks, BlockIdx(self.fat_start + block_index), "read_fat")
+				.map_err(Error::DeviceError)?;
+
+			for block in &blocks
+			{
+				for entry in block.contents.chunks_exact(entry_size as usize)
+				{
+					let is_free = match self.is_fat32
+					{
+						// The highest 4 bits of the FAT32 entries are reserved
+						true => read_u32(entry, 0) & 0x0FFF_FFFF == 0,
+						false => read_u16(entry, 0) == 0,
+					};
+					if (FIRST_CLUSTER..end).contains(&cluster) && is_free
+					{
+						free_clusters += 1;
+					}
+					cluster += 1;
+				}
+			}
+		}
+
+		Ok(free_clusters as u64 * self.bytes_per_cluster as u64)
+	}
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> u16
+{
+	u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32
+{
+	u32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]])
+}
+
+#[cfg(test)]
+mod tests
+{
+	use core::cell::RefCell;
+
+	use super::*;
+
+	/// A card in memory.
+	struct MemoryBlockDevice(RefCell<Vec<Block>>);
+
+	impl BlockDevice for MemoryBlockDevice
+	{
+		type Error = ();
+
+		fn read(&self, blocks: &mut [Block], start_block_idx: BlockIdx, _reason: &str) -> Result<(), Self::Error>
+		{
+			let device_blocks = self.0.borrow();
+			for (index, block) in blocks.iter_mut().enumerate()
+			{
+				// Reading past the end returns empty blocks, like a card bigger than the volume
+				*block = device_blocks
+					.get(start_block_idx.0 as usize + index)
+					.cloned()
+					.unwrap_or_else(Block::new);
+			}
+			Ok(())
+		}
+
+		fn write(&self, blocks: &[Block], start_block_idx: BlockIdx) -> Result<(), Self::Error>
+		{
+			let mut device_blocks = self.0.borrow_mut();
+			for (index, block) in blocks.iter().enumerate()
+			{
+				device_blocks[start_block_idx.0 as usize + index] = block.clone();
+			}
+			Ok(())
+		}
+
+		fn num_blocks(&self) -> Result<BlockCount, Self::Error>
+		{
+			Ok(BlockCount(self.0.borrow().len() as u32))
+		}
+	}
+
+	const LBA_START: u32 = 8;
+
+	/// Returns a card with a FAT16 partition of 5000 clusters of 2 blocks, whose first `used_clusters` are allocated.
+	fn fat16_card(used_clusters: u32) -> MemoryBlockDevice
+	{
+		const FAT_SIZE: u16 = 20;
+		let mut blocks = vec![Block::new(); LBA_START as usize + 1 + 2 * FAT_SIZE as usize];
+
+		blocks[0].contents[PARTITION1_LBA_START_OFFSET..PARTITION1_LBA_START_OFFSET + 4]
+			.copy_from_slice(&LBA_START.to_le_bytes());
+
+		let boot_sector = &mut blocks[LBA_START as usize].contents;
+		boot_sector[11..13].copy_from_slice(&512_u16.to_le_bytes());
+		boot_sector[13] = 2;
+		boot_sector[14..16].copy_from_slice(&1_u16.to_le_bytes());
+		boot_sector[16] = 2;
+		boot_sector[17..19].copy_from_slice(&512_u16.to_le_bytes());
+		let total_blocks = 1 + 2 * FAT_SIZE + 32 + 5000 * 2;
+		boot_sector[19..21].copy_from_slice(&total_blocks.to_le_bytes());
+		boot_sector[22..24].copy_from_slice(&FAT_SIZE.to_le_bytes());
+		boot_sector[510..].copy_from_slice(&[0x55, 0xAA]);
+
+		let fat_start = LBA_START as usize + 1;
+		for cluster in 0..FIRST_CLUSTER + used_clusters
+		{
+			let block = &mut blocks[fat_start + cluster as usize / 256].contents;
+			let offset = cluster as usize % 256 * 2;
+			block[offset..offset + 2].copy_from_slice(&0xFFFF_u16.to_le_bytes());
+		}
+
+		MemoryBlockDevice(RefCell::new(blocks))
+	}
+
+	#[test]
+	fn layout_is_read_from_the_boot_sector()
+	{
+		let layout = FatLayout::read(&fat16_card(0)).unwrap();
+
+		assert_eq!(
+			layout,
+			FatLayout {
+				fat_start: LBA_START + 1,
+				cluster_count: 5000,
+				bytes_per_cluster: 1024,
+				is_fat32: false,
+			}
+		);
+		assert_eq!(layout.capacity_bytes(), 5000 * 1024);
+		assert_eq!(layout.allocated_bytes(1025), 2048);
+		assert_eq!(layout.allocated_bytes(0), 0);
+	}
+
+	#[test]
+	fn free_clusters_are_counted()
+	{
+		let card = fat16_card(300);
+		let layout = FatLayout::read(&card).unwrap();
+
+		assert_eq!(layout.free_bytes(&card).unwrap(), 4700 * 1024);
+	}
+
+	#[test]
+	fn card_without_a_fat_is_refused()
+	{
+		let card = fat16_card(0);
+		card.0.borrow_mut()[LBA_START as usize].contents[510] = 0;
+
+		assert!(matches!(FatLayout::read(&card), Err(Error::FormatError(_))));
+	}
+}
@@ -0,0 +1,420 @@
+mod avi;
+mod captures;
+mod events;
+mod fat;
+mod path;
+mod retention;
+mod timelapse;
+
+use a13c_embedded::{
+	features::storage::embedded_sdmmc::*,
+	peripherals::time::real_time::time::{Date, Time},
+};
+pub use avi::*;
+pub use captures::*;
+pub use events::*;
+use fat::FatLayout;
+pub use path::*;
+pub use retention::*;
+pub use timelapse::*;
+
+/// Dates before this year mean that the real time clock hasn't been synchronized yet (it starts from the epoch).
+pub(crate) const FIRST_VALID_YEAR: i32 = 2024;
+
+pub struct Storage<
+	D: BlockDevice,
+	T: TimeSource,
+	const MAX_DIRS: usize = 3,
+	const MAX_FILES: usize = 3,
+	const MAX_VOLUMES: usize = 1,
+> {
+	volume_manager: VolumeManager<D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>,
+	volume0: RawVolume,
+	raw_root_dir: Option<RawDirectory>,
+	next_sequence_number: Option<u32>,
+	retention_policy: RetentionPolicy,
+	last_age_check: Option<Date>,
+	/// Bytes used by the captures, kept up to date while storing and deleting them.
+	used_bytes: u64,
+	capacity_bytes: u64,
+	/// `None` if the FAT couldn't be read, and then the free space is estimated from [`Self::used_bytes`].
+	fat_layout: Option<FatLayout>,
+	/// Measured from the FAT (check [`Self::measure_free_bytes`]) and then estimated while storing and deleting the
+	/// captures, until it's measured again.
+	free_bytes: u64,
+	is_free_bytes_measured: bool,
+	/// The event being recorded and the writer of its video.
+	event_video: Option<(EventPath, AviWriter)>,
+	timelapse_day: Option<TimelapseDay>,
+}
+
+impl<D: BlockDevice, T: TimeSource, const MAX_DIRS: usize, const MAX_FILES: usize, const MAX_VOLUMES: usize>
+	Storage<D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>
+{
+	pub fn new(block_device: D, time_source: T, retention_policy: RetentionPolicy) -> Result<Self, Error<D::Error>>
+	{
+		let capacity_bytes = match block_device.num_blocks()
+		{
+			Ok(num_blocks) =>
+			{
+				let capacity_bytes = num_blocks.0 as u64 * Block::LEN as u64;
+				log::info!("Card size is {} bytes", capacity_bytes);
+				capacity_bytes
+			},
+			Err(error) =>
+			{
+				log::warn!("Error: {:#?}", error);
+				0
+			},
+		};
+
+		let fat_layout = match FatLayout::read(&block_device)
+		{
+			Ok(fat_layout) => Some(fat_layout),
+			Err(error) =>
+			{
+				log::warn!("Couldn't read the FAT, the free space will be estimated: {:?}", error);
+				None
+			},
+		};
+		let capacity_bytes = fat_layout.map_or(capacity_bytes, |fat_layout| fat_layout.capacity_bytes());
+
+		let mut volume_manager = VolumeManager::new_with_limits(block_device, time_source, 0);
+		let mut volume0 = volume_manager.open_volume(VolumeIdx(0))?;
+		let raw_root_dir = volume0.open_root_dir()?.to_raw_directory();
+
+		let volume0 = volume0.to_raw_volume();
+
+		let mut storage = Self {
+			volume_manager,
+			volume0,
+			raw_root_dir: Some(raw_root_dir),
+			next_sequence_number: None,
+			retention_policy,
+			last_age_check: None,
+			used_bytes: 0,
+			capacity_bytes,
+			fat_layout,
+			free_bytes: 0,
+			is_free_bytes_measured: false,
+			event_video: None,
+			timelapse_day: None,
+		};
+		storage.used_bytes = storage.captures_size()?;
+		log::info!("Captures use {} bytes", storage.used_bytes);
+		storage.measure_free_bytes()?;
+		log::info!("{} bytes are free", storage.free_bytes);
+		storage.repair_interrupted_event();
+
+		Ok(storage)
+	}
+
+	/// Stores the image in a new file and returns its path (check [`CapturePath`]).
+	///
+	/// The path is based on `date_and_time` if the real time clock is set, otherwise on a sequence number saved in the
+	/// SD card. In both cases an existing file is never overwritten.
+	///
+	/// The retention policy is enforced before writing the image, and if the card is full anyway the oldest captures
+	/// are deleted and the image is written again.
+	pub fn store_image(
+		&mut self, image: &[u8], date_and_time: Option<(Date, Time)>,
+	) -> Result<CapturePath, Error<D::Error>>
+	{
+		let date_and_time = date_and_time.filter(|(date, _)| date.year() >= FIRST_VALID_YEAR);
+		self.enforce_retention_policy(image.len() as u64, date_and_time.map(|(date, _)| date))?;
+
+		let path = match self.try_store_image(image, date_and_time)
+		{
+			Err(Error::DiskFull | Error::NotEnoughSpace) =>
+			{
+				log::warn!("The SD card is full, deleting the oldest captures");
+				self.delete_oldest_captures()?;
+				self.try_store_image(image, date_and_time)
+			},
+			result => result,
+		}?;
+		self.count_written_bytes(image.len() as u64);
+
+		Ok(path)
+	}
+
+	fn try_store_image(
+		&mut self, image: &[u8], date_and_time: Option<(Date, Time)>,
+	) -> Result<CapturePath, Error<D::Error>>
+	{
+		let mut path = match date_and_time
+		{
+			Some((date, time)) => CapturePath::dated(date, time),
+			None => CapturePath::Sequence { number: 0 },
+		};
+
+		let directory = self.open_directories(&path.directories())?;
+		let result = self.store_image_in_directory(image, directory, &mut path);
+		self.close_directory(directory)?;
+
+		result.map(|()| path)
+	}
+
+	fn store_image_in_directory(
+		&mut self, image: &[u8], directory: RawDirectory, path: &mut CapturePath,
+	) -> Result<(), Error<D::Error>>
+	{
+		if let CapturePath::Sequence { number } = path
+		{
+			*number = self.next_sequence_number(directory)?;
+		}
+
+		let file = self.create_new_file(directory, path)?;
+		let write_result = self.volume_manager.write(file, image);
+		self.volume_manager.close_file(file)?;
+		if let Err(error) = write_result
+		{
+			// Don't leave a truncated image behind
+			let _ = self
+				.volume_manager
+				.delete_file_in_dir(directory, path.file_name().as_str());
+			return Err(error);
+		}
+
+		if let CapturePath::Sequence { number } = path
+		{
+			self.save_next_sequence_number(directory, *number + 1)?;
+		}
+
+		Ok(())
+	}
+
+	/// Creates the file at `path`, or at the next available path if it already exists (check [`CapturePath::advance`]).
+	fn create_new_file(&mut self, directory: RawDirectory, path: &mut CapturePath) -> Result<RawFile, Error<D::Error>>
+	{
+		loop
+		{
+			match self
+				.volume_manager
+				.open_file_in_dir(directory, path.file_name().as_str(), Mode::ReadWriteCreate)
+			{
+				Err(Error::FileAlreadyExists) if path.advance() => continue,
+				result => return result,
+			}
+		}
+	}
+
+	fn next_sequence_number(&mut self, directory: RawDirectory) -> Result<u32, Error<D::Error>>
+	{
+		if let Some(next_sequence_number) = self.next_sequence_number
+		{
+			return Ok(next_sequence_number);
+		}
+
+		let next_sequence_number =
+			match self
+				.volume_manager
+				.open_file_in_dir(directory, SEQUENCE_FILE_NAME, Mode::ReadOnly)
+			{
+				Ok(file) =>
+				{
+					let mut buffer = [0; 10];
+					let read_result = self.volume_manager.read(file, &mut buffer);
+					self.volume_manager.close_file(file)?;
+					let read_bytes = read_result?;
+
+					core::str::from_utf8(&buffer[..read_bytes])
+						.ok()
+						.and_then(|number| number.trim().parse().ok())
+						.unwrap_or(0)
+				},
+				Err(Error::NotFound) => 0,
+				Err(error) => return Err(error),
+			};
+		self.next_sequence_number = Some(next_sequence_number);
+
+		Ok(next_sequence_number)
+	}
+
+	fn save_next_sequence_number(
+		&mut self, directory: RawDirectory, next_sequence_number: u32,
+	) -> Result<(), Error<D::Error>>
+	{
+		self.next_sequence_number = Some(next_sequence_number);
+
+		let file =
+			self.volume_manager
+				.open_file_in_dir(directory, SEQUENCE_FILE_NAME, Mode::ReadWriteCreateOrTruncate)?;
+		let write_result = self
+			.volume_manager
+			.write(file, next_sequence_number.to_string().as_bytes());
+		self.volume_manager.close_file(file)?;
+
+		write_result
+	}
+
+	/// Writes a new file with `bytes`, or replaces the existing one.
+	fn write_file(&mut self, directory: RawDirectory, file_name: &str, bytes: &[u8]) -> Result<(), Error<D::Error>>
+	{
+		let file = self
+			.volume_manager
+			.open_file_in_dir(directory, file_name, Mode::ReadWriteCreateOrTruncate)?;
+		let write_result = self.volume_manager.write(file, bytes);
+		self.volume_manager.close_file(file)?;
+		if write_result.is_err()
+		{
+			// Don't leave a truncated file behind
+			let _ = self.volume_manager.delete_file_in_dir(directory, file_name);
+		}
+
+		write_result
+	}
+
+	/// Opens the file at the end of `directories` (creating the missing directories and the file) and calls `action`
+	/// with it. The file is closed before returning, so that it's complete on the card except for the last write.
+	fn with_file<R>(
+		&mut self, directories: &[String], file_name: &str,
+		action: impl FnOnce(&mut SdCardFile<D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>) -> Result<R, Error<D::Error>>,
+	) -> Result<R, Error<D::Error>>
+	{
+		let directory = self.open_directories(directories)?;
+		let result = self
+			.volume_manager
+			.open_file_in_dir(directory, file_name, Mode::ReadWriteCreateOrAppend)
+			.and_then(|file| {
+				let result = action(&mut SdCardFile {
+					volume_manager: &mut self.volume_manager,
+					file,
+				});
+				self.volume_manager.close_file(file)?;
+				result
+			});
+		self.close_directory(directory)?;
+
+		result
+	}
+
+	/// Opens the directory at the end of `names`, starting from the root directory and creating the missing ones.
+	/// The returned directory must be closed with [`Self::close_directory`].
+	fn open_directories(&mut self, names: &[String]) -> Result<RawDirectory, Error<D::Error>>
+	{
+		let mut directory = self.raw_root_dir.ok_or(Error::BadHandle)?;
+		for name in names
+		{
+			let child_directory = self.open_or_make_directory(directory, name);
+			self.close_directory(directory)?;
+			directory = child_directory?;
+		}
+
+		Ok(directory)
+	}
+
+	/// Like [`Self::open_directories`], but returns [`Error::NotFound`] instead of creating the missing directories.
+	fn open_existing_directories(&mut self, names: &[String]) -> Result<RawDirectory, Error<D::Error>>
+	{
+		let mut directory = self.raw_root_dir.ok_or(Error::BadHandle)?;
+		for name in names
+		{
+			let child_directory = self.volume_manager.open_dir(directory, name.as_str());
+			self.close_directory(directory)?;
+			directory = child_directory?;
+		}
+
+		Ok(directory)
+	}
+
+	/// Returns the entries of the directory at `names` (without `.`, `..` and the volume label).
+	fn directory_entries(&mut self, names: &[String]) -> Result<Vec<DirEntry>, Error<D::Error>>
+	{
+		let directory = self.open_existing_directories(names)?;
+		let mut entries = Vec::new();
+		let result = self.volume_manager.iterate_dir(directory, |entry| {
+			if !entry.attributes.is_volume() && !entry.name.to_string().starts_with('.')
+			{
+				entries.push(entry.clone());
+			}
+		});
+		self.close_directory(directory)?;
+		result?;
+
+		Ok(entries)
+	}
+
+	fn open_or_make_directory(&mut self, parent: RawDirectory, name: &str) -> Result<RawDirectory, Error<D::Error>>
+	{
+		match self.volume_manager.make_dir_in_dir(parent, name)
+		{
+			Ok(()) | Err(Error::DirAlreadyExists) => self.volume_manager.open_dir(parent, name),
+			Err(error) => Err(error),
+		}
+	}
+
+	/// Closes the directory, unless it's the root directory (which stays open as long as this struct exists).
+	fn close_directory(&mut self, directory: RawDirectory) -> Result<(), Error<D::Error>>
+	{
+		if Some(directory) == self.raw_root_dir
+		{
+			Ok(())
+		}
+		else
+		{
+			self.volume_manager.close_dir(directory)
+		}
+	}
+}
+
+impl<D: BlockDevice, T: TimeSource, const MAX_DIRS: usize, const MAX_FILES: usize, const MAX_VOLUMES: usize> Drop
+	for Storage<D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>
+{
+	fn drop(&mut self)
+	{
+		if let Some(raw_root_dir) = self.raw_root_dir.take()
+		{
+			self.volume_manager.close_dir(raw_root_dir).unwrap();
+		}
+		self.volume_manager.close_volume(self.volume0).unwrap();
+	}
+}
+
+/// A file open in the [`VolumeManager`], which can be used as an [`AviFile`].
+pub(super) struct SdCardFile<
+	'a,
+	D: BlockDevice,
+	T: TimeSource,
+	const MAX_DIRS: usize,
+	const MAX_FILES: usize,
+	const MAX_VOLUMES: usize,
+> {
+	volume_manager: &'a mut VolumeManager<D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>,
+	file: RawFile,
+}
+
+impl<D: BlockDevice, T: TimeSource, const MAX_DIRS: usize, const MAX_FILES: usize, const MAX_VOLUMES: usize> AviFile
+	for SdCardFile<'_, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>
+{
+	type Error = Error<D::Error>;
+
+	fn length(&mut self) -> Result<u32, Self::Error>
+	{
+		self.volume_manager.file_length(self.file)
+	}
+
+	fn read_at(&mut self, offset: u32, buffer: &mut [u8]) -> Result<usize, Self::Error>
+	{
+		self.volume_manager.file_seek_from_start(self.file, offset)?;
+
+		let mut read_bytes = 0;
+		while read_bytes < buffer.len()
+		{
+			match self.volume_manager.read(self.file, &mut buffer[read_bytes..])
+			{
+				Ok(0) | Err(Error::EndOfFile) => break,
+				Ok(bytes) => read_bytes += bytes,
+				Err(error) => return Err(error),
+			}
+		}
+
+		Ok(read_bytes)
+	}
+
+	fn write_at(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error>
+	{
+		self.volume_manager.file_seek_from_start(self.file, offset)?;
+		self.volume_manager.write(self.file, bytes)
+	}
+}
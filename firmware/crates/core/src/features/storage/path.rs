@@ -0,0 +1,283 @@
+use core::{
+	fmt::{Display, Formatter},
+	str::FromStr,
+};
+
+use a13c_embedded::peripherals::time::real_time::time::{Date, Month, Time};
+
+/// The path of a capture stored in the SD card.
+///
+/// The filesystem library only supports 8.3 file names, so the `HHMMSS_nnn` name of a capture is split between a
+/// directory for the hour and a `MMSS_nnn` file name.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CapturePath
+{
+	/// `YYYY/MM/DD/HH/MMSS_nnn.JPG`, where `nnn` distinguishes the captures taken in the same second.
+	Dated
+	{
+		date: Date, time: Time, index: u16
+	},
+	/// `NOCLOCK/nnnnnnnn.JPG`, used when the real time clock isn't set yet.
+	/// The number is kept in [`SEQUENCE_FILE_NAME`] so that it keeps growing across reboots.
+	Sequence
+	{
+		number: u32
+	},
+}
+
+pub const NO_CLOCK_DIRECTORY_NAME: &str = "NOCLOCK";
+pub const SEQUENCE_FILE_NAME: &str = "SEQUENCE.TXT";
+
+impl CapturePath
+{
+	const MAX_INDEX: u16 = 999;
+
+	pub fn dated(date: Date, time: Time) -> Self
+	{
+		Self::Dated { date, time, index: 0 }
+	}
+
+	/// Returns the names of the directories that contain the file, starting from the root directory (it's never empty).
+	pub fn directories(&self) -> Vec<String>
+	{
+		match self
+		{
+			Self::Dated { date, time, .. } => vec![
+				format!("{:04}", date.year()),
+				format!("{:02}", date.month() as u8),
+				format!("{:02}", date.day()),
+				format!("{:02}", time.hour()),
+			],
+			Self::Sequence { .. } => vec![NO_CLOCK_DIRECTORY_NAME.to_string()],
+		}
+	}
+
+	pub fn file_name(&self) -> String
+	{
+		format!("{}.JPG", self.base_name())
+	}
+
+	/// The file name without the extension.
+	fn base_name(&self) -> String
+	{
+		match self
+		{
+			Self::Dated { time, index, .. } => format!("{:02}{:02}_{:03}", time.minute(), time.second(), index),
+			Self::Sequence { number } => format!("{:08}", number),
+		}
+	}
+
+	/// Moves to the next path available for a capture taken at the same moment.
+	/// Returns `false` if there are no more paths available.
+	pub fn advance(&mut self) -> bool
+	{
+		match self
+		{
+			Self::Dated { index, .. } if *index < Self::MAX_INDEX =>
+			{
+				*index += 1;
+				true
+			},
+			Self::Sequence { number } if *number < 99_999_999 =>
+			{
+				*number += 1;
+				true
+			},
+			_ => false,
+		}
+	}
+}
+
+impl Display for CapturePath
+{
+	fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result
+	{
+		for directory in self.directories()
+		{
+			write!(f, "{}/", directory)?;
+		}
+		write!(f, "{}", self.file_name())
+	}
+}
+
+/// Parses the same format returned by [`Display`], so that a path shown to the user can be used to reach the capture.
+impl FromStr for CapturePath
+{
+	type Err = InvalidCapturePath;
+
+	fn from_str(path: &str) -> Result<Self, Self::Err>
+	{
+		let parts: Vec<&str> = path.split('/').collect();
+		match parts.as_slice()
+		{
+			[year, month, day, hour, file_name] =>
+			{
+				let (minute_and_second, index) = file_name
+					.strip_suffix(".JPG")
+					.and_then(|name| name.split_once('_'))
+					.ok_or(InvalidCapturePath)?;
+				let date = Date::from_calendar_date(
+					parse_digits(year, 4)?,
+					Month::try_from(parse_digits::<u8>(month, 2)?).map_err(|_| InvalidCapturePath)?,
+					parse_digits(day, 2)?,
+				)
+				.map_err(|_| InvalidCapturePath)?;
+				let time = Time::from_hms(
+					parse_digits(hour, 2)?,
+					parse_digits(minute_and_second.get(..2).ok_or(InvalidCapturePath)?, 2)?,
+					parse_digits(minute_and_second.get(2..).ok_or(InvalidCapturePath)?, 2)?,
+				)
+				.map_err(|_| InvalidCapturePath)?;
+
+				Ok(Self::Dated {
+					date,
+					time,
+					index: parse_digits(index, 3)?,
+				})
+			},
+			[NO_CLOCK_DIRECTORY_NAME, file_name] =>
+			{
+				let number = file_name.strip_suffix(".JPG").ok_or(InvalidCapturePath)?;
+				Ok(Self::Sequence {
+					number: parse_digits(number, 8)?,
+				})
+			},
+			_ => Err(InvalidCapturePath),
+		}
+	}
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InvalidCapturePath;
+
+/// The directory of an event recorded around a trigger: it's named like a capture, but with the `EVT` extension
+/// (`YYYY/MM/DD/HH/MMSS_nnn.EVT` or `NOCLOCK/nnnnnnnn.EVT`), and it contains the [`EVENT_VIDEO_FILE_NAME`] with the
+/// frames of the event and its [`EVENT_MANIFEST_FILE_NAME`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EventPath(pub CapturePath);
+
+pub const EVENT_DIRECTORY_EXTENSION: &str = "EVT";
+pub const EVENT_VIDEO_FILE_NAME: &str = "EVENT.AVI";
+pub const EVENT_MANIFEST_FILE_NAME: &str = "EVENT.JSN";
+
+impl EventPath
+{
+	/// Returns the names of the directories from the root directory to the event directory (included).
+	pub fn directories(&self) -> Vec<String>
+	{
+		let mut directories = self.0.directories();
+		directories.push(self.directory_name());
+		directories
+	}
+
+	pub fn directory_name(&self) -> String
+	{
+		format!("{}.{}", self.0.base_name(), EVENT_DIRECTORY_EXTENSION)
+	}
+}
+
+impl Display for EventPath
+{
+	fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result
+	{
+		write!(f, "{}", self.directories().join("/"))
+	}
+}
+
+/// Parses the same format returned by [`Display`].
+impl FromStr for EventPath
+{
+	type Err = InvalidCapturePath;
+
+	fn from_str(path: &str) -> Result<Self, Self::Err>
+	{
+		let path = path
+			.strip_suffix(EVENT_DIRECTORY_EXTENSION)
+			.and_then(|path| path.strip_suffix('.'))
+			.ok_or(InvalidCapturePath)?;
+
+		format!("{}.JPG", path).parse().map(Self)
+	}
+}
+
+/// Returns `true` if `name` is the name of an event directory (check [`EventPath`]).
+pub fn is_event_directory_name(name: &str) -> bool
+{
+	name.rsplit_once('.')
+		.is_some_and(|(_, extension)| extension == EVENT_DIRECTORY_EXTENSION)
+}
+
+/// Parses `text` only if it's made of exactly `length` digits.
+fn parse_digits<N: FromStr>(text: &str, length: usize) -> Result<N, InvalidCapturePath>
+{
+	if text.len() != length || !text.bytes().all(|byte| byte.is_ascii_digit())
+	{
+		return Err(InvalidCapturePath);
+	}
+
+	text.parse().map_err(|_| InvalidCapturePath)
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	#[test]
+	fn displayed_paths_are_parsed()
+	{
+		let dated = CapturePath::Dated {
+			date: Date::from_calendar_date(2024, Month::May, 1).unwrap(),
+			time: Time::from_hms(13, 1, 2).unwrap(),
+			index: 7,
+		};
+		assert_eq!(dated.to_string(), "2024/05/01/13/0102_007.JPG");
+		assert_eq!("2024/05/01/13/0102_007.JPG".parse(), Ok(dated));
+
+		let sequence = CapturePath::Sequence { number: 42 };
+		assert_eq!(sequence.to_string(), "NOCLOCK/00000042.JPG");
+		assert_eq!("NOCLOCK/00000042.JPG".parse(), Ok(sequence));
+	}
+
+	#[test]
+	fn invalid_dates_and_names_are_refused()
+	{
+		for path in [
+			"2024/02/30/13/0102_000.JPG",
+			"2024/13/01/13/0102_000.JPG",
+			"2024/05/01/24/0102_000.JPG",
+			"2024/05/01/13/0160_000.JPG",
+			"2024/05/01/13/0102_0000.JPG",
+			"2024/05/01/13/0102_000.AVI",
+			"2024/5/01/13/0102_000.JPG",
+			"2024/05/01/13/+102_000.JPG",
+			"NOCLOCK/0000042.JPG",
+			"NOCLOCK/-0000042.JPG",
+			"SEQUENCE.TXT",
+			"",
+		]
+		{
+			assert_eq!(path.parse::<CapturePath>(), Err(InvalidCapturePath), "{}", path);
+		}
+	}
+
+	#[test]
+	fn paths_out_of_the_capture_directories_are_refused()
+	{
+		for path in [
+			"../2024/05/01/13/0102_000.JPG",
+			"2024/05/01/../0102_000.JPG",
+			"2024/05/01/13/../../../../SEQUENCE.TXT",
+			"/2024/05/01/13/0102_000.JPG",
+			"2024/05/01/13//0102_000.JPG",
+			"NOCLOCK/../NOCLOCK/00000042.JPG",
+			"NOCLOCK/./00000042.JPG",
+			"NOCLOCK/00000042.JPG/..",
+			"NOCLOCK\\..\\00000042.JPG",
+			"TIMELAPSE/2024/05/01/0102_000.JPG",
+		]
+		{
+			assert_eq!(path.parse::<CapturePath>(), Err(InvalidCapturePath), "{}", path);
+		}
+	}
+}
@@ -0,0 +1,488 @@
+use a13c_embedded::{
+	features::storage::embedded_sdmmc::*,
+	peripherals::time::real_time::time::{Date, Month},
+};
+use serde::Serialize;
+
+use super::{
+	is_event_directory_name, timelapse::timelapse_directory_date, Storage, NO_CLOCK_DIRECTORY_NAME, SEQUENCE_FILE_NAME,
+	TIMELAPSE_DIRECTORY_NAME,
+};
+
+/// Limits on the captures kept in the SD card. When one of them is exceeded, the oldest captures are deleted
+/// (check [`Storage::enforce_retention_policy`]). A `None` field means that there's no limit.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RetentionPolicy
+{
+	/// Maximum number of bytes used by all the captures.
+	pub max_used_bytes: Option<u64>,
+	/// Maximum age of a capture (based on the date in its path).
+	pub max_age_days: Option<u16>,
+	/// Minimum percentage (from 0 to 100) of the card that must be free.
+	pub min_free_space_percentage: Option<u8>,
+}
+
+/// How the space of the SD card is used, as tracked by the [`Storage`] (check [`Storage::free_bytes`]).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct StorageStats
+{
+	pub used_bytes: u64,
+	pub free_bytes: u64,
+	pub capacity_bytes: u64,
+}
+
+/// Number of directories between the root directory and the files of a dated capture (check [`super::CapturePath`]).
+pub(super) const DATED_DIRECTORIES_DEPTH: usize = 4;
+
+impl<D: BlockDevice, T: TimeSource, const MAX_DIRS: usize, const MAX_FILES: usize, const MAX_VOLUMES: usize>
+	Storage<D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>
+{
+	/// Deletes the oldest captures until the retention policy is respected, also considering the `incoming_bytes` that
+	/// are about to be written. The dated captures are deleted an hour directory (or a timelapse day) at a time, and
+	/// the ones stored while the real time clock wasn't set are deleted one at a time, only after all the dated ones.
+	///
+	/// The age limit is checked once a day, and only if `today` is provided (and again at the next call if a capture
+	/// that is too old is open).
+	pub fn enforce_retention_policy(&mut self, incoming_bytes: u64, today: Option<Date>)
+		-> Result<(), Error<D::Error>>
+	{
+		if let (Some(max_age_days), Some(today)) = (self.retention_policy.max_age_days, today)
+		{
+			if self.last_age_check != Some(today) && self.delete_captures_older_than(today, max_age_days)?
+			{
+				self.last_age_check = Some(today);
+			}
+		}
+
+		while self.exceeds_space_limits(incoming_bytes)
+		{
+			// The free space is only estimated after storing or deleting captures, so it's measured before deleting
+			if !self.is_free_bytes_measured && self.retention_policy.min_free_space_percentage.is_some()
+			{
+				self.measure_free_bytes()?;
+				continue;
+			}
+			if self.delete_oldest_captures()? == 0
+			{
+				break;
+			}
+		}
+
+		Ok(())
+	}
+
+	/// Returns the free bytes in the card, counted in the FAT when the retention policy needs it and lowered by the
+	/// whole clusters of the captures stored since then (so it can be a bit lower than the real value).
+	pub fn free_bytes(&self) -> u64
+	{
+		self.free_bytes
+	}
+
+	pub fn used_bytes(&self) -> u64
+	{
+		self.used_bytes
+	}
+
+	pub fn stats(&self) -> StorageStats
+	{
+		StorageStats {
+			used_bytes: self.used_bytes,
+			free_bytes: self.free_bytes,
+			capacity_bytes: self.capacity_bytes,
+		}
+	}
+
+	/// Counts the free clusters in the FAT. If it can't be read, the free space is estimated assuming that only the
+	/// captures use space in the card.
+	pub(super) fn measure_free_bytes(&mut self) -> Result<(), Error<D::Error>>
+	{
+		self.free_bytes = match self.fat_layout
+		{
+			Some(fat_layout) => fat_layout.free_bytes(self.volume_manager.device())?,
+			None => self.capacity_bytes.saturating_sub(self.used_bytes),
+		};
+		self.is_free_bytes_measured = true;
+
+		Ok(())
+	}
+
+	/// Counts the bytes of captures that were just written.
+	pub(super) fn count_written_bytes(&mut self, bytes: u64)
+	{
+		self.used_bytes += bytes;
+		self.free_bytes = self.free_bytes.saturating_sub(self.allocated_bytes(bytes));
+		self.is_free_bytes_measured = false;
+	}
+
+	/// Counts the bytes of the captures that were just deleted.
+	pub(super) fn count_deleted_bytes(&mut self, bytes: u64)
+	{
+		self.used_bytes = self.used_bytes.saturating_sub(bytes);
+		self.free_bytes = (self.free_bytes + self.allocated_bytes(bytes)).min(self.capacity_bytes);
+		self.is_free_bytes_measured = false;
+	}
+
+	/// Rounds `bytes` up to whole clusters. The writes that extend a file are rounded too, so the free space is
+	/// underestimated until it's measured again.
+	fn allocated_bytes(&self, bytes: u64) -> u64
+	{
+		self.fat_layout
+			.map_or(bytes, |fat_layout| fat_layout.allocated_bytes(bytes))
+	}
+
+	fn exceeds_space_limits(&self, incoming_bytes: u64) -> bool
+	{
+		let exceeds_max_used_bytes = self
+			.retention_policy
+			.max_used_bytes
+			.is_some_and(|max_used_bytes| self.used_bytes + incoming_bytes > max_used_bytes);
+		let exceeds_min_free_space =
+			self.retention_policy
+				.min_free_space_percentage
+				.is_some_and(|min_free_space_percentage| {
+					let free_bytes = self.free_bytes.saturating_sub(self.allocated_bytes(incoming_bytes));
+					free_bytes * 100 < self.capacity_bytes * min_free_space_percentage.min(100) as u64
+				});
+
+		exceeds_max_used_bytes || exceeds_min_free_space
+	}
+
+	/// Returns `false` if it stopped at a directory whose captures couldn't be deleted because they're open.
+	fn delete_captures_older_than(&mut self, today: Date, max_age_days: u16) -> Result<bool, Error<D::Error>>
+	{
+		while let Some(path) = self.oldest_dated_directory()?
+		{
+			let is_too_old = directory_date(&path)
+				.map(|date| (today - date).whole_days() > max_age_days as i64)
+				.unwrap_or(false);
+			if !is_too_old
+			{
+				break;
+			}
+
+			// The open captures are skipped, so the same directory would be found again
+			if self.delete_captures_in_directory(&path)? == 0
+			{
+				return Ok(false);
+			}
+		}
+
+		Ok(true)
+	}
+
+	/// Deletes the captures in the oldest directory (or the oldest capture stored while the real time clock wasn't set)
+	/// and returns the number of bytes freed.
+	pub(super) fn delete_oldest_captures(&mut self) -> Result<u64, Error<D::Error>>
+	{
+		match self.oldest_dated_directory()?
+		{
+			Some(path) => self.delete_captures_in_directory(&path),
+			None => self.delete_oldest_no_clock_capture(),
+		}
+	}
+
+	/// Deletes the image or the event with the lowest sequence number, and returns the number of bytes freed.
+	fn delete_oldest_no_clock_capture(&mut self) -> Result<u64, Error<D::Error>>
+	{
+		let path = [NO_CLOCK_DIRECTORY_NAME.to_string()];
+		let mut entries = match self.directory_entries(&path)
+		{
+			Ok(entries) => entries,
+			Err(Error::NotFound) => return Ok(0),
+			Err(error) => return Err(error),
+		};
+		// The sequence numbers have a fixed number of digits, so they're sorted like the names
+		entries.sort_by(|entry, other_entry| entry.name.base_name().cmp(other_entry.name.base_name()));
+
+		for entry in entries
+		{
+			let name = entry.name.to_string();
+			if entry.attributes.is_directory()
+			{
+				// The events whose files were already deleted are skipped
+				let freed_bytes = match is_event_directory_name(&name)
+				{
+					true => self.delete_captures_in_directory(&[path[0].clone(), name])?,
+					false => 0,
+				};
+				if freed_bytes > 0
+				{
+					return Ok(freed_bytes);
+				}
+			}
+			else if name != SEQUENCE_FILE_NAME
+			{
+				let directory = self.open_existing_directories(&path)?;
+				let result = self.volume_manager.delete_file_in_dir(directory, &entry.name);
+				self.close_directory(directory)?;
+				match result
+				{
+					Ok(()) => (),
+					// It's being downloaded
+					Err(Error::FileAlreadyOpen) => continue,
+					Err(error) => return Err(error),
+				}
+
+				self.count_deleted_bytes(entry.size as u64);
+				log::info!("Deleted capture {}/{}", NO_CLOCK_DIRECTORY_NAME, name);
+				if entry.size > 0
+				{
+					return Ok(entry.size as u64);
+				}
+			}
+		}
+
+		Ok(0)
+	}
+
+	/// Returns the path of the oldest directory with files, between the hour directories and the timelapse days.
+	fn oldest_dated_directory(&mut self) -> Result<Option<Vec<String>>, Error<D::Error>>
+	{
+		let capture_directory = self.oldest_dated_capture_directory()?;
+		let timelapse_directory = self.oldest_timelapse_directory()?;
+
+		Ok(match (capture_directory, timelapse_directory)
+		{
+			(Some(capture_directory), Some(timelapse_directory)) =>
+			{
+				if directory_date(&timelapse_directory) < directory_date(&capture_directory)
+				{
+					Some(timelapse_directory)
+				}
+				else
+				{
+					Some(capture_directory)
+				}
+			},
+			(capture_directory, timelapse_directory) => capture_directory.or(timelapse_directory),
+		})
+	}
+
+	/// Returns the path of the oldest timelapse day that contains at least a file.
+	fn oldest_timelapse_directory(&mut self) -> Result<Option<Vec<String>>, Error<D::Error>>
+	{
+		let mut path = vec![TIMELAPSE_DIRECTORY_NAME.to_string()];
+		let entries = match self.directory_entries(&path)
+		{
+			Ok(entries) => entries,
+			Err(Error::NotFound) => return Ok(None),
+			Err(error) => return Err(error),
+		};
+
+		let mut day_names: Vec<String> = entries
+			.iter()
+			.filter(|entry| entry.attributes.is_directory())
+			.map(|entry| entry.name.to_string())
+			.filter(|name| timelapse_directory_date(name).is_some())
+			.collect();
+		day_names.sort();
+
+		for day_name in day_names
+		{
+			if self.directory_has_files(&mut path, day_name.clone())?
+			{
+				path.push(day_name);
+				return Ok(Some(path));
+			}
+		}
+
+		Ok(None)
+	}
+
+	/// Returns the path of the oldest hour directory that contains at least a file.
+	fn oldest_dated_capture_directory(&mut self) -> Result<Option<Vec<String>>, Error<D::Error>>
+	{
+		let mut path = Vec::with_capacity(DATED_DIRECTORIES_DEPTH);
+		if self.find_oldest_capture_directory(&mut path)?
+		{
+			Ok(Some(path))
+		}
+		else
+		{
+			Ok(None)
+		}
+	}
+
+	fn find_oldest_capture_directory(&mut self, path: &mut Vec<String>) -> Result<bool, Error<D::Error>>
+	{
+		let entries = match self.directory_entries(path)
+		{
+			Ok(entries) => entries,
+			Err(Error::NotFound) => return Ok(false),
+			Err(error) => return Err(error),
+		};
+
+		if path.len() == DATED_DIRECTORIES_DEPTH
+		{
+			if entries.iter().any(|entry| !entry.attributes.is_directory())
+			{
+				return Ok(true);
+			}
+			// The event directories stay after their files are deleted, so they only count if they still have files
+			for entry in entries.iter().filter(|entry| entry.attributes.is_directory())
+			{
+				let name = entry.name.to_string();
+				if is_event_directory_name(&name) && self.directory_has_files(path, name)?
+				{
+					return Ok(true);
+				}
+			}
+			return Ok(false);
+		}
+
+		let mut directory_names: Vec<String> = entries
+			.iter()
+			.filter(|entry| entry.attributes.is_directory())
+			.map(|entry| entry.name.to_string())
+			.filter(|name| !name.is_empty() && name.bytes().all(|byte| byte.is_ascii_digit()))
+			.collect();
+		directory_names.sort();
+
+		for directory_name in directory_names
+		{
+			path.push(directory_name);
+			if self.find_oldest_capture_directory(path)?
+			{
+				return Ok(true);
+			}
+			path.pop();
+		}
+
+		Ok(false)
+	}
+
+	fn directory_has_files(&mut self, path: &mut Vec<String>, name: String) -> Result<bool, Error<D::Error>>
+	{
+		path.push(name);
+		let entries = self.directory_entries(path);
+		path.pop();
+
+		Ok(entries?.iter().any(|entry| !entry.attributes.is_directory()))
+	}
+
+	/// Deletes all the captures in the directory at `path` (including the events) and returns the number of bytes
+	/// freed. The directories stay, because the filesystem library can't delete them.
+	fn delete_captures_in_directory(&mut self, path: &[String]) -> Result<u64, Error<D::Error>>
+	{
+		let entries = match self.directory_entries(path)
+		{
+			Ok(entries) => entries,
+			Err(Error::NotFound) => return Ok(0),
+			Err(error) => return Err(error),
+		};
+
+		// The events are deleted first, so that only a directory at a time is kept open
+		let mut freed_event_bytes = 0;
+		for entry in entries.iter().filter(|entry| entry.attributes.is_directory())
+		{
+			let name = entry.name.to_string();
+			if is_event_directory_name(&name)
+			{
+				let mut event_path = path.to_vec();
+				event_path.push(name);
+				freed_event_bytes += self.delete_captures_in_directory(&event_path)?;
+			}
+		}
+
+		let directory = self.open_existing_directories(path)?;
+		let mut freed_bytes = 0;
+		let mut result = Ok(());
+		for entry in entries
+		{
+			let name = entry.name.to_string();
+			if entry.attributes.is_directory() || name == SEQUENCE_FILE_NAME
+			{
+				continue;
+			}
+
+			match self.volume_manager.delete_file_in_dir(directory, &entry.name)
+			{
+				Ok(()) => freed_bytes += entry.size as u64,
+				// It's being downloaded, so it's deleted the next time
+				Err(Error::FileAlreadyOpen) =>
+				{
+					log::warn!("Couldn't delete the open capture {}/{}", path.join("/"), name)
+				},
+				Err(error) =>
+				{
+					result = Err(error);
+					break;
+				},
+			}
+		}
+		self.close_directory(directory)?;
+		result?;
+
+		self.count_deleted_bytes(freed_bytes);
+		log::info!("Deleted {} bytes of captures in {}", freed_bytes, path.join("/"));
+		if path.first().is_some_and(|name| name == TIMELAPSE_DIRECTORY_NAME)
+		{
+			// The next frame of the day must check the directory again
+			self.timelapse_day = None;
+		}
+
+		Ok(freed_bytes + freed_event_bytes)
+	}
+
+	/// Returns the total size of the files in the capture directories.
+	pub(super) fn captures_size(&mut self) -> Result<u64, Error<D::Error>>
+	{
+		let mut path = Vec::with_capacity(DATED_DIRECTORIES_DEPTH);
+		let dated_captures_size = self.directory_tree_size(&mut path)?;
+
+		path.push(NO_CLOCK_DIRECTORY_NAME.to_string());
+		let no_clock_captures_size = self.directory_tree_size(&mut path)?;
+
+		path[0] = TIMELAPSE_DIRECTORY_NAME.to_string();
+		let timelapse_size = self.directory_tree_size(&mut path)?;
+
+		Ok(dated_captures_size + no_clock_captures_size + timelapse_size)
+	}
+
+	fn directory_tree_size(&mut self, path: &mut Vec<String>) -> Result<u64, Error<D::Error>>
+	{
+		let entries = match self.directory_entries(path)
+		{
+			Ok(entries) => entries,
+			Err(Error::NotFound) => return Ok(0),
+			Err(error) => return Err(error),
+		};
+
+		let mut size = 0;
+		for entry in entries
+		{
+			let name = entry.name.to_string();
+			if !entry.attributes.is_directory()
+			{
+				if !path.is_empty() && name != SEQUENCE_FILE_NAME
+				{
+					size += entry.size as u64;
+				}
+			}
+			else if (path.len() < DATED_DIRECTORIES_DEPTH && name.bytes().all(|byte| byte.is_ascii_digit()))
+				|| is_event_directory_name(&name)
+			{
+				path.push(name);
+				size += self.directory_tree_size(path)?;
+				path.pop();
+			}
+		}
+
+		Ok(size)
+	}
+}
+
+/// Returns the date of a `YYYY/MM/DD/HH` directory or of a timelapse day.
+fn directory_date(path: &[String]) -> Option<Date>
+{
+	if path.first()? == TIMELAPSE_DIRECTORY_NAME
+	{
+		return timelapse_directory_date(path.get(1)?);
+	}
+
+	let year = path.first()?.parse().ok()?;
+	let month = Month::try_from(path.get(1)?.parse::<u8>().ok()?).ok()?;
+	let day = path.get(2)?.parse().ok()?;
+
+	Date::from_calendar_date(year, month, day).ok()
+}
@@ -0,0 +1,231 @@
+use core::{
+	fmt::{Display, Formatter},
+	time::Duration,
+};
+
+use a13c_embedded::{
+	features::storage::embedded_sdmmc::*,
+	peripherals::time::real_time::time::{Date, Month},
+};
+
+use super::{AviFile, AviWriter, Storage};
+use crate::features::frames::Frame;
+
+pub const TIMELAPSE_DIRECTORY_NAME: &str = "TLAPSE";
+pub const TIMELAPSE_VIDEO_FILE_NAME: &str = "DAY.AVI";
+
+/// The path of a frame of the timelapse: `TLAPSE/YYYYMMDD/nnnnn.JPG`, where the frames of each day are numbered from 0.
+/// The directory of the day also contains the [`TIMELAPSE_VIDEO_FILE_NAME`], if the video is assembled.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TimelapseFramePath
+{
+	pub date: Date,
+	pub number: u32,
+}
+
+impl TimelapseFramePath
+{
+	const MAX_NUMBER: u32 = 99_999;
+
+	pub fn directories(&self) -> Vec<String>
+	{
+		timelapse_day_directories(self.date)
+	}
+
+	pub fn file_name(&self) -> String
+	{
+		format!("{:05}.JPG", self.number)
+	}
+}
+
+impl Display for TimelapseFramePath
+{
+	fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result
+	{
+		write!(f, "{}/{}", self.directories().join("/"), self.file_name())
+	}
+}
+
+/// The day of the last frame stored in the timelapse, so that the directory doesn't have to be read at each frame.
+pub(super) struct TimelapseDay
+{
+	date: Date,
+	next_number: u32,
+	/// `None` if the video isn't assembled, or if it has to be resumed from the file.
+	video: Option<AviWriter>,
+}
+
+impl<D: BlockDevice, T: TimeSource, const MAX_DIRS: usize, const MAX_FILES: usize, const MAX_VOLUMES: usize>
+	Storage<D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>
+{
+	/// Stores the frame in the timelapse of `date`, after the frames already stored in that day (check
+	/// [`TimelapseFramePath`]). If `video_frame_duration` is set the frame is also appended to the video of the day,
+	/// which is finished after each frame so that it can always be played.
+	///
+	/// The retention policy is enforced like in [`Self::store_image`], and the timelapse days are deleted together with
+	/// the other captures, from the oldest.
+	pub fn store_timelapse_frame(
+		&mut self, date: Date, frame: &Frame, video_frame_duration: Option<Duration>,
+	) -> Result<TimelapseFramePath, Error<D::Error>>
+	{
+		let copies = if video_frame_duration.is_some() { 2 } else { 1 };
+		self.enforce_retention_policy(frame.pixels.len() as u64 * copies, Some(date))?;
+
+		let path = match self.try_store_timelapse_frame(date, frame)
+		{
+			Err(Error::DiskFull | Error::NotEnoughSpace) =>
+			{
+				log::warn!("The SD card is full, deleting the oldest captures");
+				self.delete_oldest_captures()?;
+				self.try_store_timelapse_frame(date, frame)
+			},
+			result => result,
+		}?;
+		self.count_written_bytes(frame.pixels.len() as u64);
+
+		if let Some(frame_duration) = video_frame_duration
+		{
+			self.append_to_timelapse_video(date, frame, frame_duration)?;
+		}
+
+		Ok(path)
+	}
+
+	fn try_store_timelapse_frame(&mut self, date: Date, frame: &Frame) -> Result<TimelapseFramePath, Error<D::Error>>
+	{
+		let directories = timelapse_day_directories(date);
+		let next_number = match &self.timelapse_day
+		{
+			Some(day) if day.date == date => day.next_number,
+			_ => self.next_timelapse_frame_number(&directories)?,
+		};
+		let mut path = TimelapseFramePath {
+			date,
+			number: next_number,
+		};
+
+		let directory = self.open_directories(&directories)?;
+		let result = self.create_new_timelapse_frame(directory, &mut path, &frame.pixels);
+		self.close_directory(directory)?;
+		result?;
+
+		match self.timelapse_day.as_mut()
+		{
+			Some(day) if day.date == date => day.next_number = path.number + 1,
+			_ =>
+			{
+				self.timelapse_day = Some(TimelapseDay {
+					date,
+					next_number: path.number + 1,
+					video: None,
+				})
+			},
+		}
+
+		Ok(path)
+	}
+
+	/// Writes the frame at `path`, or at the next available number if it already exists.
+	fn create_new_timelapse_frame(
+		&mut self, directory: RawDirectory, path: &mut TimelapseFramePath, jpeg: &[u8],
+	) -> Result<(), Error<D::Error>>
+	{
+		let file = loop
+		{
+			match self
+				.volume_manager
+				.open_file_in_dir(directory, path.file_name().as_str(), Mode::ReadWriteCreate)
+			{
+				Err(Error::FileAlreadyExists) if path.number < TimelapseFramePath::MAX_NUMBER => path.number += 1,
+				result => break result?,
+			}
+		};
+
+		let write_result = self.volume_manager.write(file, jpeg);
+		self.volume_manager.close_file(file)?;
+		if write_result.is_err()
+		{
+			// Don't leave a truncated image behind
+			let _ = self
+				.volume_manager
+				.delete_file_in_dir(directory, path.file_name().as_str());
+		}
+
+		write_result
+	}
+
+	/// Returns the number after the highest one of the frames already stored in the directory of the day.
+	fn next_timelapse_frame_number(&mut self, directories: &[String]) -> Result<u32, Error<D::Error>>
+	{
+		let entries = match self.directory_entries(directories)
+		{
+			Ok(entries) => entries,
+			Err(Error::NotFound) => return Ok(0),
+			Err(error) => return Err(error),
+		};
+
+		Ok(entries
+			.iter()
+			.filter(|entry| !entry.attributes.is_directory())
+			.filter_map(|entry| entry.name.to_string().strip_suffix(".JPG")?.parse::<u32>().ok())
+			.map(|number| number + 1)
+			.max()
+			.unwrap_or(0))
+	}
+
+	fn append_to_timelapse_video(
+		&mut self, date: Date, frame: &Frame, frame_duration: Duration,
+	) -> Result<(), Error<D::Error>>
+	{
+		let writer = self.timelapse_day.as_mut().and_then(|day| day.video.take());
+		let result = self.with_file(&timelapse_day_directories(date), TIMELAPSE_VIDEO_FILE_NAME, |file| {
+			// After a reboot the video of the day is continued
+			let mut writer = match writer
+			{
+				Some(writer) => writer,
+				None => AviWriter::resume(file, frame_duration)?
+					.unwrap_or_else(|| AviWriter::with_frame_duration(frame_duration)),
+			};
+			let initial_length = file.length()?;
+			writer.write_frame(file, &frame.pixels, frame.size, frame.timestamp)?;
+			writer.finish(file)?;
+
+			Ok((writer, file.length()?.saturating_sub(initial_length)))
+		});
+
+		// If the writing failed the writer is dropped, so that the next frame resumes the video from the file
+		let (writer, written_bytes) = result?;
+		self.count_written_bytes(written_bytes as u64);
+		if let Some(day) = self.timelapse_day.as_mut()
+		{
+			day.video = Some(writer);
+		}
+
+		Ok(())
+	}
+}
+
+/// Returns the `TLAPSE/YYYYMMDD` directories of a timelapse day.
+pub(super) fn timelapse_day_directories(date: Date) -> Vec<String>
+{
+	vec![
+		TIMELAPSE_DIRECTORY_NAME.to_string(),
+		format!("{:04}{:02}{:02}", date.year(), date.month() as u8, date.day()),
+	]
+}
+
+/// Returns the date of a `YYYYMMDD` timelapse directory.
+pub(super) fn timelapse_directory_date(name: &str) -> Option<Date>
+{
+	if name.len() != 8 || !name.bytes().all(|byte| byte.is_ascii_digit())
+	{
+		return None;
+	}
+
+	Date::from_calendar_date(
+		name[..4].parse().ok()?,
+		Month::try_from(name[4..6].parse::<u8>().ok()?).ok()?,
+		name[6..].parse().ok()?,
+	)
+	.ok()
+}
@@ -0,0 +1,155 @@
+use core::{ops::RangeInclusive, time::Duration};
+
+use a13c_embedded::{
+	peripherals::time::real_time::time::{Date, Time},
+	utils::collections::list::List,
+};
+
+use crate::features::{storage::FIRST_VALID_YEAR, trigger::EnableOnConditions};
+
+/// Configures the [`Timelapse`].
+#[derive(Clone)]
+pub struct TimelapseSettings<L: List<RangeInclusive<Time>>>
+{
+	/// Time between two frames. The frames are aligned to midnight, so with 5 minutes they are captured at 00:00,
+	/// 00:05, 00:10...
+	pub interval: Duration,
+	pub enable_on: EnableOnConditions<L>,
+	/// How long each frame is shown in the video assembled for each day, `None` to only store the frames.
+	pub daily_video_frame_duration: Option<Duration>,
+}
+
+/// Decides when to capture the frames of the timelapse: once per interval while it's enabled, independently from the
+/// motion. The frames are stored with [`crate::features::storage::Storage::store_timelapse_frame`].
+pub struct Timelapse<L: List<RangeInclusive<Time>>>
+{
+	settings: TimelapseSettings<L>,
+	/// The day and the number of the interval of the last frame.
+	last_slot: Option<(Date, u64)>,
+}
+
+impl<L: List<RangeInclusive<Time>>> Timelapse<L>
+{
+	pub fn new(settings: TimelapseSettings<L>) -> Self
+	{
+		Self {
+			settings,
+			last_slot: None,
+		}
+	}
+
+	/// Returns `true` if a frame has to be captured in this tick. The frames aren't captured while the real time clock
+	/// isn't set, because they are grouped by day.
+	pub fn tick(&mut self, date: Date, time: Time) -> bool
+	{
+		if date.year() < FIRST_VALID_YEAR || !self.settings.enable_on.should_be_enabled(date, time)
+		{
+			return false;
+		}
+
+		let (hour, minute, second) = time.as_hms();
+		let seconds_since_midnight = hour as u64 * 60 * 60 + minute as u64 * 60 + second as u64;
+		let slot = (date, seconds_since_midnight / self.settings.interval.as_secs().max(1));
+		if self.last_slot == Some(slot)
+		{
+			return false;
+		}
+		self.last_slot = Some(slot);
+
+		true
+	}
+
+	pub fn daily_video_frame_duration(&self) -> Option<Duration>
+	{
+		self.settings.daily_video_frame_duration
+	}
+}
+
+#[cfg(test)]
+mod tests
+{
+	use a13c_embedded::peripherals::time::real_time::time::Month;
+
+	use super::*;
+
+	fn timelapse(
+		interval: Duration, enable_on: EnableOnConditions<Vec<RangeInclusive<Time>>>,
+	) -> Timelapse<Vec<RangeInclusive<Time>>>
+	{
+		Timelapse::new(TimelapseSettings {
+			interval,
+			enable_on,
+			daily_video_frame_duration: None,
+		})
+	}
+
+	/// Ticks the timelapse every 30 seconds from `start` for `minutes`, and returns the times of the frames.
+	fn frame_times(
+		timelapse: &mut Timelapse<Vec<RangeInclusive<Time>>>, date: Date, start: Time, minutes: u32,
+	) -> Vec<Time>
+	{
+		(0..minutes * 2)
+			.map(|index| start + Duration::from_secs(index as u64 * 30))
+			.filter(|time| timelapse.tick(date, *time))
+			.collect()
+	}
+
+	fn date() -> Date
+	{
+		Date::from_calendar_date(2024, Month::May, 1).unwrap()
+	}
+
+	fn time(hour: u8, minute: u8) -> Time
+	{
+		Time::from_hms(hour, minute, 0).unwrap()
+	}
+
+	#[test]
+	fn frames_are_aligned_to_the_interval()
+	{
+		let mut timelapse = timelapse(Duration::from_secs(5 * 60), EnableOnConditions::Always);
+
+		let frame_times = frame_times(&mut timelapse, date(), Time::from_hms(12, 3, 30).unwrap(), 15);
+		assert_eq!(
+			frame_times,
+			[
+				Time::from_hms(12, 3, 30).unwrap(),
+				time(12, 5),
+				time(12, 10),
+				time(12, 15)
+			]
+		);
+	}
+
+	#[test]
+	fn frames_are_captured_only_in_the_time_windows()
+	{
+		let mut timelapse = timelapse(
+			Duration::from_secs(5 * 60),
+			EnableOnConditions::TimeWindows {
+				ranges: vec![time(12, 10)..=time(12, 20)],
+			},
+		);
+
+		let frame_times = frame_times(&mut timelapse, date(), time(12, 0), 30);
+		assert_eq!(frame_times, [time(12, 10), time(12, 15), time(12, 20)]);
+	}
+
+	#[test]
+	fn a_new_day_starts_a_new_interval()
+	{
+		let mut timelapse = timelapse(Duration::from_secs(60 * 60), EnableOnConditions::Always);
+
+		assert!(timelapse.tick(date(), time(10, 0)));
+		assert!(!timelapse.tick(date(), time(10, 30)));
+		assert!(timelapse.tick(date().next_day().unwrap(), time(10, 30)));
+	}
+
+	#[test]
+	fn nothing_is_captured_without_clock()
+	{
+		let mut timelapse = timelapse(Duration::from_secs(60), EnableOnConditions::Always);
+
+		assert!(!timelapse.tick(Date::from_calendar_date(1970, Month::January, 1).unwrap(), time(10, 0)));
+	}
+}
@@ -0,0 +1,170 @@
+use core::{
+	fmt::{Display, Formatter},
+	str::FromStr,
+};
+
+use a13c_embedded::peripherals::time::real_time::time::{Date, Month, Time};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Whether the [`ImageTrigger`](super::ImageTrigger) reacts to motion. It's changed with the `/arm` and `/disarm`
+/// requests, and it's saved in the [`Settings`](crate::features::settings::Settings) so that it survives a reboot.
+///
+/// While it's armed the trigger is still enabled only when its [`EnableOnConditions`](super::EnableOnConditions) allow
+/// it.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum Arming
+{
+	#[default]
+	Armed,
+	/// Armed until a date and time of the real time clock, then [`Arming::Disarmed`].
+	ArmedUntil
+	{
+		until: LocalDateTime,
+	},
+	Disarmed,
+}
+
+/// The states of the [`ImageTrigger`](super::ImageTrigger).
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TriggerState
+{
+	Disarmed,
+	/// Waiting for motion.
+	Armed,
+	/// Motion has been detected less than the trigger duration ago, so the images are stored.
+	Triggered,
+	/// The trigger duration is over, and motion is ignored until the cooldown is over too.
+	Cooldown,
+}
+
+/// Part of the response to the `/status` request, and published with MQTT.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TriggerStatus
+{
+	pub state: TriggerState,
+	pub arming: Arming,
+	/// `false` if the [`EnableOnConditions`](super::EnableOnConditions) don't enable the trigger right now, even if
+	/// it's armed.
+	pub scheduled: bool,
+}
+
+/// A date and time of the real time clock (so in its time zone), written like `2024-05-01T13:01:02`. The seconds are
+/// optional when it's parsed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct LocalDateTime
+{
+	pub date: Date,
+	pub time: Time,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct InvalidDateTime;
+
+impl Display for LocalDateTime
+{
+	fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result
+	{
+		write!(
+			f,
+			"{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+			self.date.year(),
+			self.date.month() as u8,
+			self.date.day(),
+			self.time.hour(),
+			self.time.minute(),
+			self.time.second()
+		)
+	}
+}
+
+impl FromStr for LocalDateTime
+{
+	type Err = InvalidDateTime;
+
+	fn from_str(string: &str) -> Result<Self, Self::Err>
+	{
+		fn parse<T: FromStr>(part: Option<&str>) -> Result<T, InvalidDateTime>
+		{
+			part.ok_or(InvalidDateTime)?.parse().map_err(|_| InvalidDateTime)
+		}
+
+		let (date, time) = string.split_once('T').ok_or(InvalidDateTime)?;
+		let mut date = date.split('-');
+		let mut time = time.split(':');
+		let (year, month, day) = (parse(date.next())?, parse::<u8>(date.next())?, parse(date.next())?);
+		let (hour, minute) = (parse(time.next())?, parse(time.next())?);
+		let second = time.next().map(|second| parse(Some(second))).transpose()?.unwrap_or(0);
+		if date.next().is_some() || time.next().is_some()
+		{
+			return Err(InvalidDateTime);
+		}
+
+		Ok(Self {
+			date: Date::from_calendar_date(year, Month::try_from(month).map_err(|_| InvalidDateTime)?, day)
+				.map_err(|_| InvalidDateTime)?,
+			time: Time::from_hms(hour, minute, second).map_err(|_| InvalidDateTime)?,
+		})
+	}
+}
+
+impl Serialize for LocalDateTime
+{
+	fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error>
+	{
+		serializer.collect_str(self)
+	}
+}
+
+impl<'de> Deserialize<'de> for LocalDateTime
+{
+	fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error>
+	{
+		String::deserialize(deserializer)?
+			.parse()
+			.map_err(serde::de::Error::custom)
+	}
+}
+
+impl Display for InvalidDateTime
+{
+	fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result
+	{
+		write!(f, "The date and time must be written like 2024-05-01T13:01:02")
+	}
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	#[test]
+	fn date_time_is_parsed_with_and_without_seconds()
+	{
+		let date_time = LocalDateTime {
+			date: Date::from_calendar_date(2024, Month::May, 1).unwrap(),
+			time: Time::from_hms(18, 30, 0).unwrap(),
+		};
+
+		assert_eq!("2024-05-01T18:30".parse(), Ok(date_time));
+		assert_eq!("2024-05-01T18:30:00".parse(), Ok(date_time));
+		assert_eq!(date_time.to_string(), "2024-05-01T18:30:00");
+		assert_eq!("2024-05-01".parse::<LocalDateTime>(), Err(InvalidDateTime));
+		assert_eq!("2024-13-01T18:30".parse::<LocalDateTime>(), Err(InvalidDateTime));
+		assert_eq!("2024-05-01T18:30:00:00".parse::<LocalDateTime>(), Err(InvalidDateTime));
+	}
+
+	#[test]
+	fn arming_is_saved_as_json()
+	{
+		let arming = Arming::ArmedUntil {
+			until: "2024-05-01T18:30".parse().unwrap(),
+		};
+
+		let json = serde_json::to_string(&arming).unwrap();
+		assert_eq!(json, r#"{"mode":"armed_until","until":"2024-05-01T18:30:00"}"#);
+		assert_eq!(serde_json::from_str::<Arming>(&json).unwrap(), arming);
+	}
+}
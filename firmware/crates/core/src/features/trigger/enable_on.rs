@@ -1,21 +1,29 @@
 use core::ops::RangeInclusive;
 
-use a13c_embedded::{peripherals::time::real_time::time::Time, utils::collections::list::List};
+use a13c_embedded::{
+	peripherals::time::real_time::time::{Date, Time},
+	utils::collections::list::List,
+};
+
+use super::Schedule;
 
 #[derive(Clone)]
 pub enum EnableOnConditions<L: List<RangeInclusive<Time>>>
 {
 	Always,
 	Never,
+	/// A range whose end is before its start wraps past midnight, so `22:00..=06:00` is enabled during the night.
 	TimeWindows
 	{
 		ranges: L,
 	},
+	/// Check [`Schedule`].
+	Schedule(Schedule),
 }
 
 impl<L: List<RangeInclusive<Time>>> EnableOnConditions<L>
 {
-	pub fn should_be_enabled(&self, current_time: Time) -> bool
+	pub fn should_be_enabled(&self, current_date: Date, current_time: Time) -> bool
 	{
 		match self
 		{
@@ -27,15 +35,195 @@ impl<L: List<RangeInclusive<Time>>> EnableOnConditions<L>
 				{
 					if let Some(time_window) = ranges.get(i)
 					{
-						if time_window.contains(&current_time)
+						let is_inside = match time_window.start() <= time_window.end()
+						{
+							true => time_window.contains(&current_time),
+							false => current_time >= *time_window.start() || current_time <= *time_window.end(),
+						};
+						if is_inside
 						{
 							return true;
 						}
 					}
 				}
 			},
+			EnableOnConditions::Schedule(schedule) => return schedule.is_enabled(current_date, current_time),
 		}
 
 		false
 	}
+
+	/// Copies the time windows in a `Vec`, so that conditions coming from different lists can be used interchangeably.
+	pub fn to_vec(&self) -> EnableOnConditions<Vec<RangeInclusive<Time>>>
+	{
+		match self
+		{
+			EnableOnConditions::Always => EnableOnConditions::Always,
+			EnableOnConditions::Never => EnableOnConditions::Never,
+			EnableOnConditions::TimeWindows { ranges } => EnableOnConditions::TimeWindows {
+				ranges: (0..ranges.length()).filter_map(|i| ranges.get(i).cloned()).collect(),
+			},
+			EnableOnConditions::Schedule(schedule) => EnableOnConditions::Schedule(schedule.clone()),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests
+{
+	use a13c_embedded::peripherals::time::real_time::time::{Month, Weekday};
+
+	use super::*;
+	use crate::features::trigger::{
+		DateException, ScheduleRule, ScheduleTime, ScheduleWindow, SolarLocation, Weekdays,
+	};
+
+	fn time(hour: u8, minute: u8) -> Time
+	{
+		Time::from_hms(hour, minute, 0).unwrap()
+	}
+
+	fn may(day: u8) -> Date
+	{
+		Date::from_calendar_date(2024, Month::May, day).unwrap()
+	}
+
+	fn window(start: Time, end: Time) -> ScheduleWindow
+	{
+		ScheduleWindow::new(ScheduleTime::At(start), ScheduleTime::At(end))
+	}
+
+	fn schedule(schedule: Schedule) -> EnableOnConditions<Vec<RangeInclusive<Time>>>
+	{
+		EnableOnConditions::Schedule(schedule)
+	}
+
+	#[test]
+	fn time_windows_can_wrap_past_midnight()
+	{
+		let conditions = EnableOnConditions::TimeWindows {
+			ranges: vec![time(22, 0)..=time(6, 0)],
+		};
+
+		assert!(conditions.should_be_enabled(may(1), time(23, 30)));
+		assert!(conditions.should_be_enabled(may(1), time(0, 0)));
+		assert!(conditions.should_be_enabled(may(1), time(6, 0)));
+		assert!(!conditions.should_be_enabled(may(1), time(6, 1)));
+		assert!(!conditions.should_be_enabled(may(1), time(12, 0)));
+	}
+
+	#[test]
+	fn rules_apply_only_to_their_weekdays()
+	{
+		// 2024-05-03 is a Friday
+		let conditions = schedule(Schedule {
+			rules: vec![ScheduleRule {
+				weekdays: Weekdays::WORKDAYS,
+				window: window(time(9, 0), time(17, 0)),
+			}],
+			..Default::default()
+		});
+
+		assert!(conditions.should_be_enabled(may(3), time(12, 0)));
+		assert!(!conditions.should_be_enabled(may(3), time(18, 0)));
+		assert!(!conditions.should_be_enabled(may(4), time(12, 0)));
+	}
+
+	#[test]
+	fn wrapping_rules_continue_in_the_next_day()
+	{
+		let conditions = schedule(Schedule {
+			rules: vec![ScheduleRule {
+				weekdays: Weekdays::from_days(&[Weekday::Friday]),
+				window: window(time(22, 0), time(6, 0)),
+			}],
+			..Default::default()
+		});
+
+		assert!(!conditions.should_be_enabled(may(3), time(5, 0)));
+		assert!(conditions.should_be_enabled(may(3), time(23, 0)));
+		assert!(conditions.should_be_enabled(may(4), time(5, 0)));
+		assert!(!conditions.should_be_enabled(may(4), time(23, 0)));
+	}
+
+	#[test]
+	fn exceptions_replace_the_rules()
+	{
+		let conditions = schedule(Schedule {
+			rules: vec![ScheduleRule {
+				weekdays: Weekdays::ALL,
+				window: window(time(9, 0), time(17, 0)),
+			}],
+			exceptions: vec![
+				DateException {
+					dates: may(1)..=may(1),
+					windows: Vec::new(),
+				},
+				DateException {
+					dates: may(10)..=may(12),
+					windows: vec![window(time(0, 0), time(23, 59))],
+				},
+			],
+			..Default::default()
+		});
+
+		assert!(!conditions.should_be_enabled(may(1), time(12, 0)));
+		assert!(conditions.should_be_enabled(may(2), time(12, 0)));
+		assert!(conditions.should_be_enabled(may(11), time(20, 0)));
+		assert!(!conditions.should_be_enabled(may(13), time(20, 0)));
+	}
+
+	#[test]
+	fn windows_can_be_relative_to_the_sun()
+	{
+		let night = ScheduleRule {
+			weekdays: Weekdays::ALL,
+			window: ScheduleWindow::night(),
+		};
+		let dusk = ScheduleRule {
+			weekdays: Weekdays::ALL,
+			window: ScheduleWindow::new(
+				ScheduleTime::Sunset { offset_minutes: -30 },
+				ScheduleTime::Sunset { offset_minutes: 30 },
+			),
+		};
+		// In Rome the sun sets around 20:20 in May
+		let location = SolarLocation {
+			latitude: 41.9,
+			longitude: 12.5,
+			utc_offset_seconds: 2 * 60 * 60,
+		};
+		let conditions = schedule(Schedule {
+			rules: vec![night.clone()],
+			location: Some(location),
+			..Default::default()
+		});
+
+		assert!(!conditions.should_be_enabled(may(3), time(12, 0)));
+		assert!(!conditions.should_be_enabled(may(3), time(20, 0)));
+		assert!(conditions.should_be_enabled(may(3), time(21, 0)));
+		assert!(conditions.should_be_enabled(may(4), time(4, 0)));
+
+		let conditions = schedule(Schedule {
+			rules: vec![dusk],
+			location: Some(location),
+			..Default::default()
+		});
+		assert!(conditions.should_be_enabled(may(3), time(20, 0)));
+		assert!(!conditions.should_be_enabled(may(3), time(21, 0)));
+	}
+
+	#[test]
+	fn windows_relative_to_the_sun_need_the_location()
+	{
+		let conditions = schedule(Schedule {
+			rules: vec![ScheduleRule {
+				weekdays: Weekdays::ALL,
+				window: ScheduleWindow::daylight(),
+			}],
+			..Default::default()
+		});
+
+		assert!(!conditions.should_be_enabled(may(3), time(12, 0)));
+	}
 }
@@ -1,39 +1,127 @@
+mod arming;
 mod enable_on;
+mod motion;
+mod pir;
+mod schedule;
 
-use core::ops::RangeInclusive;
+use core::{ops::RangeInclusive, time::Duration};
 
 use a13c_embedded::{
-	drivers::input_pin::DelayedInputPin,
-	peripherals::time::real_time::time::{Date, Duration, Time},
+	peripherals::time::real_time::time::{Date, Time},
 	utils::collections::list::List,
 };
+pub use arming::*;
 use embedded_hal::digital::InputPin;
 pub use enable_on::*;
+pub use motion::*;
+pub use pir::*;
+pub use schedule::*;
+use serde::Serialize;
+
+use crate::configuration::peripherals::camera::Image;
+
+/// What detects the motion that triggers the storage of the images.
+#[derive(Clone, Debug, PartialEq)]
+pub enum TriggerSources
+{
+	Pir,
+	FrameDifference(MotionDetectorSettings),
+	/// Motion must be detected by both sources (less false alarms).
+	PirAndFrameDifference(MotionDetectorSettings),
+	/// Motion can be detected by either source (less missed motion).
+	PirOrFrameDifference(MotionDetectorSettings),
+}
+
+/// What detected the motion that caused a trigger.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TriggerSource
+{
+	Pir,
+	FrameDifference,
+	PirAndFrameDifference,
+}
+
+/// A trigger of the [`ImageTrigger`] (check [`ImageTrigger::motion_event`]), notified to MQTT and to the webhooks.
+/// The `id` is made of the digits of the `timestamp`.
+///
+/// ```json
+/// {"id":"20240501130102","timestamp":"2024-05-01T13:01:02","trigger_source":"pir"}
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct MotionEvent
+{
+	pub id: String,
+	pub timestamp: LocalDateTime,
+	pub trigger_source: TriggerSource,
+}
 
 /// Decides if the camera should capture images and also if it should store the image in the storage device.
+///
+/// Images are captured while the trigger is armed (check [`Arming`]) and enabled (check [`EnableOnConditions`]), and they
+/// are stored for `trigger_duration` after motion is detected by the [`TriggerSources`]. After that, motion is ignored
+/// for the `cooldown` (check [`TriggerState`]).
 pub struct ImageTrigger<P: InputPin, L: List<RangeInclusive<Time>>>
 {
-	pir_sensor: DelayedInputPin<P, Duration>,
+	pir_sensor: PirSensor<P>,
+	/// `None` if the frames aren't one of the sources.
+	motion_detector: Option<MotionDetector>,
+	sources: TriggerSources,
+	pir_motion: bool,
+	/// Reset at each tick, until [`Self::detect_motion_in`] is called.
+	frame_motion: bool,
+	/// What detected the motion in the current tick, `None` if there was no trigger.
+	motion_source: Option<TriggerSource>,
+	/// If the state became [`TriggerState::Triggered`] in the current tick.
+	has_just_triggered: bool,
 
 	date_and_time_of_last_tick: Option<(Date, Time)>,
-	trigger_date_and_time: Option<(Date, Time)>,
+	/// `None` if motion has never been detected.
+	time_since_trigger: Option<Duration>,
 	trigger_duration: Duration,
+	cooldown: Duration,
+	state: TriggerState,
 
+	arming: Arming,
+	/// If the `enable_on` conditions are satisfied.
+	is_scheduled: bool,
+	/// If the trigger is both armed and scheduled.
 	is_enabled: bool,
 	enable_on: EnableOnConditions<L>,
 }
 
 impl<P: InputPin, L: List<RangeInclusive<Time>>> ImageTrigger<P, L>
 {
-	pub fn new(pir_sensor: P, enable_on: EnableOnConditions<L>, trigger_duration: core::time::Duration) -> Self
+	pub fn new(
+		pir_sensor: P, pir_settings: PirSettings, sources: TriggerSources, enable_on: EnableOnConditions<L>,
+		trigger_duration: Duration, cooldown: Duration,
+	) -> Self
 	{
+		let motion_detector = match &sources
+		{
+			TriggerSources::Pir => None,
+			TriggerSources::FrameDifference(settings)
+			| TriggerSources::PirAndFrameDifference(settings)
+			| TriggerSources::PirOrFrameDifference(settings) => Some(MotionDetector::new(settings.clone())),
+		};
+
 		Self {
-			pir_sensor: DelayedInputPin::new(pir_sensor, false, Duration::seconds(60)),
+			pir_sensor: PirSensor::new(pir_sensor, pir_settings),
+			motion_detector,
+			sources,
+			pir_motion: false,
+			frame_motion: false,
+			motion_source: None,
+			has_just_triggered: false,
 
 			date_and_time_of_last_tick: None,
-			trigger_duration: trigger_duration.try_into().unwrap(),
-			trigger_date_and_time: None,
+			time_since_trigger: None,
+			trigger_duration,
+			cooldown,
+			state: TriggerState::Armed,
 
+			arming: Arming::Armed,
+			is_scheduled: false,
 			is_enabled: false,
 			enable_on,
 		}
@@ -43,27 +131,152 @@ impl<P: InputPin, L: List<RangeInclusive<Time>>> ImageTrigger<P, L>
 	{
 		if let Some((current_date, current_time)) = current_date_and_time
 		{
-			self.is_enabled = self.enable_on.should_be_enabled(current_time);
-
-			if self.are_date_and_time_of_last_tick_valid(current_date, current_time)
-			{
-				self.pir_sensor
-					.tick(self.duration_since_last_tick(current_date, current_time));
-			}
-
-			if self.is_enabled
+			if let Arming::ArmedUntil { until } = self.arming
 			{
-				if self.pir_sensor.is_high()?
+				if (current_date, current_time) >= (until.date, until.time)
 				{
-					self.trigger_date_and_time = Some((current_date, current_time));
+					log::info!("The trigger was armed until {}, disarming it", until);
+					self.arming = Arming::Disarmed;
 				}
 			}
+			self.is_scheduled = self.enable_on.should_be_enabled(current_date, current_time);
+			self.is_enabled = self.is_scheduled && self.arming != Arming::Disarmed;
+
+			let elapsed = self.duration_since_last_tick(current_date, current_time);
+			self.pir_motion = self.pir_sensor.tick(elapsed)?;
+			self.frame_motion = false;
+			self.motion_source = None;
+			self.has_just_triggered = false;
+
+			self.time_since_trigger = self
+				.time_since_trigger
+				.map(|time_since_trigger| time_since_trigger + elapsed);
+			self.update_trigger();
 		}
 		self.date_and_time_of_last_tick = current_date_and_time;
 
 		Ok(())
 	}
 
+	/// Compares the `image` with the previous one, if the frames are one of the [`TriggerSources`]. It must be called
+	/// after [`Self::tick`] with the image captured in the same tick.
+	pub fn detect_motion_in(&mut self, image: &impl Image)
+	{
+		let Some(motion_detector) = self.motion_detector.as_mut()
+		else
+		{
+			return;
+		};
+
+		match image.to_grayscale()
+		{
+			Some(frame) => self.frame_motion = motion_detector.detect(&frame),
+			None => log::warn!("Motion can't be detected because the image can't be converted to grayscale"),
+		}
+		self.update_trigger();
+	}
+
+	fn update_trigger(&mut self)
+	{
+		// Only the configured sources count, whatever the others detect
+		let (pir_motion, frame_motion) = match self.sources
+		{
+			TriggerSources::Pir => (self.pir_motion, false),
+			TriggerSources::FrameDifference(_) => (false, self.frame_motion),
+			TriggerSources::PirAndFrameDifference(_) | TriggerSources::PirOrFrameDifference(_) =>
+			{
+				(self.pir_motion, self.frame_motion)
+			},
+		};
+		let motion = match self.sources
+		{
+			TriggerSources::PirAndFrameDifference(_) => pir_motion && frame_motion,
+			_ => pir_motion || frame_motion,
+		};
+		self.update_state();
+		if self.is_enabled && motion && self.state != TriggerState::Cooldown
+		{
+			self.time_since_trigger = Some(Duration::ZERO);
+			self.motion_source = match (pir_motion, frame_motion)
+			{
+				(true, true) => Some(TriggerSource::PirAndFrameDifference),
+				(false, true) => Some(TriggerSource::FrameDifference),
+				_ => Some(TriggerSource::Pir),
+			};
+			self.update_state();
+		}
+	}
+
+	fn update_state(&mut self)
+	{
+		if self.arming == Arming::Disarmed
+		{
+			self.time_since_trigger = None;
+		}
+		let state = match self.time_since_trigger
+		{
+			_ if self.arming == Arming::Disarmed => TriggerState::Disarmed,
+			Some(time_since_trigger) if time_since_trigger <= self.trigger_duration => TriggerState::Triggered,
+			Some(time_since_trigger) if time_since_trigger <= self.trigger_duration + self.cooldown =>
+			{
+				TriggerState::Cooldown
+			},
+			_ => TriggerState::Armed,
+		};
+
+		if state != self.state
+		{
+			log::info!("The trigger went from {:?} to {:?}", self.state, state);
+			self.has_just_triggered |= state == TriggerState::Triggered;
+			self.state = state;
+		}
+	}
+
+	/// Changes the arming of the trigger, which is applied immediately (an [`Arming::ArmedUntil`] in the past is
+	/// disarmed at the next tick).
+	pub fn set_arming(&mut self, arming: Arming)
+	{
+		if arming != self.arming
+		{
+			log::info!("The trigger's arming changed from {:?} to {:?}", self.arming, arming);
+			self.arming = arming;
+		}
+		self.is_enabled = self.is_scheduled && self.arming != Arming::Disarmed;
+		self.update_state();
+	}
+
+	pub fn arming(&self) -> Arming
+	{
+		self.arming
+	}
+
+	pub fn status(&self) -> TriggerStatus
+	{
+		TriggerStatus {
+			state: self.state,
+			arming: self.arming,
+			scheduled: self.is_scheduled,
+		}
+	}
+
+	/// Returns what detected the motion in the current tick, or `None` if there was no trigger in it.
+	pub fn motion_source(&self) -> Option<TriggerSource>
+	{
+		self.motion_source
+	}
+
+	/// Returns the event if the trigger started in the current tick (motion that extends a trigger isn't a new event).
+	pub fn motion_event(&self, date_and_time: LocalDateTime) -> Option<MotionEvent>
+	{
+		let trigger_source = self.motion_source.filter(|_| self.has_just_triggered)?;
+
+		Some(MotionEvent {
+			id: date_and_time.to_string().chars().filter(char::is_ascii_digit).collect(),
+			timestamp: date_and_time,
+			trigger_source,
+		})
+	}
+
 	/// Check the struct's documentation.
 	pub fn needs_to_capture_image(&self) -> bool
 	{
@@ -73,32 +286,427 @@ impl<P: InputPin, L: List<RangeInclusive<Time>>> ImageTrigger<P, L>
 	/// Check the struct's documentation.
 	pub fn needs_to_store_image(&self) -> bool
 	{
-		return false;
-		if let Some((trigger_date, trigger_time)) = self.trigger_date_and_time
-		{
-			let duration_since_trigger = -self.duration_since_last_tick(trigger_date, trigger_time);
-			duration_since_trigger <= self.trigger_duration
-		}
+		self.is_enabled && self.state == TriggerState::Triggered
+	}
+
+	/// Returns [`Duration::ZERO`] if this is the first tick or if the clock jumped (backward, or forward by more than a
+	/// year, like when it's synchronized for the first time).
+	fn duration_since_last_tick(&self, date: Date, time: Time) -> Duration
+	{
+		const MAX_DURATION_BETWEEN_TICKS: Duration = Duration::from_secs(365 * 24 * 60 * 60);
+
+		let Some((last_date, last_time)) = self.date_and_time_of_last_tick
 		else
 		{
-			false
-		}
+			return Duration::ZERO;
+		};
+
+		Duration::try_from((date - last_date) + (time - last_time))
+			.ok()
+			.filter(|duration| *duration < MAX_DURATION_BETWEEN_TICKS)
+			.unwrap_or_default()
 	}
+}
+
+#[cfg(test)]
+mod tests
+{
+	use a13c_embedded::{peripherals::time::real_time::time::Month, utils::math::micromath::micromath::vector::U16x2};
+	use embedded_hal::digital::ErrorType;
 
-	fn are_date_and_time_of_last_tick_valid(&self, current_date: Date, current_time: Time) -> bool
+	use super::*;
+	use crate::configuration::peripherals::camera::GrayscaleImage;
+
+	/// Returns the levels of the script, one for each read.
+	struct ScriptedPin(std::collections::VecDeque<bool>);
+
+	impl ErrorType for ScriptedPin
 	{
-		self.duration_since_last_tick(current_date, current_time) < Duration::days(365)
+		type Error = core::convert::Infallible;
 	}
 
-	fn duration_since_last_tick(&self, date: Date, time: Time) -> Duration
+	impl InputPin for ScriptedPin
 	{
-		if let Some(date_and_time_of_last_tick) = self.date_and_time_of_last_tick
+		fn is_high(&mut self) -> Result<bool, Self::Error>
 		{
-			(date - date_and_time_of_last_tick.0) + (time - date_and_time_of_last_tick.1)
+			Ok(self.0.pop_front().unwrap_or(false))
 		}
-		else
+
+		fn is_low(&mut self) -> Result<bool, Self::Error>
+		{
+			self.is_high().map(|is_high| !is_high)
+		}
+	}
+
+	/// An image whose pixels all have the same brightness.
+	struct UniformImage(u8);
+
+	impl Image for UniformImage
+	{
+		fn get_pixels(&self) -> &[u8]
+		{
+			&[]
+		}
+
+		fn get_size(&self) -> U16x2
+		{
+			U16x2 { x: 8, y: 8 }
+		}
+
+		fn get_timestamp(&self) -> Duration
 		{
 			Duration::ZERO
 		}
+
+		fn to_grayscale(&self) -> Option<GrayscaleImage>
+		{
+			Some(GrayscaleImage {
+				size: self.get_size(),
+				pixels: vec![self.0; 64],
+			})
+		}
+	}
+
+	const NO_FILTER: PirSettings = PirSettings {
+		debounce: Duration::ZERO,
+		hold_time: Duration::ZERO,
+	};
+
+	fn trigger(
+		levels: &[bool], pir_settings: PirSettings, enable_on: EnableOnConditions<Vec<RangeInclusive<Time>>>,
+		trigger_duration: Duration,
+	) -> ImageTrigger<ScriptedPin, Vec<RangeInclusive<Time>>>
+	{
+		ImageTrigger::new(
+			ScriptedPin(levels.iter().copied().collect()),
+			pir_settings,
+			TriggerSources::Pir,
+			enable_on,
+			trigger_duration,
+			Duration::ZERO,
+		)
+	}
+
+	/// Ticks the trigger once per second starting from 12:00:00, and returns `needs_to_store_image` after each tick.
+	fn store_decisions<L: List<RangeInclusive<Time>>>(
+		trigger: &mut ImageTrigger<ScriptedPin, L>, ticks: u8,
+	) -> Vec<bool>
+	{
+		let date = Date::from_calendar_date(2024, Month::May, 1).unwrap();
+		(0..ticks)
+			.map(|second| {
+				trigger
+					.tick(Some((date, Time::from_hms(12, 0, second).unwrap())))
+					.unwrap();
+				trigger.needs_to_store_image()
+			})
+			.collect()
+	}
+
+	#[test]
+	fn nothing_is_stored_without_motion()
+	{
+		let mut trigger = trigger(
+			&[false; 5],
+			NO_FILTER,
+			EnableOnConditions::Always,
+			Duration::from_secs(10),
+		);
+
+		assert_eq!(store_decisions(&mut trigger, 5), [false; 5]);
+		assert!(trigger.needs_to_capture_image());
+	}
+
+	#[test]
+	fn images_are_stored_only_during_trigger_duration_after_motion()
+	{
+		let mut trigger = trigger(
+			&[false, true, false, false, false, false],
+			NO_FILTER,
+			EnableOnConditions::Always,
+			Duration::from_secs(2),
+		);
+
+		assert_eq!(
+			store_decisions(&mut trigger, 6),
+			[false, true, true, true, false, false]
+		);
+	}
+
+	#[test]
+	fn motion_restarts_trigger_duration()
+	{
+		let mut trigger = trigger(
+			&[true, false, true, false, false, false],
+			NO_FILTER,
+			EnableOnConditions::Always,
+			Duration::from_secs(1),
+		);
+
+		assert_eq!(store_decisions(&mut trigger, 6), [true, true, true, true, false, false]);
+	}
+
+	#[test]
+	fn debounce_ignores_short_pulses()
+	{
+		let pir_settings = PirSettings {
+			debounce: Duration::from_secs(2),
+			hold_time: Duration::ZERO,
+		};
+		let mut trigger = trigger(
+			&[true, true, false, true, true, true, false],
+			pir_settings,
+			EnableOnConditions::Always,
+			Duration::ZERO,
+		);
+
+		assert_eq!(
+			store_decisions(&mut trigger, 7),
+			[false, false, false, false, false, true, false]
+		);
+	}
+
+	#[test]
+	fn hold_time_keeps_motion_after_the_pin_goes_low()
+	{
+		let pir_settings = PirSettings {
+			debounce: Duration::ZERO,
+			hold_time: Duration::from_secs(2),
+		};
+		let mut trigger = trigger(
+			&[true, false, false, false, false],
+			pir_settings,
+			EnableOnConditions::Always,
+			Duration::ZERO,
+		);
+
+		assert_eq!(store_decisions(&mut trigger, 5), [true, true, false, false, false]);
+	}
+
+	#[test]
+	fn motion_outside_the_time_windows_is_ignored()
+	{
+		let enable_on = EnableOnConditions::TimeWindows {
+			ranges: vec![Time::from_hms(12, 0, 2).unwrap()..=Time::from_hms(12, 0, 3).unwrap()],
+		};
+		let mut trigger = trigger(
+			&[true, false, false, true, true, false],
+			NO_FILTER,
+			enable_on,
+			Duration::ZERO,
+		);
+
+		assert_eq!(
+			store_decisions(&mut trigger, 6),
+			[false, false, false, true, false, false]
+		);
+	}
+
+	#[test]
+	fn clock_jumps_dont_count_as_elapsed_time()
+	{
+		let date = Date::from_calendar_date(2024, Month::May, 1).unwrap();
+		let mut trigger = trigger(
+			&[true, false],
+			NO_FILTER,
+			EnableOnConditions::Always,
+			Duration::from_secs(1),
+		);
+
+		trigger.tick(Some((date, Time::from_hms(12, 0, 0).unwrap()))).unwrap();
+		trigger.tick(Some((date, Time::from_hms(11, 0, 0).unwrap()))).unwrap();
+
+		assert!(trigger.needs_to_store_image());
+	}
+
+	#[test]
+	fn disarmed_trigger_ignores_motion()
+	{
+		let mut trigger = trigger(
+			&[true, true, true],
+			NO_FILTER,
+			EnableOnConditions::Always,
+			Duration::from_secs(10),
+		);
+		trigger.set_arming(Arming::Disarmed);
+
+		assert_eq!(store_decisions(&mut trigger, 3), [false; 3]);
+		assert!(!trigger.needs_to_capture_image());
+		assert_eq!(trigger.status().state, TriggerState::Disarmed);
+	}
+
+	#[test]
+	fn armed_until_disarms_at_that_time()
+	{
+		let mut trigger = trigger(&[true; 5], NO_FILTER, EnableOnConditions::Always, Duration::ZERO);
+		trigger.set_arming(Arming::ArmedUntil {
+			until: "2024-05-01T12:00:02".parse().unwrap(),
+		});
+
+		assert_eq!(store_decisions(&mut trigger, 5), [true, true, false, false, false]);
+		assert_eq!(trigger.arming(), Arming::Disarmed);
+	}
+
+	#[test]
+	fn motion_is_ignored_during_the_cooldown()
+	{
+		let mut trigger = ImageTrigger::new(
+			ScriptedPin([true, false, true, true, true].into_iter().collect()),
+			NO_FILTER,
+			TriggerSources::Pir,
+			EnableOnConditions::<Vec<RangeInclusive<Time>>>::Always,
+			Duration::from_secs(1),
+			Duration::from_secs(2),
+		);
+
+		let date = Date::from_calendar_date(2024, Month::May, 1).unwrap();
+		let states: Vec<_> = (0..5)
+			.map(|second| {
+				trigger
+					.tick(Some((date, Time::from_hms(12, 0, second).unwrap())))
+					.unwrap();
+				trigger.status().state
+			})
+			.collect();
+		assert_eq!(
+			states,
+			[
+				TriggerState::Triggered,
+				TriggerState::Triggered,
+				TriggerState::Cooldown,
+				TriggerState::Cooldown,
+				TriggerState::Triggered
+			]
+		);
+	}
+
+	#[test]
+	fn motion_event_is_returned_only_when_the_trigger_starts()
+	{
+		let mut trigger = trigger(
+			&[true, true, false, false, true],
+			NO_FILTER,
+			EnableOnConditions::Always,
+			Duration::from_secs(1),
+		);
+
+		let date = Date::from_calendar_date(2024, Month::May, 1).unwrap();
+		let events: Vec<_> = (0..5)
+			.map(|second| {
+				let time = Time::from_hms(12, 0, second).unwrap();
+				trigger.tick(Some((date, time))).unwrap();
+				trigger.motion_event(LocalDateTime { date, time })
+			})
+			.collect();
+		assert_eq!(
+			events[0],
+			Some(MotionEvent {
+				id: "20240501120000".to_string(),
+				timestamp: LocalDateTime {
+					date,
+					time: Time::from_hms(12, 0, 0).unwrap()
+				},
+				trigger_source: TriggerSource::Pir,
+			})
+		);
+		assert_eq!(events[1..4], [None, None, None]);
+		assert!(events[4].is_some());
+	}
+
+	/// Like [`store_decisions`], but after each tick an image with the brightness at the same index is captured.
+	fn store_decisions_with_images<L: List<RangeInclusive<Time>>>(
+		trigger: &mut ImageTrigger<ScriptedPin, L>, brightnesses: &[u8],
+	) -> Vec<bool>
+	{
+		let date = Date::from_calendar_date(2024, Month::May, 1).unwrap();
+		brightnesses
+			.iter()
+			.zip(0..)
+			.map(|(brightness, second)| {
+				trigger
+					.tick(Some((date, Time::from_hms(12, 0, second).unwrap())))
+					.unwrap();
+				trigger.detect_motion_in(&UniformImage(*brightness));
+				trigger.needs_to_store_image()
+			})
+			.collect()
+	}
+
+	fn trigger_with_sources(
+		levels: &[bool], sources: TriggerSources,
+	) -> ImageTrigger<ScriptedPin, Vec<RangeInclusive<Time>>>
+	{
+		ImageTrigger::new(
+			ScriptedPin(levels.iter().copied().collect()),
+			NO_FILTER,
+			sources,
+			EnableOnConditions::Always,
+			Duration::ZERO,
+			Duration::ZERO,
+		)
+	}
+
+	#[test]
+	fn frame_difference_detects_motion()
+	{
+		let mut trigger = trigger_with_sources(
+			&[false; 4],
+			TriggerSources::FrameDifference(MotionDetectorSettings::default()),
+		);
+
+		assert_eq!(
+			store_decisions_with_images(&mut trigger, &[50, 50, 200, 200]),
+			[false, false, true, false]
+		);
+	}
+
+	#[test]
+	fn frame_difference_ignores_the_pir()
+	{
+		let mut trigger = trigger_with_sources(
+			&[true; 3],
+			TriggerSources::FrameDifference(MotionDetectorSettings::default()),
+		);
+
+		assert_eq!(
+			store_decisions_with_images(&mut trigger, &[50, 50, 200]),
+			[false, false, true]
+		);
+		let date = Date::from_calendar_date(2024, Month::May, 1).unwrap();
+		let time = Time::from_hms(12, 0, 2).unwrap();
+		assert_eq!(
+			trigger
+				.motion_event(LocalDateTime { date, time })
+				.map(|event| event.trigger_source),
+			Some(TriggerSource::FrameDifference)
+		);
+	}
+
+	#[test]
+	fn and_needs_motion_from_both_sources()
+	{
+		let mut trigger = trigger_with_sources(
+			&[true, true, false, true],
+			TriggerSources::PirAndFrameDifference(MotionDetectorSettings::default()),
+		);
+
+		assert_eq!(
+			store_decisions_with_images(&mut trigger, &[50, 50, 200, 50]),
+			[false, false, false, true]
+		);
+	}
+
+	#[test]
+	fn or_needs_motion_from_either_source()
+	{
+		let mut trigger = trigger_with_sources(
+			&[true, false, false, false],
+			TriggerSources::PirOrFrameDifference(MotionDetectorSettings::default()),
+		);
+
+		assert_eq!(
+			store_decisions_with_images(&mut trigger, &[50, 50, 200, 200]),
+			[true, false, true, false]
+		);
 	}
 }
@@ -0,0 +1,266 @@
+use a13c_embedded::utils::math::micromath::micromath::vector::U16x2;
+
+use crate::configuration::peripherals::camera::GrayscaleImage;
+
+/// How [`MotionDetector`] decides that two frames are different.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MotionDetectorSettings
+{
+	/// A pixel has changed if its brightness changed by more than this (from 0 to 255).
+	pub threshold: u8,
+	/// Motion is detected if at least this fraction (from 0 to 1) of the pixels in the regions of interest changed.
+	pub min_changed_fraction: f32,
+	/// Only the pixels in these regions are compared. If it's empty the whole image is compared.
+	pub regions_of_interest: Vec<RegionOfInterest>,
+}
+
+impl Default for MotionDetectorSettings
+{
+	fn default() -> Self
+	{
+		Self {
+			threshold: 25,
+			min_changed_fraction: 0.02,
+			regions_of_interest: Vec::new(),
+		}
+	}
+}
+
+/// A rectangle of the image, whose coordinates are fractions (from 0 to 1) of the size of the image, so that it doesn't
+/// depend on the resolution.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RegionOfInterest
+{
+	pub x: f32,
+	pub y: f32,
+	pub width: f32,
+	pub height: f32,
+}
+
+impl RegionOfInterest
+{
+	fn contains(&self, x: f32, y: f32) -> bool
+	{
+		x >= self.x && x < self.x + self.width && y >= self.y && y < self.y + self.height
+	}
+}
+
+/// Detects motion by comparing downscaled grayscale versions of consecutive frames.
+pub struct MotionDetector
+{
+	settings: MotionDetectorSettings,
+	previous_frame: Option<GrayscaleImage>,
+}
+
+impl MotionDetector
+{
+	/// The frames are downscaled until their width is at most this, to make the comparison fast and less sensitive to
+	/// noise.
+	const MAX_WIDTH: u16 = 80;
+
+	pub fn new(settings: MotionDetectorSettings) -> Self
+	{
+		Self {
+			settings,
+			previous_frame: None,
+		}
+	}
+
+	/// Compares the `frame` with the one of the previous call. The first frame (or a frame with a different size than
+	/// the previous one) never contains motion.
+	pub fn detect(&mut self, frame: &GrayscaleImage) -> bool
+	{
+		if frame.pixels.len() < frame.size.x as usize * frame.size.y as usize
+		{
+			log::warn!("The grayscale frame has less pixels than its size");
+			return false;
+		}
+
+		let frame = downscale(frame, Self::MAX_WIDTH);
+		let motion = self
+			.previous_frame
+			.as_ref()
+			.filter(|previous_frame| previous_frame.size == frame.size)
+			.is_some_and(|previous_frame| {
+				self.changed_fraction(previous_frame, &frame) >= self.settings.min_changed_fraction
+			});
+		self.previous_frame = Some(frame);
+
+		motion
+	}
+
+	fn changed_fraction(&self, previous_frame: &GrayscaleImage, frame: &GrayscaleImage) -> f32
+	{
+		let width = frame.size.x as usize;
+		let height = frame.size.y as usize;
+
+		let mut compared_pixels = 0;
+		let mut changed_pixels = 0;
+		for y in 0..height
+		{
+			for x in 0..width
+			{
+				// The center of the pixel, as a fraction of the size of the image
+				let center_x = (x as f32 + 0.5) / width as f32;
+				let center_y = (y as f32 + 0.5) / height as f32;
+				let is_compared = self.settings.regions_of_interest.is_empty()
+					|| self
+						.settings
+						.regions_of_interest
+						.iter()
+						.any(|region| region.contains(center_x, center_y));
+				if !is_compared
+				{
+					continue;
+				}
+
+				let index = y * width + x;
+				compared_pixels += 1;
+				if frame.pixels[index].abs_diff(previous_frame.pixels[index]) > self.settings.threshold
+				{
+					changed_pixels += 1;
+				}
+			}
+		}
+
+		if compared_pixels == 0
+		{
+			return 0.;
+		}
+		changed_pixels as f32 / compared_pixels as f32
+	}
+}
+
+/// Averages blocks of pixels so that the width of the returned image is at most `max_width`.
+fn downscale(image: &GrayscaleImage, max_width: u16) -> GrayscaleImage
+{
+	let factor = image.size.x.div_ceil(max_width).max(1) as usize;
+	let width = image.size.x as usize / factor;
+	let height = image.size.y as usize / factor;
+	let source_width = image.size.x as usize;
+
+	let mut pixels = Vec::with_capacity(width * height);
+	for y in 0..height
+	{
+		for x in 0..width
+		{
+			let mut sum = 0_u32;
+			for source_y in y * factor..(y + 1) * factor
+			{
+				let row = &image.pixels[source_y * source_width..];
+				sum += row[x * factor..(x + 1) * factor]
+					.iter()
+					.map(|pixel| *pixel as u32)
+					.sum::<u32>();
+			}
+			pixels.push((sum / (factor * factor) as u32) as u8);
+		}
+	}
+
+	GrayscaleImage {
+		size: U16x2 {
+			x: width as u16,
+			y: height as u16,
+		},
+		pixels,
+	}
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	const SIZE: U16x2 = U16x2 { x: 160, y: 120 };
+
+	fn frame(brightness: impl Fn(u16, u16) -> u8) -> GrayscaleImage
+	{
+		GrayscaleImage {
+			size: SIZE,
+			pixels: (0..SIZE.y)
+				.flat_map(|y| (0..SIZE.x).map(move |x| (x, y)))
+				.map(|(x, y)| brightness(x, y))
+				.collect(),
+		}
+	}
+
+	/// A dark frame with a bright square whose top left corner is at (`x`, `y`).
+	fn frame_with_square(x: u16, y: u16, side: u16) -> GrayscaleImage
+	{
+		frame(|pixel_x, pixel_y| {
+			if (x..x + side).contains(&pixel_x) && (y..y + side).contains(&pixel_y)
+			{
+				200
+			}
+			else
+			{
+				20
+			}
+		})
+	}
+
+	#[test]
+	fn first_frame_has_no_motion()
+	{
+		let mut motion_detector = MotionDetector::new(MotionDetectorSettings::default());
+
+		assert!(!motion_detector.detect(&frame_with_square(0, 0, 40)));
+	}
+
+	#[test]
+	fn same_frame_has_no_motion()
+	{
+		let mut motion_detector = MotionDetector::new(MotionDetectorSettings::default());
+
+		motion_detector.detect(&frame_with_square(0, 0, 40));
+		assert!(!motion_detector.detect(&frame_with_square(0, 0, 40)));
+	}
+
+	#[test]
+	fn moving_object_is_motion()
+	{
+		let mut motion_detector = MotionDetector::new(MotionDetectorSettings::default());
+
+		motion_detector.detect(&frame_with_square(0, 0, 40));
+		assert!(motion_detector.detect(&frame_with_square(80, 60, 40)));
+	}
+
+	#[test]
+	fn changes_below_the_threshold_are_ignored()
+	{
+		let mut motion_detector = MotionDetector::new(MotionDetectorSettings::default());
+
+		motion_detector.detect(&frame(|_, _| 100));
+		assert!(!motion_detector.detect(&frame(|_, _| 110)));
+	}
+
+	#[test]
+	fn changed_area_smaller_than_the_minimum_is_ignored()
+	{
+		let mut motion_detector = MotionDetector::new(MotionDetectorSettings {
+			min_changed_fraction: 0.5,
+			..Default::default()
+		});
+
+		motion_detector.detect(&frame_with_square(0, 0, 40));
+		assert!(!motion_detector.detect(&frame_with_square(80, 60, 40)));
+	}
+
+	#[test]
+	fn changes_outside_the_regions_of_interest_are_ignored()
+	{
+		let mut motion_detector = MotionDetector::new(MotionDetectorSettings {
+			regions_of_interest: vec![RegionOfInterest {
+				x: 0.,
+				y: 0.5,
+				width: 0.5,
+				height: 0.5,
+			}],
+			..Default::default()
+		});
+
+		motion_detector.detect(&frame_with_square(0, 0, 40));
+		assert!(!motion_detector.detect(&frame_with_square(120, 0, 40)));
+		assert!(motion_detector.detect(&frame_with_square(0, 80, 40)));
+	}
+}
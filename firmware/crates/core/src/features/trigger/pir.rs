@@ -0,0 +1,66 @@
+use core::time::Duration;
+
+use embedded_hal::digital::InputPin;
+
+/// How the output of a PIR sensor is filtered.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PirSettings
+{
+	/// The output must stay high for at least this long before it's considered motion (it filters the spikes).
+	pub debounce: Duration,
+	/// Motion is still reported for this long after the output goes low.
+	pub hold_time: Duration,
+}
+
+/// A PIR sensor connected to an input pin, whose output is debounced and held (check [`PirSettings`]).
+pub struct PirSensor<P: InputPin>
+{
+	pin: P,
+	settings: PirSettings,
+	/// How long the output has been high, `None` if it's low.
+	high_for: Option<Duration>,
+	/// How long the motion will still be reported, `None` if there's no motion.
+	hold_remaining: Option<Duration>,
+}
+
+impl<P: InputPin> PirSensor<P>
+{
+	pub fn new(pin: P, settings: PirSettings) -> Self
+	{
+		Self {
+			pin,
+			settings,
+			high_for: None,
+			hold_remaining: None,
+		}
+	}
+
+	/// Reads the pin and returns if there's motion. `elapsed` is the time since the previous call.
+	pub fn tick(&mut self, elapsed: Duration) -> Result<bool, P::Error>
+	{
+		self.high_for = match self.pin.is_high()?
+		{
+			true => Some(self.high_for.map_or(Duration::ZERO, |high_for| high_for + elapsed)),
+			false => None,
+		};
+
+		if self.high_for.is_some_and(|high_for| high_for >= self.settings.debounce)
+		{
+			self.hold_remaining = Some(self.settings.hold_time);
+		}
+		else
+		{
+			self.hold_remaining = self
+				.hold_remaining
+				.and_then(|hold_remaining| hold_remaining.checked_sub(elapsed))
+				.filter(|hold_remaining| !hold_remaining.is_zero());
+		}
+
+		Ok(self.has_motion())
+	}
+
+	pub fn has_motion(&self) -> bool
+	{
+		self.hold_remaining.is_some()
+	}
+}
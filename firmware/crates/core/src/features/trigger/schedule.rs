@@ -0,0 +1,287 @@
+use core::{f64::consts::PI, ops::RangeInclusive};
+
+use a13c_embedded::peripherals::time::real_time::time::{Date, Duration as SignedDuration, Time, Weekday};
+
+/// A weekly schedule: each day is enabled in the windows of the rules of its weekday, unless the day is in one of the
+/// `exceptions`.
+#[derive(Clone, Debug, Default)]
+pub struct Schedule
+{
+	pub rules: Vec<ScheduleRule>,
+	/// Replace the rules in the days they contain (for example on holidays). If more than one contains the same day,
+	/// the first one is used.
+	pub exceptions: Vec<DateException>,
+	/// Required by the windows relative to the sun, which are never enabled without it.
+	pub location: Option<SolarLocation>,
+}
+
+#[derive(Clone, Debug)]
+pub struct ScheduleRule
+{
+	pub weekdays: Weekdays,
+	pub window: ScheduleWindow,
+}
+
+/// In the `dates` the day is enabled only in the `windows` instead of the ones of the rules, so an empty list disables
+/// the whole day.
+#[derive(Clone, Debug)]
+pub struct DateException
+{
+	pub dates: RangeInclusive<Date>,
+	pub windows: Vec<ScheduleWindow>,
+}
+
+/// A range of the day with both ends included. If `end` is before `start` the window wraps past midnight, and the part
+/// after midnight belongs to the day in which the window starts: a rule for Friday from 22:00 to 06:00 also enables
+/// Saturday until 06:00, but not Friday until 06:00.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ScheduleWindow
+{
+	pub start: ScheduleTime,
+	pub end: ScheduleTime,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScheduleTime
+{
+	At(Time),
+	/// Negative offsets are before the sunrise.
+	Sunrise
+	{
+		offset_minutes: i16,
+	},
+	/// Negative offsets are before the sunset.
+	Sunset
+	{
+		offset_minutes: i16,
+	},
+}
+
+/// Where the camera is, to compute the sunrise and the sunset of each day.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SolarLocation
+{
+	/// Degrees, positive to the north.
+	pub latitude: f64,
+	/// Degrees, positive to the east.
+	pub longitude: f64,
+	/// The offset of the time of the real time clock from UTC, which must be the same one the clock is configured
+	/// with.
+	pub utc_offset_seconds: i32,
+}
+
+/// A set of days of the week.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Weekdays(u8);
+
+impl Schedule
+{
+	pub fn is_enabled(&self, date: Date, time: Time) -> bool
+	{
+		let enabled_today = self.windows_on(date).any(|window| match self.resolve(window, date)
+		{
+			Some((start, end)) => time >= start && (end < start || time <= end),
+			None => false,
+		});
+		let enabled_by_yesterday = date.previous_day().is_some_and(|yesterday| {
+			self.windows_on(yesterday).any(
+				|window| matches!(self.resolve(window, yesterday), Some((start, end)) if end < start && time <= end),
+			)
+		});
+
+		enabled_today || enabled_by_yesterday
+	}
+
+	/// Returns the windows that start in `date`.
+	fn windows_on(&self, date: Date) -> impl Iterator<Item = &ScheduleWindow>
+	{
+		let exception = self.exceptions.iter().find(|exception| exception.dates.contains(&date));
+		let exception_windows = exception.into_iter().flat_map(|exception| exception.windows.iter());
+		let rule_windows = self
+			.rules
+			.iter()
+			.filter(move |rule| exception.is_none() && rule.weekdays.contains(date.weekday()))
+			.map(|rule| &rule.window);
+
+		exception_windows.chain(rule_windows)
+	}
+
+	/// Returns the start and the end of the `window` in `date`, or `None` if they depend on the sun and it can't be
+	/// computed.
+	fn resolve(&self, window: &ScheduleWindow, date: Date) -> Option<(Time, Time)>
+	{
+		let sun_times = || self.location?.sunrise_and_sunset(date);
+		let resolve = |time: ScheduleTime| match time
+		{
+			ScheduleTime::At(time) => Some(time),
+			ScheduleTime::Sunrise { offset_minutes } =>
+			{
+				Some(sun_times()?.0 + SignedDuration::minutes(offset_minutes as i64))
+			},
+			ScheduleTime::Sunset { offset_minutes } =>
+			{
+				Some(sun_times()?.1 + SignedDuration::minutes(offset_minutes as i64))
+			},
+		};
+
+		Some((resolve(window.start)?, resolve(window.end)?))
+	}
+}
+
+impl ScheduleWindow
+{
+	pub fn new(start: ScheduleTime, end: ScheduleTime) -> Self
+	{
+		Self { start, end }
+	}
+
+	/// Returns the window from the sunrise to the sunset.
+	pub fn daylight() -> Self
+	{
+		Self::new(
+			ScheduleTime::Sunrise { offset_minutes: 0 },
+			ScheduleTime::Sunset { offset_minutes: 0 },
+		)
+	}
+
+	/// Returns the window from the sunset to the sunrise of the next day.
+	pub fn night() -> Self
+	{
+		Self::new(
+			ScheduleTime::Sunset { offset_minutes: 0 },
+			ScheduleTime::Sunrise { offset_minutes: 0 },
+		)
+	}
+}
+
+impl SolarLocation
+{
+	/// Returns the local times of the sunrise and of the sunset in `date`, or `None` if the sun doesn't rise or doesn't
+	/// set in that day (near the poles). It uses the approximated equations of the NOAA, which are accurate to a couple
+	/// of minutes.
+	pub fn sunrise_and_sunset(&self, date: Date) -> Option<(Time, Time)>
+	{
+		let fractional_year = 2. * PI / 365. * (date.ordinal() as f64 - 1.);
+		let harmonic = |coefficients: [f64; 7]| {
+			coefficients[0]
+				+ (1..=3)
+					.map(|n| {
+						let angle = n as f64 * fractional_year;
+						coefficients[2 * n - 1] * angle.cos() + coefficients[2 * n] * angle.sin()
+					})
+					.sum::<f64>()
+		};
+		// In minutes
+		let equation_of_time = 229.18 * harmonic([0.000075, 0.001868, -0.032077, -0.014615, -0.040849, 0., 0.]);
+		// In radians
+		let declination = harmonic([0.006918, -0.399912, 0.070257, -0.006758, 0.000907, -0.002697, 0.00148]);
+
+		let latitude = self.latitude.to_radians();
+		// 90.833° accounts for the refraction and the size of the sun
+		let cos_hour_angle =
+			90.833_f64.to_radians().cos() / (latitude.cos() * declination.cos()) - latitude.tan() * declination.tan();
+		if !(-1. ..=1.).contains(&cos_hour_angle)
+		{
+			return None;
+		}
+		let hour_angle = cos_hour_angle.acos().to_degrees();
+
+		let solar_noon_minutes = 720. - 4. * self.longitude - equation_of_time + self.utc_offset_seconds as f64 / 60.;
+		let minutes_to_time = |minutes: f64| Time::MIDNIGHT + SignedDuration::seconds((minutes * 60.).round() as i64);
+
+		Some((
+			minutes_to_time(solar_noon_minutes - 4. * hour_angle),
+			minutes_to_time(solar_noon_minutes + 4. * hour_angle),
+		))
+	}
+}
+
+impl Weekdays
+{
+	pub const NONE: Self = Self(0);
+	pub const ALL: Self = Self(0b111_1111);
+	pub const WORKDAYS: Self = Self(0b001_1111);
+	pub const WEEKEND: Self = Self(0b110_0000);
+
+	pub fn from_days(days: &[Weekday]) -> Self
+	{
+		days.iter().fold(Self::NONE, |weekdays, day| weekdays.with(*day))
+	}
+
+	pub const fn with(self, day: Weekday) -> Self
+	{
+		Self(self.0 | 1 << day.number_days_from_monday())
+	}
+
+	pub const fn contains(self, day: Weekday) -> bool
+	{
+		self.0 & 1 << day.number_days_from_monday() != 0
+	}
+}
+
+#[cfg(test)]
+mod tests
+{
+	use a13c_embedded::peripherals::time::real_time::time::Month;
+
+	use super::*;
+
+	fn assert_close(actual: Time, expected: Time)
+	{
+		let difference = (actual - expected).whole_seconds().abs();
+		assert!(difference <= 3 * 60, "{} is too far from {}", actual, expected);
+	}
+
+	#[test]
+	fn sun_times_match_the_almanac()
+	{
+		let london = SolarLocation {
+			latitude: 51.5,
+			longitude: -0.13,
+			utc_offset_seconds: 0,
+		};
+		let (sunrise, sunset) = london
+			.sunrise_and_sunset(Date::from_calendar_date(2024, Month::December, 21).unwrap())
+			.unwrap();
+		assert_close(sunrise, Time::from_hms(8, 4, 0).unwrap());
+		assert_close(sunset, Time::from_hms(15, 54, 0).unwrap());
+
+		let rome = SolarLocation {
+			latitude: 41.9,
+			longitude: 12.5,
+			utc_offset_seconds: 2 * 60 * 60,
+		};
+		let (sunrise, sunset) = rome
+			.sunrise_and_sunset(Date::from_calendar_date(2024, Month::June, 21).unwrap())
+			.unwrap();
+		assert_close(sunrise, Time::from_hms(5, 36, 0).unwrap());
+		assert_close(sunset, Time::from_hms(20, 48, 0).unwrap());
+	}
+
+	#[test]
+	fn sun_times_dont_exist_in_the_polar_summer()
+	{
+		let tromso = SolarLocation {
+			latitude: 69.65,
+			longitude: 18.96,
+			utc_offset_seconds: 2 * 60 * 60,
+		};
+
+		assert_eq!(
+			tromso.sunrise_and_sunset(Date::from_calendar_date(2024, Month::June, 21).unwrap()),
+			None
+		);
+	}
+
+	#[test]
+	fn weekdays_contain_only_their_days()
+	{
+		let weekdays = Weekdays::from_days(&[Weekday::Monday, Weekday::Sunday]);
+
+		assert!(weekdays.contains(Weekday::Monday));
+		assert!(weekdays.contains(Weekday::Sunday));
+		assert!(!weekdays.contains(Weekday::Tuesday));
+		assert!(Weekdays::WEEKEND.contains(Weekday::Saturday));
+		assert!(!Weekdays::WORKDAYS.contains(Weekday::Saturday));
+	}
+}
@@ -0,0 +1,409 @@
+use core::time::Duration;
+use std::{collections::VecDeque, sync::Arc, time::Instant};
+
+use spin::Mutex;
+
+use crate::{
+	configuration::peripherals::http_client::HttpClient,
+	features::{frames::Frame, trigger::MotionEvent},
+};
+
+/// How often the thread of the webhooks checks if a delivery is due.
+const POLL_PERIOD: Duration = Duration::from_millis(100);
+/// The TLS handshake of the HTTPS requests needs most of it.
+const STACK_SIZE: usize = 10 * 1024;
+const MULTIPART_BOUNDARY: &str = "----camera-webhook-5f1c2a9e7d3b";
+
+/// Configures the [`Webhooks`] feature.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WebhookSettings
+{
+	/// Each motion event is POSTed to all of them.
+	pub urls: Vec<String>,
+	/// If it's `true`, the image captured when the event started is sent too.
+	pub include_snapshot: bool,
+	/// How many deliveries (one for each event and URL) can wait to be sent. When there are more, the oldest ones are
+	/// dropped.
+	pub max_queued_deliveries: usize,
+	/// How many times a delivery is tried before it's dropped.
+	pub max_attempts: u32,
+	/// The delay before the first retry, which doubles after each one.
+	pub first_retry_delay: Duration,
+}
+
+/// POSTs the [`MotionEvent`]s to the URLs of the [`WebhookSettings`] from a thread of its own, so that an endpoint
+/// that's slow or down never blocks the main loop.
+///
+/// The body is the JSON of the event (`application/json`), or if the snapshot is included a `multipart/form-data` with
+/// the JSON in the `event` part and the JPEG in the `snapshot` part. The deliveries that fail (including the ones whose
+/// response isn't 2xx) are retried with an exponential backoff.
+pub struct Webhooks
+{
+	queue: Arc<Mutex<DeliveryQueue>>,
+	include_snapshot: bool,
+}
+
+/// An event shared by its deliveries to all the URLs.
+struct WebhookEvent
+{
+	id: String,
+	json: Vec<u8>,
+	snapshot: Option<Arc<Frame>>,
+}
+
+struct Delivery
+{
+	url_index: usize,
+	event: Arc<WebhookEvent>,
+	/// How many times it has been tried.
+	attempts: u32,
+	next_attempt_at: Instant,
+}
+
+/// The deliveries waiting to be sent, in the order they were queued.
+struct DeliveryQueue
+{
+	deliveries: VecDeque<Delivery>,
+	settings: WebhookSettings,
+}
+
+impl Webhooks
+{
+	/// Starts the thread that sends the requests with the `client`.
+	pub fn new<H: HttpClient>(mut client: H, settings: WebhookSettings) -> Result<Self, std::io::Error>
+	{
+		let include_snapshot = settings.include_snapshot;
+		let queue = Arc::new(Mutex::new(DeliveryQueue {
+			deliveries: VecDeque::new(),
+			settings,
+		}));
+
+		std::thread::Builder::new()
+			.name("webhooks".to_string())
+			.stack_size(STACK_SIZE)
+			.spawn({
+				let queue = Arc::clone(&queue);
+				move || loop
+				{
+					// The lock isn't held during the request, so that the main loop can keep queueing events
+					let due_delivery = queue.lock().take_due(Instant::now());
+					match due_delivery
+					{
+						Some(delivery) =>
+						{
+							let url = queue.lock().settings.urls[delivery.url_index].clone();
+							if !delivery.event.send(&mut client, &url)
+							{
+								queue.lock().retry(delivery, Instant::now());
+							}
+						},
+						None => std::thread::sleep(POLL_PERIOD),
+					}
+				}
+			})?;
+
+		Ok(Self {
+			queue,
+			include_snapshot,
+		})
+	}
+
+	/// Queues the `event` for all the URLs without waiting for it to be sent. The `snapshot` is ignored if it isn't
+	/// included in the [`WebhookSettings`].
+	pub fn notify(&self, event: &MotionEvent, snapshot: Option<Arc<Frame>>)
+	{
+		let event = WebhookEvent {
+			id: event.id.clone(),
+			json: serde_json::to_vec(event).expect("`MotionEvent` can always be serialized"),
+			snapshot: snapshot.filter(|_| self.include_snapshot),
+		};
+		self.queue.lock().push(Arc::new(event), Instant::now());
+	}
+}
+
+impl WebhookEvent
+{
+	/// Returns `true` if the server responded with a 2xx status code (the error is logged otherwise).
+	fn send<H: HttpClient>(&self, client: &mut H, url: &str) -> bool
+	{
+		let result = match &self.snapshot
+		{
+			Some(snapshot) => client.post(
+				url,
+				&format!("multipart/form-data; boundary={}", MULTIPART_BOUNDARY),
+				&self.multipart_body(snapshot),
+			),
+			None => client.post(url, "application/json", &self.json),
+		};
+
+		match result
+		{
+			Ok(status) if (200..300).contains(&status) =>
+			{
+				log::info!("Sent the event {} to {}", self.id, url);
+				true
+			},
+			Ok(status) =>
+			{
+				log::warn!("Couldn't send the event {} to {}: status {}", self.id, url, status);
+				false
+			},
+			Err(error) =>
+			{
+				log::warn!("Couldn't send the event {} to {}: {:?}", self.id, url, error);
+				false
+			},
+		}
+	}
+
+	fn multipart_body(&self, snapshot: &Frame) -> Vec<u8>
+	{
+		let mut body = Vec::with_capacity(self.json.len() + snapshot.pixels.len() + 512);
+		body.extend_from_slice(
+			format!(
+				"--{}\r\nContent-Disposition: form-data; name=\"event\"\r\nContent-Type: application/json\r\n\r\n",
+				MULTIPART_BOUNDARY
+			)
+			.as_bytes(),
+		);
+		body.extend_from_slice(&self.json);
+		body.extend_from_slice(
+			format!(
+				"\r\n--{}\r\nContent-Disposition: form-data; name=\"snapshot\"; filename=\"{}.jpg\"\r\nContent-Type: \
+				 image/jpeg\r\n\r\n",
+				MULTIPART_BOUNDARY, self.id
+			)
+			.as_bytes(),
+		);
+		body.extend_from_slice(&snapshot.pixels);
+		body.extend_from_slice(format!("\r\n--{}--\r\n", MULTIPART_BOUNDARY).as_bytes());
+
+		body
+	}
+}
+
+impl DeliveryQueue
+{
+	fn push(&mut self, event: Arc<WebhookEvent>, now: Instant)
+	{
+		for url_index in 0..self.settings.urls.len()
+		{
+			self.push_delivery(Delivery {
+				url_index,
+				event: Arc::clone(&event),
+				attempts: 0,
+				next_attempt_at: now,
+			});
+		}
+	}
+
+	fn push_delivery(&mut self, delivery: Delivery)
+	{
+		if self.deliveries.len() >= self.settings.max_queued_deliveries
+		{
+			if let Some(dropped) = self.deliveries.pop_front()
+			{
+				log::warn!(
+					"Dropped the event {} for {} because too many are waiting to be sent",
+					dropped.event.id,
+					self.settings.urls[dropped.url_index]
+				);
+			}
+		}
+		if self.settings.max_queued_deliveries > 0
+		{
+			self.deliveries.push_back(delivery);
+		}
+	}
+
+	/// Removes and returns the oldest delivery that has to be tried at `now`.
+	fn take_due(&mut self, now: Instant) -> Option<Delivery>
+	{
+		let index = self
+			.deliveries
+			.iter()
+			.position(|delivery| delivery.next_attempt_at <= now)?;
+		self.deliveries.remove(index)
+	}
+
+	/// Queues the `delivery` that has just failed again, unless it has been tried too many times.
+	fn retry(&mut self, mut delivery: Delivery, now: Instant)
+	{
+		delivery.attempts += 1;
+		if delivery.attempts >= self.settings.max_attempts
+		{
+			log::error!(
+				"Dropped the event {} for {} after {} attempts",
+				delivery.event.id,
+				self.settings.urls[delivery.url_index],
+				delivery.attempts
+			);
+			return;
+		}
+
+		delivery.next_attempt_at = now
+			+ self
+				.settings
+				.first_retry_delay
+				.saturating_mul(1 << (delivery.attempts - 1).min(16));
+		self.push_delivery(delivery);
+	}
+}
+
+#[cfg(test)]
+mod tests
+{
+	use a13c_embedded::utils::math::micromath::micromath::vector::U16x2;
+
+	use super::*;
+	use crate::features::trigger::TriggerSource;
+
+	/// Stands in for the HTTP receivers: it records the requests, and fails the first `failures` ones.
+	#[derive(Clone, Default)]
+	struct FakeReceiver
+	{
+		/// The URL, content type and body of each request that succeeded.
+		received: Arc<Mutex<Vec<(String, String, Vec<u8>)>>>,
+		failures: Arc<Mutex<u32>>,
+	}
+
+	impl HttpClient for FakeReceiver
+	{
+		type Error = ();
+
+		fn post(&mut self, url: &str, content_type: &str, body: &[u8]) -> Result<u16, Self::Error>
+		{
+			let mut failures = self.failures.lock();
+			if *failures > 0
+			{
+				*failures -= 1;
+				return Ok(503);
+			}
+
+			self.received
+				.lock()
+				.push((url.to_string(), content_type.to_string(), body.to_vec()));
+			Ok(200)
+		}
+
+		/// The webhooks only send POST requests.
+		fn get(&mut self, _: &str, _: &mut dyn FnMut(&[u8]) -> bool) -> Result<u16, Self::Error>
+		{
+			Err(())
+		}
+	}
+
+	fn settings(urls: &[&str]) -> WebhookSettings
+	{
+		WebhookSettings {
+			urls: urls.iter().map(|url| url.to_string()).collect(),
+			include_snapshot: false,
+			max_queued_deliveries: 4,
+			max_attempts: 3,
+			first_retry_delay: Duration::from_secs(10),
+		}
+	}
+
+	fn event(id: &str) -> Arc<WebhookEvent>
+	{
+		Arc::new(WebhookEvent {
+			id: id.to_string(),
+			json: Vec::new(),
+			snapshot: None,
+		})
+	}
+
+	fn motion_event() -> MotionEvent
+	{
+		MotionEvent {
+			id: "20240501130102".to_string(),
+			timestamp: "2024-05-01T13:01:02".parse().unwrap(),
+			trigger_source: TriggerSource::Pir,
+		}
+	}
+
+	#[test]
+	fn oldest_deliveries_are_dropped_when_the_queue_is_full()
+	{
+		let now = Instant::now();
+		let mut queue = DeliveryQueue {
+			deliveries: VecDeque::new(),
+			settings: settings(&["http://a", "http://b"]),
+		};
+		queue.push(event("1"), now);
+		queue.push(event("2"), now);
+		queue.push(event("3"), now);
+
+		let queued: Vec<_> = queue
+			.deliveries
+			.iter()
+			.map(|delivery| (delivery.event.id.as_str(), delivery.url_index))
+			.collect();
+		assert_eq!(queued, [("2", 0), ("2", 1), ("3", 0), ("3", 1)]);
+	}
+
+	#[test]
+	fn failed_deliveries_are_retried_with_backoff_until_the_max_attempts()
+	{
+		let now = Instant::now();
+		let mut queue = DeliveryQueue {
+			deliveries: VecDeque::new(),
+			settings: settings(&["http://a"]),
+		};
+		queue.push(event("1"), now);
+
+		let delivery = queue.take_due(now).unwrap();
+		queue.retry(delivery, now);
+		assert!(queue.take_due(now + Duration::from_secs(9)).is_none());
+		let delivery = queue.take_due(now + Duration::from_secs(10)).unwrap();
+
+		queue.retry(delivery, now);
+		assert!(queue.take_due(now + Duration::from_secs(19)).is_none());
+		let delivery = queue.take_due(now + Duration::from_secs(20)).unwrap();
+
+		queue.retry(delivery, now);
+		assert!(queue.deliveries.is_empty());
+	}
+
+	#[test]
+	fn events_are_posted_with_the_snapshot_in_the_background()
+	{
+		let receiver = FakeReceiver::default();
+		*receiver.failures.lock() = 1;
+		let mut settings = settings(&["http://receiver/events"]);
+		settings.include_snapshot = true;
+		settings.first_retry_delay = Duration::from_millis(10);
+		let webhooks = Webhooks::new(receiver.clone(), settings).unwrap();
+
+		let snapshot = Frame {
+			sequence_number: 0,
+			pixels: b"jpeg".to_vec(),
+			size: U16x2 { x: 1, y: 1 },
+			timestamp: Duration::ZERO,
+			published_at: Instant::now(),
+		};
+		webhooks.notify(&motion_event(), Some(Arc::new(snapshot)));
+
+		let started_at = Instant::now();
+		while receiver.received.lock().is_empty()
+		{
+			assert!(
+				started_at.elapsed() < Duration::from_secs(5),
+				"The event hasn't been received"
+			);
+			std::thread::sleep(Duration::from_millis(10));
+		}
+
+		let received = receiver.received.lock();
+		let (url, content_type, body) = &received[0];
+		assert_eq!(url, "http://receiver/events");
+		assert_eq!(
+			*content_type,
+			format!("multipart/form-data; boundary={}", MULTIPART_BOUNDARY)
+		);
+		let body = String::from_utf8_lossy(body);
+		assert!(body.contains(r#"{"id":"20240501130102","timestamp":"2024-05-01T13:01:02","trigger_source":"pir"}"#));
+		assert!(body.contains("filename=\"20240501130102.jpg\"\r\nContent-Type: image/jpeg\r\n\r\njpeg\r\n"));
+		assert!(body.ends_with(&format!("--{}--\r\n", MULTIPART_BOUNDARY)));
+	}
+}
@@ -2,23 +2,71 @@ pub mod configuration;
 pub mod errors;
 pub mod features;
 
-use core::time::Duration;
+use core::{ops::RangeInclusive, time::Duration};
+use std::{sync::Arc, time::Instant};
 
-use a13c_embedded::peripherals::{time::real_time::RealTimeClock, watchdog::*};
+use a13c_embedded::peripherals::{
+	time::real_time::{
+		time::{Date, Time},
+		RealTimeClock,
+	},
+	watchdog::*,
+};
 use configuration::{
 	customization::Customization,
 	peripherals::{
-		camera::{Camera as CameraTrait, Image},
+		camera::{Camera as CameraTrait, CaptureSettings, SensorControl, SensorSettings},
+		tls::TlsIdentityStore,
 		Peripherals,
 	},
 	Configuration,
 };
+use embedded_hal::digital::{OutputPin, PinState};
+use embedded_svc::wifi::Wifi;
 use errors::*;
 use features::{
-	http_server::{register_all_requests, HttpServerData},
+	connectivity::{WifiState, WifiStatus, WifiSupervisor},
+	frames::{Frame, FrameBroker, SnapshotRequests},
+	http_server::{
+		auth::{HttpAuthRequests, SharedHttpAuth},
+		register_all_requests,
+		tls::{certificate_fingerprint, TlsIdentity, TlsRequests, TlsResponse},
+		ArmingRequests, BootSettingsRequests, CameraStatus, HttpServerData, SensorRequests,
+	},
+	mqtt::{HomeAssistantDevice, Mqtt, MqttBrokerRequests, MqttCommand},
+	ota::{
+		signature::{FirmwareVersion, ImagePolicy, VerifyingKey},
+		start_pull, ManifestUrlRequests, OtaPullSettings, OtaUpdater, SharedOtaWriter,
+	},
+	provisioning::{
+		connect_to_network, start_captive_dns, start_setup_access_point, SetupButton, WifiProvisioning, WifiRequests,
+		WifiResponse,
+	},
+	recording::EventRecorder,
+	rtsp::start_rtsp_server,
+	settings::Settings,
 	storage::Storage,
-	trigger::ImageTrigger,
+	timelapse::Timelapse,
+	trigger::{ImageTrigger, LocalDateTime},
+	webhooks::Webhooks,
 };
+use spin::Mutex;
+
+/// Number of frames kept in memory for the consumers that are slower than the camera (check [`FrameBroker`]).
+const FRAME_BROKER_CAPACITY: usize = 2;
+/// How long the camera waits before restarting to apply a WiFi, certificate, update manifest, MQTT broker or boot
+/// settings change or to boot a firmware update, so that the HTTP response that asked for it can be sent.
+const RESTART_DELAY: Duration = Duration::from_secs(1);
+
+/// One of the files can be a capture being downloaded (check [`Storage::open_capture`]).
+type SdCardStorage<C> = Storage<
+	<<C as Configuration>::Peripherals as Peripherals>::SdCard,
+	<<C as Configuration>::Peripherals as Peripherals>::SdCardTimeSource,
+	3,
+	4,
+	1,
+>;
+type SharedOtaUpdater<C> = Arc<Mutex<OtaUpdater<<<C as Configuration>::Peripherals as Peripherals>::OtaFlash>>>;
 
 pub struct Camera<C: Configuration>
 {
@@ -28,23 +76,52 @@ pub struct Camera<C: Configuration>
 	wifi_driver: <C::Peripherals as Peripherals>::WifiDriver,
 	get_ip_address_from_wifi_driver_fn:
 		fn(&<<C as Configuration>::Peripherals as Peripherals>::WifiDriver) -> Option<std::net::IpAddr>,
-	storage: Storage<
-		<C::Peripherals as Peripherals>::SdCardSpi,
-		<C::Peripherals as Peripherals>::SdCardCS,
-		<C::Peripherals as Peripherals>::SdCardDelay,
-		<C::Peripherals as Peripherals>::SdCardTimeSource,
-		3,
-		3,
-		1,
-	>,
+	get_rssi_from_wifi_driver_fn: fn(&<<C as Configuration>::Peripherals as Peripherals>::WifiDriver) -> Option<i8>,
+	storage: Arc<Mutex<SdCardStorage<C>>>,
 	watchdog: Option<<<C::Peripherals as Peripherals>::WatchdogCreator as WatchdogCreator>::Watchdog>,
-	test_counter: u32,
-	http_server_data: HttpServerData,
-	image_trigger: ImageTrigger<
-		<C::Peripherals as Peripherals>::PirSensorPin,
-		<C::Customization as Customization>::EnableOnConditionsList,
-	>,
+	frame_broker: FrameBroker,
+	snapshot_requests: SnapshotRequests,
+	sensor_requests: SensorRequests,
+	arming_requests: ArmingRequests,
+	wifi_requests: WifiRequests,
+	wifi_provisioning: WifiProvisioning,
+	/// The copy of `settings.http_auth` checked by the HTTP servers.
+	http_auth: SharedHttpAuth,
+	http_auth_requests: HttpAuthRequests,
+	/// `None` if the certificate of the HTTPS servers can't be changed.
+	tls_identity_store: Option<<C::Peripherals as Peripherals>::TlsIdentityStore>,
+	tls_requests: TlsRequests,
+	ota_manifest_requests: ManifestUrlRequests,
+	mqtt_broker_requests: MqttBrokerRequests,
+	boot_settings_requests: BootSettingsRequests,
+	/// The fingerprint of the certificate the HTTPS servers have been started with.
+	tls_certificate_sha256: Option<String>,
+	/// `None` in the setup mode.
+	wifi_supervisor: Option<WifiSupervisor>,
+	/// `None` if the board doesn't have one.
+	setup_button: Option<SetupButton<<C::Peripherals as Peripherals>::SetupButtonPin>>,
+	restart_fn: fn() -> !,
+	/// `Some` after a WiFi, certificate or manifest change or a firmware update, which are applied by restarting.
+	restart_at: Option<Instant>,
+	/// `None` if the firmware of the board can't be updated.
+	ota: Option<SharedOtaUpdater<C>>,
+	/// How many ticks must complete before the running firmware is marked valid.
+	ticks_before_valid: u32,
+	/// How many ticks have completed since the camera has started (up to `ticks_before_valid`).
+	completed_ticks: u32,
+	image_trigger: ImageTrigger<<C::Peripherals as Peripherals>::PirSensorPin, Vec<RangeInclusive<Time>>>,
+	/// `None` if each image is stored on its own.
+	event_recorder: Option<EventRecorder>,
+	timelapse: Option<Timelapse<<C::Customization as Customization>::EnableOnConditionsList>>,
+	/// `None` if MQTT isn't configured.
+	mqtt: Option<Mqtt<<C::Peripherals as Peripherals>::MqttClient>>,
+	/// `None` if there are no webhooks.
+	webhooks: Option<Webhooks>,
+	/// `None` if the board doesn't have one.
+	flash_led: Option<<C::Peripherals as Peripherals>::FlashLed>,
 	real_time_clock: <C::Peripherals as Peripherals>::RealTimeClock,
+	settings: Settings,
+	settings_store: <C::Peripherals as Peripherals>::SettingsStore,
 }
 
 impl<C: Configuration> Camera<C>
@@ -54,6 +131,56 @@ impl<C: Configuration> Camera<C>
 		let mut peripherals = configuration.peripherals();
 		let customization = configuration.customization();
 
+		let mut settings_store = peripherals
+			.take_settings_store()
+			.ok_or(CreationError::PeripheralMissing { name: "Settings store" })?;
+		let settings = Settings::load_or_default(&mut settings_store);
+
+		let mut wifi_driver = peripherals
+			.take_wifi_driver()
+			.ok_or(CreationError::PeripheralMissing { name: "WiFi driver" })?;
+		let wifi_supervisor = match settings.wifi_networks.is_empty()
+		{
+			false => Some(WifiSupervisor::new(
+				settings.wifi_networks.clone(),
+				customization.wifi_supervisor(),
+				Instant::now(),
+			)),
+			true =>
+			{
+				let mac_address = (C::Peripherals::get_mac_address_from_wifi_driver_function())(&wifi_driver);
+				start_setup_access_point(&mut wifi_driver, &customization.provisioning(), mac_address)
+					.map_err(CreationError::Wifi)?;
+				// Without it the setup page can still be opened with the address of the camera
+				match (C::Peripherals::get_access_point_ip_address_from_wifi_driver_function())(&wifi_driver)
+				{
+					Some(ip) =>
+					{
+						if let Err(error) = start_captive_dns(ip)
+						{
+							log::warn!("Couldn't start the captive portal DNS server: {}", error);
+						}
+					},
+					None => log::warn!("The access point has no IP, the captive portal DNS server isn't started"),
+				}
+				None
+			},
+		};
+		let is_in_setup_mode = wifi_supervisor.is_none();
+
+		let camera = peripherals
+			.take_camera()
+			.ok_or(CreationError::PeripheralMissing { name: "Camera" })?;
+		camera
+			.get_sensor()
+			.set_settings(&settings.sensor)
+			.map_err(CreationError::Camera)?;
+		let enable_image_trigger_on = match &settings.trigger
+		{
+			Some(trigger) => trigger.to_enable_on_conditions(),
+			None => customization.enable_image_trigger_on().to_vec(),
+		};
+
 		let mut http_server = (peripherals
 			.take_http_server()
 			.ok_or(CreationError::PeripheralMissing { name: "HTTP server" })?)()
@@ -65,55 +192,203 @@ impl<C: Configuration> Camera<C>
 					name: "Stream HTTP server",
 				})?)()
 			.map_err(CreationError::StartStreamHttpServer)?;
-		let http_server_data = HttpServerData::new();
-		register_all_requests(&mut http_server, &mut stream_http_server, http_server_data.clone())
-			.map_err(CreationError::RegisterURIHandlerHttpServer)?;
-
-		Ok(Self {
-			test_counter: 0,
-			camera: peripherals
-				.take_camera()
-				.ok_or(CreationError::PeripheralMissing { name: "Camera" })?,
-			http_server,
-			stream_http_server,
-			wifi_driver: peripherals
-				.take_wifi_driver()
-				.ok_or(CreationError::PeripheralMissing { name: "WiFi driver" })?,
-			get_ip_address_from_wifi_driver_fn: C::Peripherals::get_ip_address_from_wifi_driver_function(),
-			storage: Storage::new(
-				peripherals
-					.take_sd_card_spi()
-					.ok_or(CreationError::PeripheralMissing { name: "SD Card SPI" })?,
-				peripherals
-					.take_sd_card_cs()
-					.ok_or(CreationError::PeripheralMissing { name: "SD Card CS" })?,
+		// Taken after starting the servers, which generate the certificate at the first boot
+		let mut tls_identity_store = peripherals.take_tls_identity_store();
+		let tls_certificate_sha256 = match tls_identity_store.as_mut().map(TlsIdentityStore::certificate)
+		{
+			Some(Ok(certificate)) => certificate.as_deref().and_then(certificate_fingerprint),
+			Some(Err(error)) =>
+			{
+				log::warn!("Couldn't read the HTTPS certificate: {:?}", error);
+				None
+			},
+			None => None,
+		};
+		let tls_requests = TlsRequests::default();
+		let ota_manifest_requests = ManifestUrlRequests::default();
+		let mqtt_broker_requests = MqttBrokerRequests::default();
+		let boot_settings_requests = BootSettingsRequests::default();
+		let storage = Arc::new(Mutex::new(
+			Storage::new(
 				peripherals
-					.take_sd_card_delay()
-					.ok_or(CreationError::PeripheralMissing { name: "SD Card delay" })?,
+					.take_sd_card()
+					.ok_or(CreationError::PeripheralMissing { name: "SD Card" })?,
 				peripherals
 					.take_sd_card_time_source()
 					.ok_or(CreationError::PeripheralMissing {
 						name: "SD Card time source",
 					})?,
+				customization.retention_policy(),
 			)
 			.map_err(CreationError::SdCard)?,
+		));
+		let frame_broker = FrameBroker::new(FRAME_BROKER_CAPACITY);
+		let snapshot_requests = SnapshotRequests::default();
+		let sensor_requests = SensorRequests::default();
+		let arming_requests = ArmingRequests::default();
+		let wifi_requests = WifiRequests::default();
+		if !settings.http_auth.is_enabled()
+		{
+			log::warn!("No HTTP credentials have been set, they must be set from the setup page to control the camera");
+		}
+		let http_auth = Arc::new(Mutex::new(settings.http_auth.clone()));
+		let http_auth_requests = HttpAuthRequests::default();
+		let ota_settings = customization.ota();
+		let image_policy = ImagePolicy {
+			board: ota_settings.board,
+			trusted_keys: ota_settings
+				.trusted_public_keys
+				.iter()
+				.filter_map(|key| {
+					VerifyingKey::from_bytes(key)
+						.map_err(|error| log::warn!("A trusted public key for the updates isn't valid: {}", error))
+						.ok()
+				})
+				.collect(),
+			rollback_floor: settings.ota_rollback_floor,
+		};
+		let ota = peripherals
+			.take_ota_flash()
+			.map(|ota_flash| Arc::new(Mutex::new(OtaUpdater::new(ota_flash, image_policy))));
+		register_all_requests(
+			&mut http_server,
+			&mut stream_http_server,
+			HttpServerData {
+				captures: storage.clone(),
+				frame_broker: frame_broker.clone(),
+				snapshot_requests: snapshot_requests.clone(),
+				sensor_requests: sensor_requests.clone(),
+				arming_requests: arming_requests.clone(),
+				wifi_requests: wifi_requests.clone(),
+				is_in_setup_mode,
+				ota: ota.clone().map(|ota| ota as SharedOtaWriter),
+				ota_manifest_requests: ota_manifest_requests.clone(),
+				mqtt_broker_requests: mqtt_broker_requests.clone(),
+				boot_settings_requests: boot_settings_requests.clone(),
+				auth: http_auth.clone(),
+				auth_requests: http_auth_requests.clone(),
+				tls_requests: tls_identity_store.is_some().then(|| tls_requests.clone()),
+			},
+		)
+		.map_err(CreationError::RegisterURIHandlerHttpServer)?;
+		if let Some(rtsp_settings) = customization.rtsp()
+		{
+			start_rtsp_server(rtsp_settings, frame_broker.clone(), http_auth.clone())
+				.map_err(CreationError::StartRtspServer)?;
+		}
+
+		let mut image_trigger = ImageTrigger::new(
+			peripherals
+				.take_pir_sensor_pin()
+				.ok_or(CreationError::PeripheralMissing { name: "PIR sensor pin" })?,
+			customization.pir_sensor(),
+			customization.trigger_sources(),
+			enable_image_trigger_on,
+			customization.trigger_duration(),
+			customization.trigger_cooldown(),
+		);
+		image_trigger.set_arming(settings.arming);
+
+		let mut flash_led = peripherals.take_flash_led();
+		if let Some(Err(error)) = flash_led.as_mut().map(|flash_led| flash_led.set_low())
+		{
+			log::warn!("Couldn't turn off the flash LED: {:?}", error);
+		}
+		let mqtt = match (customization.mqtt(), peripherals.take_mqtt_client())
+		{
+			(Some(mqtt_settings), Some(create_mqtt_client)) =>
+			{
+				let client = create_mqtt_client(Mqtt::<<C::Peripherals as Peripherals>::MqttClient>::last_will(
+					&mqtt_settings,
+				))
+				.map_err(CreationError::StartMqttClient)?;
+				let home_assistant_device = (C::Peripherals::get_mac_address_from_wifi_driver_function())(&wifi_driver)
+					.map(|mac_address| HomeAssistantDevice {
+						mac_address,
+						has_flash_led: flash_led.is_some(),
+					});
+
+				let mut mqtt = Mqtt::new(client, mqtt_settings, home_assistant_device);
+				if flash_led.is_some()
+				{
+					mqtt.publish_flash_led(false);
+				}
+				Some(mqtt)
+			},
+			_ => None,
+		};
+		let webhooks = match (customization.webhooks(), peripherals.take_http_client())
+		{
+			(Some(webhook_settings), Some(http_client)) if !webhook_settings.urls.is_empty() =>
+			{
+				Some(Webhooks::new(http_client, webhook_settings).map_err(CreationError::StartWebhooks)?)
+			},
+			_ => None,
+		};
+		if let (Some(ota), Some(manifest_url)) = (ota.as_ref(), settings.ota_manifest_url.clone())
+		{
+			match peripherals.take_http_client()
+			{
+				Some(http_client) =>
+				{
+					let pull_settings = OtaPullSettings {
+						manifest_url,
+						check_period: ota_settings.check_period,
+					};
+					start_pull(http_client, ota.clone(), pull_settings).map_err(CreationError::StartOtaPull)?
+				},
+				None => log::warn!("The board can't send HTTP requests, so it can't check for firmware updates"),
+			}
+		}
+
+		Ok(Self {
+			camera,
+			http_server,
+			stream_http_server,
+			wifi_driver,
+			get_ip_address_from_wifi_driver_fn: C::Peripherals::get_ip_address_from_wifi_driver_function(),
+			get_rssi_from_wifi_driver_fn: C::Peripherals::get_rssi_from_wifi_driver_function(),
+			storage,
 			watchdog: peripherals
 				.take_watchdog_creator()
 				.map(|watchdog_creator| watchdog_creator.watch_current_thread())
 				.flatten(),
-			image_trigger: ImageTrigger::new(
-				peripherals
-					.take_pir_sensor_pin()
-					.ok_or(CreationError::PeripheralMissing { name: "PIR sensor pin" })?,
-				customization.enable_image_trigger_on(),
-				customization.trigger_duration(),
-			),
-			http_server_data,
+			image_trigger,
+			event_recorder: customization.event_recording().map(EventRecorder::new),
+			timelapse: customization.timelapse().map(Timelapse::new),
+			mqtt,
+			webhooks,
+			flash_led,
+			frame_broker,
+			snapshot_requests,
+			sensor_requests,
+			arming_requests,
+			wifi_requests,
+			wifi_provisioning: WifiProvisioning::default(),
+			http_auth,
+			http_auth_requests,
+			tls_identity_store,
+			tls_requests,
+			ota_manifest_requests,
+			mqtt_broker_requests,
+			boot_settings_requests,
+			tls_certificate_sha256,
+			wifi_supervisor,
+			setup_button: peripherals
+				.take_setup_button_pin()
+				.map(|pin| SetupButton::new(pin, customization.provisioning().button_long_press)),
+			restart_fn: C::Peripherals::get_restart_function(),
+			restart_at: None,
+			ota,
+			ticks_before_valid: ota_settings.ticks_before_valid,
+			completed_ticks: 0,
 			real_time_clock: peripherals
 				.take_real_time_clock()
 				.ok_or(CreationError::<C>::PeripheralMissing {
 					name: "Real time clock",
 				})?,
+			settings,
+			settings_store,
 		})
 	}
 
@@ -124,30 +399,591 @@ impl<C: Configuration> Camera<C>
 			watchdog.feed().map_err(TickError::WatchdogReset)?;
 		}
 
+		self.control_sensor()?;
+		self.capture_requested_snapshots()?;
+		self.control_arming();
+		self.supervise_wifi();
+		self.control_wifi();
+		self.control_http_auth();
+		self.control_tls();
+		self.control_ota_manifest();
+		self.control_mqtt_broker();
+		self.control_boot_settings();
+		self.restart_if_scheduled();
+		self.execute_mqtt_commands()?;
+
 		if let Ok(current_date_and_time) = self.real_time_clock.now()
 		{
 			self.image_trigger
 				.tick(Some(current_date_and_time))
 				.map_err(TickError::CouldntReadPirSensorPin)?;
+			// An `Arming::ArmedUntil` could have expired
+			self.save_arming();
 
+			let mut frame = None;
 			if self.image_trigger.needs_to_capture_image()
 			{
-				let image = self.camera.get_image().map_err(TickError::Camera)?;
-				self.http_server_data
-					.write_image(image.get_pixels(), image.get_timestamp());
+				let captured_frame = {
+					let image = self.camera.get_image().map_err(TickError::Camera)?;
+					self.image_trigger.detect_motion_in(&image);
+					self.frame_broker.publish(&image)
+				};
+				let frame = frame.insert(captured_frame);
 
-				if self.image_trigger.needs_to_store_image()
+				match self.event_recorder.as_mut()
 				{
-					self.test_counter += 1;
-					self.storage
-						.store_image(image.get_pixels(), &format!("img_{}.jpg", self.test_counter))
-						.unwrap();
-					log::info!("Stored image: img_{}.jpg", self.test_counter);
+					Some(event_recorder) => event_recorder
+						.record(
+							Arc::clone(frame),
+							self.image_trigger.motion_source(),
+							Some(current_date_and_time),
+							&mut *self.storage.lock(),
+						)
+						.map_err(TickError::Storage)?,
+					None if self.image_trigger.needs_to_store_image() =>
+					{
+						let path = self
+							.storage
+							.lock()
+							.store_image(&frame.pixels, Some(current_date_and_time))
+							.map_err(TickError::Storage)?;
+						log::info!("Stored image: {}", path);
+					},
+					None => (),
 				}
-				self.http_server_data.write_image(&[], Duration::default());
+			}
+			else if let Some(event_recorder) = self.event_recorder.as_mut()
+			{
+				event_recorder
+					.finish_event(&mut *self.storage.lock())
+					.map_err(TickError::Storage)?;
+			}
+
+			self.notify_motion_event(current_date_and_time, frame.as_ref());
+			self.capture_timelapse_frame(current_date_and_time, frame)?;
+			self.publish_to_mqtt();
+		}
+
+		self.completed_ticks = (self.completed_ticks + 1).min(self.ticks_before_valid);
+		self.control_ota();
+
+		Ok(())
+	}
+
+	/// Stores a frame in the timelapse if it's time to, reusing the `frame` captured in this tick if there's one.
+	fn capture_timelapse_frame(
+		&mut self, (date, time): (Date, Time), frame: Option<Arc<Frame>>,
+	) -> Result<(), TickError<C>>
+	{
+		let Some(timelapse) = self.timelapse.as_mut()
+		else
+		{
+			return Ok(());
+		};
+		if !timelapse.tick(date, time)
+		{
+			return Ok(());
+		}
+		let video_frame_duration = timelapse.daily_video_frame_duration();
+
+		let frame = match frame
+		{
+			Some(frame) => frame,
+			None => self
+				.frame_broker
+				.publish(&self.camera.get_image().map_err(TickError::Camera)?),
+		};
+		let path = self
+			.storage
+			.lock()
+			.store_timelapse_frame(date, &frame, video_frame_duration)
+			.map_err(TickError::Storage)?;
+		log::info!("Stored timelapse frame: {}", path);
+
+		Ok(())
+	}
+
+	/// Reads or changes the settings of the sensor as requested by the HTTP server.
+	fn control_sensor(&mut self) -> Result<(), TickError<C>>
+	{
+		while let Some((id, new_settings)) = self.sensor_requests.take_pending()
+		{
+			let settings = self.read_or_change_sensor_settings(new_settings);
+			self.sensor_requests.fulfill(id, settings.as_ref().ok().copied());
+			settings?;
+		}
+
+		Ok(())
+	}
+
+	/// Returns the settings of the sensor after applying the `new_settings` (if any), which are saved.
+	fn read_or_change_sensor_settings(
+		&mut self, new_settings: Option<SensorSettings>,
+	) -> Result<SensorSettings, TickError<C>>
+	{
+		let settings = {
+			let sensor = self.camera.get_sensor();
+			match new_settings
+			{
+				Some(new_settings) => sensor.set_settings(&new_settings).and_then(|()| sensor.get_settings()),
+				None => sensor.get_settings(),
+			}
+		}
+		.map_err(TickError::Camera)?;
+
+		if new_settings.is_some() && settings != self.settings.sensor
+		{
+			self.settings.sensor = settings;
+			self.save_settings();
+		}
+
+		Ok(settings)
+	}
+
+	/// Executes the commands received with MQTT since the last tick.
+	fn execute_mqtt_commands(&mut self) -> Result<(), TickError<C>>
+	{
+		let Some(mqtt) = self.mqtt.as_mut()
+		else
+		{
+			return Ok(());
+		};
+		let commands = mqtt.poll((self.get_ip_address_from_wifi_driver_fn)(&self.wifi_driver));
+
+		for command in commands
+		{
+			match command
+			{
+				MqttCommand::SetArming(arming) =>
+				{
+					self.image_trigger.set_arming(arming);
+					self.save_arming();
+				},
+				MqttCommand::Snapshot =>
+				{
+					let frame = self
+						.frame_broker
+						.publish(&self.camera.get_image().map_err(TickError::Camera)?);
+					if let Some(mqtt) = self.mqtt.as_mut()
+					{
+						mqtt.publish_snapshot(&frame.pixels);
+					}
+				},
+				MqttCommand::SetFlashLed(on) =>
+				{
+					let Some(flash_led) = self.flash_led.as_mut()
+					else
+					{
+						log::warn!("The flash LED can't be turned on or off because the board doesn't have one");
+						continue;
+					};
+					match flash_led.set_state(PinState::from(on))
+					{
+						Ok(()) =>
+						{
+							if let Some(mqtt) = self.mqtt.as_mut()
+							{
+								mqtt.publish_flash_led(on);
+							}
+						},
+						Err(error) => log::warn!("Couldn't turn the flash LED on or off: {:?}", error),
+					}
+				},
+				MqttCommand::ChangeSensorSettings(changes) =>
+				{
+					let new_settings = match self.settings.sensor.with_json_changes(&changes)
+					{
+						Ok(new_settings) => new_settings,
+						Err(error) =>
+						{
+							log::warn!("Invalid sensor settings received with MQTT: {}", error);
+							continue;
+						},
+					};
+					let settings = self.read_or_change_sensor_settings(Some(new_settings))?;
+					if let Some(mqtt) = self.mqtt.as_mut()
+					{
+						mqtt.publish_sensor_settings(&settings);
+					}
+				},
 			}
 		}
 
 		Ok(())
 	}
+
+	/// Sends the motion event that has just started (if any) to MQTT and to the webhooks, with the `frame` captured in
+	/// this tick as its snapshot.
+	fn notify_motion_event(&mut self, (date, time): (Date, Time), frame: Option<&Arc<Frame>>)
+	{
+		let Some(event) = self.image_trigger.motion_event(LocalDateTime { date, time })
+		else
+		{
+			return;
+		};
+
+		if let Some(mqtt) = self.mqtt.as_mut()
+		{
+			mqtt.publish_motion_event(&event, frame.map(|frame| frame.pixels.as_slice()));
+		}
+		if let Some(webhooks) = self.webhooks.as_ref()
+		{
+			webhooks.notify(&event, frame.cloned());
+		}
+	}
+
+	/// Publishes the status of the trigger and the stats.
+	fn publish_to_mqtt(&mut self)
+	{
+		let Some(mqtt) = self.mqtt.as_mut()
+		else
+		{
+			return;
+		};
+
+		mqtt.publish_status(self.image_trigger.status());
+		if mqtt.needs_stats()
+		{
+			mqtt.publish_stats(
+				self.storage.lock().stats(),
+				(self.get_rssi_from_wifi_driver_fn)(&self.wifi_driver),
+			);
+		}
+	}
+
+	/// Scans the networks or changes the saved one as requested by the HTTP server (or by the setup button).
+	fn control_wifi(&mut self)
+	{
+		while let Some((id, request)) = self.wifi_requests.take_pending()
+		{
+			let response = self.wifi_provisioning.execute(
+				request,
+				&mut self.wifi_driver,
+				&mut self.settings.wifi_networks,
+				Instant::now(),
+			);
+			if response == WifiResponse::Restarting
+			{
+				self.save_wifi_networks_and_restart();
+			}
+			self.wifi_requests.fulfill(id, response);
+		}
+
+		match self.setup_button.as_mut().map(SetupButton::tick)
+		{
+			Some(Ok(true)) =>
+			{
+				log::info!("The setup button has been held, returning to the setup mode");
+				self.settings.wifi_networks.clear();
+				self.save_wifi_networks_and_restart();
+			},
+			Some(Err(error)) => log::warn!("Couldn't read the setup button: {:?}", error),
+			Some(Ok(false)) | None => (),
+		}
+	}
+
+	/// Saves the changed WiFi networks (without any the camera returns to the setup mode) and schedules the restart that
+	/// applies them.
+	fn save_wifi_networks_and_restart(&mut self)
+	{
+		self.save_settings();
+		self.schedule_restart();
+	}
+
+	/// Saves the credentials changed by the HTTP server, which are used from the next request.
+	fn control_http_auth(&mut self)
+	{
+		while let Some((id, http_auth)) = self.http_auth_requests.take_pending()
+		{
+			log::info!(
+				"Changed the HTTP credentials: {} users and {} tokens",
+				http_auth.users.len(),
+				http_auth.token_hashes.len()
+			);
+			*self.http_auth.lock() = http_auth.clone();
+			self.settings.http_auth = http_auth;
+			self.save_settings();
+			self.http_auth_requests.fulfill(id, ());
+		}
+	}
+
+	/// Saves the certificate uploaded to the HTTP server and schedules the restart that makes the HTTPS servers use it.
+	fn control_tls(&mut self)
+	{
+		while let Some((id, identity)) = self.tls_requests.take_pending()
+		{
+			let response = self.save_tls_identity(&identity);
+			if response == TlsResponse::Restarting
+			{
+				self.schedule_restart();
+			}
+			self.tls_requests.fulfill(id, response);
+		}
+	}
+
+	fn save_tls_identity(&mut self, identity: &TlsIdentity) -> TlsResponse
+	{
+		let Some(tls_identity_store) = self.tls_identity_store.as_mut()
+		else
+		{
+			return TlsResponse::NotSaved("The certificate of the board can't be changed".to_string());
+		};
+		if let Err(error) = tls_identity_store.check(identity)
+		{
+			return TlsResponse::Refused(format!("The board can't use the certificate: {:?}", error));
+		}
+
+		match tls_identity_store.write(identity)
+		{
+			Ok(()) =>
+			{
+				log::info!(
+					"Saved the HTTPS certificate {}",
+					certificate_fingerprint(&identity.certificate).unwrap_or_default()
+				);
+				TlsResponse::Restarting
+			},
+			Err(error) =>
+			{
+				log::error!("Couldn't save the HTTPS certificate: {:?}", error);
+				TlsResponse::NotSaved("Couldn't save the certificate".to_string())
+			},
+		}
+	}
+
+	/// Marks the running firmware valid once enough ticks have completed, and schedules the restart that boots an update
+	/// once it has been installed (after raising the rollback floor to the minimum version of the update).
+	fn control_ota(&mut self)
+	{
+		let Some(ota) = self.ota.as_ref()
+		else
+		{
+			return;
+		};
+		// Erasing the partition for an update holds the lock for seconds, longer than the watchdog allows
+		let Some(mut ota) = ota.try_lock()
+		else
+		{
+			return;
+		};
+
+		if !ota.is_running_valid() && self.completed_ticks >= self.ticks_before_valid
+		{
+			ota.mark_running_valid();
+		}
+		let min_rollback_version = ota.installed_image().map(|metadata| metadata.min_rollback_version);
+		drop(ota);
+		if let Some(min_rollback_version) = min_rollback_version
+		{
+			self.raise_rollback_floor(min_rollback_version);
+			self.schedule_restart();
+		}
+	}
+
+	fn raise_rollback_floor(&mut self, min_rollback_version: FirmwareVersion)
+	{
+		if min_rollback_version > self.settings.ota_rollback_floor
+		{
+			self.settings.ota_rollback_floor = min_rollback_version;
+			self.save_settings();
+		}
+	}
+
+	/// Saves the manifest URL changed by the HTTP server and schedules the restart that makes the camera check it.
+	fn control_ota_manifest(&mut self)
+	{
+		while let Some((id, manifest_url)) = self.ota_manifest_requests.take_pending()
+		{
+			log::info!("Changed the manifest of the firmware updates to {:?}", manifest_url);
+			self.settings.ota_manifest_url = manifest_url;
+			self.save_settings();
+			self.schedule_restart();
+			self.ota_manifest_requests.fulfill(id, ());
+		}
+	}
+
+	/// Saves the MQTT broker changed by the HTTP server and schedules the restart that makes the board connect to it.
+	fn control_mqtt_broker(&mut self)
+	{
+		while let Some((id, broker)) = self.mqtt_broker_requests.take_pending()
+		{
+			log::info!("Changed the MQTT broker to {:?}", broker);
+			self.settings.mqtt_broker = broker;
+			self.save_settings();
+			self.schedule_restart();
+			self.mqtt_broker_requests.fulfill(id, ());
+		}
+	}
+
+	/// Reads the [`BootSettings`](features::settings::BootSettings) for the HTTP server, or saves them and schedules
+	/// the restart that applies them if they changed.
+	fn control_boot_settings(&mut self)
+	{
+		while let Some((id, boot_settings)) = self.boot_settings_requests.take_pending()
+		{
+			if let Some(boot_settings) = boot_settings.filter(|settings| *settings != self.settings.boot_settings())
+			{
+				log::info!("Changed the boot settings to {:?}", boot_settings);
+				self.settings.set_boot_settings(boot_settings);
+				self.save_settings();
+				self.schedule_restart();
+			}
+			self.boot_settings_requests.fulfill(id, self.settings.boot_settings());
+		}
+	}
+
+	fn schedule_restart(&mut self)
+	{
+		self.restart_at.get_or_insert_with(|| Instant::now() + RESTART_DELAY);
+	}
+
+	/// Restarts once the delay has passed, but not while the running firmware is an update that hasn't been marked valid
+	/// yet, otherwise the bootloader would roll it back.
+	fn restart_if_scheduled(&mut self)
+	{
+		match self.restart_at
+		{
+			Some(restart_at) if Instant::now() >= restart_at => (),
+			_ => return,
+		}
+
+		if let Some(ota) = self.ota.clone()
+		{
+			// The updater is locked while it erases or writes the flash, so the restart waits for it too. The lock is
+			// then kept, so that no update can be installed without saving its rollback floor
+			let Some(ota) = ota.try_lock()
+			else
+			{
+				return;
+			};
+			if !ota.is_running_valid()
+			{
+				return;
+			}
+			// An update installed since the last tick is booted by this restart
+			if let Some(metadata) = ota.installed_image()
+			{
+				self.raise_rollback_floor(metadata.min_rollback_version);
+			}
+		}
+
+		log::info!("Restarting to apply the changes");
+		(self.restart_fn)();
+	}
+
+	/// Starts connecting to a saved network when the [`WifiSupervisor`] says to.
+	fn supervise_wifi(&mut self)
+	{
+		let Some(wifi_supervisor) = self.wifi_supervisor.as_mut()
+		else
+		{
+			return;
+		};
+
+		let is_link_up = self.wifi_driver.is_connected().unwrap_or(false)
+			&& (self.get_ip_address_from_wifi_driver_fn)(&self.wifi_driver).is_some();
+		if let Some(network) = wifi_supervisor.tick(is_link_up, Instant::now())
+		{
+			// If it fails the attempt times out, and the next network is tried
+			if let Err(error) = connect_to_network(&mut self.wifi_driver, network)
+			{
+				log::warn!(
+					"Couldn't start connecting to the WiFi network {}: {:?}",
+					network.ssid,
+					error
+				);
+			}
+		}
+	}
+
+	fn camera_status(&self) -> CameraStatus
+	{
+		CameraStatus {
+			trigger: self.image_trigger.status(),
+			wifi: WifiStatus {
+				state: self
+					.wifi_supervisor
+					.as_ref()
+					.map_or(WifiState::Setup, WifiSupervisor::state),
+				ssid: self
+					.wifi_supervisor
+					.as_ref()
+					.and_then(WifiSupervisor::network)
+					.map(|network| network.ssid.clone()),
+				ip_address: (self.get_ip_address_from_wifi_driver_fn)(&self.wifi_driver),
+				rssi_dbm: (self.get_rssi_from_wifi_driver_fn)(&self.wifi_driver),
+			},
+			tls_certificate_sha256: self.tls_certificate_sha256.clone(),
+		}
+	}
+
+	/// Reads or changes the arming of the trigger as requested by the HTTP server.
+	fn control_arming(&mut self)
+	{
+		while let Some((id, arming)) = self.arming_requests.take_pending()
+		{
+			if let Some(arming) = arming
+			{
+				self.image_trigger.set_arming(arming);
+			}
+			self.arming_requests.fulfill(id, self.camera_status());
+		}
+		self.save_arming();
+	}
+
+	fn save_arming(&mut self)
+	{
+		let arming = self.image_trigger.arming();
+		if arming != self.settings.arming
+		{
+			self.settings.arming = arming;
+			self.save_settings();
+		}
+	}
+
+	/// The settings are still applied if they can't be saved, they just won't survive a reboot.
+	fn save_settings(&mut self)
+	{
+		if let Err(error) = self.settings.save(&mut self.settings_store)
+		{
+			log::error!("Couldn't save the settings: {:?}", error);
+		}
+	}
+
+	/// Captures the images requested by the HTTP server, each one with its own settings.
+	fn capture_requested_snapshots(&mut self) -> Result<(), TickError<C>>
+	{
+		while let Some((id, settings)) = self.snapshot_requests.take_pending()
+		{
+			let frame = self.capture_with_settings(settings);
+			self.snapshot_requests.fulfill(id, frame.as_ref().ok().cloned());
+			frame?;
+		}
+
+		Ok(())
+	}
+
+	fn capture_with_settings(&mut self, settings: CaptureSettings) -> Result<Arc<Frame>, TickError<C>>
+	{
+		if settings.is_empty()
+		{
+			return Ok(self
+				.frame_broker
+				.publish(&self.camera.get_image().map_err(TickError::Camera)?));
+		}
+
+		let previous_settings = self
+			.camera
+			.apply_capture_settings(settings)
+			.map_err(TickError::Camera)?;
+		let frame = self
+			.camera
+			.get_image()
+			.map(|image| self.frame_broker.publish_detached(&image));
+		self.camera
+			.restore_capture_settings(previous_settings)
+			.map_err(TickError::Camera)?;
+
+		frame.map_err(TickError::Camera)
+	}
 }
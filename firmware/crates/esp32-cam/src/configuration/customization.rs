@@ -2,7 +2,19 @@ use core::{ops::RangeInclusive, time::Duration};
 
 use a13c_embedded::peripherals::time::real_time::time::Time;
 use firmware_core::{
-	configuration::customization::Customization as CustomizationTrait, features::trigger::EnableOnConditions,
+	configuration::customization::Customization as CustomizationTrait,
+	features::{
+		connectivity::WifiSupervisorSettings,
+		mqtt::{HomeAssistantSettings, MqttSettings},
+		ota::{parse_hex, OtaSettings},
+		provisioning::ProvisioningSettings,
+		recording::EventRecordingSettings,
+		rtsp::RtspSettings,
+		storage::RetentionPolicy,
+		timelapse::TimelapseSettings,
+		trigger::{EnableOnConditions, PirSettings, TriggerSources},
+		webhooks::WebhookSettings,
+	},
 };
 pub struct Customization;
 
@@ -23,4 +35,124 @@ impl CustomizationTrait for Customization
 	{
 		Duration::from_secs(3 * 60 * 60)
 	}
+
+	fn trigger_cooldown(&self) -> Duration
+	{
+		Duration::from_secs(30)
+	}
+
+	fn pir_sensor(&self) -> PirSettings
+	{
+		PirSettings {
+			debounce: Duration::from_millis(300),
+			hold_time: Duration::from_secs(60),
+		}
+	}
+
+	fn trigger_sources(&self) -> TriggerSources
+	{
+		TriggerSources::Pir
+	}
+
+	fn retention_policy(&self) -> RetentionPolicy
+	{
+		RetentionPolicy {
+			max_used_bytes: None,
+			max_age_days: Some(30),
+			min_free_space_percentage: Some(10),
+		}
+	}
+
+	fn event_recording(&self) -> Option<EventRecordingSettings>
+	{
+		Some(EventRecordingSettings {
+			pre_trigger: Duration::from_secs(3),
+			post_roll: Duration::from_secs(10),
+			max_duration: Duration::from_secs(60),
+			// About 3 seconds of 800x600 frames, leaving room in the 4 MiB of PSRAM for the frame buffers of the camera
+			max_pre_trigger_bytes: 1024 * 1024,
+		})
+	}
+
+	fn timelapse(&self) -> Option<TimelapseSettings<Self::EnableOnConditionsList>>
+	{
+		Some(TimelapseSettings {
+			interval: Duration::from_secs(5 * 60),
+			enable_on: EnableOnConditions::TimeWindows {
+				ranges: vec![Time::from_hms(7, 0, 0).unwrap()..=Time::from_hms(19, 0, 0).unwrap()],
+			},
+			// 10 frames per second, so that a day lasts about 15 seconds
+			daily_video_frame_duration: Some(Duration::from_millis(100)),
+		})
+	}
+
+	fn mqtt(&self) -> Option<MqttSettings>
+	{
+		Some(MqttSettings {
+			base_topic: "esp32-cam".to_string(),
+			stats_period: Duration::from_secs(5 * 60),
+			home_assistant: Some(HomeAssistantSettings {
+				discovery_prefix: "homeassistant".to_string(),
+				device_name: "ESP32-CAM".to_string(),
+			}),
+		})
+	}
+
+	fn webhooks(&self) -> Option<WebhookSettings>
+	{
+		Some(WebhookSettings {
+			// Like "http://192.168.1.2:8123/api/webhook/camera"
+			urls: Vec::new(),
+			include_snapshot: true,
+			// Each delivery with the snapshot keeps a frame in memory
+			max_queued_deliveries: 4,
+			max_attempts: 5,
+			first_retry_delay: Duration::from_secs(5),
+		})
+	}
+
+	fn provisioning(&self) -> ProvisioningSettings
+	{
+		ProvisioningSettings {
+			access_point_ssid_prefix: "ESP32-CAM-setup".to_string(),
+			// Open, so that it can be joined without knowing anything about the camera
+			access_point_password: None,
+			button_long_press: Duration::from_secs(5),
+		}
+	}
+
+	fn wifi_supervisor(&self) -> WifiSupervisorSettings
+	{
+		WifiSupervisorSettings {
+			connect_timeout: Duration::from_secs(15),
+			first_retry_delay: Duration::from_secs(5),
+			max_retry_delay: Duration::from_secs(5 * 60),
+		}
+	}
+
+	fn ota(&self) -> OtaSettings
+	{
+		OtaSettings {
+			check_period: Duration::from_secs(24 * 60 * 60),
+			board: "esp32-cam".to_string(),
+			// The public keys printed by `firmware-signer`, separated by commas. Without any all the updates are refused
+			trusted_public_keys: option_env!("OTA_PUBLIC_KEYS")
+				.unwrap_or_default()
+				.split(',')
+				.filter(|key| !key.is_empty())
+				.filter_map(|key| parse_hex(key.trim()))
+				.collect(),
+			// A few seconds with the camera and the WiFi working
+			ticks_before_valid: 100,
+		}
+	}
+
+	fn rtsp(&self) -> Option<RtspSettings>
+	{
+		Some(RtspSettings {
+			port: 554,
+			// Each client sends the frames from its own thread, which the WiFi can't keep up with beyond a couple
+			max_clients: 2,
+		})
+	}
 }
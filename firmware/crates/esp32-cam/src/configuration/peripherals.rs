@@ -1,13 +1,14 @@
 use core::time::Duration;
-use std::net::IpAddr;
+use std::net::{IpAddr, Ipv4Addr};
 
 use a13c_embedded::{
+	features::storage::embedded_sdmmc::SdCard,
 	hardware::espressif::{
 		features::communication::http_server::HttpServer,
 		peripherals::{delay::Delay, real_time::RealTime, watchdog::WatchdogCreator},
 	},
 	peripherals::time::real_time::time::UtcOffset,
-	utils::physical_quantities::frequency::Frequency,
+	utils::{math::micromath::micromath::vector::U16x2, physical_quantities::frequency::Frequency},
 };
 use enumset::EnumSet;
 use esp_idf_hal::{
@@ -30,61 +31,102 @@ use esp_idf_svc::{
 	http::server::{Configuration, EspHttpServer},
 	nvs::EspDefaultNvsPartition,
 	sntp::*,
-	wifi::{Configuration as WifiConfiguration, *},
+	wifi::*,
 };
 use firmware_core::{
-	configuration::peripherals::Peripherals as PeripheralsTrait,
-	features::http_server::{stream::PossibleHttpRequest as StreamPossibleHttpRequest, PossibleHttpRequest},
+	configuration::peripherals::{
+		camera::PixelFormat as CorePixelFormat, mqtt::MqttLastWill, Peripherals as PeripheralsTrait,
+	},
+	features::{
+		http_server::{stream::PossibleHttpRequest as StreamPossibleHttpRequest, PossibleHttpRequest},
+		settings::{CameraSettings, Settings},
+	},
 };
 
+#[cfg(esp_idf_esp_https_server_enable)]
+use crate::tls::ServerIdentity;
 use crate::{
-	esp32_camera::{Camera, CameraGrabMode, FrameBufferLocation, FrameSize, PixelFormat},
+	esp32_camera::{Camera, CameraGrabMode, FrameBufferLocation, FrameSize},
+	http_client::EspHttpClient,
+	mqtt::EspMqtt,
+	ota::EspOtaFlash,
+	settings_store::NvsSettingsStore,
 	time_source::TimeSource,
+	tls::NvsTlsIdentityStore,
 };
 
-pub const HTTP_SERVER_CONFIG: Configuration = Configuration {
-	http_port: 80,
-	ctrl_port: 32768,
-	https_port: 443,
-	core_id: 1,
-	max_sessions: 2,
-	session_timeout: Duration::from_secs(20 * 60),
-	#[cfg(not(esp_idf_esp_https_server_enable))]
-	stack_size: 6144 + firmware_core::features::http_server::STACK_SIZE,
-	#[cfg(esp_idf_esp_https_server_enable)]
-	stack_size: 10240 + firmware_core::features::http_server::STACK_SIZE,
-	max_open_sockets: 2,
-	max_uri_handlers: firmware_core::features::http_server::http_request_handlers_count(),
-	max_resp_headers: 8,
-	lru_purge_enable: false,
-	uri_match_wildcard: false,
-	#[cfg(esp_idf_esp_https_server_enable)]
-	server_certificate: X509::der(include_bytes!("../../cert.pem")),
-	#[cfg(esp_idf_esp_https_server_enable)]
-	private_key: X509::der(include_bytes!("../../key.pem")),
+/// Used if the camera settings have never been saved.
+const DEFAULT_CAMERA_SETTINGS: CameraSettings = CameraSettings {
+	width: 800,
+	height: 600,
+	pixel_format: CorePixelFormat::Jpeg,
+	jpeg_quality: 100,
 };
+/// Used if the timezone has never been saved.
+const DEFAULT_UTC_OFFSET_SECONDS: i32 = 2 * 60 * 60;
 
-pub const STREAM_HTTP_SERVER_CONFIG: Configuration = Configuration {
-	http_port: 81,
-	ctrl_port: 32769,
-	https_port: 60000,
-	core_id: 1,
-	max_sessions: 1,
-	session_timeout: Duration::from_secs(20 * 60),
-	#[cfg(not(esp_idf_esp_https_server_enable))]
-	stack_size: 6144 + firmware_core::features::http_server::STACK_SIZE,
-	#[cfg(esp_idf_esp_https_server_enable)]
-	stack_size: 10240 + firmware_core::features::http_server::STACK_SIZE,
-	max_open_sockets: 2,
-	max_uri_handlers: firmware_core::features::http_server::stream::http_request_handlers_count(),
-	max_resp_headers: 8,
-	lru_purge_enable: false,
-	uri_match_wildcard: false,
+/// With `CONFIG_ESP_HTTPS_SERVER_ENABLE` the servers serve HTTPS with the identity saved in the `nvs` (generated at the
+/// first boot), otherwise they serve plain HTTP.
+pub fn http_server_configuration(nvs: &EspDefaultNvsPartition) -> Result<Configuration, EspError>
+{
 	#[cfg(esp_idf_esp_https_server_enable)]
-	server_certificate: X509::der(include_bytes!("../../cert.pem")),
+	let identity = ServerIdentity::get_or_load(nvs)?;
+	#[cfg(not(esp_idf_esp_https_server_enable))]
+	let _ = nvs;
+
+	Ok(Configuration {
+		http_port: 80,
+		ctrl_port: 32768,
+		https_port: 443,
+		core_id: 1,
+		max_sessions: 2,
+		session_timeout: Duration::from_secs(20 * 60),
+		#[cfg(not(esp_idf_esp_https_server_enable))]
+		stack_size: 6144 + firmware_core::features::http_server::STACK_SIZE,
+		#[cfg(esp_idf_esp_https_server_enable)]
+		stack_size: 10240 + firmware_core::features::http_server::STACK_SIZE,
+		max_open_sockets: 2,
+		max_uri_handlers: firmware_core::features::http_server::http_request_handlers_count(),
+		max_resp_headers: 8,
+		lru_purge_enable: false,
+		uri_match_wildcard: true,
+		#[cfg(esp_idf_esp_https_server_enable)]
+		server_certificate: X509::pem_until_nul(identity.certificate),
+		#[cfg(esp_idf_esp_https_server_enable)]
+		private_key: X509::pem_until_nul(identity.private_key),
+	})
+}
+
+/// Check [`http_server_configuration`].
+pub fn stream_http_server_configuration(nvs: &EspDefaultNvsPartition) -> Result<Configuration, EspError>
+{
 	#[cfg(esp_idf_esp_https_server_enable)]
-	private_key: X509::der(include_bytes!("../../key.pem")),
-};
+	let identity = ServerIdentity::get_or_load(nvs)?;
+	#[cfg(not(esp_idf_esp_https_server_enable))]
+	let _ = nvs;
+
+	Ok(Configuration {
+		http_port: 81,
+		ctrl_port: 32769,
+		https_port: 60000,
+		core_id: 1,
+		max_sessions: 1,
+		session_timeout: Duration::from_secs(20 * 60),
+		#[cfg(not(esp_idf_esp_https_server_enable))]
+		stack_size: 6144 + firmware_core::features::http_server::STACK_SIZE,
+		#[cfg(esp_idf_esp_https_server_enable)]
+		stack_size: 10240 + firmware_core::features::http_server::STACK_SIZE,
+		max_open_sockets: 2,
+		max_uri_handlers: firmware_core::features::http_server::stream::http_request_handlers_count(),
+		max_resp_headers: 8,
+		lru_purge_enable: false,
+		uri_match_wildcard: false,
+		#[cfg(esp_idf_esp_https_server_enable)]
+		server_certificate: X509::pem_until_nul(identity.certificate),
+		#[cfg(esp_idf_esp_https_server_enable)]
+		private_key: X509::pem_until_nul(identity.private_key),
+	})
+}
 
 impl PeripheralsTrait for Peripherals
 {
@@ -95,17 +137,28 @@ impl PeripheralsTrait for Peripherals
 	type StreamServer = HttpServer<'static, StreamPossibleHttpRequest>;
 	type ServerError = EspIOError;
 
-	type SdCardSpi = SpiSingleDeviceDriver<'static>;
-	type SdCardCS = PinDriver<'static, Gpio13, Output>;
-	type SdCardDelay = Delay;
+	type SdCard = SdCard<SpiSingleDeviceDriver<'static>, PinDriver<'static, Gpio13, Output>, Delay>;
 	type SdCardTimeSource = TimeSource;
 
-	type PirSensorPin = a13c_embedded::hardware::mock::MockInputPin; //PinDriver<'static, Gpio16, Input>;
+	type PirSensorPin = PinDriver<'static, Gpio16, Input>;
+	type FlashLed = PinDriver<'static, Gpio4, Output>;
+	type SetupButtonPin = PinDriver<'static, Gpio12, Input>;
+
+	type SettingsStore = NvsSettingsStore;
 
 	type WatchdogCreator = WatchdogCreator;
 
 	type RealTimeClock = RealTime<Self::WifiDriver>;
 
+	type MqttClient = EspMqtt;
+	type MqttClientError = EspError;
+
+	type HttpClient = EspHttpClient;
+
+	type OtaFlash = EspOtaFlash;
+
+	type TlsIdentityStore = NvsTlsIdentityStore;
+
 	fn take_camera(&mut self) -> Option<Self::Camera>
 	{
 		self.camera.take()
@@ -123,10 +176,38 @@ impl PeripheralsTrait for Peripherals
 				.sta_netif()
 				.get_ip_info()
 				.ok()
+				// The address is 0.0.0.0 until the DHCP server assigns one
+				.filter(|info| !info.ip.is_unspecified())
 				.map(|info| IpAddr::V4(info.ip))
 		}
 	}
 
+	fn get_mac_address_from_wifi_driver_function() -> fn(&Self::WifiDriver) -> Option<[u8; 6]>
+	{
+		|wifi_driver| wifi_driver.sta_netif().get_mac().ok()
+	}
+
+	fn get_rssi_from_wifi_driver_function() -> fn(&Self::WifiDriver) -> Option<i8>
+	{
+		|_| {
+			let mut access_point_info = esp_idf_sys::wifi_ap_record_t::default();
+			// It fails if the station isn't connected to an access point
+			esp_idf_sys::esp!(unsafe { esp_idf_sys::esp_wifi_sta_get_ap_info(&mut access_point_info) })
+				.ok()
+				.map(|()| access_point_info.rssi)
+		}
+	}
+
+	fn get_access_point_ip_address_from_wifi_driver_function() -> fn(&Self::WifiDriver) -> Option<Ipv4Addr>
+	{
+		|wifi_driver| wifi_driver.ap_netif().get_ip_info().ok().map(|info| info.ip)
+	}
+
+	fn get_restart_function() -> fn() -> !
+	{
+		esp_idf_hal::reset::restart
+	}
+
 	fn take_http_server(&mut self) -> Option<Box<dyn FnOnce() -> Result<Self::Server, Self::ServerError>>>
 	{
 		self.http_server.take()
@@ -138,29 +219,34 @@ impl PeripheralsTrait for Peripherals
 		self.stream_http_server.take()
 	}
 
-	fn take_sd_card_spi(&mut self) -> Option<Self::SdCardSpi>
+	fn take_sd_card(&mut self) -> Option<Self::SdCard>
 	{
-		self.sd_card_spi.take()
+		self.sd_card.take()
 	}
 
-	fn take_sd_card_cs(&mut self) -> Option<Self::SdCardCS>
+	fn take_sd_card_time_source(&mut self) -> Option<Self::SdCardTimeSource>
 	{
-		self.sd_card_cs.take()
+		self.sd_card_time_source.take()
 	}
 
-	fn take_sd_card_delay(&mut self) -> Option<Self::SdCardDelay>
+	fn take_pir_sensor_pin(&mut self) -> Option<Self::PirSensorPin>
 	{
-		self.sd_card_delay.take()
+		self.pir_sensor_pin.take()
 	}
 
-	fn take_sd_card_time_source(&mut self) -> Option<Self::SdCardTimeSource>
+	fn take_flash_led(&mut self) -> Option<Self::FlashLed>
 	{
-		self.sd_card_time_source.take()
+		self.flash_led.take()
 	}
 
-	fn take_pir_sensor_pin(&mut self) -> Option<Self::PirSensorPin>
+	fn take_setup_button_pin(&mut self) -> Option<Self::SetupButtonPin>
 	{
-		self.pir_sensor_pin.take()
+		self.setup_button_pin.take()
+	}
+
+	fn take_settings_store(&mut self) -> Option<Self::SettingsStore>
+	{
+		self.settings_store.take()
 	}
 
 	fn take_watchdog_creator(&mut self) -> Option<Self::WatchdogCreator>
@@ -172,6 +258,28 @@ impl PeripheralsTrait for Peripherals
 	{
 		self.real_time_clock.take()
 	}
+
+	fn take_mqtt_client(
+		&mut self,
+	) -> Option<Box<dyn FnOnce(MqttLastWill) -> Result<Self::MqttClient, Self::MqttClientError>>>
+	{
+		self.mqtt_client.take()
+	}
+
+	fn take_http_client(&mut self) -> Option<Self::HttpClient>
+	{
+		Some(EspHttpClient)
+	}
+
+	fn take_ota_flash(&mut self) -> Option<Self::OtaFlash>
+	{
+		self.ota_flash.take()
+	}
+
+	fn take_tls_identity_store(&mut self) -> Option<Self::TlsIdentityStore>
+	{
+		self.tls_identity_store.take()
+	}
 }
 
 pub const SD_CARD_SPI_DRIVER_CONFIG: DriverConfig = DriverConfig {
@@ -202,13 +310,24 @@ pub struct Peripherals
 	stream_http_server: Option<
 		Box<dyn FnOnce() -> Result<<Self as PeripheralsTrait>::StreamServer, <Self as PeripheralsTrait>::ServerError>>,
 	>,
-	sd_card_spi: Option<<Self as PeripheralsTrait>::SdCardSpi>,
-	sd_card_cs: Option<<Self as PeripheralsTrait>::SdCardCS>,
-	sd_card_delay: Option<<Self as PeripheralsTrait>::SdCardDelay>,
+	sd_card: Option<<Self as PeripheralsTrait>::SdCard>,
 	sd_card_time_source: Option<<Self as PeripheralsTrait>::SdCardTimeSource>,
 	pir_sensor_pin: Option<<Self as PeripheralsTrait>::PirSensorPin>,
+	flash_led: Option<<Self as PeripheralsTrait>::FlashLed>,
+	setup_button_pin: Option<<Self as PeripheralsTrait>::SetupButtonPin>,
+	settings_store: Option<<Self as PeripheralsTrait>::SettingsStore>,
 	watchdog_creator: <Self as PeripheralsTrait>::WatchdogCreator,
 	real_time_clock: Option<<Self as PeripheralsTrait>::RealTimeClock>,
+	mqtt_client: Option<
+		Box<
+			dyn FnOnce(
+				MqttLastWill,
+			)
+				-> Result<<Self as PeripheralsTrait>::MqttClient, <Self as PeripheralsTrait>::MqttClientError>,
+		>,
+	>,
+	ota_flash: Option<<Self as PeripheralsTrait>::OtaFlash>,
+	tls_identity_store: Option<<Self as PeripheralsTrait>::TlsIdentityStore>,
 }
 
 impl Peripherals
@@ -218,16 +337,13 @@ impl Peripherals
 		let sys_loop = EspSystemEventLoop::take()?;
 		let nvs = EspDefaultNvsPartition::take()?;
 
-		let mut wifi_driver = EspWifi::wrap(WifiDriver::new(peripherals.modem, sys_loop, Some(nvs))?)?;
-		wifi_driver.set_configuration(&WifiConfiguration::Client(ClientConfiguration {
-			ssid: env!("WIFI_SSID").try_into().unwrap(),
-			bssid: None,
-			auth_method: AuthMethod::WPA2Personal,
-			password: env!("WIFI_PASSWORD").try_into().unwrap(),
-			channel: None,
-		}))?;
-		wifi_driver.start()?;
-		wifi_driver.connect()?;
+		// The settings that must be known before creating the peripherals are applied here, the others by the core
+		let mut settings_store = NvsSettingsStore::new(nvs.clone())?;
+		let settings = Settings::load_or_default(&mut settings_store);
+		let camera_settings = settings.camera.unwrap_or(DEFAULT_CAMERA_SETTINGS);
+
+		// It's configured by the core, depending on whether a network has been saved
+		let wifi_driver = EspWifi::wrap(WifiDriver::new(peripherals.modem, sys_loop, Some(nvs.clone()))?)?;
 
 		let i2c = Box::leak(Box::new(I2cDriver::new(
 			peripherals.i2c0,
@@ -236,7 +352,18 @@ impl Peripherals
 			&Camera::I2C_CONFIGURATION,
 		)?));
 
-		let utc_offset = UtcOffset::from_hms(2, 0, 0).unwrap();
+		// The output of the PIR sensor is push-pull, the pull-down only keeps the pin low if the sensor is disconnected
+		let mut pir_sensor_pin = PinDriver::input(peripherals.pins.gpio16)?;
+		pir_sensor_pin.set_pull(Pull::Down)?;
+
+		// The button connects the pin to ground. The GPIO12 is a strapping pin that selects the flash voltage, so it
+		// mustn't have an external pull-up and the button mustn't be held while the board resets
+		let mut setup_button_pin = PinDriver::input(peripherals.pins.gpio12)?;
+		setup_button_pin.set_pull(Pull::Up)?;
+
+		let utc_offset =
+			UtcOffset::from_whole_seconds(settings.utc_offset_seconds.unwrap_or(DEFAULT_UTC_OFFSET_SECONDS))
+				.unwrap_or(UtcOffset::UTC);
 
 		Ok(Self {
 			camera: Some(Camera::new(
@@ -258,35 +385,53 @@ impl Peripherals
 				esp_idf_hal::ledc::TIMER0::timer(),
 				esp_idf_hal::ledc::CHANNEL0::channel(),
 				Frequency::from_megahertz(10),
-				PixelFormat::JPEG,
-				FrameSize::SVGA,
+				camera_settings.pixel_format.into(),
+				FrameSize::closest_to(U16x2 {
+					x: camera_settings.width,
+					y: camera_settings.height,
+				}),
+				camera_settings.jpeg_quality,
 				CameraGrabMode::WhenEmpty,
 				FrameBufferLocation::PSRAM,
 			)?),
 			wifi_driver: Some(wifi_driver),
-			http_server: Some(Box::new(move || {
-				Ok(HttpServer::new(EspHttpServer::new(&HTTP_SERVER_CONFIG)?))
+			http_server: Some(Box::new({
+				let nvs = nvs.clone();
+				move || {
+					let configuration = http_server_configuration(&nvs)?;
+					Ok(HttpServer::new(EspHttpServer::new(&configuration)?))
+				}
 			})),
-			stream_http_server: Some(Box::new(move || {
-				Ok(HttpServer::new(EspHttpServer::new(&STREAM_HTTP_SERVER_CONFIG)?))
+			stream_http_server: Some(Box::new({
+				let nvs = nvs.clone();
+				move || {
+					let configuration = stream_http_server_configuration(&nvs)?;
+					Ok(HttpServer::new(EspHttpServer::new(&configuration)?))
+				}
 			})),
-			sd_card_spi: Some(SpiSingleDeviceDriver::new_single(
-				peripherals.spi2,
-				peripherals.pins.gpio14,
-				peripherals.pins.gpio15,
-				Some(peripherals.pins.gpio2),
-				None as Option<AnyOutputPin>,
-				&SD_CARD_SPI_DRIVER_CONFIG,
-				&SD_CARD_SPI_CONFIG,
-			)?),
-			sd_card_cs: Some(PinDriver::output(peripherals.pins.gpio13)?),
-			sd_card_delay: Some(Delay),
+			sd_card: Some(SdCard::new(
+				SpiSingleDeviceDriver::new_single(
+					peripherals.spi2,
+					peripherals.pins.gpio14,
+					peripherals.pins.gpio15,
+					Some(peripherals.pins.gpio2),
+					None as Option<AnyOutputPin>,
+					&SD_CARD_SPI_DRIVER_CONFIG,
+					&SD_CARD_SPI_CONFIG,
+				)?,
+				PinDriver::output(peripherals.pins.gpio13)?,
+				Delay,
+			)),
 			sd_card_time_source: Some(TimeSource(RealTime::new(
 				utc_offset.clone(),
 				None,
 				Some(EspSntp::new(&SntpConf { ..Default::default() })?),
 			))),
-			pir_sensor_pin: Some(a13c_embedded::hardware::mock::MockInputPin::Ok { is_high: true }), // PinDriver::input(peripherals.pins.gpio16)?),
+			pir_sensor_pin: Some(pir_sensor_pin),
+			// The SD card is used in SPI mode, so the GPIO4 isn't its data line 1
+			flash_led: Some(PinDriver::output(peripherals.pins.gpio4)?),
+			setup_button_pin: Some(setup_button_pin),
+			settings_store: Some(settings_store),
 			watchdog_creator: WatchdogCreator(TWDTDriver::new(
 				peripherals.twdt,
 				&TWDTConfig {
@@ -296,6 +441,22 @@ impl Peripherals
 				},
 			)?),
 			real_time_clock: Some(RealTime::new(utc_offset, None, None)),
+			// MQTT is enabled only once a broker has been saved with `POST /mqtt/broker`
+			mqtt_client: settings.mqtt_broker.map(|broker| {
+				Box::new(move |last_will| {
+					EspMqtt::new(
+						&broker.url,
+						broker.username.as_deref(),
+						broker.password.as_deref(),
+						last_will,
+					)
+				}) as Box<dyn FnOnce(_) -> _>
+			}),
+			ota_flash: Some(EspOtaFlash::new()),
+			// The certificate can only be changed if the servers serve HTTPS
+			tls_identity_store: cfg!(esp_idf_esp_https_server_enable)
+				.then(|| NvsTlsIdentityStore::new(nvs))
+				.transpose()?,
 		})
 	}
 }
@@ -68,4 +68,56 @@ impl<'a> Image for FrameBuffer<'a>
 		let timestamp = self.timestamp();
 		Duration::new(timestamp.tv_sec as u64, timestamp.tv_usec as u32 * 1_000)
 	}
+
+	fn to_grayscale(&self) -> Option<GrayscaleImage>
+	{
+		match self.format()
+		{
+			PixelFormat::GRAYSCALE => Some(GrayscaleImage {
+				size: self.get_size(),
+				pixels: self.data().to_vec(),
+			}),
+			PixelFormat::JPEG =>
+			{
+				// The decoder can scale the image by 8 while decoding it, which is much faster than decoding it all
+				const SCALE_FACTOR: usize = 8;
+
+				let width = self.width() / SCALE_FACTOR;
+				let height = self.height() / SCALE_FACTOR;
+				let mut rgb565 = vec![0; width * height * 2];
+				let data = self.data();
+				let decoded = unsafe {
+					camera::jpg2rgb565(
+						data.as_ptr(),
+						data.len(),
+						rgb565.as_mut_ptr(),
+						camera::jpg_scale_t_JPG_SCALE_8X,
+					)
+				};
+				if !decoded
+				{
+					return None;
+				}
+
+				Some(GrayscaleImage {
+					size: U16x2 {
+						x: width as u16,
+						y: height as u16,
+					},
+					// The decoder writes the pixels in big endian
+					pixels: rgb565
+						.chunks_exact(2)
+						.map(|pixel| {
+							let pixel = u16::from_be_bytes([pixel[0], pixel[1]]) as u32;
+							let red = (pixel >> 11) << 3;
+							let green = ((pixel >> 5) & 0x3F) << 2;
+							let blue = (pixel & 0x1F) << 3;
+							((red * 77 + green * 150 + blue * 29) >> 8) as u8
+						})
+						.collect(),
+				})
+			},
+			_ => None,
+		}
+	}
 }
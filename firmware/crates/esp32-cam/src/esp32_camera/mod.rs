@@ -4,19 +4,24 @@ mod frame_buffer;
 mod sensor;
 mod settings;
 
-use std::marker::PhantomData;
+use std::{cell::Cell, marker::PhantomData};
 
 use a13c_embedded::utils::{math::micromath::micromath::vector::U16x2, physical_quantities::frequency::Frequency};
 use camera::{camera_config_t__bindgen_ty_1, camera_config_t__bindgen_ty_2};
 use esp_idf_hal::{gpio::*, i2c::I2cDriver, peripheral::Peripheral};
 use esp_idf_sys::*;
-use firmware_core::configuration::peripherals::camera::{Camera as CameraTrait, Image};
+use firmware_core::configuration::peripherals::camera::{
+	Camera as CameraTrait, CaptureSettings, GrayscaleImage, Image, SensorSettings,
+};
 pub use frame_buffer::FrameBuffer;
 pub use sensor::*;
 pub use settings::*;
 
 pub struct Camera<'a>
 {
+	frame_size: Cell<FrameSize>,
+	/// From 0 (best image) to 63 (smallest file).
+	jpeg_quality: Cell<i32>,
 	_p: PhantomData<&'a ()>,
 }
 
@@ -29,6 +34,10 @@ impl<'a> Camera<'a>
 		timeout: None,
 		intr_flags: enumset::EnumSet::EMPTY,
 	};
+	const FRAME_BUFFERS_COUNT: usize = 2;
+	/// Lower values can make the JPEG images bigger than the frame buffers.
+	const BEST_JPEG_QUALITY: i32 = 10;
+	const WORST_JPEG_QUALITY: i32 = 63;
 
 	pub fn new(
 		pin_pwdn: impl Peripheral<P = impl InputPin + OutputPin> + 'a,
@@ -44,10 +53,12 @@ impl<'a> Camera<'a>
 		pin_href: impl Peripheral<P = impl InputPin + OutputPin> + 'a,
 		pin_pclk: impl Peripheral<P = impl InputPin + OutputPin> + 'a, i2c_driver: &I2cDriver<'a>,
 		ledc_timer: esp_idf_sys::ledc_timer_t, ledc_channel: esp_idf_sys::ledc_channel_t, xclk_frequency: Frequency,
-		pixel_format: PixelFormat, frame_size: FrameSize, camera_grab_mode: CameraGrabMode,
+		pixel_format: PixelFormat, frame_size: FrameSize, jpeg_quality: u8, camera_grab_mode: CameraGrabMode,
 		frame_buffer_location: FrameBufferLocation,
 	) -> Result<Self, esp_idf_sys::EspError>
 	{
+		let jpeg_quality = Self::jpeg_quality_from_percentage(jpeg_quality);
+
 		esp_idf_hal::into_ref!(
 			pin_pwdn, pin_xclk, pin_d0, pin_d1, pin_d2, pin_d3, pin_d4, pin_d5, pin_d6, pin_d7, pin_vsync, pin_href,
 			pin_pclk
@@ -82,8 +93,8 @@ impl<'a> Camera<'a>
 			pixel_format: pixel_format.into(),
 			frame_size: frame_size.into(),
 
-			jpeg_quality: 10,
-			fb_count: 2,
+			jpeg_quality,
+			fb_count: Self::FRAME_BUFFERS_COUNT,
 			grab_mode: camera_grab_mode.into(),
 
 			fb_location: frame_buffer_location.into(),
@@ -91,33 +102,49 @@ impl<'a> Camera<'a>
 		};
 
 		esp_idf_sys::esp!(unsafe { camera::esp_camera_init(&config) })?;
-		let self_ = Self { _p: PhantomData };
+		let self_ = Self {
+			frame_size: Cell::new(frame_size),
+			jpeg_quality: Cell::new(jpeg_quality),
+			_p: PhantomData,
+		};
 
 		let sensor = self_.get_sensor();
-		sensor.set_brightness(0)?;
-		sensor.set_contrast(0)?;
-		sensor.set_saturation(0)?;
-		sensor.set_special_effect(0)?;
-		sensor.set_whitebal(true)?;
-		sensor.set_awb_gain(true)?;
-		sensor.set_wb_mode(0)?; // 0 to 4 - if awb_gain enabled (0 - Auto, 1 - Sunny, 2 - Cloudy, 3 - Office, 4 - Home)
-		sensor.set_exposure_ctrl(true)?;
-		sensor.set_aec2(false)?;
-		sensor.set_gain_ctrl(false)?;
-		sensor.set_agc_gain(3)?; // 0 to 30
-		sensor.set_gainceiling(6)?; // 0 to 6
-		sensor.set_bpc(false)?;
-		sensor.set_wpc(true)?;
-		sensor.set_raw_gma(false)?; // If enabled (makes much lighter and noisy)
-		sensor.set_lenc(true)?;
-		sensor.set_hmirror(false)?;
-		sensor.set_vflip(false)?;
-		sensor.set_dcw(true)?;
-		sensor.set_colorbar(false)?;
+		sensor.write_settings(&SensorSettings::default())?;
 
 		Ok(self_)
 	}
 
+	/// Converts a quality from 0 (smallest file) to 100 (best image) to the one of the driver.
+	fn jpeg_quality_from_percentage(percentage: u8) -> i32
+	{
+		Self::WORST_JPEG_QUALITY
+			- percentage.min(100) as i32 * (Self::WORST_JPEG_QUALITY - Self::BEST_JPEG_QUALITY) / 100
+	}
+
+	fn set_capture_settings(&self, frame_size: Option<FrameSize>, jpeg_quality: Option<i32>)
+		-> Result<(), CameraError>
+	{
+		let sensor = self.get_sensor();
+		if let Some(frame_size) = frame_size
+		{
+			sensor.set_framesize(frame_size.into()).map_err(CameraError::Sensor)?;
+			self.frame_size.set(frame_size);
+		}
+		if let Some(jpeg_quality) = jpeg_quality
+		{
+			sensor.set_quality(jpeg_quality).map_err(CameraError::Sensor)?;
+			self.jpeg_quality.set(jpeg_quality);
+		}
+
+		// The frame buffers that are already full were captured with the previous settings
+		for _ in 0..Self::FRAME_BUFFERS_COUNT
+		{
+			drop(self.get_framebuffer());
+		}
+
+		Ok(())
+	}
+
 	pub fn get_framebuffer(&self) -> Option<FrameBuffer>
 	{
 		let fb = unsafe { camera::esp_camera_fb_get() };
@@ -152,6 +179,8 @@ impl<'a> CameraTrait for Camera<'a>
 {
 	type Image<'b> = FrameBuffer<'b> where Self: 'b;
 	type Error = CameraError;
+	type Sensor<'b> = CameraSensor<'b> where Self: 'b;
+	type PreviousCaptureSettings = PreviousCaptureSettings;
 
 	fn get_image<'b>(&'b self) -> Result<Self::Image<'b>, Self::Error>
 	{
@@ -159,10 +188,44 @@ impl<'a> CameraTrait for Camera<'a>
 
 		Ok(framebuffer)
 	}
+
+	fn apply_capture_settings(&self, settings: CaptureSettings) -> Result<PreviousCaptureSettings, Self::Error>
+	{
+		let previous_settings = PreviousCaptureSettings {
+			frame_size: Some(self.frame_size.get()),
+			jpeg_quality: Some(self.jpeg_quality.get()),
+		};
+
+		self.set_capture_settings(
+			settings.resolution.map(FrameSize::closest_to),
+			settings.quality.map(Self::jpeg_quality_from_percentage),
+		)?;
+
+		Ok(previous_settings)
+	}
+
+	fn restore_capture_settings(&self, previous_settings: PreviousCaptureSettings) -> Result<(), Self::Error>
+	{
+		self.set_capture_settings(previous_settings.frame_size, previous_settings.jpeg_quality)
+	}
+
+	fn get_sensor<'b>(&'b self) -> Self::Sensor<'b>
+	{
+		Camera::get_sensor(self)
+	}
+}
+
+/// The settings replaced for a capture, in the units of the driver.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PreviousCaptureSettings
+{
+	frame_size: Option<FrameSize>,
+	jpeg_quality: Option<i32>,
 }
 
 #[derive(Debug)]
 pub enum CameraError
 {
 	NoFrameBuffer,
+	Sensor(EspError),
 }
@@ -3,6 +3,9 @@
 use std::marker::PhantomData;
 
 use esp_idf_sys::*;
+use firmware_core::configuration::peripherals::camera::{SensorControl, SensorSettings};
+
+use super::CameraError;
 
 pub struct CameraSensor<'a>
 {
@@ -12,6 +15,62 @@ pub struct CameraSensor<'a>
 
 impl<'a> CameraSensor<'a>
 {
+	/// Returns the settings that were last applied to the sensor.
+	pub fn read_settings(&self) -> SensorSettings
+	{
+		let status = unsafe { (*self.sensor).status };
+		SensorSettings {
+			brightness: status.brightness,
+			contrast: status.contrast,
+			saturation: status.saturation,
+			special_effect: status.special_effect,
+			white_balance: status.awb != 0,
+			white_balance_gain: status.awb_gain != 0,
+			white_balance_mode: status.wb_mode,
+			exposure_control: status.aec != 0,
+			aec2: status.aec2 != 0,
+			ae_level: status.ae_level,
+			aec_value: status.aec_value,
+			gain_control: status.agc != 0,
+			agc_gain: status.agc_gain,
+			gain_ceiling: status.gainceiling,
+			black_pixel_correction: status.bpc != 0,
+			white_pixel_correction: status.wpc != 0,
+			raw_gamma: status.raw_gma != 0,
+			lens_correction: status.lenc != 0,
+			horizontal_mirror: status.hmirror != 0,
+			vertical_flip: status.vflip != 0,
+			downsize: status.dcw != 0,
+			color_bar: status.colorbar != 0,
+		}
+	}
+
+	pub fn write_settings(&self, settings: &SensorSettings) -> Result<(), EspError>
+	{
+		self.set_brightness(settings.brightness as i32)?;
+		self.set_contrast(settings.contrast as i32)?;
+		self.set_saturation(settings.saturation as i32)?;
+		self.set_special_effect(settings.special_effect as i32)?;
+		self.set_whitebal(settings.white_balance)?;
+		self.set_awb_gain(settings.white_balance_gain)?;
+		self.set_wb_mode(settings.white_balance_mode as i32)?;
+		self.set_exposure_ctrl(settings.exposure_control)?;
+		self.set_aec2(settings.aec2)?;
+		self.set_ae_level(settings.ae_level as i32)?;
+		self.set_aec_value(settings.aec_value as i32)?;
+		self.set_gain_ctrl(settings.gain_control)?;
+		self.set_agc_gain(settings.agc_gain as i32)?;
+		self.set_gainceiling(settings.gain_ceiling as camera::gainceiling_t)?;
+		self.set_bpc(settings.black_pixel_correction)?;
+		self.set_wpc(settings.white_pixel_correction)?;
+		self.set_raw_gma(settings.raw_gamma)?;
+		self.set_lenc(settings.lens_correction)?;
+		self.set_hmirror(settings.horizontal_mirror)?;
+		self.set_vflip(settings.vertical_flip)?;
+		self.set_dcw(settings.downsize)?;
+		self.set_colorbar(settings.color_bar)
+	}
+
 	pub fn init_status(&self) -> Result<(), EspError>
 	{
 		esp!(unsafe { (*self.sensor).init_status.unwrap()(self.sensor) })
@@ -170,3 +229,18 @@ impl<'a> CameraSensor<'a>
 		esp!(unsafe { (*self.sensor).set_xclk.unwrap()(self.sensor, timer, xclk) })
 	}
 }
+
+impl<'a> SensorControl for CameraSensor<'a>
+{
+	type Error = CameraError;
+
+	fn get_settings(&self) -> Result<SensorSettings, Self::Error>
+	{
+		Ok(self.read_settings())
+	}
+
+	fn set_settings(&self, settings: &SensorSettings) -> Result<(), Self::Error>
+	{
+		self.write_settings(settings).map_err(CameraError::Sensor)
+	}
+}
@@ -1,4 +1,5 @@
 #![allow(unused)]
+use a13c_embedded::utils::math::micromath::micromath::vector::U16x2;
 use esp_idf_sys::camera;
 
 #[repr(C)]
@@ -24,6 +25,27 @@ pub enum PixelFormat
 	RGB555,
 }
 
+impl From<firmware_core::configuration::peripherals::camera::PixelFormat> for PixelFormat
+{
+	fn from(value: firmware_core::configuration::peripherals::camera::PixelFormat) -> Self
+	{
+		use firmware_core::configuration::peripherals::camera::PixelFormat as CorePixelFormat;
+
+		match value
+		{
+			CorePixelFormat::Rgb565 => PixelFormat::RGB565,
+			CorePixelFormat::Yuv422 => PixelFormat::YUV422,
+			CorePixelFormat::Yuv420 => PixelFormat::YUV420,
+			CorePixelFormat::Grayscale => PixelFormat::GRAYSCALE,
+			CorePixelFormat::Jpeg => PixelFormat::JPEG,
+			CorePixelFormat::Rgb888 => PixelFormat::RGB888,
+			CorePixelFormat::Raw => PixelFormat::RAW,
+			CorePixelFormat::Rgb444 => PixelFormat::RGB444,
+			CorePixelFormat::Rgb555 => PixelFormat::RGB555,
+		}
+	}
+}
+
 impl Into<camera::pixformat_t> for PixelFormat
 {
 	fn into(self) -> camera::pixformat_t
@@ -65,6 +87,7 @@ impl From<camera::pixformat_t> for PixelFormat
 
 #[allow(non_camel_case_types)]
 #[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum FrameSize
 {
 	/// 96x96
@@ -153,6 +176,74 @@ impl Into<camera::framesize_t> for FrameSize
 	}
 }
 
+impl FrameSize
+{
+	/// The frame sizes supported by the OV2640 sensor of the ESP32-CAM, from the smallest.
+	pub const OV2640_FRAME_SIZES: [FrameSize; 14] = [
+		FrameSize::Size96X96,
+		FrameSize::QQVGA,
+		FrameSize::QCIF,
+		FrameSize::HQVGA,
+		FrameSize::Size240X240,
+		FrameSize::QVGA,
+		FrameSize::CIF,
+		FrameSize::HVGA,
+		FrameSize::VGA,
+		FrameSize::SVGA,
+		FrameSize::XGA,
+		FrameSize::HD,
+		FrameSize::SXGA,
+		FrameSize::UXGA,
+	];
+
+	pub fn resolution(&self) -> U16x2
+	{
+		let (x, y) = match self
+		{
+			FrameSize::Size96X96 => (96, 96),
+			FrameSize::QQVGA => (160, 120),
+			FrameSize::QCIF => (176, 144),
+			FrameSize::HQVGA => (240, 176),
+			FrameSize::Size240X240 => (240, 240),
+			FrameSize::QVGA => (320, 240),
+			FrameSize::CIF => (400, 296),
+			FrameSize::HVGA => (480, 320),
+			FrameSize::VGA => (640, 480),
+			FrameSize::SVGA => (800, 600),
+			FrameSize::XGA => (1024, 768),
+			FrameSize::HD => (1280, 720),
+			FrameSize::SXGA => (1280, 1024),
+			FrameSize::UXGA => (1600, 1200),
+			FrameSize::FHD => (1920, 1080),
+			FrameSize::P_HD => (720, 1280),
+			FrameSize::P_3MP => (864, 1536),
+			FrameSize::QXGA => (2048, 1536),
+			FrameSize::QHD => (2560, 1440),
+			FrameSize::WQXGA => (2560, 1600),
+			FrameSize::P_FHD => (1080, 1920),
+			FrameSize::QSXGA => (2560, 1920),
+			FrameSize::INVALID => (0, 0),
+		};
+
+		U16x2 { x, y }
+	}
+
+	/// Returns the biggest frame size supported by the OV2640 that fits in `resolution`, or the smallest one if none
+	/// of them fits.
+	pub fn closest_to(resolution: U16x2) -> Self
+	{
+		Self::OV2640_FRAME_SIZES
+			.iter()
+			.rev()
+			.find(|frame_size| {
+				let frame_size_resolution = frame_size.resolution();
+				frame_size_resolution.x <= resolution.x && frame_size_resolution.y <= resolution.y
+			})
+			.copied()
+			.unwrap_or(Self::OV2640_FRAME_SIZES[0])
+	}
+}
+
 /// Configuration structure for camera initialization
 pub enum CameraGrabMode
 {
@@ -0,0 +1,69 @@
+use core::time::Duration;
+
+use embedded_svc::{
+	http::Method,
+	io::{Read, Write},
+};
+use esp_idf_svc::{
+	http::client::{Configuration, EspHttpConnection},
+	io::EspIOError,
+};
+use firmware_core::configuration::peripherals::http_client::HttpClient;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+/// The body of a GET response is read in chunks of this size.
+const CHUNK_SIZE: usize = 4 * 1024;
+
+/// The HTTP client of ESP-IDF, with a new connection for each request (so that each one can go to a different
+/// server). HTTPS servers are verified with the certificate bundle of ESP-IDF.
+pub struct EspHttpClient;
+
+impl HttpClient for EspHttpClient
+{
+	type Error = EspIOError;
+
+	fn post(&mut self, url: &str, content_type: &str, body: &[u8]) -> Result<u16, Self::Error>
+	{
+		let mut connection = connection()?;
+
+		let content_length = body.len().to_string();
+		connection.initiate_request(
+			Method::Post,
+			url,
+			&[("Content-Type", content_type), ("Content-Length", &content_length)],
+		)?;
+		connection.write_all(body)?;
+		connection.initiate_response()?;
+
+		Ok(connection.status())
+	}
+
+	fn get(&mut self, url: &str, on_body_chunk: &mut dyn FnMut(&[u8]) -> bool) -> Result<u16, Self::Error>
+	{
+		let mut connection = connection()?;
+		connection.initiate_request(Method::Get, url, &[])?;
+		connection.initiate_response()?;
+
+		let mut chunk = vec![0; CHUNK_SIZE];
+		loop
+		{
+			match connection.read(&mut chunk)?
+			{
+				0 => break,
+				read_bytes if !on_body_chunk(&chunk[..read_bytes]) => break,
+				_ => (),
+			}
+		}
+
+		Ok(connection.status())
+	}
+}
+
+fn connection() -> Result<EspHttpConnection, EspIOError>
+{
+	Ok(EspHttpConnection::new(&Configuration {
+		timeout: Some(TIMEOUT),
+		crt_bundle_attach: Some(esp_idf_sys::esp_crt_bundle_attach),
+		..Default::default()
+	})?)
+}
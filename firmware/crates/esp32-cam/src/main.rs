@@ -1,6 +1,11 @@
 mod configuration;
 mod esp32_camera;
+mod http_client;
+mod mqtt;
+mod ota;
+mod settings_store;
 mod time_source;
+mod tls;
 
 use configuration::{Configuration, Peripherals};
 use esp_idf_hal::peripherals::Peripherals as EspPeripherals;
@@ -25,6 +30,10 @@ fn main()
 			match error
 			{
 				firmware_core::errors::TickError::Camera(_) => (),
+				firmware_core::errors::TickError::Storage(error) =>
+				{
+					log::error!("Couldn't store the image: {:?}", error)
+				},
 				_ => panic!(""),
 			}
 		}
@@ -0,0 +1,95 @@
+use std::{
+	collections::VecDeque,
+	sync::{Arc, Mutex},
+};
+
+use esp_idf_svc::mqtt::client::{Details, EspMqttClient, EventPayload, LwtConfiguration, MqttClientConfiguration, QoS};
+use esp_idf_sys::EspError;
+use firmware_core::configuration::peripherals::mqtt::{MqttClient, MqttEvent, MqttLastWill};
+
+/// The events received while the main loop is busy are dropped after this many, oldest first.
+const MAX_QUEUED_EVENTS: usize = 16;
+
+/// The MQTT client of ESP-IDF, which connects and reconnects by itself in its own task.
+pub struct EspMqtt
+{
+	client: EspMqttClient<'static>,
+	events: Arc<Mutex<VecDeque<MqttEvent>>>,
+}
+
+impl EspMqtt
+{
+	pub fn new(
+		broker_url: &str, username: Option<&str>, password: Option<&str>, last_will: MqttLastWill,
+	) -> Result<Self, EspError>
+	{
+		let events = Arc::new(Mutex::new(VecDeque::new()));
+		let client = EspMqttClient::new_cb(
+			broker_url,
+			&MqttClientConfiguration {
+				username,
+				password,
+				lwt: Some(LwtConfiguration {
+					topic: &last_will.topic,
+					payload: &last_will.payload,
+					qos: QoS::AtLeastOnce,
+					retain: true,
+				}),
+				..Default::default()
+			},
+			{
+				let events = Arc::clone(&events);
+				move |event| {
+					let event = match event.payload()
+					{
+						EventPayload::Connected(_) => MqttEvent::Connected,
+						EventPayload::Disconnected => MqttEvent::Disconnected,
+						// The messages split in more events are bigger than the commands can be, so they are ignored
+						EventPayload::Received {
+							topic: Some(topic),
+							data,
+							details: Details::Complete,
+							..
+						} => MqttEvent::Message {
+							topic: topic.to_string(),
+							payload: data.to_vec(),
+						},
+						_ => return,
+					};
+
+					let mut events = events.lock().unwrap();
+					if events.len() == MAX_QUEUED_EVENTS
+					{
+						events.pop_front();
+					}
+					events.push_back(event);
+				}
+			},
+		)?;
+
+		Ok(Self { client, events })
+	}
+}
+
+impl MqttClient for EspMqtt
+{
+	type Error = EspError;
+
+	fn publish(&mut self, topic: &str, payload: &[u8], retain: bool) -> Result<(), Self::Error>
+	{
+		// Enqueued instead of published, so that it doesn't block until the broker receives it
+		self.client
+			.enqueue(topic, QoS::AtLeastOnce, retain, payload)
+			.map(|_| ())
+	}
+
+	fn subscribe(&mut self, topic: &str) -> Result<(), Self::Error>
+	{
+		self.client.subscribe(topic, QoS::AtLeastOnce).map(|_| ())
+	}
+
+	fn poll_event(&mut self) -> Option<MqttEvent>
+	{
+		self.events.lock().unwrap().pop_front()
+	}
+}
@@ -0,0 +1,104 @@
+use esp_idf_sys::{esp, esp_ota_handle_t, esp_partition_t, EspError};
+use firmware_core::configuration::peripherals::ota::OtaFlash;
+
+/// The OTA partitions of the flash (check `partitions.csv`), managed by ESP-IDF. The bootloader rolls back an update
+/// that hasn't been marked valid, because `CONFIG_BOOTLOADER_APP_ROLLBACK_ENABLE` is set.
+pub struct EspOtaFlash
+{
+	/// `Some` while an image is being written.
+	update: Option<Update>,
+}
+
+struct Update
+{
+	handle: esp_ota_handle_t,
+	partition: *const esp_partition_t,
+}
+
+// The partition is a pointer to the partition table of ESP-IDF, which is never freed
+unsafe impl Send for Update {}
+
+impl EspOtaFlash
+{
+	pub fn new() -> Self
+	{
+		Self { update: None }
+	}
+}
+
+impl OtaFlash for EspOtaFlash
+{
+	type Error = EspError;
+
+	/// The version of the crate, which is the one `firmware-signer` must be given when signing this binary (the app
+	/// description of ESP-IDF has the version of the git tree instead).
+	fn running_version(&self) -> String
+	{
+		env!("CARGO_PKG_VERSION").to_string()
+	}
+
+	fn is_running_pending_verification(&mut self) -> Result<bool, Self::Error>
+	{
+		let mut state = esp_idf_sys::esp_ota_img_states_t_ESP_OTA_IMG_UNDEFINED;
+		esp!(unsafe {
+			esp_idf_sys::esp_ota_get_state_partition(esp_idf_sys::esp_ota_get_running_partition(), &mut state)
+		})?;
+
+		Ok(state == esp_idf_sys::esp_ota_img_states_t_ESP_OTA_IMG_PENDING_VERIFY)
+	}
+
+	fn mark_running_valid(&mut self) -> Result<(), Self::Error>
+	{
+		esp!(unsafe { esp_idf_sys::esp_ota_mark_app_valid_cancel_rollback() })
+	}
+
+	fn begin(&mut self, size: Option<usize>) -> Result<(), Self::Error>
+	{
+		let partition = unsafe { esp_idf_sys::esp_ota_get_next_update_partition(core::ptr::null()) };
+		if partition.is_null()
+		{
+			return Err(EspError::from_infallible::<{ esp_idf_sys::ESP_ERR_NOT_FOUND }>());
+		}
+
+		let mut handle = 0;
+		esp!(unsafe {
+			esp_idf_sys::esp_ota_begin(
+				partition,
+				size.unwrap_or(esp_idf_sys::OTA_SIZE_UNKNOWN as usize),
+				&mut handle,
+			)
+		})?;
+		self.update = Some(Update { handle, partition });
+
+		Ok(())
+	}
+
+	fn write(&mut self, chunk: &[u8]) -> Result<(), Self::Error>
+	{
+		let update = self
+			.update
+			.as_ref()
+			.ok_or(EspError::from_infallible::<{ esp_idf_sys::ESP_ERR_INVALID_STATE }>())?;
+		esp!(unsafe { esp_idf_sys::esp_ota_write(update.handle, chunk.as_ptr().cast(), chunk.len()) })
+	}
+
+	fn complete(&mut self) -> Result<(), Self::Error>
+	{
+		let update = self
+			.update
+			.take()
+			.ok_or(EspError::from_infallible::<{ esp_idf_sys::ESP_ERR_INVALID_STATE }>())?;
+		// It frees the handle even if the image isn't valid
+		esp!(unsafe { esp_idf_sys::esp_ota_end(update.handle) })?;
+		esp!(unsafe { esp_idf_sys::esp_ota_set_boot_partition(update.partition) })
+	}
+
+	fn abort(&mut self) -> Result<(), Self::Error>
+	{
+		match self.update.take()
+		{
+			Some(update) => esp!(unsafe { esp_idf_sys::esp_ota_abort(update.handle) }),
+			None => Ok(()),
+		}
+	}
+}
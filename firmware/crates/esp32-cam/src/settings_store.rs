@@ -0,0 +1,40 @@
+use esp_idf_svc::nvs::{EspDefaultNvsPartition, EspNvs, NvsDefault};
+use esp_idf_sys::EspError;
+use firmware_core::configuration::peripherals::settings_store::SettingsStore;
+
+/// Saves the settings as a blob in the default NVS partition.
+pub struct NvsSettingsStore(EspNvs<NvsDefault>);
+
+impl NvsSettingsStore
+{
+	const NAMESPACE: &'static str = "camera";
+	const KEY: &'static str = "settings";
+
+	pub fn new(partition: EspDefaultNvsPartition) -> Result<Self, EspError>
+	{
+		Ok(Self(EspNvs::new(partition, Self::NAMESPACE, true)?))
+	}
+}
+
+impl SettingsStore for NvsSettingsStore
+{
+	type Error = EspError;
+
+	fn read(&mut self) -> Result<Option<Vec<u8>>, Self::Error>
+	{
+		let Some(length) = self.0.blob_len(Self::KEY)?
+		else
+		{
+			return Ok(None);
+		};
+
+		let mut blob = vec![0; length];
+		Ok(self.0.get_blob(Self::KEY, &mut blob)?.map(|blob| blob.to_vec()))
+	}
+
+	fn write(&mut self, blob: &[u8]) -> Result<(), Self::Error>
+	{
+		// NVS writes the new blob before erasing the old one, so a reset during the write keeps the old settings
+		self.0.set_blob(Self::KEY, blob)
+	}
+}
@@ -0,0 +1,306 @@
+use core::ffi::{c_char, c_int, c_uchar, c_void, CStr};
+#[cfg(esp_idf_esp_https_server_enable)]
+use std::sync::OnceLock;
+
+use esp_idf_svc::nvs::{EspDefaultNvsPartition, EspNvs, NvsDefault};
+use esp_idf_sys::{
+	mbedtls_mpi, mbedtls_pk_context, mbedtls_x509_crt, mbedtls_x509write_cert, EspError, MBEDTLS_X509_CRT_VERSION_3,
+};
+use firmware_core::{configuration::peripherals::tls::TlsIdentityStore, features::http_server::tls::TlsIdentity};
+
+/// The subject and the issuer of the generated certificate.
+const GENERATED_CERTIFICATE_NAME: &[u8] = b"CN=esp32-cam\0";
+/// The clock isn't synchronized when the certificate is generated, so it's valid from a date in the past until the
+/// last one that fits in the `UTCTime` of X.509.
+const GENERATED_CERTIFICATE_NOT_BEFORE: &[u8] = b"20240101000000\0";
+const GENERATED_CERTIFICATE_NOT_AFTER: &[u8] = b"20491231235959\0";
+const GENERATED_SERIAL_NUMBER_SIZE: usize = 16;
+/// Enough for the PEM of a P-256 certificate or private key.
+const PEM_BUFFER_SIZE: usize = 2 * 1024;
+
+/// Saves the certificate and the private key of the HTTPS servers as a PEM blob in the default NVS partition. If none
+/// has been saved, a self-signed P-256 certificate is generated with mbedTLS.
+pub struct NvsTlsIdentityStore(EspNvs<NvsDefault>);
+
+#[derive(Debug)]
+pub enum TlsIdentityError
+{
+	Nvs(EspError),
+	/// The saved identity can't be parsed.
+	InvalidPem(String),
+	/// `code` is the negative error code returned by the mbedTLS `function`.
+	MbedTls
+	{
+		function: &'static str,
+		code: c_int,
+	},
+}
+
+impl NvsTlsIdentityStore
+{
+	const NAMESPACE: &'static str = "camera";
+	const KEY: &'static str = "tls_identity";
+
+	pub fn new(partition: EspDefaultNvsPartition) -> Result<Self, EspError>
+	{
+		Ok(Self(EspNvs::new(partition, Self::NAMESPACE, true)?))
+	}
+
+	/// Returns the saved identity, after generating and saving a self-signed one if there's none (like at the first
+	/// boot).
+	pub fn load_or_generate(&mut self) -> Result<TlsIdentity, TlsIdentityError>
+	{
+		if let Some(length) = self.0.blob_len(Self::KEY).map_err(TlsIdentityError::Nvs)?
+		{
+			let mut blob = vec![0; length];
+			if let Some(blob) = self.0.get_blob(Self::KEY, &mut blob).map_err(TlsIdentityError::Nvs)?
+			{
+				return core::str::from_utf8(blob)
+					.map_err(|error| error.to_string())
+					.and_then(TlsIdentity::from_pem)
+					.map_err(TlsIdentityError::InvalidPem);
+			}
+		}
+
+		log::info!("Generating a self-signed HTTPS certificate");
+		let identity = generate_self_signed_identity()?;
+		self.write(&identity)?;
+
+		Ok(identity)
+	}
+}
+
+impl TlsIdentityStore for NvsTlsIdentityStore
+{
+	type Error = TlsIdentityError;
+
+	fn certificate(&mut self) -> Result<Option<String>, Self::Error>
+	{
+		self.load_or_generate().map(|identity| Some(identity.certificate))
+	}
+
+	fn check(&mut self, identity: &TlsIdentity) -> Result<(), Self::Error>
+	{
+		let certificate = nul_terminated(&identity.certificate);
+		let private_key = nul_terminated(&identity.private_key);
+
+		unsafe {
+			let mut chain = mbedtls_x509_crt::default();
+			esp_idf_sys::mbedtls_x509_crt_init(&mut chain);
+			let mut key = mbedtls_pk_context::default();
+			esp_idf_sys::mbedtls_pk_init(&mut key);
+
+			// The first certificate of the chain is the one of the camera
+			let result = mbedtls(
+				"mbedtls_x509_crt_parse",
+				esp_idf_sys::mbedtls_x509_crt_parse(&mut chain, certificate.as_ptr(), certificate.len()),
+			)
+			.and_then(|()| {
+				mbedtls(
+					"mbedtls_pk_parse_key",
+					esp_idf_sys::mbedtls_pk_parse_key(
+						&mut key,
+						private_key.as_ptr(),
+						private_key.len(),
+						core::ptr::null(),
+						0,
+					),
+				)
+			})
+			.and_then(|()| {
+				mbedtls(
+					"mbedtls_pk_check_pair",
+					esp_idf_sys::mbedtls_pk_check_pair(&chain.pk, &key),
+				)
+			});
+
+			esp_idf_sys::mbedtls_pk_free(&mut key);
+			esp_idf_sys::mbedtls_x509_crt_free(&mut chain);
+			result
+		}
+	}
+
+	fn write(&mut self, identity: &TlsIdentity) -> Result<(), Self::Error>
+	{
+		// NVS writes the new blob before erasing the old one, so a reset during the write keeps the old identity
+		self.0
+			.set_blob(Self::KEY, identity.to_pem().as_bytes())
+			.map_err(TlsIdentityError::Nvs)
+	}
+}
+
+/// The identity of the HTTPS servers in PEM terminated by a NUL (like ESP-IDF requires), which the servers borrow for
+/// the whole run.
+#[cfg(esp_idf_esp_https_server_enable)]
+#[derive(Clone, Copy)]
+pub struct ServerIdentity
+{
+	pub certificate: &'static [u8],
+	pub private_key: &'static [u8],
+}
+
+#[cfg(esp_idf_esp_https_server_enable)]
+static SERVER_IDENTITY: OnceLock<ServerIdentity> = OnceLock::new();
+
+#[cfg(esp_idf_esp_https_server_enable)]
+impl ServerIdentity
+{
+	/// Loads (or generates) the identity the first time it's called, so that both servers share the same one.
+	pub fn get_or_load(partition: &EspDefaultNvsPartition) -> Result<Self, EspError>
+	{
+		if let Some(identity) = SERVER_IDENTITY.get()
+		{
+			return Ok(*identity);
+		}
+
+		let identity = NvsTlsIdentityStore::new(partition.clone())?
+			.load_or_generate()
+			.map_err(|error| {
+				log::error!("Couldn't load the HTTPS certificate: {:?}", error);
+				EspError::from_infallible::<{ esp_idf_sys::ESP_FAIL }>()
+			})?;
+		Ok(*SERVER_IDENTITY.get_or_init(|| Self {
+			certificate: nul_terminated(&identity.certificate).leak(),
+			private_key: nul_terminated(&identity.private_key).leak(),
+		}))
+	}
+}
+
+/// The random number generator given to mbedTLS. The one of the hardware is truly random while the radio is on, like
+/// when the identity is generated at the first boot (the servers are started after the access point of the setup mode).
+unsafe extern "C" fn fill_random(_: *mut c_void, output: *mut c_uchar, length: usize) -> c_int
+{
+	esp_idf_sys::esp_fill_random(output.cast(), length);
+	0
+}
+
+fn generate_self_signed_identity() -> Result<TlsIdentity, TlsIdentityError>
+{
+	unsafe {
+		let mut key = mbedtls_pk_context::default();
+		esp_idf_sys::mbedtls_pk_init(&mut key);
+		let mut certificate = mbedtls_x509write_cert::default();
+		esp_idf_sys::mbedtls_x509write_crt_init(&mut certificate);
+		let mut serial_number = mbedtls_mpi::default();
+		esp_idf_sys::mbedtls_mpi_init(&mut serial_number);
+
+		let identity = write_self_signed_identity(&mut key, &mut certificate, &mut serial_number);
+
+		esp_idf_sys::mbedtls_mpi_free(&mut serial_number);
+		esp_idf_sys::mbedtls_x509write_crt_free(&mut certificate);
+		esp_idf_sys::mbedtls_pk_free(&mut key);
+		identity
+	}
+}
+
+/// The contexts must have been initialized.
+unsafe fn write_self_signed_identity(
+	key: &mut mbedtls_pk_context, certificate: &mut mbedtls_x509write_cert, serial_number: &mut mbedtls_mpi,
+) -> Result<TlsIdentity, TlsIdentityError>
+{
+	mbedtls(
+		"mbedtls_pk_setup",
+		esp_idf_sys::mbedtls_pk_setup(
+			key,
+			esp_idf_sys::mbedtls_pk_info_from_type(esp_idf_sys::mbedtls_pk_type_t_MBEDTLS_PK_ECKEY),
+		),
+	)?;
+	// The context of an EC key is its key pair (`mbedtls_pk_ec` is an inline function, so it has no binding)
+	mbedtls(
+		"mbedtls_ecp_gen_key",
+		esp_idf_sys::mbedtls_ecp_gen_key(
+			esp_idf_sys::mbedtls_ecp_group_id_MBEDTLS_ECP_DP_SECP256R1,
+			key.pk_ctx.cast(),
+			Some(fill_random),
+			core::ptr::null_mut(),
+		),
+	)?;
+	mbedtls(
+		"mbedtls_mpi_fill_random",
+		esp_idf_sys::mbedtls_mpi_fill_random(
+			serial_number,
+			GENERATED_SERIAL_NUMBER_SIZE,
+			Some(fill_random),
+			core::ptr::null_mut(),
+		),
+	)?;
+
+	esp_idf_sys::mbedtls_x509write_crt_set_version(certificate, MBEDTLS_X509_CRT_VERSION_3 as c_int);
+	esp_idf_sys::mbedtls_x509write_crt_set_md_alg(certificate, esp_idf_sys::mbedtls_md_type_t_MBEDTLS_MD_SHA256);
+	esp_idf_sys::mbedtls_x509write_crt_set_subject_key(certificate, key);
+	esp_idf_sys::mbedtls_x509write_crt_set_issuer_key(certificate, key);
+	let name = GENERATED_CERTIFICATE_NAME.as_ptr() as *const c_char;
+	mbedtls(
+		"mbedtls_x509write_crt_set_subject_name",
+		esp_idf_sys::mbedtls_x509write_crt_set_subject_name(certificate, name),
+	)?;
+	mbedtls(
+		"mbedtls_x509write_crt_set_issuer_name",
+		esp_idf_sys::mbedtls_x509write_crt_set_issuer_name(certificate, name),
+	)?;
+	mbedtls(
+		"mbedtls_x509write_crt_set_serial",
+		esp_idf_sys::mbedtls_x509write_crt_set_serial(certificate, serial_number),
+	)?;
+	mbedtls(
+		"mbedtls_x509write_crt_set_validity",
+		esp_idf_sys::mbedtls_x509write_crt_set_validity(
+			certificate,
+			GENERATED_CERTIFICATE_NOT_BEFORE.as_ptr() as *const c_char,
+			GENERATED_CERTIFICATE_NOT_AFTER.as_ptr() as *const c_char,
+		),
+	)?;
+
+	let mut certificate_pem = vec![0; PEM_BUFFER_SIZE];
+	mbedtls(
+		"mbedtls_x509write_crt_pem",
+		esp_idf_sys::mbedtls_x509write_crt_pem(
+			certificate,
+			certificate_pem.as_mut_ptr(),
+			certificate_pem.len(),
+			Some(fill_random),
+			core::ptr::null_mut(),
+		),
+	)?;
+	let mut private_key_pem = vec![0; PEM_BUFFER_SIZE];
+	mbedtls(
+		"mbedtls_pk_write_key_pem",
+		esp_idf_sys::mbedtls_pk_write_key_pem(key, private_key_pem.as_mut_ptr(), private_key_pem.len()),
+	)?;
+
+	// Parsed like an uploaded identity, so that it's saved in the same form
+	TlsIdentity::from_pem(&format!(
+		"{}{}",
+		pem_from_buffer(&certificate_pem),
+		pem_from_buffer(&private_key_pem)
+	))
+	.map_err(TlsIdentityError::InvalidPem)
+}
+
+/// The mbedTLS functions return a negative error code if they fail.
+fn mbedtls(function: &'static str, code: c_int) -> Result<(), TlsIdentityError>
+{
+	if code < 0
+	{
+		return Err(TlsIdentityError::MbedTls { function, code });
+	}
+
+	Ok(())
+}
+
+/// mbedTLS writes the PEM in a buffer terminated by a NUL.
+fn pem_from_buffer(buffer: &[u8]) -> String
+{
+	CStr::from_bytes_until_nul(buffer)
+		.map(|pem| pem.to_string_lossy().into_owned())
+		.unwrap_or_default()
+}
+
+/// mbedTLS and ESP-IDF can only parse a PEM terminated by a NUL.
+fn nul_terminated(pem: &str) -> Vec<u8>
+{
+	let mut bytes = Vec::with_capacity(pem.len() + 1);
+	bytes.extend_from_slice(pem.as_bytes());
+	bytes.push(0);
+	bytes
+}
@@ -0,0 +1,228 @@
+use std::{io::Read, path::PathBuf};
+
+use firmware_core::features::ota::{
+	parse_hex,
+	signature::{FirmwareVersion, ImageMetadata, SigningKey},
+};
+use sha2::{Digest, Sha256};
+
+/// Where the secret keys are generated from.
+const RANDOM_SOURCE: &str = "/dev/urandom";
+/// The size of the app partitions of each board, which an update must fit in (check the `partitions.csv` of the board).
+const APP_PARTITION_SIZES: [(&str, usize); 1] = [("esp32-cam", 0x1F0000)];
+
+const USAGE: &str = "Usage:
+  firmware-signer generate-key <secret key file>
+  firmware-signer public-key <secret key file>
+  firmware-signer sign <secret key file> <board> <version> <min rollback version> <binary> <signed image>
+
+The public key must be trusted by the camera (like with the `OTA_PUBLIC_KEYS` environment variable when building the
+ESP32-CAM firmware). The signed image can then be uploaded to `POST /ota` or pointed to by the manifest of the updates.
+The binaries that don't fit in the app partitions of a known board are refused.";
+
+fn main()
+{
+	let Some(command) = Command::parse(std::env::args().skip(1))
+	else
+	{
+		eprintln!("{}", USAGE);
+		std::process::exit(1);
+	};
+
+	if let Err(error) = command.run()
+	{
+		eprintln!("{}", error);
+		std::process::exit(1);
+	}
+}
+
+enum Command
+{
+	/// Writes a new secret key and prints its public key.
+	GenerateKey
+	{
+		secret_key: PathBuf
+	},
+	PublicKey
+	{
+		secret_key: PathBuf
+	},
+	/// Writes the signed header followed by the binary, and prints the SHA-256 of the signed image.
+	Sign
+	{
+		secret_key: PathBuf,
+		board: String,
+		version: FirmwareVersion,
+		min_rollback_version: FirmwareVersion,
+		binary: PathBuf,
+		signed_image: PathBuf,
+	},
+}
+
+impl Command
+{
+	fn parse(mut arguments: impl Iterator<Item = String>) -> Option<Self>
+	{
+		let command = match arguments.next()?.as_str()
+		{
+			"generate-key" => Self::GenerateKey {
+				secret_key: arguments.next()?.into(),
+			},
+			"public-key" => Self::PublicKey {
+				secret_key: arguments.next()?.into(),
+			},
+			"sign" => Self::Sign {
+				secret_key: arguments.next()?.into(),
+				board: arguments.next()?,
+				version: arguments.next()?.parse().ok()?,
+				min_rollback_version: arguments.next()?.parse().ok()?,
+				binary: arguments.next()?.into(),
+				signed_image: arguments.next()?.into(),
+			},
+			_ => return None,
+		};
+
+		arguments.next().is_none().then_some(command)
+	}
+
+	fn run(self) -> Result<(), String>
+	{
+		match self
+		{
+			Self::GenerateKey { secret_key } =>
+			{
+				let mut bytes = [0; 32];
+				std::fs::File::open(RANDOM_SOURCE)
+					.and_then(|mut random| random.read_exact(&mut bytes))
+					.map_err(|error| format!("Couldn't read {}: {}", RANDOM_SOURCE, error))?;
+				let key = SigningKey::from_bytes(&bytes);
+				std::fs::write(&secret_key, to_hex(&key.to_bytes()))
+					.map_err(|error| format!("Couldn't write {}: {}", secret_key.display(), error))?;
+				println!("{}", to_hex(key.verifying_key().as_bytes()));
+			},
+			Self::PublicKey { secret_key } =>
+			{
+				let key = read_secret_key(&secret_key)?;
+				println!("{}", to_hex(key.verifying_key().as_bytes()));
+			},
+			Self::Sign {
+				secret_key,
+				board,
+				version,
+				min_rollback_version,
+				binary,
+				signed_image,
+			} =>
+			{
+				let key = read_secret_key(&secret_key)?;
+				let binary =
+					std::fs::read(&binary).map_err(|error| format!("Couldn't read {}: {}", binary.display(), error))?;
+				let image = sign_image(&key, board, version, min_rollback_version, &binary)?;
+				std::fs::write(&signed_image, &image)
+					.map_err(|error| format!("Couldn't write {}: {}", signed_image.display(), error))?;
+				println!("{}", to_hex(&Sha256::digest(&image)));
+			},
+		}
+
+		Ok(())
+	}
+}
+
+fn read_secret_key(path: &PathBuf) -> Result<SigningKey, String>
+{
+	let hex = std::fs::read_to_string(path).map_err(|error| format!("Couldn't read {}: {}", path.display(), error))?;
+	let bytes = parse_hex(hex.trim()).ok_or_else(|| format!("{} isn't a secret key", path.display()))?;
+
+	Ok(SigningKey::from_bytes(&bytes))
+}
+
+/// Returns the signed header followed by the `binary`.
+fn sign_image(
+	key: &SigningKey, board: String, version: FirmwareVersion, min_rollback_version: FirmwareVersion, binary: &[u8],
+) -> Result<Vec<u8>, String>
+{
+	if let Some((_, partition_size)) = APP_PARTITION_SIZES.iter().find(|(name, _)| *name == board)
+	{
+		if binary.len() > *partition_size
+		{
+			return Err(format!(
+				"The binary is {} bytes, but the app partitions of the {} hold {} bytes",
+				binary.len(),
+				board,
+				partition_size
+			));
+		}
+	}
+	let header = ImageMetadata::of_binary(board, version, min_rollback_version, binary)
+		.and_then(|metadata| metadata.sign(key))
+		.ok_or("The binary must be smaller than 4 GiB and the board at most 32 bytes long")?;
+
+	let mut image = Vec::with_capacity(header.len() + binary.len());
+	image.extend_from_slice(&header);
+	image.extend_from_slice(binary);
+
+	Ok(image)
+}
+
+fn to_hex(bytes: &[u8]) -> String
+{
+	bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests
+{
+	use firmware_core::features::ota::signature::SIGNED_HEADER_SIZE;
+
+	use super::*;
+
+	#[test]
+	fn signed_image_is_verified_by_the_firmware()
+	{
+		let key = SigningKey::from_bytes(&[7; 32]);
+		let version = "1.2.0".parse().unwrap();
+		let min_rollback_version = "1.1.0".parse().unwrap();
+
+		let image = sign_image(&key, "esp32-cam".to_string(), version, min_rollback_version, b"binary").unwrap();
+		let (header, binary) = image.split_at(SIGNED_HEADER_SIZE);
+		let metadata = ImageMetadata::verify(header.try_into().unwrap(), &[key.verifying_key()]).unwrap();
+
+		assert_eq!(binary, b"binary");
+		assert_eq!(metadata.board, "esp32-cam");
+		assert_eq!(metadata.version, version);
+		assert_eq!(metadata.min_rollback_version, min_rollback_version);
+		assert_eq!(parse_hex(&to_hex(key.as_bytes())), Some(*key.as_bytes()));
+	}
+
+	#[test]
+	fn binaries_bigger_than_the_app_partitions_are_refused()
+	{
+		let key = SigningKey::from_bytes(&[7; 32]);
+		let version = "1.2.0".parse().unwrap();
+		let sign = |board: &str, size: usize| sign_image(&key, board.to_string(), version, version, &vec![0; size]);
+
+		assert!(sign("esp32-cam", 0x1F0000).is_ok());
+		assert!(sign("esp32-cam", 0x1F0001).is_err());
+		assert!(sign("simulator", 0x1F0001).is_ok());
+	}
+
+	#[test]
+	fn invalid_arguments_are_refused()
+	{
+		let parse = |arguments: &[&str]| Command::parse(arguments.iter().map(|argument| argument.to_string()));
+
+		assert!(parse(&["public-key", "key"]).is_some());
+		assert!(parse(&["public-key", "key", "extra"]).is_none());
+		assert!(parse(&[
+			"sign",
+			"key",
+			"esp32-cam",
+			"1.2",
+			"1.0.0",
+			"firmware.bin",
+			"firmware.signed"
+		])
+		.is_none());
+		assert!(parse(&["verify", "key"]).is_none());
+	}
+}
@@ -0,0 +1,167 @@
+use core::{ops::RangeInclusive, time::Duration};
+
+use a13c_embedded::peripherals::time::real_time::time::Time;
+use firmware_core::{
+	configuration::customization::Customization as CustomizationTrait,
+	features::{
+		connectivity::WifiSupervisorSettings,
+		mqtt::{HomeAssistantSettings, MqttSettings},
+		ota::{parse_hex, OtaSettings},
+		provisioning::ProvisioningSettings,
+		recording::EventRecordingSettings,
+		rtsp::RtspSettings,
+		storage::RetentionPolicy,
+		timelapse::TimelapseSettings,
+		trigger::{EnableOnConditions, MotionDetectorSettings, PirSettings, TriggerSources},
+		webhooks::WebhookSettings,
+	},
+};
+
+/// Environment variable with the URLs of the webhooks separated by commas, like `http://localhost:9000/events`.
+const WEBHOOK_URLS_ENVIRONMENT_VARIABLE: &str = "SIMULATOR_WEBHOOK_URLS";
+/// Environment variable with the public keys printed by `firmware-signer` separated by commas. The updates must be
+/// signed by one of them.
+const OTA_PUBLIC_KEYS_ENVIRONMENT_VARIABLE: &str = "SIMULATOR_OTA_PUBLIC_KEYS";
+/// Environment variable with the port of the RTSP server, 8554 by default (binding 554 usually needs to be root).
+const RTSP_PORT_ENVIRONMENT_VARIABLE: &str = "SIMULATOR_RTSP_PORT";
+
+pub struct Customization;
+
+impl CustomizationTrait for Customization
+{
+	type EnableOnConditionsList = Vec<RangeInclusive<Time>>;
+
+	fn enable_image_trigger_on(&self) -> EnableOnConditions<Self::EnableOnConditionsList>
+	{
+		EnableOnConditions::Always
+	}
+
+	fn trigger_duration(&self) -> Duration
+	{
+		Duration::from_secs(30)
+	}
+
+	fn trigger_cooldown(&self) -> Duration
+	{
+		Duration::from_secs(10)
+	}
+
+	fn pir_sensor(&self) -> PirSettings
+	{
+		PirSettings {
+			debounce: Duration::from_millis(200),
+			hold_time: Duration::from_secs(5),
+		}
+	}
+
+	fn trigger_sources(&self) -> TriggerSources
+	{
+		// The replayed frames usually come from a camera that didn't have a PIR sensor
+		TriggerSources::PirOrFrameDifference(MotionDetectorSettings::default())
+	}
+
+	fn retention_policy(&self) -> RetentionPolicy
+	{
+		RetentionPolicy {
+			max_used_bytes: Some(64 * 1024 * 1024),
+			max_age_days: Some(7),
+			min_free_space_percentage: Some(10),
+		}
+	}
+
+	fn event_recording(&self) -> Option<EventRecordingSettings>
+	{
+		Some(EventRecordingSettings {
+			pre_trigger: Duration::from_secs(5),
+			post_roll: Duration::from_secs(10),
+			max_duration: Duration::from_secs(120),
+			max_pre_trigger_bytes: 16 * 1024 * 1024,
+		})
+	}
+
+	fn timelapse(&self) -> Option<TimelapseSettings<Self::EnableOnConditionsList>>
+	{
+		Some(TimelapseSettings {
+			interval: Duration::from_secs(60),
+			enable_on: EnableOnConditions::Always,
+			daily_video_frame_duration: Some(Duration::from_millis(100)),
+		})
+	}
+
+	fn mqtt(&self) -> Option<MqttSettings>
+	{
+		Some(MqttSettings {
+			base_topic: "camera-simulator".to_string(),
+			stats_period: Duration::from_secs(30),
+			home_assistant: Some(HomeAssistantSettings {
+				discovery_prefix: "homeassistant".to_string(),
+				device_name: "Camera simulator".to_string(),
+			}),
+		})
+	}
+
+	fn webhooks(&self) -> Option<WebhookSettings>
+	{
+		Some(WebhookSettings {
+			urls: std::env::var(WEBHOOK_URLS_ENVIRONMENT_VARIABLE)
+				.map(|urls| urls.split(',').map(|url| url.trim().to_string()).collect())
+				.unwrap_or_default(),
+			include_snapshot: true,
+			max_queued_deliveries: 16,
+			max_attempts: 5,
+			first_retry_delay: Duration::from_secs(2),
+		})
+	}
+
+	fn provisioning(&self) -> ProvisioningSettings
+	{
+		ProvisioningSettings {
+			access_point_ssid_prefix: "Simulator-setup".to_string(),
+			access_point_password: None,
+			button_long_press: Duration::from_secs(5),
+		}
+	}
+
+	fn wifi_supervisor(&self) -> WifiSupervisorSettings
+	{
+		WifiSupervisorSettings {
+			connect_timeout: Duration::from_secs(5),
+			first_retry_delay: Duration::from_secs(1),
+			max_retry_delay: Duration::from_secs(30),
+		}
+	}
+
+	fn ota(&self) -> OtaSettings
+	{
+		OtaSettings {
+			check_period: Duration::from_secs(60),
+			board: "simulator".to_string(),
+			trusted_public_keys: std::env::var(OTA_PUBLIC_KEYS_ENVIRONMENT_VARIABLE)
+				.unwrap_or_default()
+				.split(',')
+				.filter(|key| !key.is_empty())
+				.filter_map(|key| match parse_hex(key.trim())
+				{
+					Some(key) => Some(key),
+					None =>
+					{
+						log::warn!("The public key {:?} of the updates isn't valid", key);
+						None
+					},
+				})
+				.collect(),
+			ticks_before_valid: 100,
+		}
+	}
+
+	fn rtsp(&self) -> Option<RtspSettings>
+	{
+		Some(RtspSettings {
+			port: std::env::var(RTSP_PORT_ENVIRONMENT_VARIABLE)
+				.ok()
+				.and_then(|port| port.parse().ok())
+				.unwrap_or(8554),
+			max_clients: 4,
+		})
+	}
+}
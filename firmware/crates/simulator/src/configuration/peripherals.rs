@@ -0,0 +1,284 @@
+use std::{
+	net::{IpAddr, Ipv4Addr},
+	os::unix::process::CommandExt,
+	path::Path,
+	process::Command,
+	time::Duration,
+};
+
+use a13c_embedded::peripherals::time::real_time::time::OffsetDateTime;
+use firmware_core::{
+	configuration::peripherals::{mqtt::MqttLastWill, Peripherals as PeripheralsTrait},
+	features::http_server::{stream::PossibleHttpRequest as StreamPossibleHttpRequest, PossibleHttpRequest},
+};
+
+use crate::peripherals::{
+	camera::ReplayCamera,
+	http_client::StdHttpClient,
+	http_server::StdHttpServer,
+	led::LoggedLed,
+	mqtt::TcpMqttClient,
+	ota::FileOtaFlash,
+	pir_sensor::ScriptedInputPin,
+	real_time_clock::{FakeRealTimeClock, TimeSource},
+	sd_card::FileBlockDevice,
+	settings_store::FileSettingsStore,
+	tls::FileTlsIdentityStore,
+	watchdog::NoWatchdogCreator,
+	wifi::HostWifi,
+};
+
+pub const HTTP_SERVER_PORT: u16 = 8080;
+pub const STREAM_HTTP_SERVER_PORT: u16 = 8081;
+
+/// Environment variable with the number of seconds during which the fake real time clock behaves like a clock that still
+/// hasn't been synchronized (by default it's synchronized right away).
+const CLOCK_UNSET_ENVIRONMENT_VARIABLE: &str = "SIMULATOR_CLOCK_UNSET_SECONDS";
+/// Environment variable with the address of the MQTT broker, like `localhost:1883` (MQTT is disabled without it).
+const MQTT_BROKER_ENVIRONMENT_VARIABLE: &str = "SIMULATOR_MQTT_BROKER";
+
+impl PeripheralsTrait for Peripherals
+{
+	type Camera = ReplayCamera;
+
+	type WifiDriver = HostWifi;
+	type Server = StdHttpServer<PossibleHttpRequest>;
+	type StreamServer = StdHttpServer<StreamPossibleHttpRequest>;
+	type ServerError = std::io::Error;
+
+	type SdCard = FileBlockDevice;
+	type SdCardTimeSource = TimeSource;
+
+	type PirSensorPin = ScriptedInputPin;
+	type FlashLed = LoggedLed;
+	type SetupButtonPin = ScriptedInputPin;
+
+	type SettingsStore = FileSettingsStore;
+
+	type WatchdogCreator = NoWatchdogCreator;
+
+	type RealTimeClock = FakeRealTimeClock;
+
+	type MqttClient = TcpMqttClient;
+	type MqttClientError = std::io::Error;
+
+	type HttpClient = StdHttpClient;
+
+	type OtaFlash = FileOtaFlash;
+
+	type TlsIdentityStore = FileTlsIdentityStore;
+
+	fn take_camera(&mut self) -> Option<Self::Camera>
+	{
+		self.camera.take()
+	}
+
+	fn take_wifi_driver(&mut self) -> Option<Self::WifiDriver>
+	{
+		self.wifi_driver.take()
+	}
+
+	fn get_ip_address_from_wifi_driver_function() -> fn(&Self::WifiDriver) -> Option<IpAddr>
+	{
+		|_| Some(IpAddr::V4(Ipv4Addr::LOCALHOST))
+	}
+
+	fn get_mac_address_from_wifi_driver_function() -> fn(&Self::WifiDriver) -> Option<[u8; 6]>
+	{
+		// A locally administered address, which can't belong to a real board
+		|_| Some([0x02, 0x00, 0x00, 0x00, 0x00, 0x01])
+	}
+
+	fn get_rssi_from_wifi_driver_function() -> fn(&Self::WifiDriver) -> Option<i8>
+	{
+		|_| None
+	}
+
+	fn get_access_point_ip_address_from_wifi_driver_function() -> fn(&Self::WifiDriver) -> Option<Ipv4Addr>
+	{
+		// The host doesn't really start an access point, the setup page is served on the HTTP server's port
+		|_| None
+	}
+
+	fn get_restart_function() -> fn() -> !
+	{
+		restart
+	}
+
+	fn take_http_server(&mut self) -> Option<Box<dyn FnOnce() -> Result<Self::Server, Self::ServerError>>>
+	{
+		self.http_server.take()
+	}
+
+	fn take_stream_http_server(&mut self)
+		-> Option<Box<dyn FnOnce() -> Result<Self::StreamServer, Self::ServerError>>>
+	{
+		self.stream_http_server.take()
+	}
+
+	fn take_sd_card(&mut self) -> Option<Self::SdCard>
+	{
+		self.sd_card.take()
+	}
+
+	fn take_sd_card_time_source(&mut self) -> Option<Self::SdCardTimeSource>
+	{
+		self.sd_card_time_source.take()
+	}
+
+	fn take_pir_sensor_pin(&mut self) -> Option<Self::PirSensorPin>
+	{
+		self.pir_sensor_pin.take()
+	}
+
+	fn take_flash_led(&mut self) -> Option<Self::FlashLed>
+	{
+		self.flash_led.take()
+	}
+
+	fn take_setup_button_pin(&mut self) -> Option<Self::SetupButtonPin>
+	{
+		None
+	}
+
+	fn take_settings_store(&mut self) -> Option<Self::SettingsStore>
+	{
+		self.settings_store.take()
+	}
+
+	fn take_watchdog_creator(&mut self) -> Option<Self::WatchdogCreator>
+	{
+		None
+	}
+
+	fn take_real_time_clock(&mut self) -> Option<Self::RealTimeClock>
+	{
+		self.real_time_clock.take()
+	}
+
+	fn take_mqtt_client(
+		&mut self,
+	) -> Option<Box<dyn FnOnce(MqttLastWill) -> Result<Self::MqttClient, Self::MqttClientError>>>
+	{
+		self.mqtt_client.take()
+	}
+
+	fn take_http_client(&mut self) -> Option<Self::HttpClient>
+	{
+		Some(StdHttpClient)
+	}
+
+	fn take_ota_flash(&mut self) -> Option<Self::OtaFlash>
+	{
+		self.ota_flash.take()
+	}
+
+	fn take_tls_identity_store(&mut self) -> Option<Self::TlsIdentityStore>
+	{
+		self.tls_identity_store.take()
+	}
+}
+
+pub struct Peripherals
+{
+	camera: Option<<Self as PeripheralsTrait>::Camera>,
+	wifi_driver: Option<<Self as PeripheralsTrait>::WifiDriver>,
+	http_server: Option<
+		Box<dyn FnOnce() -> Result<<Self as PeripheralsTrait>::Server, <Self as PeripheralsTrait>::ServerError>>,
+	>,
+	stream_http_server: Option<
+		Box<dyn FnOnce() -> Result<<Self as PeripheralsTrait>::StreamServer, <Self as PeripheralsTrait>::ServerError>>,
+	>,
+	sd_card: Option<<Self as PeripheralsTrait>::SdCard>,
+	sd_card_time_source: Option<<Self as PeripheralsTrait>::SdCardTimeSource>,
+	pir_sensor_pin: Option<<Self as PeripheralsTrait>::PirSensorPin>,
+	flash_led: Option<<Self as PeripheralsTrait>::FlashLed>,
+	settings_store: Option<<Self as PeripheralsTrait>::SettingsStore>,
+	real_time_clock: Option<<Self as PeripheralsTrait>::RealTimeClock>,
+	mqtt_client: Option<
+		Box<
+			dyn FnOnce(
+				MqttLastWill,
+			)
+				-> Result<<Self as PeripheralsTrait>::MqttClient, <Self as PeripheralsTrait>::MqttClientError>,
+		>,
+	>,
+	ota_flash: Option<<Self as PeripheralsTrait>::OtaFlash>,
+	tls_identity_store: Option<<Self as PeripheralsTrait>::TlsIdentityStore>,
+}
+
+impl Peripherals
+{
+	/// Creates the host peripherals:
+	/// - the camera replays the JPEG files contained in `frames_directory` (in alphabetical order)
+	/// - the SD card is the disk image at `sd_card_image` (it must contain an MBR partition table and a FAT partition)
+	/// - the PIR sensor follows the script at `pir_sensor_script` (check [`ScriptedInputPin::from_script`]), or it's
+	///   always low if there's no script
+	/// - the settings are saved next to the SD card image, with the `settings` extension
+	/// - the MQTT client connects to the broker in the `SIMULATOR_MQTT_BROKER` environment variable, if it's set
+	/// - the firmware updates are saved next to the SD card image, with the `firmware` extension
+	/// - the uploaded HTTPS certificate is saved next to the SD card image, with the `tls` extension
+	pub fn new(
+		frames_directory: &Path, sd_card_image: &Path, pir_sensor_script: Option<&Path>,
+	) -> Result<Self, std::io::Error>
+	{
+		let clock_unset_for = std::env::var(CLOCK_UNSET_ENVIRONMENT_VARIABLE)
+			.ok()
+			.and_then(|seconds| seconds.parse().ok())
+			.map(Duration::from_secs)
+			.unwrap_or_default();
+		let real_time_clock = FakeRealTimeClock::new(host_date_and_time(), clock_unset_for);
+
+		Ok(Self {
+			camera: Some(ReplayCamera::new(frames_directory)?),
+			wifi_driver: Some(HostWifi::default()),
+			http_server: Some(Box::new(move || StdHttpServer::new(HTTP_SERVER_PORT))),
+			stream_http_server: Some(Box::new(move || StdHttpServer::new(STREAM_HTTP_SERVER_PORT))),
+			sd_card: Some(FileBlockDevice::open(sd_card_image)?),
+			sd_card_time_source: Some(TimeSource(real_time_clock.clone())),
+			pir_sensor_pin: Some(match pir_sensor_script
+			{
+				Some(pir_sensor_script) => ScriptedInputPin::from_script(&std::fs::read_to_string(pir_sensor_script)?)?,
+				None => ScriptedInputPin::always(false),
+			}),
+			flash_led: Some(LoggedLed::new("Flash LED")),
+			settings_store: Some(FileSettingsStore::new(sd_card_image.with_extension("settings"))),
+			real_time_clock: Some(real_time_clock),
+			mqtt_client: std::env::var(MQTT_BROKER_ENVIRONMENT_VARIABLE)
+				.ok()
+				.map(|broker_address| {
+					Box::new(move |last_will| Ok(TcpMqttClient::new(broker_address, last_will)))
+						as Box<dyn FnOnce(_) -> _>
+				}),
+			ota_flash: Some(FileOtaFlash::new(sd_card_image.with_extension("firmware"))),
+			tls_identity_store: Some(FileTlsIdentityStore::new(sd_card_image.with_extension("tls"))),
+		})
+	}
+
+	/// Serves the HTTP servers on other ports than [`HTTP_SERVER_PORT`] and [`STREAM_HTTP_SERVER_PORT`], like `0` to
+	/// let the OS pick free ones.
+	pub fn with_http_server_ports(mut self, http_server_port: u16, stream_http_server_port: u16) -> Self
+	{
+		self.http_server = Some(Box::new(move || StdHttpServer::new(http_server_port)));
+		self.stream_http_server = Some(Box::new(move || StdHttpServer::new(stream_http_server_port)));
+		self
+	}
+}
+
+/// Replaces the process with a new instance of the simulator, with the same arguments.
+fn restart() -> !
+{
+	let error = std::env::current_exe()
+		.map(|executable| Command::new(executable).args(std::env::args_os().skip(1)).exec())
+		.unwrap_or_else(|error| error);
+	panic!("Couldn't restart the simulator: {}", error)
+}
+
+fn host_date_and_time() -> OffsetDateTime
+{
+	let seconds_since_epoch = std::time::SystemTime::now()
+		.duration_since(std::time::UNIX_EPOCH)
+		.unwrap_or_default()
+		.as_secs();
+	OffsetDateTime::from_unix_timestamp(seconds_since_epoch as i64).unwrap_or(OffsetDateTime::UNIX_EPOCH)
+}
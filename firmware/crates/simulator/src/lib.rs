@@ -0,0 +1,62 @@
+pub mod configuration;
+pub mod peripherals;
+
+use std::path::Path;
+
+use configuration::{Configuration, Peripherals};
+use firmware_core::Camera;
+
+/// Creates the camera with the host peripherals (check [`Peripherals::new`]).
+pub fn create_camera(
+	frames_directory: &Path, sd_card_image: &Path, pir_sensor_script: Option<&Path>,
+) -> Result<Camera<Configuration>, CreateCameraError>
+{
+	let peripherals = Peripherals::new(frames_directory, sd_card_image, pir_sensor_script)
+		.map_err(CreateCameraError::CantCreatePeripherals)?;
+	Camera::new(Configuration::new(peripherals)).map_err(CreateCameraError::CameraCreation)
+}
+
+#[derive(Debug)]
+pub enum CreateCameraError
+{
+	CantCreatePeripherals(std::io::Error),
+	CameraCreation(firmware_core::errors::CreationError<Configuration>),
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+	use crate::peripherals::sd_card::tests::formatted_card;
+
+	/// The markers a JPEG starts with, up to the size of the image (16x16), without the compressed data.
+	const FRAME: [u8; 21] = [
+		0xFF, 0xD8, 0xFF, 0xC0, 0x00, 0x11, 0x08, 0x00, 0x10, 0x00, 0x10, 0x03, 0x01, 0x22, 0x00, 0x02, 0x11, 0x01,
+		0x03, 0x11, 0x01,
+	];
+
+	#[test]
+	fn camera_ticks_with_the_simulator_peripherals()
+	{
+		let frames_directory = std::env::temp_dir().join(format!("simulator_frames_{}", std::process::id()));
+		std::fs::create_dir_all(&frames_directory).unwrap();
+		std::fs::write(frames_directory.join("0001.jpg"), FRAME).unwrap();
+		let sd_card_image = formatted_card("camera");
+
+		let peripherals = Peripherals::new(&frames_directory, &sd_card_image, None)
+			.unwrap()
+			.with_http_server_ports(0, 0);
+		let mut camera = Camera::new(Configuration::new(peripherals)).unwrap();
+		for _ in 0..5
+		{
+			camera.tick().unwrap();
+		}
+
+		drop(camera);
+		std::fs::remove_dir_all(frames_directory).unwrap();
+		for extension in ["img", "settings"]
+		{
+			let _ = std::fs::remove_file(sd_card_image.with_extension(extension));
+		}
+	}
+}
@@ -0,0 +1,69 @@
+use std::{path::PathBuf, time::Duration};
+
+use firmware_core::errors::TickError;
+use simulator::create_camera;
+
+/// Time the main loop sleeps between two calls to [`Camera::tick`](firmware_core::Camera::tick), so that the simulator
+/// doesn't keep a core at 100%.
+const TICK_PERIOD: Duration = Duration::from_millis(10);
+
+const USAGE: &str = "Usage: simulator <frames directory> <SD card image> [PIR sensor script]";
+
+fn main()
+{
+	env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+
+	let arguments = match Arguments::parse(std::env::args().skip(1))
+	{
+		Some(arguments) => arguments,
+		None =>
+		{
+			eprintln!("{}", USAGE);
+			std::process::exit(1);
+		},
+	};
+
+	let mut camera = create_camera(
+		&arguments.frames_directory,
+		&arguments.sd_card_image,
+		arguments.pir_sensor_script.as_deref(),
+	)
+	.unwrap();
+	loop
+	{
+		if let Err(error) = camera.tick()
+		{
+			match error
+			{
+				TickError::Camera(error) => log::warn!("Camera error: {:?}", error),
+				TickError::Storage(error) => log::error!("Couldn't store the image: {:?}", error),
+				error => log::error!("Error in the main loop: {:?}", error),
+			}
+		}
+
+		std::thread::sleep(TICK_PERIOD);
+	}
+}
+
+struct Arguments
+{
+	frames_directory: PathBuf,
+	sd_card_image: PathBuf,
+	pir_sensor_script: Option<PathBuf>,
+}
+
+impl Arguments
+{
+	fn parse(mut arguments: impl Iterator<Item = String>) -> Option<Self>
+	{
+		let frames_directory = arguments.next()?.into();
+		let sd_card_image = arguments.next()?.into();
+		let pir_sensor_script = arguments.next().map(Into::into);
+
+		Some(Self {
+			frames_directory,
+			sd_card_image,
+			pir_sensor_script,
+		})
+	}
+}
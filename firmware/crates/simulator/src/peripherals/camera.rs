@@ -0,0 +1,196 @@
+use std::{
+	cell::Cell,
+	path::{Path, PathBuf},
+	time::{Duration, Instant},
+};
+
+use a13c_embedded::utils::math::micromath::micromath::vector::U16x2;
+use firmware_core::configuration::peripherals::camera::{
+	Camera as CameraTrait, GrayscaleImage, Image, SensorControl, SensorSettings,
+};
+
+/// A camera that replays the JPEG files of a directory in a loop, one file for each call to
+/// [`get_image`](CameraTrait::get_image).
+pub struct ReplayCamera
+{
+	frames: Vec<PathBuf>,
+	next_frame: Cell<usize>,
+	started_at: Instant,
+	sensor_settings: Cell<SensorSettings>,
+}
+
+impl ReplayCamera
+{
+	pub fn new(frames_directory: &Path) -> Result<Self, std::io::Error>
+	{
+		let mut frames = Vec::new();
+		for entry in std::fs::read_dir(frames_directory)?
+		{
+			let path = entry?.path();
+			let is_jpeg = path
+				.extension()
+				.and_then(|extension| extension.to_str())
+				.is_some_and(|extension| {
+					extension.eq_ignore_ascii_case("jpg") || extension.eq_ignore_ascii_case("jpeg")
+				});
+			if is_jpeg
+			{
+				frames.push(path);
+			}
+		}
+		frames.sort();
+
+		log::info!("Replaying {} frames from {:?}", frames.len(), frames_directory);
+
+		Ok(Self {
+			frames,
+			next_frame: Cell::new(0),
+			started_at: Instant::now(),
+			sensor_settings: Cell::new(SensorSettings::default()),
+		})
+	}
+}
+
+impl CameraTrait for ReplayCamera
+{
+	type Image<'a> = ReplayImage;
+	type Error = ReplayCameraError;
+	type Sensor<'a> = ReplaySensor<'a>;
+	type PreviousCaptureSettings = ();
+
+	fn get_image<'a>(&'a self) -> Result<Self::Image<'a>, Self::Error>
+	{
+		if self.frames.is_empty()
+		{
+			return Err(ReplayCameraError::NoFrames);
+		}
+
+		let frame_index = self.next_frame.get();
+		self.next_frame.set((frame_index + 1) % self.frames.len());
+
+		let pixels = std::fs::read(&self.frames[frame_index]).map_err(ReplayCameraError::Io)?;
+		Ok(ReplayImage {
+			size: jpeg_size(&pixels).unwrap_or(U16x2 { x: 0, y: 0 }),
+			pixels,
+			timestamp: self.started_at.elapsed(),
+		})
+	}
+
+	fn get_sensor<'a>(&'a self) -> Self::Sensor<'a>
+	{
+		ReplaySensor {
+			settings: &self.sensor_settings,
+		}
+	}
+}
+
+/// Only remembers the settings, since they can't be applied to the replayed frames.
+pub struct ReplaySensor<'a>
+{
+	settings: &'a Cell<SensorSettings>,
+}
+
+impl SensorControl for ReplaySensor<'_>
+{
+	type Error = ReplayCameraError;
+
+	fn get_settings(&self) -> Result<SensorSettings, Self::Error>
+	{
+		Ok(self.settings.get())
+	}
+
+	fn set_settings(&self, settings: &SensorSettings) -> Result<(), Self::Error>
+	{
+		self.settings.set(*settings);
+		Ok(())
+	}
+}
+
+pub struct ReplayImage
+{
+	pixels: Vec<u8>,
+	size: U16x2,
+	timestamp: Duration,
+}
+
+impl Image for ReplayImage
+{
+	fn get_pixels(&self) -> &[u8]
+	{
+		&self.pixels
+	}
+
+	fn get_size(&self) -> U16x2
+	{
+		self.size
+	}
+
+	fn get_timestamp(&self) -> Duration
+	{
+		self.timestamp
+	}
+
+	fn to_grayscale(&self) -> Option<GrayscaleImage>
+	{
+		/// The decoder scales the image while decoding it, by a factor of at most 8.
+		const SCALE_FACTOR: u16 = 8;
+
+		let mut decoder = jpeg_decoder::Decoder::new(self.pixels.as_slice());
+		decoder.read_info().ok()?;
+		let (width, height) = decoder
+			.scale(self.size.x / SCALE_FACTOR, self.size.y / SCALE_FACTOR)
+			.ok()?;
+		let pixels = decoder.decode().ok()?;
+
+		let pixels = match decoder.info()?.pixel_format
+		{
+			jpeg_decoder::PixelFormat::L8 => pixels,
+			jpeg_decoder::PixelFormat::L16 => pixels.chunks_exact(2).map(|pixel| pixel[0]).collect(),
+			jpeg_decoder::PixelFormat::RGB24 => pixels
+				.chunks_exact(3)
+				.map(|pixel| ((pixel[0] as u32 * 77 + pixel[1] as u32 * 150 + pixel[2] as u32 * 29) >> 8) as u8)
+				.collect(),
+			jpeg_decoder::PixelFormat::CMYK32 => return None,
+		};
+
+		Some(GrayscaleImage {
+			size: U16x2 { x: width, y: height },
+			pixels,
+		})
+	}
+}
+
+#[derive(Debug)]
+pub enum ReplayCameraError
+{
+	NoFrames,
+	Io(std::io::Error),
+}
+
+/// Reads the size of the JPEG image from its first "start of frame" segment.
+fn jpeg_size(jpeg: &[u8]) -> Option<U16x2>
+{
+	const START_OF_FRAME_MARKERS: [u8; 3] = [0xC0, 0xC1, 0xC2];
+
+	let mut index = 2;
+	while index + 9 <= jpeg.len()
+	{
+		if jpeg[index] != 0xFF
+		{
+			return None;
+		}
+
+		let marker = jpeg[index + 1];
+		let segment_length = u16::from_be_bytes([jpeg[index + 2], jpeg[index + 3]]) as usize;
+		if START_OF_FRAME_MARKERS.contains(&marker)
+		{
+			return Some(U16x2 {
+				x: u16::from_be_bytes([jpeg[index + 7], jpeg[index + 8]]),
+				y: u16::from_be_bytes([jpeg[index + 5], jpeg[index + 6]]),
+			});
+		}
+		index += 2 + segment_length;
+	}
+
+	None
+}
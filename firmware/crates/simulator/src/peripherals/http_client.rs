@@ -0,0 +1,209 @@
+use std::{
+	io::{BufRead, BufReader, ErrorKind, Read, Write},
+	net::{TcpStream, ToSocketAddrs},
+	time::Duration,
+};
+
+use firmware_core::configuration::peripherals::http_client::HttpClient;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+/// The body of a GET response is read in chunks of this size.
+const CHUNK_SIZE: usize = 4 * 1024;
+
+/// An HTTP/1.1 client built on the standard library's sockets, with a new connection for each request. It only
+/// supports `http://` URLs.
+pub struct StdHttpClient;
+
+impl HttpClient for StdHttpClient
+{
+	type Error = std::io::Error;
+
+	fn post(&mut self, url: &str, content_type: &str, body: &[u8]) -> Result<u16, Self::Error>
+	{
+		let (mut stream, host, path) = connect(url)?;
+		write!(
+			stream,
+			"POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+			path,
+			host,
+			content_type,
+			body.len()
+		)?;
+		stream.write_all(body)?;
+
+		// Only the status line is needed, like `HTTP/1.1 200 OK`
+		let mut status_line = String::new();
+		BufReader::new(stream).read_line(&mut status_line)?;
+		parse_status(&status_line)
+	}
+
+	/// The body must have a `Content-Length` or end when the connection is closed (chunked bodies aren't supported).
+	fn get(&mut self, url: &str, on_body_chunk: &mut dyn FnMut(&[u8]) -> bool) -> Result<u16, Self::Error>
+	{
+		let (mut stream, host, path) = connect(url)?;
+		write!(
+			stream,
+			"GET {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n",
+			path, host
+		)?;
+
+		let mut reader = BufReader::new(stream);
+		let mut status_line = String::new();
+		reader.read_line(&mut status_line)?;
+		let status = parse_status(&status_line)?;
+
+		let mut remaining_length = u64::MAX;
+		loop
+		{
+			let mut header = String::new();
+			if reader.read_line(&mut header)? == 0 || header.trim_end().is_empty()
+			{
+				break;
+			}
+			if let Some((name, value)) = header.split_once(':')
+			{
+				if name.eq_ignore_ascii_case("Content-Length")
+				{
+					remaining_length = value.trim().parse().map_err(|_| invalid_response(&header))?;
+				}
+			}
+		}
+
+		let mut chunk = vec![0; CHUNK_SIZE];
+		while remaining_length > 0
+		{
+			let max_length = remaining_length.min(CHUNK_SIZE as u64) as usize;
+			match reader.read(&mut chunk[..max_length])?
+			{
+				0 => break,
+				read_bytes =>
+				{
+					remaining_length -= read_bytes as u64;
+					if !on_body_chunk(&chunk[..read_bytes])
+					{
+						break;
+					}
+				},
+			}
+		}
+
+		Ok(status)
+	}
+}
+
+/// Returns the stream connected to the server of the `url`, its host and the path.
+fn connect(url: &str) -> Result<(TcpStream, &str, &str), std::io::Error>
+{
+	let invalid_url = || std::io::Error::new(ErrorKind::InvalidInput, format!("Unsupported URL: {}", url));
+	let without_scheme = url.strip_prefix("http://").ok_or_else(invalid_url)?;
+	let (host, path) = match without_scheme.find('/')
+	{
+		Some(index) => without_scheme.split_at(index),
+		None => (without_scheme, "/"),
+	};
+	let address = match host.contains(':')
+	{
+		true => host.to_socket_addrs()?,
+		false => (host, 80).to_socket_addrs()?,
+	}
+	.next()
+	.ok_or_else(invalid_url)?;
+
+	let stream = TcpStream::connect_timeout(&address, TIMEOUT)?;
+	stream.set_read_timeout(Some(TIMEOUT))?;
+	stream.set_write_timeout(Some(TIMEOUT))?;
+
+	Ok((stream, host, path))
+}
+
+/// Parses the status line, like `HTTP/1.1 200 OK`.
+fn parse_status(status_line: &str) -> Result<u16, std::io::Error>
+{
+	status_line
+		.split_whitespace()
+		.nth(1)
+		.and_then(|status| status.parse().ok())
+		.ok_or_else(|| invalid_response(status_line))
+}
+
+fn invalid_response(line: &str) -> std::io::Error
+{
+	std::io::Error::new(ErrorKind::InvalidData, format!("Invalid response: {}", line))
+}
+
+#[cfg(test)]
+mod tests
+{
+	use std::net::TcpListener;
+
+	use super::*;
+
+	#[test]
+	fn request_is_posted_to_a_local_receiver()
+	{
+		let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+		let url = format!("http://{}/events", listener.local_addr().unwrap());
+		let receiver = std::thread::spawn(move || {
+			let (mut stream, _) = listener.accept().unwrap();
+			let mut request = Vec::new();
+			let mut buffer = [0; 1024];
+			while !request.ends_with(b"{\"id\":\"1\"}")
+			{
+				let read = stream.read(&mut buffer).unwrap();
+				assert!(read > 0, "The connection was closed before the whole body was received");
+				request.extend_from_slice(&buffer[..read]);
+			}
+			stream
+				.write_all(b"HTTP/1.1 202 Accepted\r\nContent-Length: 0\r\n\r\n")
+				.unwrap();
+			String::from_utf8(request).unwrap()
+		});
+
+		let status = StdHttpClient.post(&url, "application/json", br#"{"id":"1"}"#).unwrap();
+		let request = receiver.join().unwrap();
+
+		assert_eq!(status, 202);
+		assert!(request.starts_with("POST /events HTTP/1.1\r\n"));
+		assert!(request.contains("Content-Type: application/json\r\nContent-Length: 10\r\n"));
+	}
+
+	#[test]
+	fn body_is_read_up_to_the_content_length()
+	{
+		let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+		let url = format!("http://{}/manifest.json", listener.local_addr().unwrap());
+		let server = std::thread::spawn(move || {
+			let (mut stream, _) = listener.accept().unwrap();
+			let mut request = [0; 1024];
+			let read = stream.read(&mut request).unwrap();
+			assert!(request[..read].starts_with(b"GET /manifest.json HTTP/1.1\r\n"));
+			// The connection is left open after the body
+			stream
+				.write_all(b"HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: 5\r\n\r\nhello")
+				.unwrap();
+			stream
+		});
+
+		let mut body = Vec::new();
+		let status = StdHttpClient
+			.get(&url, &mut |chunk| {
+				body.extend_from_slice(chunk);
+				true
+			})
+			.unwrap();
+		drop(server.join().unwrap());
+
+		assert_eq!(status, 200);
+		assert_eq!(body, b"hello");
+	}
+
+	#[test]
+	fn https_isnt_supported()
+	{
+		let error = StdHttpClient
+			.post("https://example.com", "application/json", b"{}")
+			.unwrap_err();
+
+		assert_eq!(error.kind(), ErrorKind::InvalidInput);
+	}
+}
@@ -0,0 +1,367 @@
+use std::{
+	convert::Infallible,
+	io::{BufRead, BufReader, Read as _, Write as _},
+	net::{TcpListener, TcpStream},
+	sync::{Arc, Mutex},
+};
+
+use a13c_embedded::features::communication::http::server::{HttpRequest, HttpServer};
+use embedded_io::{ErrorType, Read, Write};
+use embedded_svc::http::{
+	server::{Connection, Request},
+	Headers, Method, Query,
+};
+
+/// An HTTP/1.1 server built on the standard library's sockets, that spawns a thread for each connection (so that
+/// long running handlers like the MJPEG stream don't block the other clients).
+///
+/// Like the ESP-IDF server, a registered URI ending with `*` matches all the URIs starting with the same prefix.
+pub struct StdHttpServer<R: HttpRequest>
+{
+	routes: Arc<Routes<R>>,
+}
+
+type Routes<R> = Mutex<Vec<(R, <R as HttpRequest>::Data)>>;
+
+impl<R: HttpRequest + Copy + Send + 'static> StdHttpServer<R>
+where R::Data: Send
+{
+	pub fn new(port: u16) -> Result<Self, std::io::Error>
+	{
+		let listener = TcpListener::bind(("0.0.0.0", port))?;
+		log::info!("HTTP server listening on port {}", port);
+
+		let routes = Arc::new(Routes::<R>::new(Vec::new()));
+		let accept_routes = Arc::clone(&routes);
+		std::thread::spawn(move || {
+			for stream in listener.incoming().flatten()
+			{
+				let routes = Arc::clone(&accept_routes);
+				std::thread::spawn(move || {
+					if let Err(error) = handle_connection(stream, &routes)
+					{
+						log::warn!("Error while handling HTTP connection: {:?}", error);
+					}
+				});
+			}
+		});
+
+		Ok(Self { routes })
+	}
+}
+
+impl<R: HttpRequest> HttpServer for StdHttpServer<R>
+{
+	type Error = Infallible;
+	type HttpRequest = R;
+
+	fn register_request(&mut self, request: R, data: R::Data) -> Result<(), Self::Error>
+	{
+		self.routes.lock().unwrap().push((request, data));
+		Ok(())
+	}
+}
+
+fn handle_connection<R: HttpRequest + Copy>(stream: TcpStream, routes: &Routes<R>) -> Result<(), std::io::Error>
+{
+	let mut connection = StdConnection::accept(stream)?;
+
+	let route = routes
+		.lock()
+		.unwrap()
+		.iter()
+		.find(|(request, _)| {
+			request.method() == connection.head.method && uri_matches(request.uri(), connection.head.path())
+		})
+		.map(|(request, data)| (*request, data.clone()));
+
+	match route
+	{
+		Some((request, data)) => request.handle(Request::wrap(&mut connection), data)?,
+		None => connection.initiate_response(404, None, &[])?,
+	}
+
+	if !connection.is_response_initiated()
+	{
+		connection.initiate_response(500, None, &[])?;
+	}
+	connection.flush()
+}
+
+fn uri_matches(registered_uri: &str, path: &str) -> bool
+{
+	match registered_uri.strip_suffix('*')
+	{
+		Some(prefix) => path.starts_with(prefix),
+		None => registered_uri == path,
+	}
+}
+
+pub struct RequestHead
+{
+	method: Method,
+	uri: String,
+	headers: Vec<(String, String)>,
+}
+
+impl RequestHead
+{
+	fn path(&self) -> &str
+	{
+		self.uri.split('?').next().unwrap_or_default()
+	}
+}
+
+impl Query for RequestHead
+{
+	fn uri(&self) -> &str
+	{
+		&self.uri
+	}
+
+	fn method(&self) -> Method
+	{
+		self.method
+	}
+}
+
+impl Headers for RequestHead
+{
+	fn header(&self, name: &str) -> Option<&str>
+	{
+		self.headers
+			.iter()
+			.find(|(header_name, _)| header_name.eq_ignore_ascii_case(name))
+			.map(|(_, value)| value.as_str())
+	}
+}
+
+/// The body of a request, limited to its `Content-Length`.
+pub struct RequestBody
+{
+	stream: BufReader<TcpStream>,
+	remaining_length: usize,
+}
+
+impl ErrorType for RequestBody
+{
+	type Error = std::io::Error;
+}
+
+impl Read for RequestBody
+{
+	fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error>
+	{
+		let length = buf.len().min(self.remaining_length);
+		let read_bytes = self.stream.read(&mut buf[..length])?;
+		self.remaining_length -= read_bytes;
+		Ok(read_bytes)
+	}
+}
+
+pub struct RawConnection(TcpStream);
+
+impl ErrorType for RawConnection
+{
+	type Error = std::io::Error;
+}
+
+impl Read for RawConnection
+{
+	fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error>
+	{
+		self.0.read(buf)
+	}
+}
+
+impl Write for RawConnection
+{
+	fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error>
+	{
+		self.0.write(buf)
+	}
+
+	fn flush(&mut self) -> Result<(), Self::Error>
+	{
+		self.0.flush()
+	}
+}
+
+pub struct StdConnection
+{
+	head: RequestHead,
+	body: RequestBody,
+	raw_connection: RawConnection,
+	is_response_initiated: bool,
+}
+
+impl StdConnection
+{
+	fn accept(stream: TcpStream) -> Result<Self, std::io::Error>
+	{
+		let raw_connection = RawConnection(stream.try_clone()?);
+		let mut stream = BufReader::new(stream);
+
+		let mut request_line = String::new();
+		stream.read_line(&mut request_line)?;
+		let mut request_line = request_line.split_whitespace();
+		let method = request_line.next().and_then(parse_method).ok_or_else(invalid_request)?;
+		let uri = request_line.next().ok_or_else(invalid_request)?.to_string();
+
+		let mut headers = Vec::new();
+		loop
+		{
+			let mut header_line = String::new();
+			stream.read_line(&mut header_line)?;
+			let header_line = header_line.trim_end();
+			if header_line.is_empty()
+			{
+				break;
+			}
+			if let Some((name, value)) = header_line.split_once(':')
+			{
+				headers.push((name.trim().to_string(), value.trim().to_string()));
+			}
+		}
+
+		let head = RequestHead { method, uri, headers };
+		let remaining_length = head
+			.header("Content-Length")
+			.and_then(|content_length| content_length.parse().ok())
+			.unwrap_or(0);
+
+		Ok(Self {
+			head,
+			body: RequestBody {
+				stream,
+				remaining_length,
+			},
+			raw_connection,
+			is_response_initiated: false,
+		})
+	}
+}
+
+impl Query for StdConnection
+{
+	fn uri(&self) -> &str
+	{
+		self.head.uri()
+	}
+
+	fn method(&self) -> Method
+	{
+		self.head.method()
+	}
+}
+
+impl Headers for StdConnection
+{
+	fn header(&self, name: &str) -> Option<&str>
+	{
+		self.head.header(name)
+	}
+}
+
+impl ErrorType for StdConnection
+{
+	type Error = std::io::Error;
+}
+
+impl Read for StdConnection
+{
+	fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error>
+	{
+		self.body.read(buf)
+	}
+}
+
+impl Write for StdConnection
+{
+	fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error>
+	{
+		self.raw_connection.write(buf)
+	}
+
+	fn flush(&mut self) -> Result<(), Self::Error>
+	{
+		self.raw_connection.flush()
+	}
+}
+
+impl Connection for StdConnection
+{
+	type Headers = RequestHead;
+	type Read = RequestBody;
+	type RawConnectionError = std::io::Error;
+	type RawConnection = RawConnection;
+
+	fn split(&mut self) -> (&Self::Headers, &mut Self::Read)
+	{
+		(&self.head, &mut self.body)
+	}
+
+	fn initiate_response<'a>(
+		&'a mut self, status: u16, message: Option<&'a str>, headers: &'a [(&'a str, &'a str)],
+	) -> Result<(), Self::Error>
+	{
+		let mut response_head = format!("HTTP/1.1 {} {}\r\n", status, message.unwrap_or_else(|| reason(status)));
+		for (name, value) in headers
+		{
+			response_head += &format!("{}: {}\r\n", name, value);
+		}
+		// There's no chunked encoding, so the end of the body is signaled by closing the connection.
+		response_head += "Connection: close\r\n\r\n";
+
+		self.raw_connection.write_all(response_head.as_bytes())?;
+		self.is_response_initiated = true;
+		Ok(())
+	}
+
+	fn is_response_initiated(&self) -> bool
+	{
+		self.is_response_initiated
+	}
+
+	fn raw_connection(&mut self) -> Result<&mut Self::RawConnection, Self::Error>
+	{
+		Ok(&mut self.raw_connection)
+	}
+}
+
+fn parse_method(method: &str) -> Option<Method>
+{
+	match method
+	{
+		"GET" => Some(Method::Get),
+		"HEAD" => Some(Method::Head),
+		"POST" => Some(Method::Post),
+		"PUT" => Some(Method::Put),
+		"DELETE" => Some(Method::Delete),
+		"OPTIONS" => Some(Method::Options),
+		"PATCH" => Some(Method::Patch),
+		_ => None,
+	}
+}
+
+fn reason(status: u16) -> &'static str
+{
+	match status
+	{
+		200 => "OK",
+		204 => "No Content",
+		400 => "Bad Request",
+		401 => "Unauthorized",
+		403 => "Forbidden",
+		404 => "Not Found",
+		405 => "Method Not Allowed",
+		500 => "Internal Server Error",
+		503 => "Service Unavailable",
+		_ => "",
+	}
+}
+
+fn invalid_request() -> std::io::Error
+{
+	std::io::Error::new(std::io::ErrorKind::InvalidData, "Invalid HTTP request")
+}
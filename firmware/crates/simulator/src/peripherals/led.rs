@@ -0,0 +1,45 @@
+use embedded_hal::digital::{ErrorKind, ErrorType, OutputPin};
+
+/// An LED that only logs when it's turned on or off.
+pub struct LoggedLed
+{
+	name: &'static str,
+	is_on: bool,
+}
+
+impl LoggedLed
+{
+	pub fn new(name: &'static str) -> Self
+	{
+		Self { name, is_on: false }
+	}
+
+	fn set(&mut self, is_on: bool)
+	{
+		if is_on != self.is_on
+		{
+			log::info!("{} turned {}", self.name, if is_on { "on" } else { "off" });
+			self.is_on = is_on;
+		}
+	}
+}
+
+impl ErrorType for LoggedLed
+{
+	type Error = ErrorKind;
+}
+
+impl OutputPin for LoggedLed
+{
+	fn set_low(&mut self) -> Result<(), Self::Error>
+	{
+		self.set(false);
+		Ok(())
+	}
+
+	fn set_high(&mut self) -> Result<(), Self::Error>
+	{
+		self.set(true);
+		Ok(())
+	}
+}
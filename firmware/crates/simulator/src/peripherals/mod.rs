@@ -0,0 +1,13 @@
+pub mod camera;
+pub mod http_client;
+pub mod http_server;
+pub mod led;
+pub mod mqtt;
+pub mod ota;
+pub mod pir_sensor;
+pub mod real_time_clock;
+pub mod sd_card;
+pub mod settings_store;
+pub mod tls;
+pub mod watchdog;
+pub mod wifi;
@@ -0,0 +1,490 @@
+use std::{
+	collections::VecDeque,
+	io::{ErrorKind, Read, Write},
+	net::TcpStream,
+	sync::{Arc, Mutex},
+	time::{Duration, Instant},
+};
+
+use firmware_core::configuration::peripherals::mqtt::{MqttClient, MqttEvent, MqttLastWill};
+
+/// The broker disconnects the client if it doesn't receive anything for 1.5 times this.
+const KEEP_ALIVE: Duration = Duration::from_secs(30);
+/// A PINGREQ is sent when nothing has been sent for this long.
+const PING_PERIOD: Duration = Duration::from_secs(15);
+/// How often the reading thread checks if a PINGREQ must be sent, while nothing is received.
+const READ_TIMEOUT: Duration = Duration::from_secs(1);
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+const CONNECT: u8 = 0x10;
+const CONNACK: u8 = 0x20;
+const PUBLISH: u8 = 0x30;
+const SUBSCRIBE: u8 = 0x82;
+const PINGREQ: u8 = 0xC0;
+
+/// A minimal MQTT 3.1.1 client built on the standard library's sockets. Everything is published and subscribed with QoS
+/// 0, except the last will which is published by the broker with QoS 1.
+///
+/// A thread connects to the broker (and reconnects to it when the connection is lost), and reads the packets it sends.
+pub struct TcpMqttClient
+{
+	stream: Arc<Mutex<Connection>>,
+	events: Arc<Mutex<VecDeque<MqttEvent>>>,
+	next_packet_id: u16,
+}
+
+/// The writing half of the connection with the broker.
+#[derive(Default)]
+struct Connection
+{
+	/// `None` while it's disconnected.
+	stream: Option<TcpStream>,
+	/// When the last packet was sent, to know when a PINGREQ is needed.
+	last_sent_at: Option<Instant>,
+}
+
+impl Connection
+{
+	fn send(&mut self, packet: &[u8]) -> Result<(), std::io::Error>
+	{
+		let stream = self.stream.as_mut().ok_or(ErrorKind::NotConnected)?;
+		stream.write_all(packet)?;
+		self.last_sent_at = Some(Instant::now());
+
+		Ok(())
+	}
+
+	/// Sends a PINGREQ if nothing has been sent for [`PING_PERIOD`], so that the broker keeps the connection open.
+	fn keep_alive(&mut self) -> Result<(), std::io::Error>
+	{
+		match self.last_sent_at
+		{
+			Some(last_sent_at) if last_sent_at.elapsed() < PING_PERIOD => Ok(()),
+			_ => self.send(&packet(PINGREQ, &[])),
+		}
+	}
+}
+
+impl TcpMqttClient
+{
+	/// `broker_address` is like `localhost:1883`.
+	pub fn new(broker_address: String, last_will: MqttLastWill) -> Self
+	{
+		let stream = Arc::new(Mutex::new(Connection::default()));
+		let events = Arc::new(Mutex::new(VecDeque::new()));
+
+		std::thread::spawn({
+			let stream = Arc::clone(&stream);
+			let events = Arc::clone(&events);
+			move || {
+				let client_id = format!("camera-simulator-{}", std::process::id());
+				loop
+				{
+					match connect(&broker_address, &client_id, &last_will)
+					{
+						Ok((connection, reader)) => match connection.try_clone()
+						{
+							Ok(writer) =>
+							{
+								*stream.lock().unwrap() = Connection {
+									stream: Some(writer),
+									last_sent_at: Some(Instant::now()),
+								};
+								events.lock().unwrap().push_back(MqttEvent::Connected);
+								let error = read_packets(connection, reader, &stream, &events);
+								log::warn!("Lost the connection with the MQTT broker: {}", error);
+
+								*stream.lock().unwrap() = Connection::default();
+								events.lock().unwrap().push_back(MqttEvent::Disconnected);
+							},
+							Err(error) => log::warn!("Couldn't connect to the MQTT broker: {}", error),
+						},
+						Err(error) => log::warn!("Couldn't connect to the MQTT broker: {}", error),
+					}
+					std::thread::sleep(RECONNECT_DELAY);
+				}
+			}
+		});
+
+		Self {
+			stream,
+			events,
+			next_packet_id: 1,
+		}
+	}
+
+	fn send(&self, packet: &[u8]) -> Result<(), std::io::Error>
+	{
+		self.stream.lock().unwrap().send(packet)
+	}
+}
+
+impl MqttClient for TcpMqttClient
+{
+	type Error = std::io::Error;
+
+	fn publish(&mut self, topic: &str, payload: &[u8], retain: bool) -> Result<(), Self::Error>
+	{
+		self.send(&publish_packet(topic, payload, retain))
+	}
+
+	fn subscribe(&mut self, topic: &str) -> Result<(), Self::Error>
+	{
+		let packet_id = self.next_packet_id;
+		// 0 isn't a valid packet identifier
+		self.next_packet_id = self.next_packet_id.checked_add(1).unwrap_or(1);
+
+		self.send(&subscribe_packet(packet_id, topic))
+	}
+
+	fn poll_event(&mut self) -> Option<MqttEvent>
+	{
+		self.events.lock().unwrap().pop_front()
+	}
+}
+
+/// Opens the connection and waits for the broker to accept it. The reader keeps the bytes received after the CONNACK.
+fn connect(
+	broker_address: &str, client_id: &str, last_will: &MqttLastWill,
+) -> Result<(TcpStream, PacketReader), std::io::Error>
+{
+	let mut stream = TcpStream::connect(broker_address)?;
+	stream.write_all(&connect_packet(client_id, last_will))?;
+
+	let mut reader = PacketReader::default();
+	let (header, body) = loop
+	{
+		if let Some(packet) = reader.read(&mut stream)?
+		{
+			break packet;
+		}
+	};
+	match (header & 0xF0, body.get(1))
+	{
+		(CONNACK, Some(0)) => (),
+		(CONNACK, return_code) => Err(std::io::Error::new(
+			ErrorKind::ConnectionRefused,
+			format!("return code {:?}", return_code),
+		))?,
+		_ => Err(std::io::Error::new(ErrorKind::InvalidData, "expected a CONNACK"))?,
+	}
+
+	stream.set_read_timeout(Some(READ_TIMEOUT))?;
+	Ok((stream, reader))
+}
+
+/// Reads the packets until the connection is lost, and returns why it was lost.
+fn read_packets(
+	mut connection: TcpStream, mut reader: PacketReader, stream: &Mutex<Connection>,
+	events: &Mutex<VecDeque<MqttEvent>>,
+) -> std::io::Error
+{
+	loop
+	{
+		let packet = match reader.read(&mut connection)
+		{
+			Ok(packet) => packet,
+			Err(error) if matches!(error.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => None,
+			Err(error) => return error,
+		};
+		if let Err(error) = stream.lock().unwrap().keep_alive()
+		{
+			return error;
+		}
+		let Some((header, body)) = packet
+		else
+		{
+			continue;
+		};
+
+		// The messages are received with QoS 0 (so without a packet identifier) because the subscriptions are made
+		// with it, and the other packets are just acknowledgements
+		if header & 0xF0 == PUBLISH
+		{
+			let Some(topic_length) = body
+				.get(..2)
+				.map(|length| u16::from_be_bytes([length[0], length[1]]) as usize)
+			else
+			{
+				return std::io::Error::new(ErrorKind::InvalidData, "PUBLISH without a topic");
+			};
+			let Some(topic) = body.get(2..2 + topic_length)
+			else
+			{
+				return std::io::Error::new(ErrorKind::InvalidData, "PUBLISH with a truncated topic");
+			};
+
+			events.lock().unwrap().push_back(MqttEvent::Message {
+				topic: String::from_utf8_lossy(topic).into_owned(),
+				payload: body[2 + topic_length..].to_vec(),
+			});
+		}
+	}
+}
+
+/// Collects the bytes received until they make a whole packet, so that a read that times out in the middle of a
+/// packet doesn't lose the part already received.
+#[derive(Default)]
+struct PacketReader
+{
+	buffer: Vec<u8>,
+}
+
+impl PacketReader
+{
+	/// Reads from the `stream` once, and returns the first byte of the fixed header and the rest of the packet after
+	/// the fixed header if a packet is complete.
+	fn read(&mut self, stream: &mut impl Read) -> Result<Option<(u8, Vec<u8>)>, std::io::Error>
+	{
+		if let Some(packet) = self.take_packet()?
+		{
+			return Ok(Some(packet));
+		}
+
+		let mut bytes = [0; 1024];
+		let read_bytes = stream.read(&mut bytes)?;
+		if read_bytes == 0
+		{
+			return Err(ErrorKind::UnexpectedEof.into());
+		}
+		self.buffer.extend_from_slice(&bytes[..read_bytes]);
+
+		self.take_packet()
+	}
+
+	fn take_packet(&mut self) -> Result<Option<(u8, Vec<u8>)>, std::io::Error>
+	{
+		let Some((remaining_length, length_size)) = parse_remaining_length(self.buffer.get(1..).unwrap_or_default())?
+		else
+		{
+			return Ok(None);
+		};
+		let body_start = 1 + length_size;
+		if self.buffer.len() < body_start + remaining_length
+		{
+			return Ok(None);
+		}
+
+		let header = self.buffer[0];
+		let body = self.buffer[body_start..body_start + remaining_length].to_vec();
+		self.buffer.drain(..body_start + remaining_length);
+		Ok(Some((header, body)))
+	}
+}
+
+/// Parses the variable-length encoding of the remaining length of a packet, and returns it with the number of bytes
+/// it takes. Returns `None` if more bytes are needed.
+fn parse_remaining_length(bytes: &[u8]) -> Result<Option<(usize, usize)>, std::io::Error>
+{
+	let mut remaining_length = 0;
+	for (index, byte) in bytes.iter().enumerate()
+	{
+		if index == 4
+		{
+			return Err(std::io::Error::new(
+				ErrorKind::InvalidData,
+				"remaining length longer than 4 bytes",
+			));
+		}
+		remaining_length |= ((byte & 0x7F) as usize) << (7 * index);
+		if byte & 0x80 == 0
+		{
+			return Ok(Some((remaining_length, index + 1)));
+		}
+	}
+
+	Ok(None)
+}
+
+fn connect_packet(client_id: &str, last_will: &MqttLastWill) -> Vec<u8>
+{
+	let mut body = Vec::new();
+	write_string(&mut body, b"MQTT");
+	// Protocol level of MQTT 3.1.1
+	body.push(4);
+	// Clean session, will flag, will QoS 1 and will retain
+	body.push(0x02 | 0x04 | 0x08 | 0x20);
+	body.extend_from_slice(&(KEEP_ALIVE.as_secs() as u16).to_be_bytes());
+	write_string(&mut body, client_id.as_bytes());
+	write_string(&mut body, last_will.topic.as_bytes());
+	write_string(&mut body, &last_will.payload);
+
+	packet(CONNECT, &body)
+}
+
+fn publish_packet(topic: &str, payload: &[u8], retain: bool) -> Vec<u8>
+{
+	let mut body = Vec::new();
+	write_string(&mut body, topic.as_bytes());
+	body.extend_from_slice(payload);
+
+	packet(PUBLISH | retain as u8, &body)
+}
+
+fn subscribe_packet(packet_id: u16, topic: &str) -> Vec<u8>
+{
+	let mut body = packet_id.to_be_bytes().to_vec();
+	write_string(&mut body, topic.as_bytes());
+	// QoS 0
+	body.push(0);
+
+	packet(SUBSCRIBE, &body)
+}
+
+fn packet(header: u8, body: &[u8]) -> Vec<u8>
+{
+	let mut packet = vec![header];
+	let mut remaining_length = body.len();
+	loop
+	{
+		let byte = (remaining_length % 128) as u8;
+		remaining_length /= 128;
+		if remaining_length == 0
+		{
+			packet.push(byte);
+			break;
+		}
+		packet.push(byte | 0x80);
+	}
+	packet.extend_from_slice(body);
+
+	packet
+}
+
+fn write_string(buffer: &mut Vec<u8>, string: &[u8])
+{
+	buffer.extend_from_slice(&(string.len() as u16).to_be_bytes());
+	buffer.extend_from_slice(string);
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	/// Returns the bytes in chunks of the given sizes, and times out after each one like a socket with a read timeout.
+	struct ChunkedStream
+	{
+		chunks: VecDeque<Vec<u8>>,
+		timed_out: bool,
+	}
+
+	impl ChunkedStream
+	{
+		fn new(bytes: &[u8], chunk_sizes: &[usize]) -> Self
+		{
+			let mut rest = bytes;
+			let mut chunks = VecDeque::new();
+			for chunk_size in chunk_sizes
+			{
+				let (chunk, remaining) = rest.split_at(*chunk_size);
+				chunks.push_back(chunk.to_vec());
+				rest = remaining;
+			}
+			chunks.push_back(rest.to_vec());
+
+			Self {
+				chunks,
+				timed_out: false,
+			}
+		}
+	}
+
+	impl Read for ChunkedStream
+	{
+		fn read(&mut self, buffer: &mut [u8]) -> std::io::Result<usize>
+		{
+			self.timed_out = !self.timed_out;
+			if !self.timed_out
+			{
+				return Err(ErrorKind::WouldBlock.into());
+			}
+
+			let chunk = self.chunks.pop_front().unwrap_or_default();
+			buffer[..chunk.len()].copy_from_slice(&chunk);
+			Ok(chunk.len())
+		}
+	}
+
+	/// Reads until a packet is complete, ignoring the timeouts.
+	fn read_packet(reader: &mut PacketReader, stream: &mut ChunkedStream) -> (u8, Vec<u8>)
+	{
+		loop
+		{
+			match reader.read(stream)
+			{
+				Ok(Some(packet)) => return packet,
+				Ok(None) => (),
+				Err(error) if error.kind() == ErrorKind::WouldBlock => (),
+				Err(error) => panic!("{}", error),
+			}
+		}
+	}
+
+	#[test]
+	fn connect_has_the_keep_alive_and_the_last_will()
+	{
+		let last_will = MqttLastWill {
+			topic: "cam/availability".to_string(),
+			payload: b"offline".to_vec(),
+		};
+
+		let mut expected = vec![0x10, 42, 0, 4];
+		expected.extend_from_slice(b"MQTT");
+		expected.extend_from_slice(&[4, 0x2E, 0, 30, 0, 3]);
+		expected.extend_from_slice(b"cam");
+		expected.extend_from_slice(&[0, 16]);
+		expected.extend_from_slice(b"cam/availability");
+		expected.extend_from_slice(&[0, 7]);
+		expected.extend_from_slice(b"offline");
+		assert_eq!(connect_packet("cam", &last_will), expected);
+	}
+
+	#[test]
+	fn publish_and_subscribe_are_encoded()
+	{
+		assert_eq!(
+			publish_packet("a/b", b"ON", true),
+			[0x31, 7, 0, 3, b'a', b'/', b'b', b'O', b'N']
+		);
+		assert_eq!(publish_packet("a", b"", false), [0x30, 3, 0, 1, b'a']);
+		assert_eq!(subscribe_packet(258, "a/#"), [0x82, 8, 1, 2, 0, 3, b'a', b'/', b'#', 0]);
+	}
+
+	#[test]
+	fn remaining_length_uses_up_to_4_bytes()
+	{
+		assert_eq!(parse_remaining_length(&[0]).unwrap(), Some((0, 1)));
+		assert_eq!(parse_remaining_length(&[127, 5]).unwrap(), Some((127, 1)));
+		assert_eq!(parse_remaining_length(&[0x80, 1]).unwrap(), Some((128, 2)));
+		assert_eq!(
+			parse_remaining_length(&[0xFF, 0xFF, 0xFF, 0x7F]).unwrap(),
+			Some((268_435_455, 4))
+		);
+		assert_eq!(parse_remaining_length(&[0xFF, 0xFF]).unwrap(), None);
+		assert!(parse_remaining_length(&[0xFF, 0xFF, 0xFF, 0xFF, 1]).is_err());
+
+		let body = vec![7; 300];
+		let packet = packet(PUBLISH, &body);
+		assert_eq!(packet[..3], [0x30, 0xAC, 0x02]);
+		assert_eq!(parse_remaining_length(&packet[1..]).unwrap(), Some((300, 2)));
+	}
+
+	#[test]
+	fn packets_split_across_reads_are_reassembled()
+	{
+		let mut bytes = publish_packet("topic", &[1; 200], false);
+		bytes.extend_from_slice(&publish_packet("other", b"x", false));
+		// Split inside the remaining length, inside the body and between the packets
+		let mut stream = ChunkedStream::new(&bytes, &[2, 100, 108]);
+		let mut reader = PacketReader::default();
+
+		let (header, body) = read_packet(&mut reader, &mut stream);
+		assert_eq!(header, PUBLISH);
+		assert_eq!(body.len(), 7 + 200);
+		assert_eq!(&body[2..7], b"topic");
+
+		let (_, body) = read_packet(&mut reader, &mut stream);
+		assert_eq!(body, [0, 5, b'o', b't', b'h', b'e', b'r', b'x']);
+	}
+}
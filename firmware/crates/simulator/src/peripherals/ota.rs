@@ -0,0 +1,81 @@
+use std::{fs::File, io::Write, path::PathBuf};
+
+use firmware_core::configuration::peripherals::ota::OtaFlash;
+
+/// Saves the installed updates in a file of the host. The simulator can't boot them (it restarts itself), so the running
+/// firmware is never pending verification.
+pub struct FileOtaFlash
+{
+	path: PathBuf,
+	/// `Some` while an image is being written.
+	file: Option<File>,
+}
+
+impl FileOtaFlash
+{
+	pub fn new(path: PathBuf) -> Self
+	{
+		Self { path, file: None }
+	}
+
+	/// The image is written to a temporary file, so that the installed one is kept if the update is aborted.
+	fn temporary_path(&self) -> PathBuf
+	{
+		self.path.with_extension("tmp")
+	}
+}
+
+impl OtaFlash for FileOtaFlash
+{
+	type Error = std::io::Error;
+
+	fn running_version(&self) -> String
+	{
+		env!("CARGO_PKG_VERSION").to_string()
+	}
+
+	fn is_running_pending_verification(&mut self) -> Result<bool, Self::Error>
+	{
+		Ok(false)
+	}
+
+	fn mark_running_valid(&mut self) -> Result<(), Self::Error>
+	{
+		Ok(())
+	}
+
+	fn begin(&mut self, _: Option<usize>) -> Result<(), Self::Error>
+	{
+		self.file = Some(File::create(self.temporary_path())?);
+		Ok(())
+	}
+
+	fn write(&mut self, chunk: &[u8]) -> Result<(), Self::Error>
+	{
+		self.file
+			.as_mut()
+			.ok_or_else(|| std::io::Error::other("The update hasn't been started"))?
+			.write_all(chunk)
+	}
+
+	fn complete(&mut self) -> Result<(), Self::Error>
+	{
+		let file = self
+			.file
+			.take()
+			.ok_or_else(|| std::io::Error::other("The update hasn't been started"))?;
+		file.sync_all()?;
+		std::fs::rename(self.temporary_path(), &self.path)?;
+		log::info!("Installed the update in {}", self.path.display());
+		Ok(())
+	}
+
+	fn abort(&mut self) -> Result<(), Self::Error>
+	{
+		if self.file.take().is_some()
+		{
+			std::fs::remove_file(self.temporary_path())?;
+		}
+		Ok(())
+	}
+}
@@ -0,0 +1,99 @@
+use std::time::{Duration, Instant};
+
+use embedded_hal::digital::{ErrorKind, ErrorType, InputPin};
+
+/// An input pin whose level follows a script of timed steps, relative to when the pin was created.
+pub struct ScriptedInputPin
+{
+	/// Sorted by the first element of the tuple (the time since `started_at` after which the level changes).
+	steps: Vec<(Duration, bool)>,
+	initial_level: bool,
+	started_at: Instant,
+}
+
+impl ScriptedInputPin
+{
+	pub fn always(is_high: bool) -> Self
+	{
+		Self::new(is_high, Vec::new())
+	}
+
+	pub fn new(initial_level: bool, mut steps: Vec<(Duration, bool)>) -> Self
+	{
+		steps.sort_by_key(|(time, _)| *time);
+
+		Self {
+			steps,
+			initial_level,
+			started_at: Instant::now(),
+		}
+	}
+
+	/// Parses a script where each line contains the number of seconds (it can have decimals) after which the pin
+	/// changes level, and the new level (`high` or `low`). Empty lines and lines starting with `#` are ignored.
+	/// The pin is low before the first step.
+	///
+	/// ```text
+	/// # Someone walks by after 5 seconds and stays for 2.5 seconds
+	/// 5 high
+	/// 7.5 low
+	/// ```
+	pub fn from_script(script: &str) -> Result<Self, std::io::Error>
+	{
+		let mut steps = Vec::new();
+		for line in script.lines().map(str::trim)
+		{
+			if line.is_empty() || line.starts_with('#')
+			{
+				continue;
+			}
+
+			let invalid_line = || {
+				std::io::Error::new(
+					std::io::ErrorKind::InvalidData,
+					format!("Invalid PIR script line: {}", line),
+				)
+			};
+			let (seconds, level) = line.split_once(char::is_whitespace).ok_or_else(invalid_line)?;
+			let seconds: f64 = seconds.parse().map_err(|_| invalid_line())?;
+			let is_high = match level.trim()
+			{
+				"high" => true,
+				"low" => false,
+				_ => return Err(invalid_line()),
+			};
+			steps.push((Duration::from_secs_f64(seconds), is_high));
+		}
+
+		Ok(Self::new(false, steps))
+	}
+
+	fn level(&self) -> bool
+	{
+		let elapsed = self.started_at.elapsed();
+		self.steps
+			.iter()
+			.take_while(|(time, _)| *time <= elapsed)
+			.last()
+			.map(|(_, is_high)| *is_high)
+			.unwrap_or(self.initial_level)
+	}
+}
+
+impl ErrorType for ScriptedInputPin
+{
+	type Error = ErrorKind;
+}
+
+impl InputPin for ScriptedInputPin
+{
+	fn is_high(&mut self) -> Result<bool, Self::Error>
+	{
+		Ok(self.level())
+	}
+
+	fn is_low(&mut self) -> Result<bool, Self::Error>
+	{
+		Ok(!self.level())
+	}
+}
@@ -0,0 +1,75 @@
+use std::time::{Duration, Instant};
+
+use a13c_embedded::{
+	features::storage::embedded_sdmmc::{TimeSource as TimeSourceTrait, Timestamp},
+	peripherals::time::real_time::{
+		time::{Date, OffsetDateTime, Time},
+		RealTimeClock,
+	},
+};
+
+/// A real time clock that starts from a given date and time and then advances with the host's monotonic clock.
+///
+/// To simulate a clock that still hasn't been synchronized (like the ESP32's before the SNTP response arrives), it can
+/// return an error for a while after its creation.
+#[derive(Clone)]
+pub struct FakeRealTimeClock
+{
+	start_date_and_time: OffsetDateTime,
+	started_at: Instant,
+	unset_for: Duration,
+}
+
+impl FakeRealTimeClock
+{
+	pub fn new(start_date_and_time: OffsetDateTime, unset_for: Duration) -> Self
+	{
+		Self {
+			start_date_and_time,
+			started_at: Instant::now(),
+			unset_for,
+		}
+	}
+}
+
+impl RealTimeClock for FakeRealTimeClock
+{
+	type Error = ClockNotSet;
+
+	fn now(&self) -> Result<(Date, Time), Self::Error>
+	{
+		let elapsed = self.started_at.elapsed();
+		if elapsed < self.unset_for
+		{
+			return Err(ClockNotSet);
+		}
+
+		let now = self.start_date_and_time + elapsed;
+		Ok((now.date(), now.time()))
+	}
+}
+
+#[derive(Debug)]
+pub struct ClockNotSet;
+
+pub struct TimeSource(pub FakeRealTimeClock);
+
+impl TimeSourceTrait for TimeSource
+{
+	fn get_timestamp(&self) -> Timestamp
+	{
+		match self.0.now()
+		{
+			Ok((date, time)) => Timestamp {
+				year_since_1970: (date.year() - 1970) as u8,
+				zero_indexed_month: date.month() as u8 - 1,
+				zero_indexed_day: date.day() - 1,
+				hours: time.hour(),
+				minutes: time.minute(),
+				seconds: time.second(),
+			},
+			// The epoch of FAT, the earliest date it can store
+			Err(ClockNotSet) => Timestamp::from_fat(0, 0),
+		}
+	}
+}
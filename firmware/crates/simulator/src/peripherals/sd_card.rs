@@ -0,0 +1,304 @@
+use std::{
+	fs::File,
+	io::{Read, Seek, SeekFrom, Write},
+	path::Path,
+	sync::Mutex,
+};
+
+use a13c_embedded::features::storage::embedded_sdmmc::{Block, BlockCount, BlockDevice, BlockIdx};
+
+/// A block device backed by a disk image file, which takes the place of the SD card.
+///
+/// The image must contain an MBR partition table with a FAT partition, for example:
+/// ```text
+/// dd if=/dev/zero of=sd_card.img bs=1M count=256
+/// echo 'type=c' | sfdisk sd_card.img
+/// mkfs.vfat --offset 2048 sd_card.img
+/// ```
+pub struct FileBlockDevice
+{
+	file: Mutex<File>,
+}
+
+impl FileBlockDevice
+{
+	pub fn open(path: &Path) -> Result<Self, std::io::Error>
+	{
+		Ok(Self {
+			file: Mutex::new(File::options().read(true).write(true).open(path)?),
+		})
+	}
+}
+
+impl BlockDevice for FileBlockDevice
+{
+	type Error = std::io::Error;
+
+	fn read(&self, blocks: &mut [Block], start_block_idx: BlockIdx, _reason: &str) -> Result<(), Self::Error>
+	{
+		let mut file = self.file.lock().unwrap();
+		file.seek(SeekFrom::Start(start_block_idx.0 as u64 * Block::LEN as u64))?;
+		for block in blocks
+		{
+			file.read_exact(&mut block.contents)?;
+		}
+		Ok(())
+	}
+
+	fn write(&self, blocks: &[Block], start_block_idx: BlockIdx) -> Result<(), Self::Error>
+	{
+		let mut file = self.file.lock().unwrap();
+		file.seek(SeekFrom::Start(start_block_idx.0 as u64 * Block::LEN as u64))?;
+		for block in blocks
+		{
+			file.write_all(&block.contents)?;
+		}
+		Ok(())
+	}
+
+	fn num_blocks(&self) -> Result<BlockCount, Self::Error>
+	{
+		let length = self.file.lock().unwrap().metadata()?.len();
+		Ok(BlockCount((length / Block::LEN as u64) as u32))
+	}
+}
+
+#[cfg(test)]
+pub(crate) mod tests
+{
+	use std::{path::PathBuf, time::Duration};
+
+	use a13c_embedded::{
+		features::storage::embedded_sdmmc::Error,
+		peripherals::time::real_time::time::{Date, Month, OffsetDateTime, Time},
+	};
+	use firmware_core::features::storage::{CapturePath, RetentionPolicy, Storage};
+
+	use super::*;
+	use crate::peripherals::real_time_clock::{FakeRealTimeClock, TimeSource};
+
+	const LBA_START: u32 = 8;
+	const BLOCKS: u32 = 16 * 1024;
+	const FAT_SIZE: u16 = 32;
+	/// The clusters of 2 blocks left after the boot sector, the 2 FATs and the root directory.
+	const CLUSTER_COUNT: u64 = (BLOCKS - LBA_START - 1 - 2 * FAT_SIZE as u32 - 32) as u64 / 2;
+	const CLUSTER_SIZE: u64 = 1024;
+
+	/// Creates an 8 MB disk image with an empty FAT16 partition (what `mkfs.vfat` would do) and returns its path.
+	pub(crate) fn formatted_card(name: &str) -> PathBuf
+	{
+		let mut image = vec![0; BLOCKS as usize * Block::LEN];
+
+		let partition = &mut image[446..462];
+		partition[4] = 0x06;
+		partition[8..12].copy_from_slice(&LBA_START.to_le_bytes());
+		partition[12..16].copy_from_slice(&(BLOCKS - LBA_START).to_le_bytes());
+		image[510..512].copy_from_slice(&[0x55, 0xAA]);
+
+		let boot_sector = &mut image[LBA_START as usize * Block::LEN..][..Block::LEN];
+		boot_sector[..3].copy_from_slice(&[0xEB, 0x3C, 0x90]);
+		boot_sector[11..13].copy_from_slice(&512_u16.to_le_bytes());
+		boot_sector[13] = 2;
+		boot_sector[14..16].copy_from_slice(&1_u16.to_le_bytes());
+		boot_sector[16] = 2;
+		boot_sector[17..19].copy_from_slice(&512_u16.to_le_bytes());
+		boot_sector[19..21].copy_from_slice(&((BLOCKS - LBA_START) as u16).to_le_bytes());
+		boot_sector[21] = 0xF8;
+		boot_sector[22..24].copy_from_slice(&FAT_SIZE.to_le_bytes());
+		boot_sector[38] = 0x29;
+		boot_sector[43..54].copy_from_slice(b"NO NAME    ");
+		boot_sector[54..62].copy_from_slice(b"FAT16   ");
+		boot_sector[510..].copy_from_slice(&[0x55, 0xAA]);
+
+		for fat in 0..2
+		{
+			let fat_start = (LBA_START as usize + 1 + fat * FAT_SIZE as usize) * Block::LEN;
+			image[fat_start..fat_start + 4].copy_from_slice(&[0xF8, 0xFF, 0xFF, 0xFF]);
+		}
+
+		let path = std::env::temp_dir().join(format!("sd_card_{}_{}.img", std::process::id(), name));
+		std::fs::write(&path, image).unwrap();
+		path
+	}
+
+	fn open_storage(card: &Path, retention_policy: RetentionPolicy) -> Storage<FileBlockDevice, TimeSource>
+	{
+		let clock = FakeRealTimeClock::new(OffsetDateTime::UNIX_EPOCH, Duration::MAX);
+		Storage::new(
+			FileBlockDevice::open(card).unwrap(),
+			TimeSource(clock),
+			retention_policy,
+		)
+		.unwrap()
+	}
+
+	fn exists(storage: &mut Storage<FileBlockDevice, TimeSource>, path: CapturePath) -> bool
+	{
+		match storage.capture_size(&path)
+		{
+			Ok(_) => true,
+			Err(Error::NotFound) => false,
+			Err(error) => panic!("Couldn't check {}: {:?}", path, error),
+		}
+	}
+
+	#[test]
+	fn free_space_is_counted_in_the_fat()
+	{
+		let card = formatted_card("free_space");
+		let mut storage = open_storage(&card, RetentionPolicy::default());
+		assert_eq!(storage.stats().capacity_bytes, CLUSTER_COUNT * CLUSTER_SIZE);
+		assert_eq!(storage.free_bytes(), CLUSTER_COUNT * CLUSTER_SIZE);
+
+		storage.store_image(&[1; 1500], None).unwrap();
+		assert_eq!(storage.free_bytes(), (CLUSTER_COUNT - 2) * CLUSTER_SIZE);
+		drop(storage);
+
+		// The directory and the sequence number file take a cluster each too
+		let storage = open_storage(&card, RetentionPolicy::default());
+		assert_eq!(storage.free_bytes(), (CLUSTER_COUNT - 4) * CLUSTER_SIZE);
+		assert_eq!(storage.used_bytes(), 1500);
+		std::fs::remove_file(card).unwrap();
+	}
+
+	#[test]
+	fn oldest_no_clock_captures_are_deleted_one_at_a_time()
+	{
+		let card = formatted_card("no_clock");
+		let mut storage = open_storage(
+			&card,
+			RetentionPolicy {
+				max_used_bytes: Some(3000),
+				..Default::default()
+			},
+		);
+
+		for _ in 0..5
+		{
+			storage.store_image(&[1; 1000], None).unwrap();
+		}
+
+		let kept: Vec<bool> = (0..5)
+			.map(|number| exists(&mut storage, CapturePath::Sequence { number }))
+			.collect();
+		assert_eq!(kept, [false, false, true, true, true]);
+		assert_eq!(storage.used_bytes(), 3000);
+		drop(storage);
+		std::fs::remove_file(card).unwrap();
+	}
+
+	#[test]
+	fn oldest_hours_are_deleted_to_keep_the_free_space()
+	{
+		let card = formatted_card("min_free_space");
+		let mut storage = open_storage(
+			&card,
+			RetentionPolicy {
+				min_free_space_percentage: Some(50),
+				..Default::default()
+			},
+		);
+
+		let date = Date::from_calendar_date(2025, Month::March, 1).unwrap();
+		let paths: Vec<CapturePath> = (0..5)
+			.map(|hour| {
+				let time = Time::from_hms(hour, 0, 0).unwrap();
+				storage.store_image(&vec![1; 1_000_000], Some((date, time))).unwrap()
+			})
+			.collect();
+
+		let kept: Vec<bool> = paths.into_iter().map(|path| exists(&mut storage, path)).collect();
+		assert_eq!(kept, [false, true, true, true, true]);
+		assert!(storage.free_bytes() * 2 >= storage.stats().capacity_bytes);
+		drop(storage);
+		std::fs::remove_file(card).unwrap();
+	}
+
+	#[test]
+	fn old_captures_are_deleted_once_the_open_ones_are_closed()
+	{
+		let card = formatted_card("max_age");
+		let mut storage = open_storage(
+			&card,
+			RetentionPolicy {
+				max_age_days: Some(1),
+				..Default::default()
+			},
+		);
+
+		let date = Date::from_calendar_date(2025, Month::March, 1).unwrap();
+		let old_paths: Vec<CapturePath> = [9, 10]
+			.into_iter()
+			.map(|hour| {
+				storage
+					.store_image(&[1; 1000], Some((date, Time::from_hms(hour, 0, 0).unwrap())))
+					.unwrap()
+			})
+			.collect();
+
+		// The oldest hour can't be deleted while it's being downloaded, so the deletion stops there
+		let file = storage.open_capture(&old_paths[0]).unwrap();
+		let today = Date::from_calendar_date(2025, Month::March, 5).unwrap();
+		let new_path = storage.store_image(&[1; 1000], Some((today, Time::MIDNIGHT))).unwrap();
+		assert!(exists(&mut storage, old_paths[0]));
+		assert!(exists(&mut storage, old_paths[1]));
+
+		// They're deleted by the next capture of the same day, after the download
+		storage.close_capture(file).unwrap();
+		storage
+			.store_image(&[1; 1000], Some((today, Time::from_hms(1, 0, 0).unwrap())))
+			.unwrap();
+		assert!(!exists(&mut storage, old_paths[0]));
+		assert!(!exists(&mut storage, old_paths[1]));
+		assert!(exists(&mut storage, new_path));
+		drop(storage);
+		std::fs::remove_file(card).unwrap();
+	}
+
+	#[test]
+	fn captures_are_paged_and_read_while_others_are_stored()
+	{
+		let card = formatted_card("captures");
+		let mut storage = open_storage(&card, RetentionPolicy::default());
+
+		let date = Date::from_calendar_date(2025, Month::March, 1).unwrap();
+		let image: Vec<u8> = (0..10_000).map(|index| index as u8).collect();
+		let mut paths: Vec<CapturePath> = [(9, 0), (10, 0), (10, 30)]
+			.into_iter()
+			.map(|(hour, minute)| {
+				let time = Time::from_hms(hour, minute, 0).unwrap();
+				storage.store_image(&image, Some((date, time))).unwrap()
+			})
+			.collect();
+		paths.push(storage.store_image(&[1; 100], None).unwrap());
+
+		let page = storage.captures(1, 2).unwrap();
+		assert_eq!(page.total, 4);
+		let page_paths: Vec<CapturePath> = page.captures.iter().map(|capture| capture.path).collect();
+		assert_eq!(page_paths, [paths[1], paths[0]]);
+
+		// The file stays open while other captures are stored between the chunks
+		let file = storage.open_capture(&paths[2]).unwrap();
+		assert_eq!(file.size, 10_000);
+		let mut read_image = Vec::new();
+		let mut chunk = [0; 4096];
+		loop
+		{
+			let read_bytes = storage.read_capture(&file, &mut chunk).unwrap();
+			if read_bytes == 0
+			{
+				break;
+			}
+			read_image.extend_from_slice(&chunk[..read_bytes]);
+			storage.store_image(&[2; 100], None).unwrap();
+		}
+		assert_eq!(read_image, image);
+
+		assert!(matches!(storage.delete_capture(&paths[2]), Err(Error::FileAlreadyOpen)));
+		storage.close_capture(file).unwrap();
+		storage.delete_capture(&paths[2]).unwrap();
+		assert!(!exists(&mut storage, paths[2]));
+		drop(storage);
+		std::fs::remove_file(card).unwrap();
+	}
+}
@@ -0,0 +1,40 @@
+use std::path::PathBuf;
+
+use firmware_core::configuration::peripherals::settings_store::SettingsStore;
+
+/// Saves the settings in a file of the host.
+pub struct FileSettingsStore
+{
+	path: PathBuf,
+}
+
+impl FileSettingsStore
+{
+	pub fn new(path: PathBuf) -> Self
+	{
+		Self { path }
+	}
+}
+
+impl SettingsStore for FileSettingsStore
+{
+	type Error = std::io::Error;
+
+	fn read(&mut self) -> Result<Option<Vec<u8>>, Self::Error>
+	{
+		match std::fs::read(&self.path)
+		{
+			Ok(blob) => Ok(Some(blob)),
+			Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(None),
+			Err(error) => Err(error),
+		}
+	}
+
+	fn write(&mut self, blob: &[u8]) -> Result<(), Self::Error>
+	{
+		// Written to a temporary file first, so that the old settings are kept if the simulator is stopped halfway
+		let temporary_path = self.path.with_extension("tmp");
+		std::fs::write(&temporary_path, blob)?;
+		std::fs::rename(temporary_path, &self.path)
+	}
+}
@@ -0,0 +1,48 @@
+use std::path::PathBuf;
+
+use firmware_core::{configuration::peripherals::tls::TlsIdentityStore, features::http_server::tls::TlsIdentity};
+
+/// Saves the uploaded certificate and private key in a file of the host, in PEM. The simulator serves plain HTTP and
+/// doesn't generate a certificate, so the file is only there to try the API.
+pub struct FileTlsIdentityStore
+{
+	path: PathBuf,
+}
+
+impl FileTlsIdentityStore
+{
+	pub fn new(path: PathBuf) -> Self
+	{
+		Self { path }
+	}
+}
+
+impl TlsIdentityStore for FileTlsIdentityStore
+{
+	type Error = std::io::Error;
+
+	fn certificate(&mut self) -> Result<Option<String>, Self::Error>
+	{
+		let pem = match std::fs::read_to_string(&self.path)
+		{
+			Ok(pem) => pem,
+			Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+			Err(error) => return Err(error),
+		};
+
+		TlsIdentity::from_pem(&pem)
+			.map(|identity| Some(identity.certificate))
+			.map_err(|error| std::io::Error::new(std::io::ErrorKind::InvalidData, error))
+	}
+
+	/// The simulator can't check if the private key matches the certificate.
+	fn check(&mut self, _: &TlsIdentity) -> Result<(), Self::Error>
+	{
+		Ok(())
+	}
+
+	fn write(&mut self, identity: &TlsIdentity) -> Result<(), Self::Error>
+	{
+		std::fs::write(&self.path, identity.to_pem())
+	}
+}
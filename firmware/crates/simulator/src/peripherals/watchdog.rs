@@ -0,0 +1,28 @@
+use core::convert::Infallible;
+
+use a13c_embedded::peripherals::watchdog::{Watchdog, WatchdogCreator};
+
+/// There's no task watchdog on the host, so this never creates one.
+pub struct NoWatchdogCreator;
+
+impl WatchdogCreator for NoWatchdogCreator
+{
+	type Watchdog = NoWatchdog;
+
+	fn watch_current_thread(&self) -> Option<Self::Watchdog>
+	{
+		None
+	}
+}
+
+pub struct NoWatchdog;
+
+impl Watchdog for NoWatchdog
+{
+	type Error = Infallible;
+
+	fn feed(&mut self) -> Result<(), Self::Error>
+	{
+		Ok(())
+	}
+}
@@ -0,0 +1,90 @@
+use core::convert::Infallible;
+
+use embedded_svc::wifi::{AccessPointInfo, AuthMethod, Capability, Configuration, Wifi};
+use enumset::EnumSet;
+
+/// The host is already connected to the network, so this driver only keeps track of the state the firmware sets. The
+/// host's networks can't be scanned, so the scan finds a few fake ones that can be used to try the setup page.
+#[derive(Default)]
+pub struct HostWifi
+{
+	configuration: Configuration,
+	is_started: bool,
+	is_connected: bool,
+}
+
+impl Wifi for HostWifi
+{
+	type Error = Infallible;
+
+	fn get_capabilities(&self) -> Result<EnumSet<Capability>, Self::Error>
+	{
+		Ok(Capability::Client | Capability::AccessPoint | Capability::Mixed)
+	}
+
+	fn get_configuration(&self) -> Result<Configuration, Self::Error>
+	{
+		Ok(self.configuration.clone())
+	}
+
+	fn set_configuration(&mut self, conf: &Configuration) -> Result<(), Self::Error>
+	{
+		self.configuration = conf.clone();
+		Ok(())
+	}
+
+	fn start(&mut self) -> Result<(), Self::Error>
+	{
+		self.is_started = true;
+		Ok(())
+	}
+
+	fn stop(&mut self) -> Result<(), Self::Error>
+	{
+		self.is_started = false;
+		self.is_connected = false;
+		Ok(())
+	}
+
+	fn connect(&mut self) -> Result<(), Self::Error>
+	{
+		self.is_connected = self.is_started;
+		Ok(())
+	}
+
+	fn disconnect(&mut self) -> Result<(), Self::Error>
+	{
+		self.is_connected = false;
+		Ok(())
+	}
+
+	fn is_started(&self) -> Result<bool, Self::Error>
+	{
+		Ok(self.is_started)
+	}
+
+	fn is_connected(&self) -> Result<bool, Self::Error>
+	{
+		Ok(self.is_connected)
+	}
+
+	fn scan_n<const N: usize>(&mut self) -> Result<(heapless::Vec<AccessPointInfo, N>, usize), Self::Error>
+	{
+		Ok((heapless::Vec::new(), 0))
+	}
+
+	fn scan(&mut self) -> Result<Vec<AccessPointInfo>, Self::Error>
+	{
+		let access_point = |ssid: &str, signal_strength, auth_method| AccessPointInfo {
+			ssid: ssid.try_into().unwrap_or_default(),
+			signal_strength,
+			auth_method: Some(auth_method),
+			..Default::default()
+		};
+
+		Ok(vec![
+			access_point("Simulated home", -48, AuthMethod::WPA2Personal),
+			access_point("Simulated guests", -67, AuthMethod::None),
+		])
+	}
+}